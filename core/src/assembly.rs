@@ -0,0 +1,297 @@
+use std::time::Duration;
+
+use serde::{Deserialize, Serialize};
+
+use crate::error::CoreError;
+use crate::meeting::Transcription;
+use crate::platform::{HttpClient, Method};
+
+pub const ASSEMBLY_BASE_URL: &str = "https://api.assemblyai.com";
+
+const TRANSCRIPT_POLL_INTERVAL: Duration = Duration::from_millis(100);
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(tag = "message_type")]
+pub enum AssemblyResponse {
+    PartialTranscript {
+        text: String,
+        created: String,
+        #[serde(default)]
+        audio_start: u64,
+        #[serde(default)]
+        audio_end: u64,
+    },
+    FinalTranscript {
+        text: String,
+        created: String,
+        #[serde(default)]
+        audio_start: u64,
+        #[serde(default)]
+        audio_end: u64,
+    },
+    SessionBegins { session_id: String },
+    SessionInformation { audio_duration_seconds: f32 },
+    SessionTerminated,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct SummarizeRequest {
+    pub context: String,
+    pub final_model: String,
+    pub max_output_size: u16,
+    pub temperature: f32,
+    pub input_text: String,
+    pub answer_format: String,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct SummarizeResponse {
+    pub request_id: String,
+    pub response: String,
+}
+
+impl Default for SummarizeRequest {
+    fn default() -> Self {
+        SummarizeRequest {
+            context: String::new(),
+            final_model: "anthropic/claude-3-5-sonnet".to_string(),
+            max_output_size: 3000,
+            temperature: 0.0,
+            input_text: String::new(),
+            answer_format: "bullet points".to_string(),
+        }
+    }
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct TokenResponse {
+    pub token: String,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct UploadResponse {
+    pub upload_url: String,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct TranscribeRequest {
+    pub audio_url: String,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct AskQuestionQuestion {
+    pub question: String,
+    pub answer_format: String,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct AskQuestionRequest {
+    pub questions: Vec<AskQuestionQuestion>,
+    pub input_text: String,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct AskQuestionResponseItem {
+    pub question: String,
+    pub answer: String,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct AskQuestionResponse {
+    pub request_id: String,
+    pub response: Vec<AskQuestionResponseItem>,
+}
+
+#[derive(Debug, Serialize, Deserialize, PartialEq)]
+#[serde(rename_all = "snake_case")]
+pub enum TranscriptionStatus {
+    Queued,
+    Processing,
+    Completed,
+    Error,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct TranscribeResponse {
+    pub id: String,
+    pub status: TranscriptionStatus,
+    pub text: Option<String>,
+}
+
+// Text frames of the realtime API may be split, fragments are accumulated until they parse as a whole message
+#[derive(Debug, Default)]
+pub struct ResponseBuffer {
+    buffer: Vec<u8>,
+}
+
+impl ResponseBuffer {
+    pub fn push(&mut self, fragment: &[u8]) -> Option<AssemblyResponse> {
+        self.buffer.extend_from_slice(fragment);
+
+        let response = serde_json::from_slice::<AssemblyResponse>(self.buffer.as_slice()).ok()?;
+
+        self.buffer.clear();
+
+        Some(response)
+    }
+}
+
+#[derive(Clone)]
+pub struct Assembly<H: HttpClient> {
+    api_key: String,
+    base_url: String,
+    client: H,
+}
+
+impl<H: HttpClient> Assembly<H> {
+    pub fn new<S: Into<String>>(api_key: S, client: H) -> Self {
+        Assembly {
+            api_key: api_key.into(),
+            base_url: ASSEMBLY_BASE_URL.to_string(),
+            client,
+        }
+    }
+
+    // Points the SDK somewhere else than AssemblyAI, e.g. a mock server for tests
+    pub fn with_base_url<S: Into<String>>(mut self, base_url: S) -> Self {
+        self.base_url = base_url.into().trim_end_matches('/').to_string();
+        self
+    }
+
+    pub fn api_key(&self) -> &str {
+        self.api_key.as_str()
+    }
+
+    pub fn url(&self, path: &str) -> String {
+        format!("{}{}", self.base_url, path)
+    }
+
+    pub fn realtime_url(&self, sample_rate: u32, token: &str) -> String {
+        let base_url = self
+            .base_url
+            .replacen("https://", "wss://", 1)
+            .replacen("http://", "ws://", 1);
+
+        format!(
+            "{}/v2/realtime/ws?sample_rate={}&enable_extra_session_information=true&token={}",
+            base_url, sample_rate, token
+        )
+    }
+
+    pub fn summarize_transcripts(
+        &mut self,
+        transcription: Vec<Transcription>,
+    ) -> Result<SummarizeResponse, CoreError> {
+        let request = SummarizeRequest {
+            input_text: Self::input_text(transcription),
+            ..Default::default()
+        };
+
+        self.post("/lemur/v3/generate/summary", &request)
+    }
+
+    pub fn action_items(
+        &mut self,
+        transcription: Vec<Transcription>,
+    ) -> Result<SummarizeResponse, CoreError> {
+        let request = SummarizeRequest {
+            context: "A meeting recorded by a device placed on the table".to_string(),
+            input_text: Self::input_text(transcription),
+            ..Default::default()
+        };
+
+        self.post("/lemur/v3/generate/action-items", &request)
+    }
+
+    pub fn ask_question<T: Into<String>>(
+        &mut self,
+        question: T,
+        transcription: Vec<Transcription>,
+    ) -> Result<AskQuestionResponse, CoreError> {
+        let request = AskQuestionRequest {
+            questions: vec![AskQuestionQuestion {
+                question: question.into(),
+                answer_format: "short sentence".to_string(),
+            }],
+            input_text: Self::input_text(transcription),
+        };
+
+        self.post("/lemur/v3/generate/question-answer", &request)
+    }
+
+    pub fn get_transcript<T: Into<String>>(
+        &mut self,
+        transcript_id: T,
+    ) -> Result<TranscribeResponse, CoreError> {
+        let url = self.url(&format!("/v2/transcript/{}", transcript_id.into()));
+        let headers = [("Authorization", self.api_key.as_str())];
+
+        self.client.request(Method::Get, &url, &headers, None)?.json()
+    }
+
+    pub fn transcribe<T: Into<String>>(
+        &mut self,
+        audio_url: T,
+    ) -> Result<TranscribeResponse, CoreError> {
+        let request = TranscribeRequest {
+            audio_url: audio_url.into(),
+        };
+
+        self.post("/v2/transcript", &request)
+    }
+
+    pub fn transcribe_wait<T: Into<String>>(
+        &mut self,
+        audio_url: T,
+    ) -> Result<TranscribeResponse, CoreError> {
+        let response = self.transcribe(audio_url)?;
+
+        loop {
+            if let Ok(response) = self.get_transcript(&response.id) {
+                match response.status {
+                    TranscriptionStatus::Completed => break Ok(response),
+                    TranscriptionStatus::Queued | TranscriptionStatus::Processing => {
+                        std::thread::sleep(TRANSCRIPT_POLL_INTERVAL)
+                    }
+                    TranscriptionStatus::Error => {
+                        break Err(CoreError::HttpError {
+                            status: 200,
+                            body: format!("failed to transcribe {}", response.id),
+                        })
+                    }
+                }
+            }
+        }
+    }
+
+    // The returned url is what transcribe takes for audio that isn't publicly reachable
+    pub fn upload(&mut self, content_type: &str, audio: &[u8]) -> Result<UploadResponse, CoreError> {
+        let url = self.url("/v2/upload");
+        let headers = [("Authorization", self.api_key.as_str()), ("Content-Type", content_type)];
+
+        self.client.request(Method::Post, &url, &headers, Some(audio))?.json()
+    }
+
+    pub fn create_temporary_token(&mut self) -> Result<TokenResponse, CoreError> {
+        self.post("/v2/realtime/token", &serde_json::json!({ "expires_in": 3600 }))
+    }
+
+    fn post<T: Serialize, R: serde::de::DeserializeOwned>(&mut self, path: &str, request: &T) -> Result<R, CoreError> {
+        let url = self.url(path);
+        let body = serde_json::to_vec(request)?;
+        let headers = [
+            ("Authorization", self.api_key.as_str()),
+            ("Content-Type", "application/json"),
+        ];
+
+        self.client.request(Method::Post, &url, &headers, Some(&body))?.json()
+    }
+
+    fn input_text(transcription: Vec<Transcription>) -> String {
+        transcription
+            .into_iter()
+            .map(|transcription| transcription.text)
+            .collect::<Vec<_>>()
+            .join("\n")
+    }
+}
@@ -0,0 +1,140 @@
+use std::io::Read;
+
+use crate::error::CoreError;
+
+pub const WAV_HEADER_SIZE: usize = 44;
+// The largest data length a RIFF header can hold, for audio whose length isn't known up front
+pub const UNKNOWN_WAV_LENGTH: u32 = u32::MAX - WAV_HEADER_SIZE as u32;
+
+// Canonical PCM WAV header for `data_length` bytes of samples, for streaming raw recordings as .wav files
+pub fn wav_header(data_length: u32, sample_rate: u32, channels: u16, bits_per_sample: u16) -> [u8; WAV_HEADER_SIZE] {
+    let block_align = channels * bits_per_sample / 8;
+    let byte_rate = sample_rate * block_align as u32;
+
+    let mut header = [0u8; WAV_HEADER_SIZE];
+
+    header[0..4].copy_from_slice(b"RIFF");
+    header[4..8].copy_from_slice(&(36 + data_length).to_le_bytes());
+    header[8..12].copy_from_slice(b"WAVE");
+    header[12..16].copy_from_slice(b"fmt ");
+    header[16..20].copy_from_slice(&16u32.to_le_bytes());
+    header[20..22].copy_from_slice(&1u16.to_le_bytes());
+    header[22..24].copy_from_slice(&channels.to_le_bytes());
+    header[24..28].copy_from_slice(&sample_rate.to_le_bytes());
+    header[28..32].copy_from_slice(&byte_rate.to_le_bytes());
+    header[32..34].copy_from_slice(&block_align.to_le_bytes());
+    header[34..36].copy_from_slice(&bits_per_sample.to_le_bytes());
+    header[36..40].copy_from_slice(b"data");
+    header[40..44].copy_from_slice(&data_length.to_le_bytes());
+
+    header
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct WavFormat {
+    pub channels: u16,
+    pub sample_rate: u32,
+    pub bits_per_sample: u16,
+    pub data_length: u32,
+}
+
+// Reads the header up to the start of the samples, skipping any chunk (LIST, fact...) that isn't "fmt " or "data"
+pub fn read_wav_header<R: Read>(reader: &mut R) -> Result<WavFormat, CoreError> {
+    let mut riff = [0u8; 12];
+    reader.read_exact(&mut riff)?;
+
+    if &riff[0..4] != b"RIFF" || &riff[8..12] != b"WAVE" {
+        return Err(CoreError::InvalidAudio("not a RIFF/WAVE file".to_string()));
+    }
+
+    let mut format = None;
+
+    loop {
+        let mut chunk = [0u8; 8];
+        reader.read_exact(&mut chunk)?;
+
+        let length = u32::from_le_bytes([chunk[4], chunk[5], chunk[6], chunk[7]]);
+
+        match &chunk[0..4] {
+            b"fmt " => {
+                let mut body = vec![0u8; length as usize + (length as usize % 2)];
+                reader.read_exact(&mut body)?;
+
+                if body.len() < 16 {
+                    return Err(CoreError::InvalidAudio("fmt chunk is too short".to_string()));
+                }
+
+                let audio_format = u16::from_le_bytes([body[0], body[1]]);
+
+                // 0xFFFE is WAVE_FORMAT_EXTENSIBLE, which is still plain PCM for the files we care about
+                if audio_format != 1 && audio_format != 0xFFFE {
+                    return Err(CoreError::InvalidAudio(format!("unsupported wav encoding {}", audio_format)));
+                }
+
+                format = Some((
+                    u16::from_le_bytes([body[2], body[3]]),
+                    u32::from_le_bytes([body[4], body[5], body[6], body[7]]),
+                    u16::from_le_bytes([body[14], body[15]]),
+                ));
+            }
+            b"data" => {
+                let Some((channels, sample_rate, bits_per_sample)) = format else {
+                    return Err(CoreError::InvalidAudio("data chunk before fmt chunk".to_string()));
+                };
+
+                return Ok(WavFormat {
+                    channels,
+                    sample_rate,
+                    bits_per_sample,
+                    data_length: length,
+                });
+            }
+            _ => {
+                let padded = length as u64 + (length as u64 % 2);
+                std::io::copy(&mut reader.take(padded), &mut std::io::sink())?;
+            }
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy)]
+pub struct AudioLevel {
+    pub rms: f32,
+    pub peak: f32,
+}
+
+impl AudioLevel {
+    // Levels are in dBFS, where 0.0 is full scale and -96.0 is silence for 16-bit samples
+    pub fn measure(pcm: &[u8]) -> Self {
+        let mut sum = 0f64;
+        let mut peak = 0i32;
+        let mut count = 0usize;
+
+        for sample in pcm.chunks_exact(2) {
+            let sample = i16::from_le_bytes([sample[0], sample[1]]) as i32;
+
+            sum += (sample * sample) as f64;
+            peak = peak.max(sample.abs());
+            count += 1;
+        }
+
+        if count == 0 {
+            return Self { rms: -96.0, peak: -96.0 };
+        }
+
+        let rms = (sum / count as f64).sqrt() as f32;
+
+        Self {
+            rms: Self::to_dbfs(rms),
+            peak: Self::to_dbfs(peak as f32),
+        }
+    }
+
+    fn to_dbfs(value: f32) -> f32 {
+        if value < 1.0 {
+            return -96.0;
+        }
+
+        (20.0 * (value / i16::MAX as f32).log10()).max(-96.0)
+    }
+}
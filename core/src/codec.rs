@@ -0,0 +1,1183 @@
+use std::io::{ErrorKind, Read, Write};
+
+use serde::{Deserialize, Serialize};
+
+use crate::audio::wav_header;
+use crate::convert::{PCM_BITS_PER_SAMPLE, PCM_CHANNELS};
+
+// Bytes per ADPCM block: a 4 byte header with the first sample, then two 4-bit samples per byte
+pub const ADPCM_BLOCK_ALIGN: usize = 256;
+pub const ADPCM_SAMPLES_PER_BLOCK: usize = (ADPCM_BLOCK_ALIGN - 4) * 2 + 1;
+pub const ADPCM_WAV_HEADER_SIZE: usize = 60;
+
+pub const FLAC_BLOCK_SIZE: usize = 4096;
+
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum RecordingCodec {
+    // 16-bit PCM without a header, 1.9 MB per minute
+    Pcm,
+    // IMA ADPCM blocks without a header, 4:1 and almost free to encode
+    ImaAdpcm,
+    // A complete FLAC stream, lossless and about half to two thirds the size of PCM for speech
+    Flac,
+}
+
+impl RecordingCodec {
+    pub const ALL: [RecordingCodec; 3] = [RecordingCodec::Pcm, RecordingCodec::ImaAdpcm, RecordingCodec::Flac];
+
+    pub fn from_name(name: &str) -> Option<Self> {
+        match name.to_lowercase().as_str() {
+            "pcm" | "raw" => Some(RecordingCodec::Pcm),
+            "adpcm" | "ima_adpcm" | "ima-adpcm" => Some(RecordingCodec::ImaAdpcm),
+            "flac" => Some(RecordingCodec::Flac),
+            _ => None,
+        }
+    }
+
+    // Of the file on the card
+    pub fn extension(&self) -> &'static str {
+        match self {
+            RecordingCodec::Pcm => "raw",
+            RecordingCodec::ImaAdpcm => "adpcm",
+            RecordingCodec::Flac => "flac",
+        }
+    }
+
+    // Of the file handed out once `header` is put in front of it, for downloads, attachments and uploads
+    pub fn file_extension(&self) -> &'static str {
+        match self {
+            RecordingCodec::Pcm | RecordingCodec::ImaAdpcm => "wav",
+            RecordingCodec::Flac => "flac",
+        }
+    }
+
+    pub fn content_type(&self) -> &'static str {
+        match self {
+            RecordingCodec::Pcm | RecordingCodec::ImaAdpcm => "audio/wav",
+            RecordingCodec::Flac => "audio/flac",
+        }
+    }
+
+    // What turns `stored_length` bytes on the card into a file players understand, a FLAC stream already is one
+    pub fn header(&self, stored_length: u64, sample_rate: u32) -> Vec<u8> {
+        match self {
+            RecordingCodec::Pcm => {
+                wav_header(stored_length as u32, sample_rate, PCM_CHANNELS, PCM_BITS_PER_SAMPLE).to_vec()
+            }
+            RecordingCodec::ImaAdpcm => adpcm_wav_header(stored_length as u32, sample_rate).to_vec(),
+            RecordingCodec::Flac => vec![],
+        }
+    }
+
+    // Samples the encoder works on at a time, a recording cut after a multiple of them ends without padding
+    pub fn block_samples(&self) -> usize {
+        match self {
+            RecordingCodec::Pcm => 1,
+            RecordingCodec::ImaAdpcm => ADPCM_SAMPLES_PER_BLOCK,
+            RecordingCodec::Flac => FLAC_BLOCK_SIZE,
+        }
+    }
+
+    // Bytes of PCM `stored_length` bytes decode to, only known up front for PCM and ADPCM
+    pub fn pcm_length(&self, stored_length: u64) -> Option<u64> {
+        match self {
+            RecordingCodec::Pcm => Some(stored_length),
+            RecordingCodec::ImaAdpcm => Some(adpcm_samples(stored_length as usize) as u64 * 2),
+            RecordingCodec::Flac => None,
+        }
+    }
+
+    // Takes mono 16-bit little endian PCM, whatever is still buffered is written out when the encoder is dropped
+    pub fn encoder<W: Write + Send + 'static>(&self, writer: W, sample_rate: u32) -> Box<dyn Write + Send> {
+        match self {
+            RecordingCodec::Pcm => Box::new(writer),
+            RecordingCodec::ImaAdpcm => Box::new(AdpcmEncoder::new(writer)),
+            RecordingCodec::Flac => Box::new(FlacEncoder::new(writer, sample_rate)),
+        }
+    }
+
+    // Back to 16-bit little endian PCM
+    pub fn decoder<R: Read + Send + 'static>(&self, reader: R) -> Box<dyn Read + Send> {
+        match self {
+            RecordingCodec::Pcm => Box::new(reader),
+            RecordingCodec::ImaAdpcm => Box::new(AdpcmDecoder::new(reader)),
+            RecordingCodec::Flac => Box::new(FlacDecoder::new(reader)),
+        }
+    }
+}
+
+// WAVE_FORMAT_IMA_ADPCM header for `data_length` bytes of mono blocks, with the fact chunk non-PCM formats need
+pub fn adpcm_wav_header(data_length: u32, sample_rate: u32) -> [u8; ADPCM_WAV_HEADER_SIZE] {
+    let block_align = ADPCM_BLOCK_ALIGN as u32;
+    let byte_rate = sample_rate * block_align / ADPCM_SAMPLES_PER_BLOCK as u32;
+
+    let mut header = [0u8; ADPCM_WAV_HEADER_SIZE];
+
+    header[0..4].copy_from_slice(b"RIFF");
+    header[4..8].copy_from_slice(&(ADPCM_WAV_HEADER_SIZE as u32 - 8 + data_length).to_le_bytes());
+    header[8..12].copy_from_slice(b"WAVE");
+    header[12..16].copy_from_slice(b"fmt ");
+    header[16..20].copy_from_slice(&20u32.to_le_bytes());
+    header[20..22].copy_from_slice(&0x0011u16.to_le_bytes());
+    header[22..24].copy_from_slice(&1u16.to_le_bytes());
+    header[24..28].copy_from_slice(&sample_rate.to_le_bytes());
+    header[28..32].copy_from_slice(&byte_rate.to_le_bytes());
+    header[32..34].copy_from_slice(&(block_align as u16).to_le_bytes());
+    header[34..36].copy_from_slice(&4u16.to_le_bytes());
+    header[36..38].copy_from_slice(&2u16.to_le_bytes());
+    header[38..40].copy_from_slice(&(ADPCM_SAMPLES_PER_BLOCK as u16).to_le_bytes());
+    header[40..44].copy_from_slice(b"fact");
+    header[44..48].copy_from_slice(&4u32.to_le_bytes());
+    header[48..52].copy_from_slice(&(adpcm_samples(data_length as usize) as u32).to_le_bytes());
+    header[52..56].copy_from_slice(b"data");
+    header[56..60].copy_from_slice(&data_length.to_le_bytes());
+
+    header
+}
+
+// Samples in `length` bytes of blocks. The last block may be shorter, and ends in a padding sample when it had an
+// even number of samples
+pub fn adpcm_samples(length: usize) -> usize {
+    let last = length % ADPCM_BLOCK_ALIGN;
+
+    length / ADPCM_BLOCK_ALIGN * ADPCM_SAMPLES_PER_BLOCK
+        + match last {
+            0 => 0,
+            1..=4 => 1,
+            _ => (last - 4) * 2 + 1,
+        }
+}
+
+const ADPCM_STEPS: [i32; 89] = [
+    7, 8, 9, 10, 11, 12, 13, 14, 16, 17, 19, 21, 23, 25, 28, 31, 34, 37, 41, 45, 50, 55, 60, 66, 73, 80, 88, 97, 107,
+    118, 130, 143, 157, 173, 190, 209, 230, 253, 279, 307, 337, 371, 408, 449, 494, 544, 598, 658, 724, 796, 876, 963,
+    1060, 1166, 1282, 1411, 1552, 1707, 1878, 2066, 2272, 2499, 2749, 3024, 3327, 3660, 4026, 4428, 4871, 5358, 5894,
+    6484, 7132, 7845, 8630, 9493, 10442, 11487, 12635, 13899, 15289, 16818, 18500, 20350, 22385, 24623, 27086, 29794,
+    32767,
+];
+
+const ADPCM_INDEX_CHANGES: [i32; 8] = [-1, -1, -1, -1, 2, 4, 6, 8];
+
+// Predictor and step index, shared by the encoder and decoder so both stay in step
+#[derive(Debug, Clone, Copy, Default)]
+struct AdpcmState {
+    predictor: i32,
+    index: i32,
+}
+
+impl AdpcmState {
+    fn decode(&mut self, nibble: u8) -> i16 {
+        let step = ADPCM_STEPS[self.index as usize];
+        let mut difference = step >> 3;
+
+        if nibble & 4 != 0 {
+            difference += step;
+        }
+
+        if nibble & 2 != 0 {
+            difference += step >> 1;
+        }
+
+        if nibble & 1 != 0 {
+            difference += step >> 2;
+        }
+
+        self.predictor = match nibble & 8 != 0 {
+            true => self.predictor - difference,
+            false => self.predictor + difference,
+        }
+        .clamp(i16::MIN as i32, i16::MAX as i32);
+
+        self.index = (self.index + ADPCM_INDEX_CHANGES[(nibble & 7) as usize]).clamp(0, 88);
+
+        self.predictor as i16
+    }
+
+    fn encode(&mut self, sample: i16) -> u8 {
+        let mut step = ADPCM_STEPS[self.index as usize];
+        let mut difference = sample as i32 - self.predictor;
+        let mut nibble = 0u8;
+
+        if difference < 0 {
+            nibble = 8;
+            difference = -difference;
+        }
+
+        for bit in [4u8, 2, 1] {
+            if difference >= step {
+                nibble |= bit;
+                difference -= step;
+            }
+
+            step >>= 1;
+        }
+
+        self.decode(nibble);
+
+        nibble
+    }
+}
+
+pub struct AdpcmEncoder<W: Write> {
+    writer: W,
+    state: AdpcmState,
+    samples: Vec<i16>,
+    odd_byte: Option<u8>,
+    block: Vec<u8>,
+}
+
+impl<W: Write> AdpcmEncoder<W> {
+    pub fn new(writer: W) -> Self {
+        Self {
+            writer,
+            state: AdpcmState::default(),
+            samples: Vec::with_capacity(ADPCM_SAMPLES_PER_BLOCK),
+            odd_byte: None,
+            block: Vec::with_capacity(ADPCM_BLOCK_ALIGN),
+        }
+    }
+
+    // Writes the last, shorter block. Also happens on drop, where an error can only be ignored
+    pub fn finish(&mut self) -> std::io::Result<()> {
+        if self.samples.is_empty() == false {
+            self.write_block()?;
+        }
+
+        self.writer.flush()
+    }
+
+    fn write_block(&mut self) -> std::io::Result<()> {
+        self.state.predictor = self.samples[0] as i32;
+
+        self.block.clear();
+        self.block.extend_from_slice(&self.samples[0].to_le_bytes());
+        self.block.push(self.state.index as u8);
+        self.block.push(0);
+
+        for pair in self.samples[1..].chunks(2) {
+            let low = self.state.encode(pair[0]);
+            let high = pair.get(1).map(|sample| self.state.encode(*sample)).unwrap_or(0);
+
+            self.block.push(low | (high << 4));
+        }
+
+        self.samples.clear();
+        self.writer.write_all(&self.block)
+    }
+
+    fn push(&mut self, sample: i16) -> std::io::Result<()> {
+        self.samples.push(sample);
+
+        match self.samples.len() == ADPCM_SAMPLES_PER_BLOCK {
+            true => self.write_block(),
+            false => Ok(()),
+        }
+    }
+}
+
+impl<W: Write> Write for AdpcmEncoder<W> {
+    fn write(&mut self, buf: &[u8]) -> std::io::Result<usize> {
+        let mut bytes = buf;
+
+        if let Some(first) = self.odd_byte.take() {
+            let Some((second, rest)) = bytes.split_first() else {
+                self.odd_byte = Some(first);
+                return Ok(0);
+            };
+
+            self.push(i16::from_le_bytes([first, *second]))?;
+            bytes = rest;
+        }
+
+        for sample in bytes.chunks_exact(2) {
+            self.push(i16::from_le_bytes([sample[0], sample[1]]))?;
+        }
+
+        if bytes.len() % 2 == 1 {
+            self.odd_byte = bytes.last().copied();
+        }
+
+        Ok(buf.len())
+    }
+
+    // Only passes the flush on, a block written early would throw the block layout off
+    fn flush(&mut self) -> std::io::Result<()> {
+        self.writer.flush()
+    }
+}
+
+impl<W: Write> Drop for AdpcmEncoder<W> {
+    fn drop(&mut self) {
+        self.finish().ok();
+    }
+}
+
+pub struct AdpcmDecoder<R: Read> {
+    reader: R,
+    block: Vec<u8>,
+    output: Vec<u8>,
+    position: usize,
+}
+
+impl<R: Read> AdpcmDecoder<R> {
+    pub fn new(reader: R) -> Self {
+        Self {
+            reader,
+            block: vec![0; ADPCM_BLOCK_ALIGN],
+            output: Vec::with_capacity(ADPCM_SAMPLES_PER_BLOCK * 2),
+            position: 0,
+        }
+    }
+
+    // False once there are no more blocks
+    fn decode_block(&mut self) -> std::io::Result<bool> {
+        let length = read_full(&mut self.reader, &mut self.block)?;
+
+        self.output.clear();
+        self.position = 0;
+
+        if length < 4 {
+            return Ok(false);
+        }
+
+        let first = i16::from_le_bytes([self.block[0], self.block[1]]);
+        let mut state = AdpcmState {
+            predictor: first as i32,
+            index: (self.block[2] as i32).min(88),
+        };
+
+        self.output.extend_from_slice(&first.to_le_bytes());
+
+        for byte in &self.block[4..length] {
+            self.output.extend_from_slice(&state.decode(byte & 0x0f).to_le_bytes());
+            self.output.extend_from_slice(&state.decode(byte >> 4).to_le_bytes());
+        }
+
+        Ok(true)
+    }
+}
+
+impl<R: Read> Read for AdpcmDecoder<R> {
+    fn read(&mut self, buf: &mut [u8]) -> std::io::Result<usize> {
+        if self.position == self.output.len() && self.decode_block()? == false {
+            return Ok(0);
+        }
+
+        let length = buf.len().min(self.output.len() - self.position);
+        buf[..length].copy_from_slice(&self.output[self.position..self.position + length]);
+        self.position += length;
+
+        Ok(length)
+    }
+}
+
+// Fills as much of `buffer` as the reader has left
+fn read_full<R: Read>(reader: &mut R, buffer: &mut [u8]) -> std::io::Result<usize> {
+    let mut filled = 0;
+
+    while filled < buffer.len() {
+        match reader.read(&mut buffer[filled..]) {
+            Ok(0) => break,
+            Ok(length) => filled += length,
+            Err(error) if error.kind() == ErrorKind::Interrupted => continue,
+            Err(error) => return Err(error),
+        }
+    }
+
+    Ok(filled)
+}
+
+struct BitWriter {
+    bytes: Vec<u8>,
+    accumulator: u64,
+    bits: u32,
+}
+
+impl BitWriter {
+    fn new() -> Self {
+        Self {
+            bytes: vec![],
+            accumulator: 0,
+            bits: 0,
+        }
+    }
+
+    // Up to 32 bits at a time
+    fn write(&mut self, value: u64, bits: u32) {
+        if bits == 0 {
+            return;
+        }
+
+        self.accumulator = (self.accumulator << bits) | (value & ((1u64 << bits) - 1));
+        self.bits += bits;
+
+        while self.bits >= 8 {
+            self.bits -= 8;
+            self.bytes.push((self.accumulator >> self.bits) as u8);
+        }
+
+        self.accumulator &= (1u64 << self.bits) - 1;
+    }
+
+    fn write_unary(&mut self, zeros: u64) {
+        let mut zeros = zeros;
+
+        while zeros >= 32 {
+            self.write(0, 32);
+            zeros -= 32;
+        }
+
+        self.write(1, zeros as u32 + 1);
+    }
+
+    fn align(&mut self) {
+        if self.bits > 0 {
+            self.write(0, 8 - self.bits);
+        }
+    }
+}
+
+// The FLAC frame number and sample count encoding, UTF-8 extended to 36 bits
+fn write_utf8(writer: &mut BitWriter, value: u64) {
+    if value < 0x80 {
+        return writer.write(value, 8);
+    }
+
+    let continuation_bytes = (1..6).find(|bytes| value < 1u64 << (6 + 5 * bytes)).unwrap_or(6);
+    let marker = (0xff00u64 >> (continuation_bytes + 1)) & 0xff;
+
+    writer.write(marker | (value >> (6 * continuation_bytes)), 8);
+
+    for byte in (0..continuation_bytes).rev() {
+        writer.write(0x80 | ((value >> (6 * byte)) & 0x3f), 8);
+    }
+}
+
+fn crc8(bytes: &[u8]) -> u8 {
+    bytes.iter().fold(0u8, |crc, byte| {
+        (0..8).fold(crc ^ byte, |crc, _| match crc & 0x80 != 0 {
+            true => (crc << 1) ^ 0x07,
+            false => crc << 1,
+        })
+    })
+}
+
+fn crc16(bytes: &[u8]) -> u16 {
+    bytes.iter().fold(0u16, |crc, byte| {
+        (0..8).fold(crc ^ ((*byte as u16) << 8), |crc, _| match crc & 0x8000 != 0 {
+            true => (crc << 1) ^ 0x8005,
+            false => crc << 1,
+        })
+    })
+}
+
+fn zigzag(residual: i64) -> u64 {
+    ((residual << 1) ^ (residual >> 63)) as u64
+}
+
+// Fixed polynomial predictors of order 0 to 4, residuals start at `order`
+fn fixed_residual(samples: &[i64], order: usize) -> Vec<i64> {
+    (order..samples.len())
+        .map(|index| {
+            let sample = |back: usize| samples[index - back];
+
+            match order {
+                0 => sample(0),
+                1 => sample(0) - sample(1),
+                2 => sample(0) - 2 * sample(1) + sample(2),
+                3 => sample(0) - 3 * sample(1) + 3 * sample(2) - sample(3),
+                _ => sample(0) - 4 * sample(1) + 6 * sample(2) - 4 * sample(3) + sample(4),
+            }
+        })
+        .collect()
+}
+
+#[derive(Debug, Clone)]
+struct RicePartitions {
+    order: u32,
+    parameters: Vec<u32>,
+    bits: u64,
+}
+
+impl RicePartitions {
+    // 15 is the escape code
+    const MAX_PARAMETER: u32 = 14;
+    const MAX_ORDER: u32 = 6;
+
+    // Tries every partition order the block size allows, with the best parameter for each partition
+    fn best(residual: &[i64], predictor_order: usize, block_size: usize) -> Self {
+        let folded = residual.iter().map(|residual| zigzag(*residual)).collect::<Vec<_>>();
+
+        (0..=Self::MAX_ORDER)
+            .take_while(|order| block_size % (1 << order) == 0 && block_size >> order > predictor_order)
+            .map(|order| {
+                let partition_size = block_size >> order;
+                let mut start = 0;
+                let mut parameters = vec![];
+                let mut bits = 6u64;
+
+                for partition in 0..1usize << order {
+                    let length = match partition {
+                        0 => partition_size - predictor_order,
+                        _ => partition_size,
+                    };
+
+                    let values = &folded[start..start + length];
+                    let (parameter, partition_bits) = Self::parameter(values);
+
+                    parameters.push(parameter);
+                    bits += 4 + partition_bits;
+                    start += length;
+                }
+
+                Self { order, parameters, bits }
+            })
+            .min_by_key(|partitions| partitions.bits)
+            .unwrap_or(Self {
+                order: 0,
+                parameters: vec![0],
+                bits: u64::MAX,
+            })
+    }
+
+    fn parameter(values: &[u64]) -> (u32, u64) {
+        let sum = values.iter().sum::<u64>();
+        let mean = sum / values.len().max(1) as u64;
+        let estimate = (64 - mean.leading_zeros()).min(Self::MAX_PARAMETER);
+
+        (estimate.saturating_sub(1)..=(estimate + 1).min(Self::MAX_PARAMETER))
+            .map(|parameter| {
+                let bits = values.len() as u64 * (parameter as u64 + 1)
+                    + values.iter().map(|value| value >> parameter).sum::<u64>();
+
+                (parameter, bits)
+            })
+            .min_by_key(|(_, bits)| *bits)
+            .unwrap_or((0, 0))
+    }
+
+    fn write(&self, writer: &mut BitWriter, residual: &[i64], predictor_order: usize, block_size: usize) {
+        writer.write(0, 2);
+        writer.write(self.order as u64, 4);
+
+        let partition_size = block_size >> self.order;
+        let mut start = 0;
+
+        for (partition, parameter) in self.parameters.iter().enumerate() {
+            let length = match partition {
+                0 => partition_size - predictor_order,
+                _ => partition_size,
+            };
+
+            writer.write(*parameter as u64, 4);
+
+            for value in &residual[start..start + length] {
+                let folded = zigzag(*value);
+
+                writer.write_unary(folded >> parameter);
+                writer.write(folded, *parameter);
+            }
+
+            start += length;
+        }
+    }
+}
+
+// Streams mono 16-bit FLAC with fixed predictors, which gets speech close to what the reference encoder does at its
+// fast settings for a fraction of the work. The stream doesn't know its length, so nothing has to be patched later
+pub struct FlacEncoder<W: Write> {
+    writer: W,
+    sample_rate: u32,
+    started: bool,
+    frame_number: u64,
+    samples: Vec<i64>,
+    odd_byte: Option<u8>,
+}
+
+impl<W: Write> FlacEncoder<W> {
+    pub fn new(writer: W, sample_rate: u32) -> Self {
+        Self {
+            writer,
+            sample_rate,
+            started: false,
+            frame_number: 0,
+            samples: Vec::with_capacity(FLAC_BLOCK_SIZE),
+            odd_byte: None,
+        }
+    }
+
+    // Writes the last, shorter frame. Also happens on drop, where an error can only be ignored
+    pub fn finish(&mut self) -> std::io::Result<()> {
+        self.start()?;
+
+        if self.samples.is_empty() == false {
+            self.write_frame()?;
+        }
+
+        self.writer.flush()
+    }
+
+    fn start(&mut self) -> std::io::Result<()> {
+        if self.started {
+            return Ok(());
+        }
+
+        let mut header = BitWriter::new();
+
+        header.bytes.extend_from_slice(b"fLaC");
+
+        // Last metadata block, STREAMINFO, 34 bytes
+        header.write(1, 1);
+        header.write(0, 7);
+        header.write(34, 24);
+
+        header.write(FLAC_BLOCK_SIZE as u64, 16);
+        header.write(FLAC_BLOCK_SIZE as u64, 16);
+        // Frame sizes, total samples and the MD5 are unknown, which the format allows
+        header.write(0, 24);
+        header.write(0, 24);
+        header.write(self.sample_rate as u64, 20);
+        header.write(0, 3);
+        header.write(15, 5);
+        header.write(0, 4);
+        header.write(0, 32);
+        header.bytes.extend_from_slice(&[0; 16]);
+
+        self.started = true;
+        self.writer.write_all(&header.bytes)
+    }
+
+    fn sample_rate_code(&self) -> u64 {
+        match self.sample_rate {
+            8000 => 4,
+            16000 => 5,
+            22050 => 6,
+            24000 => 7,
+            32000 => 8,
+            44100 => 9,
+            48000 => 10,
+            96000 => 11,
+            // Taken from STREAMINFO
+            _ => 0,
+        }
+    }
+
+    fn write_frame(&mut self) -> std::io::Result<()> {
+        self.start()?;
+
+        let block_size = self.samples.len();
+        let mut frame = BitWriter::new();
+
+        frame.write(0xfff8, 16);
+        frame.write(if block_size == FLAC_BLOCK_SIZE { 12 } else { 7 }, 4);
+        frame.write(self.sample_rate_code(), 4);
+        // Mono, 16 bits per sample
+        frame.write(0, 4);
+        frame.write(4, 3);
+        frame.write(0, 1);
+        write_utf8(&mut frame, self.frame_number);
+
+        if block_size != FLAC_BLOCK_SIZE {
+            frame.write(block_size as u64 - 1, 16);
+        }
+
+        let crc = crc8(&frame.bytes);
+        frame.write(crc as u64, 8);
+
+        self.write_subframe(&mut frame);
+
+        frame.align();
+
+        let crc = crc16(&frame.bytes);
+        frame.write(crc as u64, 16);
+
+        self.frame_number += 1;
+        self.samples.clear();
+        self.writer.write_all(&frame.bytes)
+    }
+
+    fn write_subframe(&self, frame: &mut BitWriter) {
+        let samples = &self.samples;
+        let block_size = samples.len();
+
+        if samples.iter().all(|sample| *sample == samples[0]) {
+            frame.write(0, 8);
+            frame.write(samples[0] as u64, 16);
+            return;
+        }
+
+        let verbatim_bits = block_size as u64 * 16;
+
+        let best = (0..=4usize)
+            .filter(|order| *order < block_size)
+            .map(|order| {
+                let residual = fixed_residual(samples, order);
+                let partitions = RicePartitions::best(&residual, order, block_size);
+                let bits = order as u64 * 16 + partitions.bits;
+
+                (order, residual, partitions, bits)
+            })
+            .min_by_key(|(_, _, _, bits)| *bits);
+
+        match best {
+            Some((order, residual, partitions, bits)) if bits < verbatim_bits => {
+                frame.write(0x08 | order as u64, 7);
+                frame.write(0, 1);
+
+                for sample in &samples[..order] {
+                    frame.write(*sample as u64, 16);
+                }
+
+                partitions.write(frame, &residual, order, block_size);
+            }
+            _ => {
+                frame.write(1 << 1, 8);
+
+                for sample in samples {
+                    frame.write(*sample as u64, 16);
+                }
+            }
+        }
+    }
+
+    fn push(&mut self, sample: i16) -> std::io::Result<()> {
+        self.samples.push(sample as i64);
+
+        match self.samples.len() == FLAC_BLOCK_SIZE {
+            true => self.write_frame(),
+            false => Ok(()),
+        }
+    }
+}
+
+impl<W: Write> Write for FlacEncoder<W> {
+    fn write(&mut self, buf: &[u8]) -> std::io::Result<usize> {
+        let mut bytes = buf;
+
+        if let Some(first) = self.odd_byte.take() {
+            let Some((second, rest)) = bytes.split_first() else {
+                self.odd_byte = Some(first);
+                return Ok(0);
+            };
+
+            self.push(i16::from_le_bytes([first, *second]))?;
+            bytes = rest;
+        }
+
+        for sample in bytes.chunks_exact(2) {
+            self.push(i16::from_le_bytes([sample[0], sample[1]]))?;
+        }
+
+        if bytes.len() % 2 == 1 {
+            self.odd_byte = bytes.last().copied();
+        }
+
+        Ok(buf.len())
+    }
+
+    // Only passes the flush on, only the last frame may be shorter than the block size
+    fn flush(&mut self) -> std::io::Result<()> {
+        self.writer.flush()
+    }
+}
+
+impl<W: Write> Drop for FlacEncoder<W> {
+    fn drop(&mut self) {
+        self.finish().ok();
+    }
+}
+
+struct BitReader<R: Read> {
+    reader: R,
+    buffer: Vec<u8>,
+    position: usize,
+    length: usize,
+    byte: u8,
+    bits: u32,
+}
+
+impl<R: Read> BitReader<R> {
+    fn new(reader: R) -> Self {
+        Self {
+            reader,
+            buffer: vec![0; 4096],
+            position: 0,
+            length: 0,
+            byte: 0,
+            bits: 0,
+        }
+    }
+
+    fn read_byte(&mut self) -> std::io::Result<u8> {
+        if self.position == self.length {
+            self.length = read_full(&mut self.reader, &mut self.buffer)?;
+            self.position = 0;
+
+            if self.length == 0 {
+                return Err(ErrorKind::UnexpectedEof.into());
+            }
+        }
+
+        self.position += 1;
+
+        Ok(self.buffer[self.position - 1])
+    }
+
+    fn skip(&mut self, bits: u32) -> std::io::Result<()> {
+        for _ in 0..bits {
+            self.read(1)?;
+        }
+
+        Ok(())
+    }
+
+    // Up to 64 bits at a time
+    fn read(&mut self, bits: u32) -> std::io::Result<u64> {
+        let mut value = 0u64;
+
+        for _ in 0..bits {
+            if self.bits == 0 {
+                self.byte = self.read_byte()?;
+                self.bits = 8;
+            }
+
+            self.bits -= 1;
+            value = (value << 1) | ((self.byte >> self.bits) & 1) as u64;
+        }
+
+        Ok(value)
+    }
+
+    fn read_signed(&mut self, bits: u32) -> std::io::Result<i64> {
+        if bits == 0 {
+            return Ok(0);
+        }
+
+        let value = self.read(bits)?;
+
+        Ok(((value << (64 - bits)) as i64) >> (64 - bits))
+    }
+
+    fn read_unary(&mut self) -> std::io::Result<u64> {
+        let mut zeros = 0;
+
+        while self.read(1)? == 0 {
+            zeros += 1;
+        }
+
+        Ok(zeros)
+    }
+
+    fn align(&mut self) {
+        self.bits = 0;
+    }
+}
+
+fn invalid(message: &str) -> std::io::Error {
+    std::io::Error::new(ErrorKind::InvalidData, message.to_string())
+}
+
+// Decodes 16-bit FLAC into interleaved PCM, including what other encoders produce (LPC, stereo decorrelation). A
+// frame cut off at the end, like a recording interrupted by a power loss, ends the stream instead of failing it
+pub struct FlacDecoder<R: Read> {
+    reader: BitReader<R>,
+    started: bool,
+    finished: bool,
+    bits_per_sample: u32,
+    output: Vec<u8>,
+    position: usize,
+}
+
+impl<R: Read> FlacDecoder<R> {
+    pub fn new(reader: R) -> Self {
+        Self {
+            reader: BitReader::new(reader),
+            started: false,
+            finished: false,
+            bits_per_sample: 16,
+            output: vec![],
+            position: 0,
+        }
+    }
+
+    fn read_metadata(&mut self) -> std::io::Result<()> {
+        let mut magic = [0u8; 4];
+
+        for byte in magic.iter_mut() {
+            *byte = self.reader.read_byte()?;
+        }
+
+        if &magic != b"fLaC" {
+            return Err(invalid("not a FLAC stream"));
+        }
+
+        loop {
+            let last = self.reader.read(1)? == 1;
+            let block_type = self.reader.read(7)?;
+            let length = self.reader.read(24)?;
+
+            if block_type == 0 {
+                self.reader.skip(16 + 16 + 24 + 24 + 20 + 3)?;
+                self.bits_per_sample = self.reader.read(5)? as u32 + 1;
+                self.reader.skip(36)?;
+
+                for _ in 0..16 {
+                    self.reader.read_byte()?;
+                }
+            } else {
+                for _ in 0..length {
+                    self.reader.read_byte()?;
+                }
+            }
+
+            if last {
+                return Ok(());
+            }
+        }
+    }
+
+    fn decode_frame(&mut self) -> std::io::Result<()> {
+        if self.reader.read(15)? != 0x7ffc {
+            return Err(invalid("lost FLAC frame sync"));
+        }
+
+        self.reader.read(1)?;
+
+        let block_size_code = self.reader.read(4)?;
+        let sample_rate_code = self.reader.read(4)?;
+        let channel_assignment = self.reader.read(4)?;
+        let sample_size_code = self.reader.read(3)?;
+        self.reader.read(1)?;
+
+        // Frame or sample number, not needed to decode
+        let first = self.reader.read(8)?;
+
+        for _ in 0..(first as u8).leading_ones().saturating_sub(1) {
+            self.reader.read(8)?;
+        }
+
+        let block_size = match block_size_code {
+            1 => 192,
+            2..=5 => 576 << (block_size_code - 2),
+            6 => self.reader.read(8)? as usize + 1,
+            7 => self.reader.read(16)? as usize + 1,
+            8..=15 => 256 << (block_size_code - 8),
+            _ => return Err(invalid("reserved FLAC block size")),
+        };
+
+        match sample_rate_code {
+            12 => self.reader.read(8)?,
+            13 | 14 => self.reader.read(16)?,
+            _ => 0,
+        };
+
+        let bits_per_sample = match sample_size_code {
+            0 => self.bits_per_sample,
+            4 => 16,
+            _ => return Err(invalid("only 16-bit FLAC is supported")),
+        };
+
+        if bits_per_sample != 16 {
+            return Err(invalid("only 16-bit FLAC is supported"));
+        }
+
+        // Header CRC
+        self.reader.read(8)?;
+
+        let channels = match channel_assignment {
+            0..=7 => channel_assignment as usize + 1,
+            8..=10 => 2,
+            _ => return Err(invalid("reserved FLAC channel assignment")),
+        };
+
+        let mut decoded = Vec::with_capacity(channels);
+
+        for channel in 0..channels {
+            // The side channel of a stereo pair carries an extra bit
+            let side = matches!((channel_assignment, channel), (8, 1) | (9, 0) | (10, 1));
+
+            decoded.push(self.decode_subframe(block_size, bits_per_sample + side as u32)?);
+        }
+
+        self.reader.align();
+        self.reader.read(16)?;
+
+        if channels == 2 {
+            let (left, right) = decoded.split_at_mut(1);
+            let (left, right) = (&mut left[0], &mut right[0]);
+
+            for index in 0..block_size {
+                let (a, b) = (left[index], right[index]);
+
+                (left[index], right[index]) = match channel_assignment {
+                    8 => (a, a - b),
+                    9 => (a + b, b),
+                    10 => {
+                        let mid = (a << 1) | (b & 1);
+                        ((mid + b) >> 1, (mid - b) >> 1)
+                    }
+                    _ => (a, b),
+                };
+            }
+        }
+
+        self.output.clear();
+        self.position = 0;
+
+        for index in 0..block_size {
+            for channel in decoded.iter() {
+                self.output.extend_from_slice(&(channel[index] as i16).to_le_bytes());
+            }
+        }
+
+        Ok(())
+    }
+
+    fn decode_subframe(&mut self, block_size: usize, bits_per_sample: u32) -> std::io::Result<Vec<i64>> {
+        self.reader.read(1)?;
+
+        let subframe_type = self.reader.read(6)?;
+        let wasted = match self.reader.read(1)? {
+            1 => self.reader.read_unary()? as u32 + 1,
+            _ => 0,
+        };
+
+        let bits = bits_per_sample - wasted;
+
+        let mut samples = match subframe_type {
+            0 => vec![self.reader.read_signed(bits)?; block_size],
+            1 => (0..block_size)
+                .map(|_| self.reader.read_signed(bits))
+                .collect::<Result<Vec<_>, _>>()?,
+            8..=12 => {
+                let order = subframe_type as usize - 8;
+                let mut samples = self.warm_up(order, bits)?;
+
+                self.decode_residual(block_size, order, &mut samples)?;
+
+                for index in order..block_size {
+                    let sample = |back: usize| samples[index - back];
+
+                    samples[index] += match order {
+                        0 => 0,
+                        1 => sample(1),
+                        2 => 2 * sample(1) - sample(2),
+                        3 => 3 * sample(1) - 3 * sample(2) + sample(3),
+                        _ => 4 * sample(1) - 6 * sample(2) + 4 * sample(3) - sample(4),
+                    };
+                }
+
+                samples
+            }
+            32..=63 => {
+                let order = subframe_type as usize - 31;
+                let mut samples = self.warm_up(order, bits)?;
+
+                let precision = self.reader.read(4)? as u32 + 1;
+                let shift = self.reader.read_signed(5)?.max(0);
+                let coefficients = (0..order)
+                    .map(|_| self.reader.read_signed(precision))
+                    .collect::<Result<Vec<_>, _>>()?;
+
+                self.decode_residual(block_size, order, &mut samples)?;
+
+                for index in order..block_size {
+                    let prediction = coefficients
+                        .iter()
+                        .enumerate()
+                        .map(|(back, coefficient)| coefficient * samples[index - back - 1])
+                        .sum::<i64>();
+
+                    samples[index] += prediction >> shift;
+                }
+
+                samples
+            }
+            _ => return Err(invalid("reserved FLAC subframe type")),
+        };
+
+        if wasted > 0 {
+            for sample in samples.iter_mut() {
+                *sample <<= wasted;
+            }
+        }
+
+        Ok(samples)
+    }
+
+    fn warm_up(&mut self, order: usize, bits: u32) -> std::io::Result<Vec<i64>> {
+        (0..order).map(|_| self.reader.read_signed(bits)).collect()
+    }
+
+    // Appends the residual to the warm-up samples, the predictor is added on top of it afterwards
+    fn decode_residual(&mut self, block_size: usize, order: usize, samples: &mut Vec<i64>) -> std::io::Result<()> {
+        let parameter_bits = match self.reader.read(2)? {
+            0 => 4,
+            1 => 5,
+            _ => return Err(invalid("reserved FLAC residual coding")),
+        };
+
+        let escape = (1 << parameter_bits) - 1;
+        let partition_order = self.reader.read(4)?;
+        let partition_size = block_size >> partition_order;
+
+        for partition in 0..1usize << partition_order {
+            let length = match partition {
+                0 => partition_size.saturating_sub(order),
+                _ => partition_size,
+            };
+
+            let parameter = self.reader.read(parameter_bits)? as u32;
+
+            if parameter == escape {
+                let bits = self.reader.read(5)? as u32;
+
+                for _ in 0..length {
+                    samples.push(self.reader.read_signed(bits)?);
+                }
+
+                continue;
+            }
+
+            for _ in 0..length {
+                let folded = (self.reader.read_unary()? << parameter) | self.reader.read(parameter)?;
+
+                samples.push((folded >> 1) as i64 ^ -((folded & 1) as i64));
+            }
+        }
+
+        Ok(())
+    }
+}
+
+impl<R: Read> Read for FlacDecoder<R> {
+    fn read(&mut self, buf: &mut [u8]) -> std::io::Result<usize> {
+        while self.position == self.output.len() {
+            if self.finished {
+                return Ok(0);
+            }
+
+            let result = match self.started {
+                true => self.decode_frame(),
+                false => self.read_metadata().map(|_| self.started = true),
+            };
+
+            match result {
+                Ok(_) => {}
+                Err(error) if error.kind() == ErrorKind::UnexpectedEof && self.started => {
+                    self.finished = true;
+                    self.output.clear();
+                    self.position = 0;
+                }
+                Err(error) => return Err(error),
+            }
+        }
+
+        let length = buf.len().min(self.output.len() - self.position);
+        buf[..length].copy_from_slice(&self.output[self.position..self.position + length]);
+        self.position += length;
+
+        Ok(length)
+    }
+}
@@ -0,0 +1,221 @@
+use crate::dsp::saturate;
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum CombineMode {
+    // Average of both microphones
+    Mix,
+    // Whichever microphone is louder, decided per frame
+    Louder,
+    // Delays the microphone closer to the loudest talker so both add up in phase, which favours that direction
+    DelayAndSum,
+}
+
+impl CombineMode {
+    pub fn from_name(name: &str) -> Option<Self> {
+        match name.to_lowercase().as_str() {
+            "mix" => Some(CombineMode::Mix),
+            "louder" => Some(CombineMode::Louder),
+            "beamform" | "delay_and_sum" => Some(CombineMode::DelayAndSum),
+            _ => None,
+        }
+    }
+}
+
+// Turns interleaved stereo into mono, changes (channel or steering delay) are crossfaded over a frame so they don't click
+pub struct Combiner {
+    mode: CombineMode,
+    max_delay: usize,
+    // Positive when the sound reaches the left microphone first
+    delay: isize,
+    louder: usize,
+    history: [Vec<f32>; 2],
+    channels: [Vec<f32>; 2],
+}
+
+impl Combiner {
+    // Switching to the other microphone only pays off once it is clearly louder
+    const LOUDER_HYSTERESIS: f32 = 1.41;
+    // Normalized cross-correlation a frame needs before the steering follows it, below that it's mostly noise
+    const MIN_CORRELATION: f32 = 0.3;
+    const MIN_RMS: f32 = 30.0;
+
+    // Sound travels about 2 cm per sample at 16 kHz, so `max_delay` comes from the distance between the microphones
+    pub fn new(mode: CombineMode, max_delay: usize) -> Self {
+        Self {
+            mode,
+            max_delay,
+            delay: 0,
+            louder: 0,
+            history: [vec![0.0; max_delay], vec![0.0; max_delay]],
+            channels: [vec![], vec![]],
+        }
+    }
+
+    pub fn for_spacing(mode: CombineMode, sample_rate: u32, spacing_mm: u32) -> Self {
+        let delay = (spacing_mm as f32 / 1000.0 / 343.0 * sample_rate as f32).ceil() as usize;
+
+        Self::new(mode, delay.max(1))
+    }
+
+    pub fn mode(&self) -> CombineMode {
+        self.mode
+    }
+
+    pub fn delay(&self) -> isize {
+        self.delay
+    }
+
+    pub fn louder_channel(&self) -> usize {
+        self.louder
+    }
+
+    // Little endian PCM with `channels` interleaved, mono frames are returned as they are
+    pub fn combine_pcm(&mut self, frame: &[u8], channels: u16) -> Vec<u8> {
+        if channels < 2 {
+            return frame.to_vec();
+        }
+
+        let samples = frame
+            .chunks_exact(2)
+            .map(|sample| i16::from_le_bytes([sample[0], sample[1]]))
+            .collect::<Vec<_>>();
+
+        self.combine(&samples, channels as usize)
+            .iter()
+            .flat_map(|sample| sample.to_le_bytes())
+            .collect()
+    }
+
+    // Only the first two of `channels` are combined
+    pub fn combine(&mut self, interleaved: &[i16], channels: usize) -> Vec<i16> {
+        for (index, channel) in self.channels.iter_mut().enumerate() {
+            channel.clear();
+            channel.extend(interleaved.chunks_exact(channels).map(|frame| frame[index] as f32));
+        }
+
+        let output = match self.mode {
+            CombineMode::Mix => (0..self.channels[0].len())
+                .map(|index| saturate((self.channels[0][index] + self.channels[1][index]) / 2.0))
+                .collect(),
+            CombineMode::Louder => self.louder(),
+            CombineMode::DelayAndSum => self.delay_and_sum(),
+        };
+
+        for (history, channel) in self.history.iter_mut().zip(self.channels.iter()) {
+            history.extend_from_slice(channel);
+
+            let excess = history.len().saturating_sub(self.max_delay);
+            history.drain(..excess);
+        }
+
+        output
+    }
+
+    fn louder(&mut self) -> Vec<i16> {
+        let levels = [rms(&self.channels[0]), rms(&self.channels[1])];
+        let other = 1 - self.louder;
+        let previous = self.louder;
+
+        if levels[other] > levels[previous] * Self::LOUDER_HYSTERESIS {
+            self.louder = other;
+        }
+
+        let length = self.channels[0].len();
+
+        (0..length)
+            .map(|index| {
+                let fade = (index + 1) as f32 / length as f32;
+
+                saturate(self.channels[previous][index] * (1.0 - fade) + self.channels[self.louder][index] * fade)
+            })
+            .collect()
+    }
+
+    fn delay_and_sum(&mut self) -> Vec<i16> {
+        let previous = self.delay;
+
+        if let Some(delay) = self.estimate_delay() {
+            self.delay = delay;
+        }
+
+        let length = self.channels[0].len();
+
+        (0..length)
+            .map(|index| {
+                let fade = (index + 1) as f32 / length as f32;
+
+                saturate(self.steered(index, previous) * (1.0 - fade) + self.steered(index, self.delay) * fade)
+            })
+            .collect()
+    }
+
+    // The earlier microphone is delayed by `delay` samples, reaching back into the previous frame where needed
+    fn steered(&self, index: usize, delay: isize) -> f32 {
+        let (early, late) = match delay >= 0 {
+            true => (0, 1),
+            false => (1, 0),
+        };
+
+        (self.sample(early, index as isize - delay.abs()) + self.channels[late][index]) / 2.0
+    }
+
+    fn sample(&self, channel: usize, index: isize) -> f32 {
+        match index >= 0 {
+            true => self.channels[channel][index as usize],
+            false => {
+                let history = &self.history[channel];
+                let position = history.len() as isize + index;
+
+                match position >= 0 {
+                    true => history[position as usize],
+                    false => 0.0,
+                }
+            }
+        }
+    }
+
+    // Lag with the highest normalized cross-correlation, None when the frame is too quiet or too diffuse to tell
+    fn estimate_delay(&self) -> Option<isize> {
+        let left = &self.channels[0];
+        let right = &self.channels[1];
+
+        if rms(left).min(rms(right)) < Self::MIN_RMS {
+            return None;
+        }
+
+        let energy = (left.iter().map(|sample| sample * sample).sum::<f32>()
+            * right.iter().map(|sample| sample * sample).sum::<f32>())
+        .sqrt();
+
+        let max_delay = self.max_delay as isize;
+
+        let (delay, correlation) = (-max_delay..=max_delay)
+            .map(|delay| {
+                let correlation = (0..right.len())
+                    .map(|index| match delay >= 0 {
+                        true => self.sample(0, index as isize - delay) * right[index],
+                        false => left[index] * self.sample(1, index as isize + delay),
+                    })
+                    .sum::<f32>();
+
+                (delay, correlation / energy)
+            })
+            .fold((0, f32::MIN), |best, candidate| match candidate.1 > best.1 {
+                true => candidate,
+                false => best,
+            });
+
+        match correlation >= Self::MIN_CORRELATION {
+            true => Some(delay),
+            false => None,
+        }
+    }
+}
+
+fn rms(samples: &[f32]) -> f32 {
+    if samples.is_empty() {
+        return 0.0;
+    }
+
+    (samples.iter().map(|sample| sample * sample).sum::<f32>() / samples.len() as f32).sqrt()
+}
@@ -0,0 +1,248 @@
+use crate::dsp::{saturate, Biquad};
+
+// What everything after the microphone works with and what AssemblyAI expects: 16 kHz mono 16-bit little endian
+pub const PCM_SAMPLE_RATE: u32 = 16000;
+pub const PCM_CHANNELS: u16 = 1;
+pub const PCM_BITS_PER_SAMPLE: u16 = 16;
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum SampleWidth {
+    Bits16,
+    // Packed into 3 bytes, as ESP-IDF lays out 24-bit data and WAV files store it
+    Bits24,
+    // Also what 24-bit MEMS microphones deliver in a 32-bit slot, the low byte is padding
+    Bits32,
+}
+
+impl SampleWidth {
+    pub fn from_bits(bits: u16) -> Option<Self> {
+        match bits {
+            16 => Some(SampleWidth::Bits16),
+            24 => Some(SampleWidth::Bits24),
+            32 => Some(SampleWidth::Bits32),
+            _ => None,
+        }
+    }
+
+    pub fn bytes(&self) -> usize {
+        match self {
+            SampleWidth::Bits16 => 2,
+            SampleWidth::Bits24 => 3,
+            SampleWidth::Bits32 => 4,
+        }
+    }
+
+    // Scaled to the 16-bit range, keeping the extra resolution as the fraction
+    fn decode(&self, bytes: &[u8]) -> f32 {
+        match self {
+            SampleWidth::Bits16 => i16::from_le_bytes([bytes[0], bytes[1]]) as f32,
+            SampleWidth::Bits24 => i32::from_le_bytes([0, bytes[0], bytes[1], bytes[2]]) as f32 / 65536.0,
+            SampleWidth::Bits32 => i32::from_le_bytes([bytes[0], bytes[1], bytes[2], bytes[3]]) as f32 / 65536.0,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum ChannelSelection {
+    Left,
+    Right,
+    // Average of both channels
+    Mix,
+    // Both channels, still interleaved, for combining them later on
+    Both,
+}
+
+impl ChannelSelection {
+    pub fn from_name(name: &str) -> Option<Self> {
+        match name.to_lowercase().as_str() {
+            "left" => Some(ChannelSelection::Left),
+            "right" => Some(ChannelSelection::Right),
+            "mix" => Some(ChannelSelection::Mix),
+            "both" | "stereo" => Some(ChannelSelection::Both),
+            _ => None,
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct CaptureFormat {
+    pub sample_rate: u32,
+    pub width: SampleWidth,
+    pub channels: u16,
+    // Only matters with more than one channel
+    pub selection: ChannelSelection,
+}
+
+impl CaptureFormat {
+    pub fn pcm() -> Self {
+        Self {
+            sample_rate: PCM_SAMPLE_RATE,
+            width: SampleWidth::Bits16,
+            channels: PCM_CHANNELS,
+            selection: ChannelSelection::Left,
+        }
+    }
+
+    // 2 when both channels are kept, 1 otherwise
+    pub fn output_channels(&self) -> u16 {
+        match (self.channels, self.selection) {
+            (2.., ChannelSelection::Both) => 2,
+            _ => 1,
+        }
+    }
+
+    pub fn frame_bytes(&self) -> usize {
+        self.width.bytes() * self.channels.max(1) as usize
+    }
+
+    // Raw bytes that turn into roughly `pcm_bytes` of converted audio, rounded down to whole frames
+    pub fn raw_bytes_for(&self, pcm_bytes: usize) -> usize {
+        let output_frame = 2 * self.output_channels() as usize;
+        let frames = (pcm_bytes / output_frame) as u64 * self.sample_rate as u64 / PCM_SAMPLE_RATE as u64;
+
+        (frames as usize).max(1) * self.frame_bytes()
+    }
+}
+
+// Linear interpolation, low-passed first when going down so nothing above the new Nyquist folds back into speech
+pub struct Resampler {
+    step: f64,
+    position: f64,
+    previous: f32,
+    filters: Vec<Biquad>,
+}
+
+impl Resampler {
+    pub fn new(from: u32, to: u32) -> Self {
+        // Three cascaded sections, about 24 dB down half an octave above the cutoff
+        let filters = match from > to {
+            true => vec![Biquad::low_pass(from, to as f32 * 0.45); 3],
+            false => vec![],
+        };
+
+        Self {
+            step: from as f64 / to as f64,
+            position: 0.0,
+            previous: 0.0,
+            filters,
+        }
+    }
+
+    pub fn process(&mut self, input: &[f32], output: &mut Vec<f32>) {
+        for sample in input {
+            let sample = self.filters.iter_mut().fold(*sample, |sample, filter| filter.process(sample));
+
+            while self.position < 1.0 {
+                output.push(self.previous + (sample - self.previous) * self.position as f32);
+                self.position += self.step;
+            }
+
+            self.position -= 1.0;
+            self.previous = sample;
+        }
+    }
+}
+
+// Turns whatever the microphone delivers into PCM_SAMPLE_RATE 16-bit, mono unless both channels are selected,
+// keeping partial frames for the next call
+pub struct Converter {
+    format: CaptureFormat,
+    resamplers: Vec<Resampler>,
+    remainder: Vec<u8>,
+    samples: Vec<Vec<f32>>,
+    resampled: Vec<Vec<f32>>,
+    output: Vec<u8>,
+}
+
+impl Converter {
+    pub fn new(format: CaptureFormat) -> Self {
+        let channels = format.output_channels() as usize;
+
+        let resamplers = match format.sample_rate {
+            PCM_SAMPLE_RATE => vec![],
+            sample_rate => (0..channels).map(|_| Resampler::new(sample_rate, PCM_SAMPLE_RATE)).collect(),
+        };
+
+        Self {
+            format,
+            resamplers,
+            remainder: vec![],
+            samples: vec![vec![]; channels],
+            resampled: vec![vec![]; channels],
+            output: vec![],
+        }
+    }
+
+    pub fn format(&self) -> &CaptureFormat {
+        &self.format
+    }
+
+    pub fn is_passthrough(&self) -> bool {
+        let pcm = CaptureFormat::pcm();
+
+        self.format.sample_rate == pcm.sample_rate && self.format.width == pcm.width && self.format.channels <= 1
+    }
+
+    pub fn convert(&mut self, raw: &[u8]) -> &[u8] {
+        if self.is_passthrough() {
+            self.output.clear();
+            self.output.extend_from_slice(raw);
+            return &self.output;
+        }
+
+        let frame_bytes = self.format.frame_bytes();
+        let width = self.format.width;
+        let bytes = width.bytes();
+        let channels = self.format.channels.max(1) as usize;
+
+        self.remainder.extend_from_slice(raw);
+
+        for samples in self.samples.iter_mut() {
+            samples.clear();
+        }
+
+        let whole = self.remainder.len() / frame_bytes * frame_bytes;
+
+        for frame in self.remainder[..whole].chunks_exact(frame_bytes) {
+            let left = width.decode(&frame[..bytes]);
+
+            match (channels, self.format.selection) {
+                (1, _) | (_, ChannelSelection::Left) => self.samples[0].push(left),
+                (_, ChannelSelection::Right) => self.samples[0].push(width.decode(&frame[bytes..bytes * 2])),
+                (_, ChannelSelection::Mix) => {
+                    self.samples[0].push((left + width.decode(&frame[bytes..bytes * 2])) / 2.0)
+                }
+                (_, ChannelSelection::Both) => {
+                    self.samples[0].push(left);
+                    self.samples[1].push(width.decode(&frame[bytes..bytes * 2]));
+                }
+            }
+        }
+
+        self.remainder.drain(..whole);
+
+        let samples = match self.resamplers.is_empty() {
+            true => &self.samples,
+            false => {
+                for ((resampler, samples), resampled) in
+                    self.resamplers.iter_mut().zip(self.samples.iter()).zip(self.resampled.iter_mut())
+                {
+                    resampled.clear();
+                    resampler.process(samples, resampled);
+                }
+
+                &self.resampled
+            }
+        };
+
+        self.output.clear();
+
+        for index in 0..samples[0].len() {
+            for channel in samples.iter() {
+                self.output.extend_from_slice(&saturate(channel[index]).to_le_bytes());
+            }
+        }
+
+        &self.output
+    }
+}
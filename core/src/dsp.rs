@@ -0,0 +1,406 @@
+use std::f32::consts::PI;
+
+// Below this a stage treats the input as silence, about -96 dBFS
+const SILENCE: f32 = 1.0;
+
+// Each stage works in place on mono 16-bit samples and keeps its state between frames
+pub trait Stage: Send {
+    fn process(&mut self, samples: &mut [i16]);
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct AgcConfig {
+    // Level the AGC steers the RMS towards
+    pub target_dbfs: f32,
+    pub max_gain_db: f32,
+    // How fast the gain drops on loud input and recovers once it gets quiet again
+    pub attack_ms: f32,
+    pub release_ms: f32,
+    // The limiter keeps peaks below this after the gain is applied
+    pub ceiling_dbfs: f32,
+}
+
+impl Default for AgcConfig {
+    fn default() -> Self {
+        Self {
+            target_dbfs: -20.0,
+            max_gain_db: 24.0,
+            attack_ms: 20.0,
+            release_ms: 1500.0,
+            ceiling_dbfs: -1.0,
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct NoiseGateConfig {
+    pub threshold_dbfs: f32,
+    // How much the signal is turned down while the gate is closed
+    pub attenuation_db: f32,
+    // Keeps the gate open for this long after the level drops, so word endings aren't cut off
+    pub hold_ms: f32,
+}
+
+impl Default for NoiseGateConfig {
+    fn default() -> Self {
+        Self {
+            threshold_dbfs: -55.0,
+            attenuation_db: 24.0,
+            hold_ms: 250.0,
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct DspConfig {
+    pub dc_block: bool,
+    pub high_pass_hz: Option<f32>,
+    pub noise_gate: Option<NoiseGateConfig>,
+    pub agc: Option<AgcConfig>,
+}
+
+impl DspConfig {
+    // Passes the audio through untouched
+    pub fn disabled() -> Self {
+        Self {
+            dc_block: false,
+            high_pass_hz: None,
+            noise_gate: None,
+            agc: None,
+        }
+    }
+}
+
+impl Default for DspConfig {
+    fn default() -> Self {
+        Self {
+            dc_block: true,
+            high_pass_hz: Some(80.0),
+            noise_gate: None,
+            agc: Some(AgcConfig::default()),
+        }
+    }
+}
+
+pub struct DspChain {
+    stages: Vec<Box<dyn Stage>>,
+    samples: Vec<i16>,
+}
+
+impl DspChain {
+    // The gate runs before the AGC, otherwise the AGC would first lift the noise it is supposed to keep down
+    pub fn new(config: &DspConfig, sample_rate: u32) -> Self {
+        let mut stages: Vec<Box<dyn Stage>> = vec![];
+
+        if config.dc_block {
+            stages.push(Box::new(DcBlocker::new(sample_rate)));
+        }
+
+        if let Some(cutoff_hz) = config.high_pass_hz {
+            stages.push(Box::new(HighPass::new(sample_rate, cutoff_hz)));
+        }
+
+        if let Some(noise_gate) = &config.noise_gate {
+            stages.push(Box::new(NoiseGate::new(sample_rate, noise_gate)));
+        }
+
+        if let Some(agc) = &config.agc {
+            stages.push(Box::new(Agc::new(sample_rate, agc)));
+        }
+
+        Self { stages, samples: vec![] }
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.stages.is_empty()
+    }
+
+    // Little endian PCM as it comes from the AudioSource, a trailing odd byte is left alone
+    pub fn process_pcm(&mut self, pcm: &mut [u8]) {
+        if self.is_empty() {
+            return;
+        }
+
+        self.samples.clear();
+        self.samples
+            .extend(pcm.chunks_exact(2).map(|sample| i16::from_le_bytes([sample[0], sample[1]])));
+
+        let mut samples = std::mem::take(&mut self.samples);
+        self.process(&mut samples);
+
+        for (bytes, sample) in pcm.chunks_exact_mut(2).zip(samples.iter()) {
+            bytes.copy_from_slice(&sample.to_le_bytes());
+        }
+
+        self.samples = samples;
+    }
+}
+
+impl Stage for DspChain {
+    fn process(&mut self, samples: &mut [i16]) {
+        for stage in self.stages.iter_mut() {
+            stage.process(samples);
+        }
+    }
+}
+
+// One pole DC blocker, y[n] = x[n] - x[n-1] + r * y[n-1], with its corner at a few Hz
+pub struct DcBlocker {
+    r: f32,
+    previous_input: f32,
+    previous_output: f32,
+}
+
+impl DcBlocker {
+    const CORNER_HZ: f32 = 5.0;
+
+    pub fn new(sample_rate: u32) -> Self {
+        Self {
+            r: 1.0 - 2.0 * PI * Self::CORNER_HZ / sample_rate as f32,
+            previous_input: 0.0,
+            previous_output: 0.0,
+        }
+    }
+}
+
+impl Stage for DcBlocker {
+    fn process(&mut self, samples: &mut [i16]) {
+        for sample in samples.iter_mut() {
+            let input = *sample as f32;
+            let output = input - self.previous_input + self.r * self.previous_output;
+
+            self.previous_input = input;
+            self.previous_output = output;
+
+            *sample = saturate(output);
+        }
+    }
+}
+
+// RBJ cookbook biquad, shared by the high-pass stage and the resampler's anti-aliasing filter
+#[derive(Debug, Clone)]
+pub(crate) struct Biquad {
+    b0: f32,
+    b1: f32,
+    b2: f32,
+    a1: f32,
+    a2: f32,
+    x1: f32,
+    x2: f32,
+    y1: f32,
+    y2: f32,
+}
+
+impl Biquad {
+    pub(crate) fn high_pass(sample_rate: u32, cutoff_hz: f32) -> Self {
+        let (omega, alpha) = Self::prewarp(sample_rate, cutoff_hz);
+        let cos = omega.cos();
+
+        Self::normalized(1.0 + alpha, (1.0 + cos) / 2.0, -(1.0 + cos), (1.0 + cos) / 2.0, -2.0 * cos, 1.0 - alpha)
+    }
+
+    pub(crate) fn low_pass(sample_rate: u32, cutoff_hz: f32) -> Self {
+        let (omega, alpha) = Self::prewarp(sample_rate, cutoff_hz);
+        let cos = omega.cos();
+
+        Self::normalized(1.0 + alpha, (1.0 - cos) / 2.0, 1.0 - cos, (1.0 - cos) / 2.0, -2.0 * cos, 1.0 - alpha)
+    }
+
+    // Butterworth Q, so the passband stays flat
+    fn prewarp(sample_rate: u32, cutoff_hz: f32) -> (f32, f32) {
+        let omega = 2.0 * PI * cutoff_hz.min(sample_rate as f32 / 2.0 - 1.0) / sample_rate as f32;
+
+        (omega, omega.sin() / (2.0 * std::f32::consts::FRAC_1_SQRT_2))
+    }
+
+    fn normalized(a0: f32, b0: f32, b1: f32, b2: f32, a1: f32, a2: f32) -> Self {
+        Self {
+            b0: b0 / a0,
+            b1: b1 / a0,
+            b2: b2 / a0,
+            a1: a1 / a0,
+            a2: a2 / a0,
+            x1: 0.0,
+            x2: 0.0,
+            y1: 0.0,
+            y2: 0.0,
+        }
+    }
+
+    pub(crate) fn process(&mut self, input: f32) -> f32 {
+        let output = self.b0 * input + self.b1 * self.x1 + self.b2 * self.x2 - self.a1 * self.y1 - self.a2 * self.y2;
+
+        self.x2 = self.x1;
+        self.x1 = input;
+        self.y2 = self.y1;
+        self.y1 = output;
+
+        output
+    }
+}
+
+// Second order Butterworth high-pass for rumble, handling noise and HVAC hum
+pub struct HighPass {
+    filter: Biquad,
+}
+
+impl HighPass {
+    pub fn new(sample_rate: u32, cutoff_hz: f32) -> Self {
+        Self {
+            filter: Biquad::high_pass(sample_rate, cutoff_hz),
+        }
+    }
+}
+
+impl Stage for HighPass {
+    fn process(&mut self, samples: &mut [i16]) {
+        for sample in samples.iter_mut() {
+            *sample = saturate(self.filter.process(*sample as f32));
+        }
+    }
+}
+
+// Opens on the signal's envelope, closing is smoothed so the gate doesn't click
+pub struct NoiseGate {
+    threshold: f32,
+    floor: f32,
+    hold_samples: usize,
+    envelope_release: f32,
+    open_coefficient: f32,
+    close_coefficient: f32,
+    envelope: f32,
+    held: usize,
+    gain: f32,
+}
+
+impl NoiseGate {
+    pub fn new(sample_rate: u32, config: &NoiseGateConfig) -> Self {
+        Self {
+            threshold: from_dbfs(config.threshold_dbfs),
+            floor: from_db(-config.attenuation_db.abs()),
+            hold_samples: (config.hold_ms * sample_rate as f32 / 1000.0) as usize,
+            envelope_release: coefficient(sample_rate, 20.0),
+            open_coefficient: coefficient(sample_rate, 1.0),
+            close_coefficient: coefficient(sample_rate, 50.0),
+            envelope: 0.0,
+            held: 0,
+            gain: 1.0,
+        }
+    }
+}
+
+impl Stage for NoiseGate {
+    fn process(&mut self, samples: &mut [i16]) {
+        for sample in samples.iter_mut() {
+            let level = (*sample as f32).abs();
+
+            self.envelope = if level > self.envelope {
+                level
+            } else {
+                self.envelope + self.envelope_release * (level - self.envelope)
+            };
+
+            if self.envelope >= self.threshold {
+                self.held = self.hold_samples;
+            } else {
+                self.held = self.held.saturating_sub(1);
+            }
+
+            let (target, speed) = match self.envelope >= self.threshold || self.held > 0 {
+                true => (1.0, self.open_coefficient),
+                false => (self.floor, self.close_coefficient),
+            };
+
+            self.gain += speed * (target - self.gain);
+
+            *sample = saturate(*sample as f32 * self.gain);
+        }
+    }
+}
+
+// Steers the RMS towards the target without boosting silence, followed by a peak limiter so the extra gain never clips
+pub struct Agc {
+    target: f32,
+    max_gain: f32,
+    ceiling: f32,
+    power_coefficient: f32,
+    attack: f32,
+    release: f32,
+    limiter_release: f32,
+    power: f32,
+    gain: f32,
+    limiter: f32,
+}
+
+impl Agc {
+    // Below this (about -60 dBFS) the gain is held rather than raised towards max_gain
+    const GATE: f32 = 32.0;
+
+    pub fn new(sample_rate: u32, config: &AgcConfig) -> Self {
+        Self {
+            target: from_dbfs(config.target_dbfs),
+            max_gain: from_db(config.max_gain_db),
+            ceiling: from_dbfs(config.ceiling_dbfs),
+            power_coefficient: coefficient(sample_rate, 100.0),
+            attack: coefficient(sample_rate, config.attack_ms),
+            release: coefficient(sample_rate, config.release_ms),
+            limiter_release: coefficient(sample_rate, 50.0),
+            power: 0.0,
+            gain: 1.0,
+            limiter: 1.0,
+        }
+    }
+
+    pub fn gain_db(&self) -> f32 {
+        20.0 * self.gain.log10()
+    }
+}
+
+impl Stage for Agc {
+    fn process(&mut self, samples: &mut [i16]) {
+        for sample in samples.iter_mut() {
+            let input = *sample as f32;
+
+            self.power += self.power_coefficient * (input * input - self.power);
+
+            let rms = self.power.sqrt();
+
+            if rms > Self::GATE {
+                let desired = (self.target / rms).clamp(1.0 / self.max_gain, self.max_gain);
+                let speed = match desired < self.gain {
+                    true => self.attack,
+                    false => self.release,
+                };
+
+                self.gain += speed * (desired - self.gain);
+            }
+
+            let output = input * self.gain;
+
+            self.limiter += self.limiter_release * (1.0 - self.limiter);
+
+            if (output * self.limiter).abs() > self.ceiling {
+                self.limiter = self.ceiling / output.abs();
+            }
+
+            *sample = saturate(output * self.limiter);
+        }
+    }
+}
+
+pub(crate) fn saturate(value: f32) -> i16 {
+    value.round().clamp(i16::MIN as f32, i16::MAX as f32) as i16
+}
+
+fn from_db(db: f32) -> f32 {
+    10f32.powf(db / 20.0)
+}
+
+fn from_dbfs(dbfs: f32) -> f32 {
+    (from_db(dbfs) * i16::MAX as f32).max(SILENCE)
+}
+
+// Per sample smoothing factor for a one pole filter with the given time constant
+fn coefficient(sample_rate: u32, milliseconds: f32) -> f32 {
+    1.0 - (-1000.0 / (milliseconds.max(0.01) * sample_rate as f32)).exp()
+}
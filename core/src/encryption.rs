@@ -0,0 +1,444 @@
+use std::fs::File;
+use std::io::{ErrorKind, Read, Write};
+use std::path::Path;
+
+use log::warn;
+use ring::aead::{Aad, LessSafeKey, Nonce, UnboundKey, AES_256_GCM, NONCE_LEN};
+use ring::hkdf::{Salt, HKDF_SHA256};
+use ring::rand::{SecureRandom, SystemRandom};
+
+use crate::segment::{checksum_file, SegmentManifest, MANIFEST_FILE};
+
+// An encrypted file starts with MAGIC and a random nonce prefix of its own, followed by chunks of up to CHUNK_LENGTH
+// bytes, each sealed with AES-256-GCM on its own. A chunk's nonce is the prefix and its index, and the header is its
+// associated data. A file cut after a whole chunk reads as a shorter file, which is what lets a recording cut short by
+// a power loss be recovered
+pub const MAGIC: &[u8; 8] = b"ECHOENC1";
+pub const HEADER_LENGTH: usize = 16;
+pub const CHUNK_LENGTH: usize = 4096;
+pub const TAG_LENGTH: usize = 16;
+
+const SEALED_CHUNK_LENGTH: usize = CHUNK_LENGTH + TAG_LENGTH;
+const KEY_SALT: &[u8] = b"echosense storage";
+const KEY_INFO: &[u8] = b"aes-256-gcm chunks v1";
+
+// Derived from the device secret, the secret itself never leaves the device's NVS unless it is exported
+#[derive(Clone, PartialEq, Eq)]
+pub struct StorageKey {
+    bytes: [u8; 32],
+}
+
+impl std::fmt::Debug for StorageKey {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "StorageKey(..)")
+    }
+}
+
+impl StorageKey {
+    pub fn derive(secret: &[u8]) -> Self {
+        let mut bytes = [0u8; 32];
+
+        Salt::new(HKDF_SHA256, KEY_SALT)
+            .extract(secret)
+            .expand(&[KEY_INFO], &AES_256_GCM)
+            .and_then(|okm| okm.fill(&mut bytes))
+            .expect("32 bytes are a valid HKDF-SHA256 output length");
+
+        Self { bytes }
+    }
+
+    // The secret as the device exports it, 64 hex digits
+    pub fn from_hex_secret(secret: &str) -> Option<Self> {
+        decode_hex(secret).map(|bytes| Self::derive(&bytes))
+    }
+
+    fn aead(&self) -> LessSafeKey {
+        LessSafeKey::new(UnboundKey::new(&AES_256_GCM, &self.bytes).expect("the key has the length AES-256 needs"))
+    }
+}
+
+// Secrets are exchanged as hex, surrounding whitespace is ignored and nothing else but pairs of hex digits is accepted
+pub fn decode_hex(hex: &str) -> Option<Vec<u8>> {
+    let hex = hex.trim();
+
+    if hex.is_empty() || hex.len() % 2 != 0 || hex.bytes().all(|byte| byte.is_ascii_hexdigit()) == false {
+        return None;
+    }
+
+    (0..hex.len())
+        .step_by(2)
+        .map(|index| u8::from_str_radix(&hex[index..index + 2], 16).ok())
+        .collect()
+}
+
+fn nonce(header: &[u8; HEADER_LENGTH], index: u32) -> Nonce {
+    let mut nonce = [0u8; NONCE_LEN];
+
+    nonce[..8].copy_from_slice(&header[MAGIC.len()..]);
+    nonce[8..].copy_from_slice(&index.to_be_bytes());
+
+    Nonce::assume_unique_for_key(nonce)
+}
+
+fn invalid_data(message: String) -> std::io::Error {
+    std::io::Error::new(ErrorKind::InvalidData, message)
+}
+
+// Seals whole chunks as they fill up. The last, shorter one is sealed by `finish` or when the writer is dropped, never
+// on flush: sealing it again once it grows would reuse its nonce
+pub struct EncryptingWriter<W: Write> {
+    inner: Option<W>,
+    key: LessSafeKey,
+    header: [u8; HEADER_LENGTH],
+    chunk: Vec<u8>,
+    index: u32,
+}
+
+impl<W: Write> EncryptingWriter<W> {
+    pub fn new(mut inner: W, key: &StorageKey) -> std::io::Result<Self> {
+        let mut header = [0u8; HEADER_LENGTH];
+        header[..MAGIC.len()].copy_from_slice(MAGIC);
+
+        SystemRandom::new()
+            .fill(&mut header[MAGIC.len()..])
+            .map_err(|_| std::io::Error::other("no random numbers for the nonce"))?;
+
+        inner.write_all(&header)?;
+
+        Ok(Self {
+            inner: Some(inner),
+            key: key.aead(),
+            header,
+            chunk: Vec::with_capacity(SEALED_CHUNK_LENGTH),
+            index: 0,
+        })
+    }
+
+    pub fn finish(mut self) -> std::io::Result<W> {
+        self.seal_chunk()?;
+
+        let mut inner = self
+            .inner
+            .take()
+            .ok_or_else(|| std::io::Error::other("already finished"))?;
+        inner.flush()?;
+
+        Ok(inner)
+    }
+
+    fn seal_chunk(&mut self) -> std::io::Result<()> {
+        if self.chunk.is_empty() {
+            return Ok(());
+        }
+
+        let Some(inner) = self.inner.as_mut() else {
+            return Ok(());
+        };
+
+        let index = self.index;
+        let next = index
+            .checked_add(1)
+            .ok_or_else(|| invalid_data("too many chunks".to_string()))?;
+
+        self.key
+            .seal_in_place_append_tag(nonce(&self.header, index), Aad::from(&self.header), &mut self.chunk)
+            .map_err(|_| std::io::Error::other("failed to seal a chunk"))?;
+
+        inner.write_all(&self.chunk)?;
+
+        self.chunk.clear();
+        self.index = next;
+
+        Ok(())
+    }
+}
+
+impl<W: Write> Write for EncryptingWriter<W> {
+    fn write(&mut self, buf: &[u8]) -> std::io::Result<usize> {
+        let length = buf.len().min(CHUNK_LENGTH - self.chunk.len());
+
+        self.chunk.extend_from_slice(&buf[..length]);
+
+        if self.chunk.len() == CHUNK_LENGTH {
+            self.seal_chunk()?;
+        }
+
+        Ok(length)
+    }
+
+    fn flush(&mut self) -> std::io::Result<()> {
+        match self.inner.as_mut() {
+            Some(inner) => inner.flush(),
+            None => Ok(()),
+        }
+    }
+}
+
+impl<W: Write> Drop for EncryptingWriter<W> {
+    fn drop(&mut self) {
+        if let Err(error) = self.seal_chunk().and_then(|_| self.flush()) {
+            warn!("failed to seal the last chunk: {:?}", error);
+        }
+    }
+}
+
+// Fails on the first chunk that doesn't authenticate: a wrong key, a damaged file, or one cut in the middle of a chunk
+pub struct DecryptingReader<R: Read> {
+    inner: R,
+    key: LessSafeKey,
+    header: [u8; HEADER_LENGTH],
+    index: u32,
+    chunk: Vec<u8>,
+    position: usize,
+}
+
+impl<R: Read> DecryptingReader<R> {
+    pub fn new(mut inner: R, key: &StorageKey) -> std::io::Result<Self> {
+        let mut header = [0u8; HEADER_LENGTH];
+        inner.read_exact(&mut header)?;
+
+        if header.starts_with(MAGIC) == false {
+            return Err(invalid_data("not an encrypted file".to_string()));
+        }
+
+        Ok(Self {
+            inner,
+            key: key.aead(),
+            header,
+            index: 0,
+            chunk: Vec::with_capacity(SEALED_CHUNK_LENGTH),
+            position: 0,
+        })
+    }
+
+    fn open_chunk(&mut self) -> std::io::Result<()> {
+        self.chunk.resize(SEALED_CHUNK_LENGTH, 0);
+        self.position = 0;
+
+        let mut read = 0;
+
+        while read < SEALED_CHUNK_LENGTH {
+            match self.inner.read(&mut self.chunk[read..]) {
+                Ok(0) => break,
+                Ok(length) => read += length,
+                Err(error) if error.kind() == ErrorKind::Interrupted => continue,
+                Err(error) => return Err(error),
+            }
+        }
+
+        self.chunk.truncate(read);
+
+        if read == 0 {
+            return Ok(());
+        }
+
+        let index = self.index;
+
+        let length = self
+            .key
+            .open_in_place(nonce(&self.header, index), Aad::from(&self.header), &mut self.chunk)
+            .map_err(|_| invalid_data(format!("chunk {} doesn't authenticate, wrong key or damaged", index)))?
+            .len();
+
+        self.chunk.truncate(length);
+        self.index = index
+            .checked_add(1)
+            .ok_or_else(|| invalid_data("too many chunks".to_string()))?;
+
+        Ok(())
+    }
+}
+
+impl<R: Read> Read for DecryptingReader<R> {
+    fn read(&mut self, buf: &mut [u8]) -> std::io::Result<usize> {
+        if self.position == self.chunk.len() {
+            self.open_chunk()?;
+        }
+
+        let length = buf.len().min(self.chunk.len() - self.position);
+
+        buf[..length].copy_from_slice(&self.chunk[self.position..self.position + length]);
+        self.position += length;
+
+        Ok(length)
+    }
+}
+
+pub fn is_encrypted(path: &Path) -> std::io::Result<bool> {
+    let mut magic = [0u8; 8];
+
+    match File::open(path)?.read_exact(&mut magic) {
+        Ok(()) => Ok(&magic == MAGIC),
+        Err(error) if error.kind() == ErrorKind::UnexpectedEof => Ok(false),
+        Err(error) => Err(error),
+    }
+}
+
+// Encrypted with `key` when there is one, as is otherwise
+pub fn encrypt<W: Write + Send + 'static>(
+    writer: W,
+    key: Option<&StorageKey>,
+) -> std::io::Result<Box<dyn Write + Send>> {
+    Ok(match key {
+        Some(key) => Box::new(EncryptingWriter::new(writer, key)?),
+        None => Box::new(writer),
+    })
+}
+
+pub fn create(path: &Path, key: Option<&StorageKey>) -> std::io::Result<Box<dyn Write + Send>> {
+    encrypt(File::create(path)?, key)
+}
+
+// Files written before encryption was turned on are still read as they are
+pub fn open(path: &Path, key: Option<&StorageKey>) -> std::io::Result<Box<dyn Read + Send>> {
+    let file = File::open(path)?;
+
+    match (is_encrypted(path)?, key) {
+        (false, _) => Ok(Box::new(file)),
+        (true, Some(key)) => Ok(Box::new(DecryptingReader::new(file, key)?)),
+        (true, None) => Err(std::io::Error::new(
+            ErrorKind::PermissionDenied,
+            format!("{} is encrypted and there is no key", path.display()),
+        )),
+    }
+}
+
+pub fn read(path: &Path, key: Option<&StorageKey>) -> std::io::Result<Vec<u8>> {
+    let mut bytes = vec![];
+    open(path, key)?.read_to_end(&mut bytes)?;
+
+    Ok(bytes)
+}
+
+pub fn write(path: &Path, key: Option<&StorageKey>, bytes: &[u8]) -> std::io::Result<()> {
+    match key {
+        Some(key) => {
+            let mut writer = EncryptingWriter::new(File::create(path)?, key)?;
+            writer.write_all(bytes)?;
+            writer.finish()?;
+
+            Ok(())
+        }
+        None => std::fs::write(path, bytes),
+    }
+}
+
+// Chunks are sealed once, so with a key the file is read and written again as a whole. Only for small files like logs
+pub fn append(path: &Path, key: Option<&StorageKey>, bytes: &[u8]) -> std::io::Result<()> {
+    match key {
+        Some(_) => {
+            let mut content = match read(path, key) {
+                Ok(content) => content,
+                Err(error) if error.kind() == ErrorKind::NotFound => vec![],
+                Err(error) => return Err(error),
+            };
+
+            content.extend_from_slice(bytes);
+            write(path, key, &content)
+        }
+        None => std::fs::OpenOptions::new()
+            .create(true)
+            .append(true)
+            .open(path)?
+            .write_all(bytes),
+    }
+}
+
+// What an encrypted file of `stored_length` bytes holds, a chunk cut short counts as far as it goes
+pub fn plaintext_length(stored_length: u64) -> u64 {
+    let sealed = stored_length.saturating_sub(HEADER_LENGTH as u64);
+    let chunks = sealed / SEALED_CHUNK_LENGTH as u64;
+    let rest = sealed % SEALED_CHUNK_LENGTH as u64;
+
+    chunks * CHUNK_LENGTH as u64 + rest.saturating_sub(TAG_LENGTH as u64)
+}
+
+// The stored length of an encrypted file cut back to its whole chunks, the last one may have been cut short
+pub fn whole_chunks_length(stored_length: u64) -> u64 {
+    let sealed = stored_length.saturating_sub(HEADER_LENGTH as u64);
+
+    (HEADER_LENGTH as u64).min(stored_length) + sealed / SEALED_CHUNK_LENGTH as u64 * SEALED_CHUNK_LENGTH as u64
+}
+
+// What reading the file returns, decrypted or not
+pub fn readable_length(path: &Path) -> std::io::Result<u64> {
+    let length = std::fs::metadata(path)?.len();
+
+    Ok(match is_encrypted(path)? {
+        true => plaintext_length(length),
+        false => length,
+    })
+}
+
+#[derive(Debug, Clone, PartialEq, Default)]
+pub struct DecryptReport {
+    pub decrypted: u64,
+    // Files that weren't encrypted
+    pub copied: u64,
+    // Files that only decrypted up to some point, what did is kept
+    pub failed: Vec<String>,
+}
+
+// Decrypts a card pulled from a device into `destination`, which can then be used like any FileStore. Segment
+// manifests are rewritten with the lengths and checksums of the decrypted segments
+pub fn decrypt_directory(source: &Path, destination: &Path, key: &StorageKey) -> std::io::Result<DecryptReport> {
+    let mut report = DecryptReport::default();
+    let mut manifests = vec![];
+
+    decrypt_entries(source, destination, key, &mut report, &mut manifests)?;
+
+    for (directory, path) in manifests {
+        let mut manifest = SegmentManifest::load(&path)?;
+
+        for segment in manifest.segments.iter_mut().filter(|segment| segment.complete) {
+            if let Ok((length, crc32)) = checksum_file(&directory.join(&segment.file)) {
+                segment.length = length;
+                segment.crc32 = crc32;
+            }
+        }
+
+        manifest.save(&path)?;
+    }
+
+    Ok(report)
+}
+
+fn decrypt_entries(
+    source: &Path,
+    destination: &Path,
+    key: &StorageKey,
+    report: &mut DecryptReport,
+    manifests: &mut Vec<(std::path::PathBuf, std::path::PathBuf)>,
+) -> std::io::Result<()> {
+    std::fs::create_dir_all(destination)?;
+
+    for entry in std::fs::read_dir(source)? {
+        let entry = entry?;
+        let target = destination.join(entry.file_name());
+
+        if entry.file_type()?.is_dir() {
+            decrypt_entries(&entry.path(), &target, key, report, manifests)?;
+            continue;
+        }
+
+        if entry.file_name() == MANIFEST_FILE {
+            manifests.push((destination.to_path_buf(), target.clone()));
+        }
+
+        if is_encrypted(&entry.path())? == false {
+            std::fs::copy(entry.path(), &target)?;
+            report.copied += 1;
+            continue;
+        }
+
+        let result = DecryptingReader::new(File::open(entry.path())?, key)
+            .and_then(|mut reader| std::io::copy(&mut reader, &mut File::create(&target)?));
+
+        match result {
+            Ok(_) => report.decrypted += 1,
+            Err(error) => report.failed.push(format!("{}: {}", entry.path().display(), error)),
+        }
+    }
+
+    Ok(())
+}
@@ -0,0 +1,62 @@
+use std::fmt::{Debug, Display, Formatter};
+use std::string::FromUtf8Error;
+use std::sync::{MutexGuard, PoisonError};
+
+#[derive(Debug)]
+pub enum CoreError {
+    IOError(std::io::Error),
+    SerdeJsonError(serde_json::Error),
+    FromUtf8Error(FromUtf8Error),
+    MutexLockError(String),
+    ChannelSendError,
+    HttpError { status: u16, body: String },
+    MeetingNotFound(String),
+    InvalidAudio(String),
+    QRCodeError(String),
+    // Errors of the platform implementations (esp-idf, Linux...) the core knows nothing about
+    PlatformError(String),
+}
+
+impl Display for CoreError {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        Debug::fmt(self, f)
+    }
+}
+
+impl std::error::Error for CoreError {}
+
+impl From<std::io::Error> for CoreError {
+    fn from(error: std::io::Error) -> Self {
+        CoreError::IOError(error)
+    }
+}
+
+impl From<serde_json::Error> for CoreError {
+    fn from(error: serde_json::Error) -> Self {
+        CoreError::SerdeJsonError(error)
+    }
+}
+
+impl From<FromUtf8Error> for CoreError {
+    fn from(error: FromUtf8Error) -> Self {
+        CoreError::FromUtf8Error(error)
+    }
+}
+
+impl<'a, T> From<PoisonError<MutexGuard<'a, T>>> for CoreError {
+    fn from(error: PoisonError<MutexGuard<T>>) -> Self {
+        CoreError::MutexLockError(error.to_string())
+    }
+}
+
+impl<T> From<crossbeam_channel::SendError<T>> for CoreError {
+    fn from(_: crossbeam_channel::SendError<T>) -> Self {
+        CoreError::ChannelSendError
+    }
+}
+
+impl From<qrcodegen::DataTooLong> for CoreError {
+    fn from(error: qrcodegen::DataTooLong) -> Self {
+        CoreError::QRCodeError(error.to_string())
+    }
+}
@@ -0,0 +1,139 @@
+use embedded_graphics::mono_font::ascii::FONT_6X10;
+use embedded_graphics::mono_font::MonoTextStyle;
+use embedded_graphics::pixelcolor::BinaryColor;
+use embedded_graphics::prelude::{DrawTarget, OriginDimensions, Point, Size};
+use embedded_graphics::text::{Baseline, Text};
+use embedded_graphics::{Drawable, Pixel};
+
+use crate::error::CoreError;
+use crate::images::dev_to_logo::DEV_TO_LOGO;
+use crate::images::done_icon::DONE_ICON;
+use crate::images::wifi_icon::WIFI_ICON;
+use crate::platform::DrawState;
+use crate::qrcode::QRCode;
+
+// The SSD1306 on the device, 1 bit per pixel, rows top to bottom and the most significant bit is the leftmost pixel
+pub const FRAMEBUFFER_WIDTH: usize = 128;
+pub const FRAMEBUFFER_HEIGHT: usize = 64;
+
+pub fn render(state: &DrawState) -> Result<Vec<u8>, CoreError> {
+    Ok(match state {
+        DrawState::Initializing => DEV_TO_LOGO.to_vec(),
+        DrawState::Wifi => WIFI_ICON.to_vec(),
+        DrawState::Done => DONE_ICON.to_vec(),
+        DrawState::QRCode(content) => QRCode::new(content, FRAMEBUFFER_WIDTH, FRAMEBUFFER_HEIGHT, 2, (20, 3))?.to_vec(),
+        DrawState::StorageWarning { content, used_percent } => {
+            let mut framebuffer = QRCode::new(content, FRAMEBUFFER_WIDTH, FRAMEBUFFER_HEIGHT, 2, (20, 3))?.to_vec();
+            draw_gauge(&mut framebuffer, *used_percent);
+            framebuffer
+        }
+        DrawState::NoCard(content) => {
+            let mut framebuffer = QRCode::new(content, FRAMEBUFFER_WIDTH, FRAMEBUFFER_HEIGHT, 2, (20, 3))?.to_vec();
+            draw_no_card(&mut framebuffer);
+            framebuffer
+        }
+        DrawState::Text(lines) => {
+            let mut canvas = Canvas(vec![0; FRAMEBUFFER_WIDTH * FRAMEBUFFER_HEIGHT / 8]);
+            let style = MonoTextStyle::new(&FONT_6X10, BinaryColor::On);
+
+            // 6 lines of 21 characters fit, whatever is longer is cut off by the edge of the display
+            for (index, line) in lines.iter().take(FRAMEBUFFER_HEIGHT / LINE_HEIGHT).enumerate() {
+                Text::with_baseline(line, Point::new(1, (index * LINE_HEIGHT) as i32 + 2), style, Baseline::Top)
+                    .draw(&mut canvas)
+                    .ok();
+            }
+
+            canvas.0
+        }
+    })
+}
+
+// Left of the QR code, an outline filled from the bottom up
+const GAUGE_LEFT: usize = 8;
+const GAUGE_RIGHT: usize = 23;
+const GAUGE_TOP: usize = 4;
+const GAUGE_BOTTOM: usize = 59;
+
+fn draw_gauge(framebuffer: &mut [u8], used_percent: u8) {
+    let height = GAUGE_BOTTOM - GAUGE_TOP - 1;
+    let filled = height * used_percent.min(100) as usize / 100;
+
+    for y in GAUGE_TOP..=GAUGE_BOTTOM {
+        for x in GAUGE_LEFT..=GAUGE_RIGHT {
+            let outline = y == GAUGE_TOP || y == GAUGE_BOTTOM || x == GAUGE_LEFT || x == GAUGE_RIGHT;
+            let fill = y >= GAUGE_BOTTOM - filled;
+
+            if outline || fill {
+                set_pixel(framebuffer, x, y);
+            }
+        }
+    }
+}
+
+// Where the gauge would be, an SD card with its top right corner cut off and a cross over it
+const CARD_TOP: usize = 16;
+const CARD_BOTTOM: usize = 47;
+const CARD_NOTCH: usize = 5;
+
+fn draw_no_card(framebuffer: &mut [u8]) {
+    draw_line(framebuffer, (GAUGE_LEFT, CARD_TOP), (GAUGE_RIGHT - CARD_NOTCH, CARD_TOP));
+    draw_line(framebuffer, (GAUGE_RIGHT - CARD_NOTCH, CARD_TOP), (GAUGE_RIGHT, CARD_TOP + CARD_NOTCH));
+    draw_line(framebuffer, (GAUGE_RIGHT, CARD_TOP + CARD_NOTCH), (GAUGE_RIGHT, CARD_BOTTOM));
+    draw_line(framebuffer, (GAUGE_RIGHT, CARD_BOTTOM), (GAUGE_LEFT, CARD_BOTTOM));
+    draw_line(framebuffer, (GAUGE_LEFT, CARD_BOTTOM), (GAUGE_LEFT, CARD_TOP));
+
+    draw_line(framebuffer, (GAUGE_LEFT, CARD_TOP), (GAUGE_RIGHT, CARD_BOTTOM));
+    draw_line(framebuffer, (GAUGE_RIGHT, CARD_TOP), (GAUGE_LEFT, CARD_BOTTOM));
+}
+
+fn draw_line(framebuffer: &mut [u8], from: (usize, usize), to: (usize, usize)) {
+    let steps = from.0.abs_diff(to.0).max(from.1.abs_diff(to.1)).max(1);
+
+    for step in 0..=steps {
+        let x = from.0 as isize + (to.0 as isize - from.0 as isize) * step as isize / steps as isize;
+        let y = from.1 as isize + (to.1 as isize - from.1 as isize) * step as isize / steps as isize;
+
+        set_pixel(framebuffer, x as usize, y as usize);
+    }
+}
+
+const LINE_HEIGHT: usize = 10;
+
+// Lets embedded-graphics draw text into a framebuffer
+struct Canvas(Vec<u8>);
+
+impl OriginDimensions for Canvas {
+    fn size(&self) -> Size {
+        Size::new(FRAMEBUFFER_WIDTH as u32, FRAMEBUFFER_HEIGHT as u32)
+    }
+}
+
+impl DrawTarget for Canvas {
+    type Color = BinaryColor;
+    type Error = core::convert::Infallible;
+
+    fn draw_iter<I: IntoIterator<Item = Pixel<BinaryColor>>>(&mut self, pixels: I) -> Result<(), Self::Error> {
+        for Pixel(point, color) in pixels {
+            let inside = (0..FRAMEBUFFER_WIDTH as i32).contains(&point.x)
+                && (0..FRAMEBUFFER_HEIGHT as i32).contains(&point.y);
+
+            if inside && color == BinaryColor::On {
+                set_pixel(&mut self.0, point.x as usize, point.y as usize);
+            }
+        }
+
+        Ok(())
+    }
+}
+
+fn set_pixel(framebuffer: &mut [u8], x: usize, y: usize) {
+    let index = x + y * FRAMEBUFFER_WIDTH;
+
+    framebuffer[index / 8] |= 1 << (7 - index % 8);
+}
+
+pub fn pixel(framebuffer: &[u8], x: usize, y: usize) -> bool {
+    let index = x + y * FRAMEBUFFER_WIDTH;
+
+    framebuffer[index / 8] & (1 << (7 - index % 8)) != 0
+}
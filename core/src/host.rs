@@ -0,0 +1,188 @@
+use std::fs::File;
+use std::io::{BufReader, Read};
+use std::path::Path;
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+
+use log::info;
+
+use crate::audio::read_wav_header;
+use crate::convert::{CaptureFormat, ChannelSelection, Converter, SampleWidth, PCM_SAMPLE_RATE};
+use crate::error::CoreError;
+use crate::platform::{AudioSource, Clock, DrawState, StatusDisplay};
+
+// Implementations of the platform traits for a regular std/Linux host
+
+pub struct SystemClock {
+    started: Instant,
+}
+
+impl SystemClock {
+    pub fn new() -> Self {
+        Self {
+            started: Instant::now(),
+        }
+    }
+}
+
+impl Default for SystemClock {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Clock for SystemClock {
+    fn uptime(&self) -> Duration {
+        self.started.elapsed()
+    }
+
+    fn sleep(&self, duration: Duration) {
+        std::thread::sleep(duration)
+    }
+}
+
+// Plays a 16, 24 or 32-bit PCM WAV file as if it was the microphone, paced to real time unless `realtime` is off.
+// Like the microphone it is converted to 16 kHz mono, keeping only the first channel unless both are asked for
+pub struct WavFileSource {
+    reader: BufReader<File>,
+    converter: Converter,
+    remaining: usize,
+    buffer: Vec<u8>,
+    clock: Arc<dyn Clock>,
+    realtime: bool,
+    started: Option<Duration>,
+    played: Duration,
+}
+
+impl WavFileSource {
+    pub fn open<P: AsRef<Path>>(
+        path: P,
+        frame_size: usize,
+        clock: Arc<dyn Clock>,
+        realtime: bool,
+    ) -> Result<Self, CoreError> {
+        let mut reader = BufReader::new(File::open(path)?);
+        let format = read_wav_header(&mut reader)?;
+
+        let Some(width) = SampleWidth::from_bits(format.bits_per_sample) else {
+            return Err(CoreError::InvalidAudio(format!(
+                "only 16, 24 and 32-bit wav files are supported, got {} bits",
+                format.bits_per_sample
+            )));
+        };
+
+        if format.sample_rate == 0 {
+            return Err(CoreError::InvalidAudio("sample rate of 0".to_string()));
+        }
+
+        let capture = CaptureFormat {
+            sample_rate: format.sample_rate,
+            width,
+            channels: format.channels.max(1),
+            selection: ChannelSelection::Left,
+        };
+
+        Ok(Self {
+            reader,
+            buffer: vec![0; capture.raw_bytes_for(frame_size)],
+            converter: Converter::new(capture),
+            // Streamed files may not know their length up front and leave it at 0 or u32::MAX
+            remaining: match format.data_length {
+                0 | u32::MAX => usize::MAX,
+                length => length as usize,
+            },
+            clock,
+            realtime,
+            started: None,
+            played: Duration::ZERO,
+        })
+    }
+
+    // Hands out both channels of a stereo file interleaved, like a dual microphone setup, for the Combiner. The frames
+    // stay as long in time, so they shrink back to the usual size once combined
+    pub fn with_both_channels(mut self) -> Self {
+        let format = CaptureFormat {
+            selection: ChannelSelection::Both,
+            ..self.converter.format().clone()
+        };
+
+        self.converter = Converter::new(format);
+        self
+    }
+}
+
+impl AudioSource for WavFileSource {
+    fn sample_rate(&self) -> u32 {
+        PCM_SAMPLE_RATE
+    }
+
+    fn channels(&self) -> u16 {
+        self.converter.format().output_channels()
+    }
+
+    fn sample(&mut self) -> Result<Option<&[u8]>, CoreError> {
+        let wanted = self.buffer.len().min(self.remaining);
+        let mut filled = 0;
+
+        while filled < wanted {
+            match self.reader.read(&mut self.buffer[filled..wanted])? {
+                0 => break,
+                length => filled += length,
+            }
+        }
+
+        self.remaining -= filled;
+
+        if filled < self.converter.format().frame_bytes() {
+            return Ok(None);
+        }
+
+        let channels = self.converter.format().output_channels() as usize;
+        let frame = self.converter.convert(&self.buffer[..filled]);
+        let samples = frame.len() / 2 / channels;
+
+        if self.realtime {
+            let started = *self.started.get_or_insert_with(|| self.clock.uptime());
+
+            self.played += Duration::from_secs_f64(samples as f64 / PCM_SAMPLE_RATE as f64);
+
+            let ahead = self.played.saturating_sub(self.clock.uptime().saturating_sub(started));
+
+            if ahead > Duration::ZERO {
+                self.clock.sleep(ahead);
+            }
+        }
+
+        Ok(Some(frame))
+    }
+}
+
+// Stands in for the OLED display by logging every state change
+#[derive(Default)]
+pub struct ConsoleDisplay {
+    state: Option<DrawState>,
+}
+
+impl StatusDisplay for ConsoleDisplay {
+    fn draw(&mut self, state: DrawState) -> Result<(), CoreError> {
+        if self.state.as_ref() == Some(&state) {
+            return Ok(());
+        }
+
+        match &state {
+            DrawState::Initializing => info!("[display] initializing..."),
+            DrawState::Wifi => info!("[display] connecting to wifi..."),
+            DrawState::Done => info!("[display] done"),
+            DrawState::QRCode(address) => info!("[display] open {}", address),
+            DrawState::StorageWarning { content, used_percent } => {
+                info!("[display] open {}, storage {}% full", content, used_percent)
+            }
+            DrawState::NoCard(address) => info!("[display] open {}, no SD card", address),
+            DrawState::Text(lines) => info!("[display] {}", lines.join(" | ")),
+        }
+
+        self.state = Some(state);
+
+        Ok(())
+    }
+}
@@ -0,0 +1,22 @@
+pub mod assembly;
+pub mod audio;
+pub mod codec;
+pub mod combine;
+pub mod convert;
+pub mod dsp;
+pub mod encryption;
+pub mod error;
+pub mod framebuffer;
+pub mod host;
+pub mod images;
+pub mod mail;
+pub mod maintenance;
+pub mod meeting;
+pub mod pipeline;
+pub mod platform;
+pub mod qrcode;
+pub mod retention;
+pub mod segment;
+pub mod store;
+pub mod template;
+pub mod vad;
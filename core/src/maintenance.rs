@@ -0,0 +1,347 @@
+use std::fs::File;
+use std::io::{ErrorKind, Read, Write};
+use std::path::{Path, PathBuf};
+use std::time::{Duration, Instant};
+
+use log::{info, warn};
+use serde::{Deserialize, Serialize};
+
+use crate::platform::RecordingStore;
+use crate::retention::{StorageMedium, StorageUsage};
+use crate::store::FileStore;
+
+pub const BENCHMARK_FILE: &str = "benchmark.tmp";
+
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+pub enum MaintenanceAction {
+    Info,
+    Check,
+    // Only carried out with the code of an earlier request to format
+    Format,
+    Benchmark,
+}
+
+impl MaintenanceAction {
+    pub fn from_name(name: &str) -> Option<Self> {
+        match name.to_lowercase().as_str() {
+            "info" => Some(MaintenanceAction::Info),
+            "check" => Some(MaintenanceAction::Check),
+            "format" => Some(MaintenanceAction::Format),
+            "benchmark" => Some(MaintenanceAction::Benchmark),
+            _ => None,
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct StorageInfo {
+    pub medium: StorageMedium,
+    pub usage: Option<StorageUsage>,
+    pub meetings: usize,
+}
+
+#[derive(Debug, Clone, PartialEq, Default, Serialize, Deserialize)]
+pub struct CheckReport {
+    pub files: u64,
+    pub bytes: u64,
+    // Unreadable files, meetings that don't parse and segments whose checksum doesn't match
+    pub problems: Vec<String>,
+    // Leftover temporary files removed and interrupted recordings closed
+    pub repaired: Vec<String>,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct BenchmarkResult {
+    pub bytes: u64,
+    pub write_ms: u64,
+    pub read_ms: u64,
+}
+
+impl BenchmarkResult {
+    pub fn write_kbps(&self) -> u64 {
+        self.bytes * 1000 / 1024 / self.write_ms.max(1)
+    }
+
+    pub fn read_kbps(&self) -> u64 {
+        self.bytes * 1000 / 1024 / self.read_ms.max(1)
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub enum MaintenanceResult {
+    Info(StorageInfo),
+    Check(CheckReport),
+    // Formatting wipes every meeting, it has to be requested again with this code
+    ConfirmFormat { code: String, expires_in_s: u64 },
+    Formatted,
+    Benchmark(BenchmarkResult),
+    Failed(String),
+}
+
+impl MaintenanceResult {
+    // What the 128x64 display shows, up to six lines of 21 characters
+    pub fn lines(&self) -> Vec<String> {
+        match self {
+            MaintenanceResult::Info(info) => {
+                let mut lines = vec![match info.medium {
+                    StorageMedium::SdCard => "SD card".to_string(),
+                    StorageMedium::InternalFlash => "Internal flash".to_string(),
+                    StorageMedium::Memory => "No storage".to_string(),
+                }];
+
+                if let Some(usage) = &info.usage {
+                    lines.push(format!(
+                        "{} / {}",
+                        megabytes(usage.used_bytes),
+                        megabytes(usage.total_bytes)
+                    ));
+                    lines.push(format!("{}% used", usage.used_percent()));
+                }
+
+                lines.push(format!("{} meetings", info.meetings));
+                lines
+            }
+            MaintenanceResult::Check(report) => {
+                let mut lines = vec![
+                    match report.problems.len() {
+                        0 => "Check OK".to_string(),
+                        problems => format!("{} problems", problems),
+                    },
+                    format!("{} files", report.files),
+                    megabytes(report.bytes),
+                ];
+
+                if report.repaired.is_empty() == false {
+                    lines.push(format!("{} repaired", report.repaired.len()));
+                }
+
+                lines
+            }
+            MaintenanceResult::ConfirmFormat { code, expires_in_s } => vec![
+                "Format storage?".to_string(),
+                format!("Code {}", code),
+                "or hold the button".to_string(),
+                format!("again within {}s", expires_in_s),
+            ],
+            MaintenanceResult::Formatted => vec!["Storage formatted".to_string()],
+            MaintenanceResult::Benchmark(result) => vec![
+                "Benchmark".to_string(),
+                format!("Write {} KB/s", result.write_kbps()),
+                format!("Read {} KB/s", result.read_kbps()),
+            ],
+            MaintenanceResult::Failed(error) => {
+                let mut lines = vec!["Failed".to_string()];
+
+                for chunk in error.chars().collect::<Vec<_>>().chunks(21).take(5) {
+                    lines.push(chunk.iter().collect());
+                }
+
+                lines
+            }
+        }
+    }
+}
+
+fn megabytes(bytes: u64) -> String {
+    format!("{:.1} MB", bytes as f64 / 1024.0 / 1024.0)
+}
+
+// A format request waiting to be confirmed, the code is random and comes from the platform
+#[derive(Debug, Clone)]
+pub struct Confirmation {
+    code: String,
+    expires: Instant,
+}
+
+impl Confirmation {
+    pub fn new(code: String, valid_for: Duration) -> Self {
+        Self {
+            code,
+            expires: Instant::now() + valid_for,
+        }
+    }
+
+    pub fn code(&self) -> &str {
+        &self.code
+    }
+
+    pub fn expires_in(&self) -> Duration {
+        self.expires.saturating_duration_since(Instant::now())
+    }
+
+    pub fn is_expired(&self) -> bool {
+        Instant::now() >= self.expires
+    }
+
+    pub fn confirms(&self, code: &str) -> bool {
+        self.is_expired() == false && self.code.eq_ignore_ascii_case(code.trim())
+    }
+}
+
+pub fn storage_info(store: &FileStore, medium: StorageMedium, usage: Option<StorageUsage>) -> StorageInfo {
+    StorageInfo {
+        medium,
+        usage,
+        meetings: store.meeting_ids().map_or(0, |ids| ids.len()),
+    }
+}
+
+// Reads every file back to find what the filesystem can't deliver anymore, and checks the meetings and segment
+// manifests on top. Leftover temporary files are removed and recordings cut short by a power loss are closed, except
+// those of the `active` meetings that are still being written
+pub fn check(store: &FileStore, active: &[String]) -> CheckReport {
+    let mut report = CheckReport::default();
+    let active_directories = active.iter().map(|id| store.meeting_directory(id)).collect::<Vec<_>>();
+
+    walk(store.root(), &active_directories, &mut report);
+
+    for id in store.meeting_ids().unwrap_or_default() {
+        if let Err(error) = store.load_meeting(&id) {
+            report.problems.push(format!("meeting {}: {:?}", id, error));
+        }
+
+        let directory = store.meeting_directory(&id);
+        let mut manifest = match store.load_manifest(&id) {
+            Ok(manifest) => manifest,
+            Err(_) => continue,
+        };
+
+        if manifest.is_complete() == false && active.contains(&id) == false {
+            match manifest
+                .recover(&directory, store.key())
+                .and_then(|_| manifest.save(&store.manifest_path(&id)))
+            {
+                Ok(()) => report.repaired.push(format!("closed the recording of meeting {}", id)),
+                Err(error) => report.problems.push(format!("meeting {} recording: {:?}", id, error)),
+            }
+        }
+
+        for index in manifest.verify(&directory) {
+            report
+                .problems
+                .push(format!("meeting {} segment {} is damaged", id, index));
+        }
+    }
+
+    info!(
+        "checked {} files: {} problems, {} repaired",
+        report.files,
+        report.problems.len(),
+        report.repaired.len()
+    );
+
+    report
+}
+
+fn walk(directory: &Path, active: &[PathBuf], report: &mut CheckReport) {
+    let entries = match std::fs::read_dir(directory) {
+        Ok(entries) => entries,
+        Err(error) if error.kind() == ErrorKind::NotFound => return,
+        Err(error) => {
+            report.problems.push(format!("{}: {:?}", directory.display(), error));
+            return;
+        }
+    };
+
+    for entry in entries {
+        let path = match entry {
+            Ok(entry) => entry.path(),
+            Err(error) => {
+                report.problems.push(format!("{}: {:?}", directory.display(), error));
+                continue;
+            }
+        };
+
+        if path.is_dir() {
+            walk(&path, active, report);
+            continue;
+        }
+
+        // Written next to a file and renamed over it, one that is still around was never finished. Those of a meeting
+        // being recorded may just be in the middle of it
+        let temporary = path.extension().is_some_and(|extension| extension == "tmp");
+
+        if temporary && active.iter().any(|directory| path.starts_with(directory)) {
+            continue;
+        }
+
+        if temporary {
+            match std::fs::remove_file(&path) {
+                Ok(()) => report.repaired.push(format!("removed {}", path.display())),
+                Err(error) => report.problems.push(format!("{}: {:?}", path.display(), error)),
+            }
+
+            continue;
+        }
+
+        match read_all(&path) {
+            Ok(bytes) => {
+                report.files += 1;
+                report.bytes += bytes;
+            }
+            Err(error) => {
+                warn!("failed to read {}: {:?}", path.display(), error);
+                report.problems.push(format!("{}: {:?}", path.display(), error));
+            }
+        }
+    }
+}
+
+fn read_all(path: &Path) -> Result<u64, std::io::Error> {
+    std::io::copy(&mut File::open(path)?, &mut std::io::sink())
+}
+
+// Writes `bytes` in chunks the size the recording uses and reads them back, the file is removed afterwards
+pub fn benchmark(directory: &Path, bytes: usize) -> Result<BenchmarkResult, std::io::Error> {
+    let path = directory.join(BENCHMARK_FILE);
+    let chunk = (0..4096).map(|index| index as u8).collect::<Vec<_>>();
+
+    let result = (|| {
+        let started = Instant::now();
+
+        {
+            let mut file = File::create(&path)?;
+            let mut written = 0;
+
+            while written < bytes {
+                let length = chunk.len().min(bytes - written);
+
+                file.write_all(&chunk[..length])?;
+                written += length;
+            }
+
+            file.sync_all()?;
+        }
+
+        let write_ms = started.elapsed().as_millis() as u64;
+        let started = Instant::now();
+
+        let mut file = File::open(&path)?;
+        let mut buffer = vec![0u8; chunk.len()];
+        let mut read = 0;
+
+        loop {
+            match file.read(&mut buffer)? {
+                0 => break,
+                length => read += length,
+            }
+        }
+
+        if read != bytes {
+            return Err(std::io::Error::new(
+                ErrorKind::UnexpectedEof,
+                format!("read back {} of {} bytes", read, bytes),
+            ));
+        }
+
+        Ok(BenchmarkResult {
+            bytes: bytes as u64,
+            write_ms,
+            read_ms: started.elapsed().as_millis() as u64,
+        })
+    })();
+
+    std::fs::remove_file(&path).ok();
+
+    result
+}
@@ -0,0 +1,125 @@
+use std::sync::{Arc, Mutex};
+
+use serde::{Deserialize, Serialize};
+
+pub type SharedMeeting = Arc<Mutex<Meeting>>;
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Transcription {
+    pub text: String,
+    pub timestamp: String,
+    #[serde(default)]
+    pub audio_start: u64,
+    #[serde(default)]
+    pub audio_end: u64,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct QuestionAnswer {
+    pub id: String,
+    pub question: String,
+    pub answer: String,
+}
+
+// Pauses the voice activity detector found, in the same time base as the transcripts' audio_start and audio_end
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct SilentRegion {
+    pub start_ms: u64,
+    pub end_ms: u64,
+}
+
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub struct TalkTime {
+    pub speech_ms: u64,
+    pub silence_ms: u64,
+    // What was actually sent to the realtime API, which is what streaming is billed by
+    pub streamed_ms: u64,
+}
+
+impl TalkTime {
+    pub fn add(&mut self, other: &TalkTime) {
+        self.speech_ms += other.speech_ms;
+        self.silence_ms += other.silence_ms;
+        self.streamed_ms += other.streamed_ms;
+    }
+
+    pub fn speech_ratio(&self) -> f32 {
+        match self.speech_ms + self.silence_ms {
+            0 => 0.0,
+            total => self.speech_ms as f32 / total as f32,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Meeting {
+    pub id: String,
+    #[serde(default)]
+    pub title: Option<String>,
+    pub transcriptions: Vec<Transcription>,
+    pub summary: Option<String>,
+    #[serde(default)]
+    pub action_items: Option<String>,
+    pub questions: Vec<QuestionAnswer>,
+    #[serde(default)]
+    pub silent_regions: Vec<SilentRegion>,
+    #[serde(default)]
+    pub talk_time: TalkTime,
+    // Unix seconds, only known once the device's clock is set
+    #[serde(default)]
+    pub started_at: Option<u64>,
+    // Kept when the retention rules make room on the card
+    #[serde(default)]
+    pub starred: bool,
+}
+
+impl Meeting {
+    pub fn new<S: Into<String>>(id: S) -> Self {
+        Self {
+            id: id.into(),
+            title: None,
+            transcriptions: vec![],
+            summary: None,
+            action_items: None,
+            questions: vec![],
+            silent_regions: vec![],
+            talk_time: TalkTime::default(),
+            started_at: None,
+            starred: false,
+        }
+    }
+
+    // Ids are only ever hex strings, reject anything that could escape the meetings directory
+    pub fn is_valid_id(id: &str) -> bool {
+        id.is_empty() == false && id.chars().all(|character| character.is_ascii_alphanumeric())
+    }
+
+    pub fn title(&self) -> String {
+        match &self.title {
+            Some(title) if title.trim().is_empty() == false => title.trim().to_string(),
+            _ => format!("Meeting {}", self.id),
+        }
+    }
+
+    // The device has no clock of its own, the first transcript carries the server time the meeting started at
+    pub fn date(&self) -> String {
+        match self.transcriptions.first() {
+            Some(transcription) => transcription
+                .timestamp
+                .chars()
+                .take(16)
+                .collect::<String>()
+                .replace('T', " "),
+            None => "Unknown date".to_string(),
+        }
+    }
+
+    // Offset of the end of the last transcript relative to the start of the audio stream
+    pub fn duration_ms(&self) -> u64 {
+        self.transcriptions
+            .iter()
+            .map(|transcription| transcription.audio_end)
+            .max()
+            .unwrap_or(0)
+    }
+}
@@ -0,0 +1,267 @@
+use std::io::Write;
+
+use crossbeam_channel::{Receiver, Sender};
+use log::{info, warn};
+
+use crate::assembly::{Assembly, AssemblyResponse};
+use crate::combine::Combiner;
+use crate::dsp::DspChain;
+use crate::error::CoreError;
+use crate::meeting::{Meeting, QuestionAnswer, SharedMeeting, Transcription};
+use crate::platform::{AudioSource, HttpClient, RecordingStore, WsClient};
+use crate::vad::{SharedTimeline, VoiceGate};
+
+// Without storage, no SD card or a full one, the meeting lives on in memory and is saved with the next change that makes
+// it, rather than losing the transcript that was just added
+fn keep_meeting<S: RecordingStore + ?Sized>(store: &S, meeting: &Meeting) {
+    if let Err(error) = store.save_meeting(meeting) {
+        warn!("meeting {} is only kept in memory: {:?}", meeting.id, error);
+    }
+}
+
+// Every consumer gets its own channel, cloning a crossbeam receiver would split the frames between them.
+// Stereo is combined and the DSP chain runs once here, so transcription, recording and monitoring all get the same
+// processed audio. `raw` gets the stereo frames as they came from the source. A consumer that is gone is dropped and
+// the others keep getting audio, only once all of them are gone the source stops being read
+pub fn record_microphone<A: AudioSource + ?Sized>(
+    mut senders: Vec<Sender<Vec<u8>>>,
+    source: &mut A,
+    combiner: &mut Combiner,
+    dsp: &mut DspChain,
+    mut raw: Option<Sender<Vec<u8>>>,
+) -> Result<(), CoreError> {
+    let channels = source.channels();
+
+    while let Some(frame) = source.sample()? {
+        if let (Some(sender), true) = (&raw, channels > 1) {
+            if sender.send(frame.to_vec()).is_err() {
+                warn!("the consumer of the raw channels is gone");
+                raw = None;
+            }
+        }
+
+        let mut frame = combiner.combine_pcm(frame, channels);
+        dsp.process_pcm(&mut frame);
+
+        senders.retain(|sender| match sender.send(frame.clone()) {
+            Ok(()) => true,
+            Err(_) => {
+                warn!("a consumer of the microphone is gone, the others keep getting audio");
+                false
+            }
+        });
+
+        if senders.is_empty() && raw.is_none() {
+            warn!("every consumer of the microphone is gone");
+            return Ok(());
+        }
+    }
+
+    info!("audio source exhausted");
+
+    Ok(())
+}
+
+// Silence is held back by the voice gate, its talk time and silent regions end up in whichever meeting is current
+pub fn start_live_transcription<W: WsClient + ?Sized, S: RecordingStore + ?Sized>(
+    receiver: Receiver<Vec<u8>>,
+    client: &mut W,
+    gate: &mut VoiceGate,
+    meeting: &SharedMeeting,
+    store: &S,
+) -> Result<(), CoreError> {
+    while let Ok(frame) = receiver.recv() {
+        for frame in gate.process(frame.as_slice()) {
+            client.send_binary(frame.as_slice())?;
+        }
+
+        if gate.is_due() {
+            let mut meeting = meeting.lock()?;
+
+            if gate.update(&mut meeting) {
+                keep_meeting(store, &meeting);
+            }
+        }
+    }
+
+    gate.finish();
+
+    let mut meeting = meeting.lock()?;
+    gate.update(&mut meeting);
+    keep_meeting(store, &meeting);
+
+    Ok(())
+}
+
+// Follows the shared meeting, so starting a new meeting switches the recording over to its own file
+pub fn record_audio<S: RecordingStore + ?Sized>(
+    receiver: Receiver<Vec<u8>>,
+    meeting: SharedMeeting,
+    store: &S,
+) -> Result<(), CoreError> {
+    let mut recording: Option<(String, Box<dyn Write + Send>)> = None;
+
+    while let Ok(frame) = receiver.recv() {
+        let id = meeting.lock()?.id.clone();
+
+        let writer = match &mut recording {
+            Some((current, writer)) if *current == id => writer,
+            _ => {
+                let writer = store.create_recording(&id)?;
+                &mut recording.insert((id, writer)).1
+            }
+        };
+
+        writer.write_all(frame.as_slice())?;
+    }
+
+    if let Some((_, mut writer)) = recording {
+        writer.flush()?;
+    }
+
+    Ok(())
+}
+
+// Splits interleaved frames into one file per microphone, following the shared meeting like record_audio
+pub fn record_channels<S: RecordingStore + ?Sized>(
+    receiver: Receiver<Vec<u8>>,
+    channels: u16,
+    meeting: SharedMeeting,
+    store: &S,
+) -> Result<(), CoreError> {
+    let channels = channels.max(1) as usize;
+    let mut recording: Option<(String, Vec<Box<dyn Write + Send>>)> = None;
+    let mut buffers = vec![vec![]; channels];
+
+    while let Ok(frame) = receiver.recv() {
+        let id = meeting.lock()?.id.clone();
+
+        let writers = match &mut recording {
+            Some((current, writers)) if *current == id => writers,
+            _ => {
+                let writers = (0..channels)
+                    .map(|channel| store.create_channel_recording(&id, channel))
+                    .collect::<Result<Vec<_>, _>>()?;
+
+                &mut recording.insert((id, writers)).1
+            }
+        };
+
+        for buffer in buffers.iter_mut() {
+            buffer.clear();
+        }
+
+        for sample in frame.chunks_exact(channels * 2) {
+            for (channel, buffer) in buffers.iter_mut().enumerate() {
+                buffer.extend_from_slice(&sample[channel * 2..channel * 2 + 2]);
+            }
+        }
+
+        for (writer, buffer) in writers.iter_mut().zip(buffers.iter()) {
+            writer.write_all(buffer)?;
+        }
+    }
+
+    if let Some((_, writers)) = &mut recording {
+        for writer in writers.iter_mut() {
+            writer.flush()?;
+        }
+    }
+
+    Ok(())
+}
+
+#[derive(Debug, Clone)]
+pub enum TranscriptEvent {
+    Partial(Transcription),
+    Final { meeting_id: String, transcription: Transcription },
+}
+
+// Turns realtime API messages into transcripts, final ones are appended to the meeting and persisted right away.
+// The API's offsets only count the audio it received, the timeline puts back the silence the voice gate held back
+pub fn handle_transcript<S: RecordingStore + ?Sized>(
+    response: AssemblyResponse,
+    meeting: &SharedMeeting,
+    store: &S,
+    timeline: &SharedTimeline,
+) -> Result<Option<TranscriptEvent>, CoreError> {
+    let to_audio_ms = |stream_ms: u64| -> Result<u64, CoreError> { Ok(timeline.lock()?.to_audio_ms(stream_ms)) };
+
+    match response {
+        AssemblyResponse::PartialTranscript { text, created, audio_start, audio_end } if text.is_empty() == false => {
+            Ok(Some(TranscriptEvent::Partial(Transcription {
+                text,
+                timestamp: created,
+                audio_start: to_audio_ms(audio_start)?,
+                audio_end: to_audio_ms(audio_end)?,
+            })))
+        }
+        AssemblyResponse::FinalTranscript { text, created, audio_start, audio_end } if text.is_empty() == false => {
+            let transcription = Transcription {
+                text,
+                timestamp: created,
+                audio_start: to_audio_ms(audio_start)?,
+                audio_end: to_audio_ms(audio_end)?,
+            };
+
+            let mut meeting = meeting.lock()?;
+            meeting.transcriptions.push(transcription.clone());
+            keep_meeting(store, &meeting);
+
+            Ok(Some(TranscriptEvent::Final {
+                meeting_id: meeting.id.clone(),
+                transcription,
+            }))
+        }
+        _ => Ok(None),
+    }
+}
+
+pub fn summarize<H: HttpClient, S: RecordingStore + ?Sized>(
+    assembly: &mut Assembly<H>,
+    meeting: &SharedMeeting,
+    store: &S,
+) -> Result<String, CoreError> {
+    let transcriptions = meeting.lock()?.transcriptions.clone();
+    let response = assembly.summarize_transcripts(transcriptions)?;
+
+    info!("summary: {:?}", response);
+
+    let mut meeting = meeting.lock()?;
+    meeting.summary = Some(response.response.clone());
+    keep_meeting(store, &meeting);
+
+    Ok(response.response)
+}
+
+pub fn answer_question<H: HttpClient, S: RecordingStore + ?Sized>(
+    assembly: &mut Assembly<H>,
+    meeting: &SharedMeeting,
+    store: &S,
+    id: String,
+    question: String,
+) -> Result<QuestionAnswer, CoreError> {
+    let transcriptions = meeting.lock()?.transcriptions.clone();
+    let response = assembly.ask_question(question.clone(), transcriptions)?;
+
+    info!("{:?}", response);
+
+    let Some(answer) = response.response.into_iter().next() else {
+        return Err(CoreError::HttpError {
+            status: 200,
+            body: format!("no answer in response {}", response.request_id),
+        });
+    };
+
+    let question_answer = QuestionAnswer {
+        id,
+        question,
+        answer: answer.answer,
+    };
+
+    let mut meeting = meeting.lock()?;
+    meeting.questions.push(question_answer.clone());
+    keep_meeting(store, &meeting);
+
+    Ok(question_answer)
+}
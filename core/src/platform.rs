@@ -0,0 +1,109 @@
+use std::io::{Read, Write};
+use std::time::Duration;
+
+use serde::de::DeserializeOwned;
+
+use crate::error::CoreError;
+use crate::meeting::Meeting;
+
+// Mono 16-bit little endian PCM, the format everything downstream (recording, transcription, monitoring) expects.
+// Two microphone sources hand out interleaved stereo instead, which record_microphone combines before the fan-out
+pub trait AudioSource: Send {
+    fn sample_rate(&self) -> u32;
+
+    fn channels(&self) -> u16 {
+        1
+    }
+
+    // Blocks until the next frame is available, None once a finite source (e.g. a file) is exhausted
+    fn sample(&mut self) -> Result<Option<&[u8]>, CoreError>;
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum DrawState {
+    Initializing,
+    Wifi,
+    Done,
+    QRCode(String),
+    // The QR code with a gauge of how full the card is next to it, once it crosses the warning threshold
+    StorageWarning { content: String, used_percent: u8 },
+    // The QR code with a crossed out SD card next to it, while there is no card to record to
+    NoCard(String),
+    // Lines of text, e.g. the result of a storage maintenance command
+    Text(Vec<String>),
+}
+
+pub trait StatusDisplay {
+    fn draw(&mut self, state: DrawState) -> Result<(), CoreError>;
+}
+
+pub trait RecordingStore: Send + Sync {
+    fn save_meeting(&self, meeting: &Meeting) -> Result<(), CoreError>;
+
+    fn load_meeting(&self, id: &str) -> Result<Meeting, CoreError>;
+
+    // Takes PCM in the same format the AudioSource produced it, and may compress it on the way to storage
+    fn create_recording(&self, id: &str) -> Result<Box<dyn Write + Send>, CoreError>;
+
+    // One microphone of a stereo source before the channels are combined, kept on request for later processing
+    fn create_channel_recording(&self, id: &str, channel: usize) -> Result<Box<dyn Write + Send>, CoreError>;
+
+    // Decoded back to PCM, whichever codec the recording was stored with
+    fn open_recording(&self, id: &str) -> Result<Box<dyn Read + Send>, CoreError>;
+
+    // What the recording takes up in storage, compressed or not
+    fn recording_length(&self, id: &str) -> Result<u64, CoreError>;
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Method {
+    Get,
+    Post,
+}
+
+#[derive(Debug)]
+pub struct HttpResponse {
+    pub status: u16,
+    pub body: Vec<u8>,
+}
+
+impl HttpResponse {
+    pub fn is_success(&self) -> bool {
+        self.status >= 200 && self.status < 300
+    }
+
+    pub fn json<T: DeserializeOwned>(&self) -> Result<T, CoreError> {
+        if self.is_success() == false {
+            return Err(CoreError::HttpError {
+                status: self.status,
+                body: String::from_utf8_lossy(&self.body).to_string(),
+            });
+        }
+
+        Ok(serde_json::from_slice(&self.body)?)
+    }
+}
+
+pub trait HttpClient {
+    fn request(
+        &mut self,
+        method: Method,
+        url: &str,
+        headers: &[(&str, &str)],
+        body: Option<&[u8]>,
+    ) -> Result<HttpResponse, CoreError>;
+}
+
+// Only the sending half, incoming messages are delivered by each implementation through a channel
+pub trait WsClient {
+    fn send_binary(&mut self, data: &[u8]) -> Result<(), CoreError>;
+
+    fn send_text(&mut self, text: &str) -> Result<(), CoreError>;
+}
+
+pub trait Clock: Send + Sync {
+    // Time since the device (or the simulator) started
+    fn uptime(&self) -> Duration;
+
+    fn sleep(&self, duration: Duration);
+}
@@ -1,5 +1,6 @@
-use qrcode_generator::QrCodeEcc;
-use crate::custom_error::CustomError;
+use qrcodegen::{QrCode, QrCodeEcc};
+
+use crate::error::CoreError;
 
 pub struct QRCode {
     matrix: Vec<Vec<bool>>,
@@ -10,9 +11,14 @@ pub struct QRCode {
 }
 
 impl QRCode {
-    pub fn new<T: AsRef<[u8]>>(content: T, width: usize, height: usize, scale: usize, offset: (usize, usize)) -> Result<Self, CustomError> {
+    pub fn new<T: AsRef<[u8]>>(content: T, width: usize, height: usize, scale: usize, offset: (usize, usize)) -> Result<Self, CoreError> {
+        let code = QrCode::encode_binary(content.as_ref(), QrCodeEcc::Low)?;
+        let size = code.size();
+
         Ok(Self {
-            matrix: qrcode_generator::to_matrix(content, QrCodeEcc::Low)?,
+            matrix: (0..size)
+                .map(|y| (0..size).map(|x| code.get_module(x, y)).collect())
+                .collect(),
             scale,
             offset,
             width,
@@ -28,9 +34,9 @@ impl QRCode {
 
         for y in 0..self.height {
             for x in 0..self.width {
-                // Determine corresponding position in the QR code matrix
-                let qr_x = (x / self.scale) - self.offset.0;
-                let qr_y = (y / self.scale) - self.offset.1;
+                // Determine corresponding position in the QR code matrix, pixels left/above the offset wrap around and fall out of bounds
+                let qr_x = (x / self.scale).wrapping_sub(self.offset.0);
+                let qr_y = (y / self.scale).wrapping_sub(self.offset.1);
 
                 // Ensure we stay within bounds of the QR code
                 let pixel_on = if qr_x < qr_width && qr_y < qr_height {
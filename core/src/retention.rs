@@ -0,0 +1,242 @@
+use std::time::Duration;
+
+use log::info;
+use serde::{Deserialize, Serialize};
+
+use crate::error::CoreError;
+use crate::platform::RecordingStore;
+use crate::store::FileStore;
+
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+pub struct StorageUsage {
+    pub total_bytes: u64,
+    pub used_bytes: u64,
+}
+
+impl StorageUsage {
+    pub fn free_bytes(&self) -> u64 {
+        self.total_bytes.saturating_sub(self.used_bytes)
+    }
+
+    pub fn used_percent(&self) -> u8 {
+        match self.total_bytes {
+            0 => 100,
+            total => (self.used_bytes.min(total) * 100 / total) as u8,
+        }
+    }
+}
+
+// Where the meetings are stored. Audio is only recorded to an SD card
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+pub enum StorageMedium {
+    SdCard,
+    InternalFlash,
+    // Nothing is mounted, meetings only live as long as the device is on
+    Memory,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Serialize, Deserialize)]
+pub enum StorageLevel {
+    Ok,
+    Warning,
+    Critical,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+pub enum EvictionReason {
+    MaxAge,
+    MaxTotalSize,
+    LowSpace,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct RetentionPolicy {
+    // Only applied once the clock is set, meetings recorded without one are never too old
+    pub max_age: Option<Duration>,
+    // Of all meetings together, starred ones included
+    pub max_total_bytes: Option<u64>,
+    pub keep_starred: bool,
+    // Free space the oldest meetings make room for whatever the other rules say, so the recording never runs out
+    pub min_free_bytes: u64,
+    pub warning_percent: u8,
+    pub critical_percent: u8,
+}
+
+impl Default for RetentionPolicy {
+    fn default() -> Self {
+        Self {
+            max_age: None,
+            max_total_bytes: None,
+            keep_starred: true,
+            // About a quarter of an hour of uncompressed PCM
+            min_free_bytes: 32 * 1024 * 1024,
+            warning_percent: 80,
+            critical_percent: 95,
+        }
+    }
+}
+
+// What the retention rules need to know about a meeting on the card
+#[derive(Debug, Clone, PartialEq)]
+pub struct MeetingUsage {
+    pub id: String,
+    pub bytes: u64,
+    // Unix seconds, None for meetings recorded before the clock was set
+    pub started_at: Option<u64>,
+    pub starred: bool,
+}
+
+impl RetentionPolicy {
+    pub fn level(&self, usage: &StorageUsage) -> StorageLevel {
+        match usage.used_percent() {
+            percent if percent >= self.critical_percent => StorageLevel::Critical,
+            percent if percent >= self.warning_percent => StorageLevel::Warning,
+            _ => StorageLevel::Ok,
+        }
+    }
+
+    // Meetings to delete and why, oldest first. Meetings without a start time count as the oldest. `protected` ones,
+    // the meeting being recorded and those the outbox still needs, are never evicted
+    pub fn evictions(
+        &self,
+        meetings: &[MeetingUsage],
+        usage: &StorageUsage,
+        protected: &[String],
+        now: Option<u64>,
+    ) -> Vec<(String, EvictionReason)> {
+        let mut candidates = meetings
+            .iter()
+            .filter(|meeting| protected.contains(&meeting.id) == false)
+            .filter(|meeting| (self.keep_starred && meeting.starred) == false)
+            .collect::<Vec<_>>();
+
+        candidates.sort_by_key(|meeting| meeting.started_at);
+
+        let mut evictions = vec![];
+        let mut freed = 0;
+
+        let mut evict = |meeting: &MeetingUsage, reason: EvictionReason, freed: &mut u64| {
+            evictions.push((meeting.id.clone(), reason));
+            *freed += meeting.bytes;
+        };
+
+        if let (Some(max_age), Some(now)) = (self.max_age, now) {
+            let oldest = now.saturating_sub(max_age.as_secs());
+
+            candidates.retain(|meeting| match meeting.started_at {
+                Some(started_at) if started_at < oldest => {
+                    evict(meeting, EvictionReason::MaxAge, &mut freed);
+                    false
+                }
+                _ => true,
+            });
+        }
+
+        let mut remaining = candidates.into_iter();
+
+        if let Some(max_total_bytes) = self.max_total_bytes {
+            let total = meetings.iter().map(|meeting| meeting.bytes).sum::<u64>();
+
+            while total - freed > max_total_bytes {
+                match remaining.next() {
+                    Some(meeting) => evict(meeting, EvictionReason::MaxTotalSize, &mut freed),
+                    None => break,
+                }
+            }
+        }
+
+        while usage.free_bytes() + freed < self.min_free_bytes {
+            match remaining.next() {
+                Some(meeting) => evict(meeting, EvictionReason::LowSpace, &mut freed),
+                None => break,
+            }
+        }
+
+        evictions
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct StorageReport {
+    // Estimated from what was evicted, until the filesystem is asked again
+    pub usage: StorageUsage,
+    pub level: StorageLevel,
+    pub evicted: Vec<String>,
+}
+
+// Applies a RetentionPolicy to the meetings of a FileStore and keeps track of the level, so warnings are only raised
+// when it changes
+pub struct StorageManager {
+    store: FileStore,
+    policy: RetentionPolicy,
+    level: StorageLevel,
+}
+
+impl StorageManager {
+    pub fn new(store: FileStore, policy: RetentionPolicy) -> Self {
+        Self {
+            store,
+            policy,
+            level: StorageLevel::Ok,
+        }
+    }
+
+    pub fn policy(&self) -> &RetentionPolicy {
+        &self.policy
+    }
+
+    pub fn level(&self) -> StorageLevel {
+        self.level
+    }
+
+    pub fn meetings(&self) -> Result<Vec<MeetingUsage>, CoreError> {
+        Ok(self
+            .store
+            .meeting_ids()?
+            .into_iter()
+            .map(|id| {
+                let meeting = self.store.load_meeting(&id).ok();
+
+                MeetingUsage {
+                    bytes: self.store.meeting_size(&id).unwrap_or(0),
+                    started_at: meeting.as_ref().and_then(|meeting| meeting.started_at),
+                    starred: meeting.as_ref().is_some_and(|meeting| meeting.starred),
+                    id,
+                }
+            })
+            .collect())
+    }
+
+    // Evicts whatever the policy asks for, `now` is in unix seconds when the clock is set
+    pub fn check(
+        &mut self,
+        usage: StorageUsage,
+        protected: &[String],
+        now: Option<u64>,
+    ) -> Result<StorageReport, CoreError> {
+        let meetings = self.meetings()?;
+        let mut usage = usage;
+        let mut evicted = vec![];
+
+        for (id, reason) in self.policy.evictions(&meetings, &usage, protected, now) {
+            let bytes = meetings
+                .iter()
+                .find(|meeting| meeting.id == id)
+                .map_or(0, |meeting| meeting.bytes);
+
+            info!("evicting meeting {} ({:?}, {} bytes)", id, reason, bytes);
+
+            self.store.delete_meeting(&id)?;
+            usage.used_bytes = usage.used_bytes.saturating_sub(bytes);
+            evicted.push(id);
+        }
+
+        self.level = self.policy.level(&usage);
+
+        Ok(StorageReport {
+            usage,
+            level: self.level,
+            evicted,
+        })
+    }
+}
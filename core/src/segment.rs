@@ -0,0 +1,442 @@
+use std::fs::File;
+use std::io::{BufReader, BufWriter, ErrorKind, Read, Write};
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+
+use log::warn;
+use serde::{Deserialize, Serialize};
+
+use crate::codec::{RecordingCodec, ADPCM_BLOCK_ALIGN};
+use crate::encryption::{self, StorageKey};
+
+pub const MANIFEST_FILE: &str = "manifest.json";
+pub const SEGMENTS_DIRECTORY: &str = "segments";
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Segment {
+    pub index: usize,
+    // Relative to the meeting directory
+    pub file: String,
+    pub start_sample: u64,
+    pub start_ms: u64,
+    pub samples: u64,
+    pub duration_ms: u64,
+    // Bytes stored in `file` and their CRC-32, as `crc32` prints it. Only final once the segment is complete
+    pub length: u64,
+    pub crc32: String,
+    pub complete: bool,
+}
+
+// Lists the segments of a recording in playing order. A segment is added as soon as it is opened, so after a power
+// loss the one that was being written is still found, just not marked complete
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct SegmentManifest {
+    pub codec: RecordingCodec,
+    pub sample_rate: u32,
+    pub segment_ms: u64,
+    pub segments: Vec<Segment>,
+}
+
+impl SegmentManifest {
+    pub fn new(codec: RecordingCodec, sample_rate: u32, segment_duration: Duration) -> Self {
+        Self {
+            codec,
+            sample_rate,
+            segment_ms: segment_duration.as_millis() as u64,
+            segments: vec![],
+        }
+    }
+
+    pub fn load(path: &Path) -> Result<Self, std::io::Error> {
+        Ok(serde_json::from_reader(BufReader::new(File::open(path)?))?)
+    }
+
+    // Written next to the manifest and renamed over it, so a power loss leaves either the old or the new one
+    pub fn save(&self, path: &Path) -> Result<(), std::io::Error> {
+        let temporary = path.with_extension("json.tmp");
+
+        {
+            let mut writer = BufWriter::new(File::create(&temporary)?);
+            serde_json::to_writer(&mut writer, self)?;
+            writer.flush()?;
+        }
+
+        std::fs::rename(temporary, path)
+    }
+
+    pub fn is_complete(&self) -> bool {
+        self.segments.iter().all(|segment| segment.complete)
+    }
+
+    // Only known once every segment is complete
+    pub fn samples(&self) -> Option<u64> {
+        match self.is_complete() {
+            true => Some(self.segments.iter().map(|segment| segment.samples).sum()),
+            false => None,
+        }
+    }
+
+    // Marks segments that were cut short, by a power loss or a full card, complete with what made it into them. PCM
+    // and ADPCM segments are cut back to whole samples and blocks first, so they still stitch. Encrypted ones are cut
+    // back to whole chunks, which hold whole samples and blocks, and FLAC ones need the `key` to be decoded
+    pub fn recover(&mut self, directory: &Path, key: Option<&StorageKey>) -> Result<(), std::io::Error> {
+        let codec = self.codec;
+        let mut start_sample = 0;
+
+        for segment in self.segments.iter_mut() {
+            if segment.complete == false {
+                let path = directory.join(&segment.file);
+
+                let samples = match std::fs::OpenOptions::new().write(true).open(&path) {
+                    Ok(file) => {
+                        let length = file.metadata()?.len();
+                        let encrypted = encryption::is_encrypted(&path)?;
+
+                        let (whole, plaintext) = match (encrypted, codec) {
+                            (true, _) => {
+                                let whole = encryption::whole_chunks_length(length);
+                                (whole, encryption::plaintext_length(whole))
+                            }
+                            (false, RecordingCodec::Pcm) => (length / 2 * 2, length / 2 * 2),
+                            (false, RecordingCodec::ImaAdpcm) => {
+                                let whole = length / ADPCM_BLOCK_ALIGN as u64 * ADPCM_BLOCK_ALIGN as u64;
+                                (whole, whole)
+                            }
+                            (false, RecordingCodec::Flac) => (length, length),
+                        };
+
+                        file.set_len(whole)?;
+
+                        match codec.pcm_length(plaintext) {
+                            Some(pcm_length) => pcm_length / 2,
+                            None if plaintext == 0 => 0,
+                            None => {
+                                let mut decoder = codec.decoder(encryption::open(&path, key)?);
+                                std::io::copy(&mut decoder, &mut std::io::sink())? / 2
+                            }
+                        }
+                    }
+                    Err(error) if error.kind() == ErrorKind::NotFound => 0,
+                    Err(error) => return Err(error),
+                };
+
+                let (length, crc32) = checksum_file(&path).unwrap_or((0, Crc32::new().hex()));
+
+                segment.samples = samples;
+                segment.duration_ms = samples * 1000 / self.sample_rate as u64;
+                segment.length = length;
+                segment.crc32 = crc32;
+                segment.complete = true;
+            }
+
+            segment.start_sample = start_sample;
+            segment.start_ms = start_sample * 1000 / self.sample_rate as u64;
+            start_sample += segment.samples;
+        }
+
+        Ok(())
+    }
+
+    // Segments that are missing or whose checksum doesn't match, incomplete ones are skipped
+    pub fn verify(&self, directory: &Path) -> Vec<usize> {
+        self.segments
+            .iter()
+            .filter(|segment| segment.complete)
+            .filter(|segment| match checksum_file(&directory.join(&segment.file)) {
+                Ok((length, crc32)) => length != segment.length || crc32 != segment.crc32,
+                Err(_) => true,
+            })
+            .map(|segment| segment.index)
+            .collect()
+    }
+}
+
+const fn crc32_table() -> [u32; 256] {
+    let mut table = [0u32; 256];
+    let mut index = 0;
+
+    while index < 256 {
+        let mut crc = index as u32;
+        let mut bit = 0;
+
+        while bit < 8 {
+            crc = match crc & 1 {
+                1 => (crc >> 1) ^ 0xedb88320,
+                _ => crc >> 1,
+            };
+            bit += 1;
+        }
+
+        table[index] = crc;
+        index += 1;
+    }
+
+    table
+}
+
+const CRC32_TABLE: [u32; 256] = crc32_table();
+
+// The CRC-32 of zip, gzip and PNG
+#[derive(Debug, Clone, Copy)]
+pub struct Crc32(u32);
+
+impl Crc32 {
+    pub fn new() -> Self {
+        Self(0xffffffff)
+    }
+
+    pub fn update(&mut self, bytes: &[u8]) {
+        for byte in bytes {
+            self.0 = CRC32_TABLE[((self.0 ^ *byte as u32) & 0xff) as usize] ^ (self.0 >> 8);
+        }
+    }
+
+    pub fn value(&self) -> u32 {
+        self.0 ^ 0xffffffff
+    }
+
+    pub fn hex(&self) -> String {
+        format!("{:08x}", self.value())
+    }
+}
+
+impl Default for Crc32 {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+// Length and CRC-32 of a file as the manifest records them
+pub fn checksum_file(path: &Path) -> Result<(u64, String), std::io::Error> {
+    let mut file = File::open(path)?;
+    let mut crc32 = Crc32::new();
+    let mut length = 0;
+    let mut buffer = [0u8; 4096];
+
+    loop {
+        match file.read(&mut buffer)? {
+            0 => break,
+            read => {
+                crc32.update(&buffer[..read]);
+                length += read as u64;
+            }
+        }
+    }
+
+    Ok((length, crc32.hex()))
+}
+
+// Checksums what the encoder writes on its way to the file, the encoder owns it so the result is shared. That is what
+// goes into the file, after it was encrypted
+struct ChecksumWriter {
+    file: File,
+    checksum: Arc<Mutex<(u64, Crc32)>>,
+}
+
+impl Write for ChecksumWriter {
+    fn write(&mut self, buf: &[u8]) -> std::io::Result<usize> {
+        let written = self.file.write(buf)?;
+
+        let mut checksum = self
+            .checksum
+            .lock()
+            .map_err(|error| std::io::Error::other(error.to_string()))?;
+        checksum.0 += written as u64;
+        checksum.1.update(&buf[..written]);
+
+        Ok(written)
+    }
+
+    fn flush(&mut self) -> std::io::Result<()> {
+        self.file.flush()
+    }
+}
+
+struct OpenSegment {
+    encoder: Box<dyn Write + Send>,
+    checksum: Arc<Mutex<(u64, Crc32)>>,
+    written: u64,
+}
+
+// Takes PCM like any recording and starts a new file with the codec every `segment_duration`. Segments are cut after
+// whole ADPCM blocks and FLAC frames, so headerless PCM and ADPCM segments can be appended to each other as they are
+pub struct SegmentWriter {
+    directory: PathBuf,
+    manifest: SegmentManifest,
+    segment_bytes: u64,
+    current: Option<OpenSegment>,
+    key: Option<StorageKey>,
+}
+
+impl SegmentWriter {
+    // `directory` is the meeting's, the manifest goes right into it and the segments into SEGMENTS_DIRECTORY
+    pub fn create<P: Into<PathBuf>>(
+        directory: P,
+        codec: RecordingCodec,
+        sample_rate: u32,
+        segment_duration: Duration,
+    ) -> Result<Self, std::io::Error> {
+        Self::create_with_key(directory, codec, sample_rate, segment_duration, None)
+    }
+
+    // Segments are encrypted with `key` when there is one, the manifest stays readable so the card can be checked
+    // without it
+    pub fn create_with_key<P: Into<PathBuf>>(
+        directory: P,
+        codec: RecordingCodec,
+        sample_rate: u32,
+        segment_duration: Duration,
+        key: Option<StorageKey>,
+    ) -> Result<Self, std::io::Error> {
+        let directory = directory.into();
+        let block = codec.block_samples() as u64;
+        let samples = segment_duration.as_millis() as u64 * sample_rate as u64 / 1000;
+
+        std::fs::create_dir_all(directory.join(SEGMENTS_DIRECTORY))?;
+
+        // Created again for the same meeting, e.g. once the card has room again, the recording carries on after the
+        // segments it already has
+        let manifest = match SegmentManifest::load(&directory.join(MANIFEST_FILE)) {
+            Ok(mut manifest) if manifest.codec == codec && manifest.sample_rate == sample_rate => {
+                manifest.recover(&directory, key.as_ref())?;
+                manifest
+            }
+            _ => SegmentManifest::new(codec, sample_rate, segment_duration),
+        };
+
+        let writer = Self {
+            manifest,
+            segment_bytes: (samples / block).max(1) * block * 2,
+            current: None,
+            directory,
+            key,
+        };
+
+        writer.manifest.save(&writer.directory.join(MANIFEST_FILE))?;
+
+        Ok(writer)
+    }
+
+    pub fn manifest(&self) -> &SegmentManifest {
+        &self.manifest
+    }
+
+    fn start_segment(&mut self) -> std::io::Result<()> {
+        let index = self.manifest.segments.len();
+        let start_sample = self
+            .manifest
+            .segments
+            .last()
+            .map_or(0, |segment| segment.start_sample + segment.samples);
+
+        let file = format!(
+            "{}/segment-{:04}.{}",
+            SEGMENTS_DIRECTORY,
+            index,
+            self.manifest.codec.extension()
+        );
+        let checksum = Arc::new(Mutex::new((0, Crc32::new())));
+
+        let writer = ChecksumWriter {
+            file: File::create(self.directory.join(&file))?,
+            checksum: checksum.clone(),
+        };
+
+        self.current = Some(OpenSegment {
+            encoder: self
+                .manifest
+                .codec
+                .encoder(encryption::encrypt(writer, self.key.as_ref())?, self.manifest.sample_rate),
+            checksum,
+            written: 0,
+        });
+
+        self.manifest.segments.push(Segment {
+            index,
+            file,
+            start_sample,
+            start_ms: start_sample * 1000 / self.manifest.sample_rate as u64,
+            samples: 0,
+            duration_ms: 0,
+            length: 0,
+            crc32: String::new(),
+            complete: false,
+        });
+
+        self.manifest.save(&self.directory.join(MANIFEST_FILE))
+    }
+
+    // Dropping the encoder writes out whatever it still buffers, only then the checksum is final
+    fn finish_segment(&mut self) -> std::io::Result<()> {
+        let Some(OpenSegment {
+            encoder,
+            checksum,
+            written,
+        }) = self.current.take()
+        else {
+            return Ok(());
+        };
+
+        drop(encoder);
+
+        let (length, crc32) = *checksum
+            .lock()
+            .map_err(|error| std::io::Error::other(error.to_string()))?;
+        let sample_rate = self.manifest.sample_rate as u64;
+
+        if let Some(segment) = self.manifest.segments.last_mut() {
+            segment.samples = written / 2;
+            segment.duration_ms = segment.samples * 1000 / sample_rate;
+            segment.length = length;
+            segment.crc32 = crc32.hex();
+            segment.complete = true;
+        }
+
+        self.manifest.save(&self.directory.join(MANIFEST_FILE))
+    }
+}
+
+impl Write for SegmentWriter {
+    fn write(&mut self, buf: &[u8]) -> std::io::Result<usize> {
+        if buf.is_empty() {
+            return Ok(0);
+        }
+
+        if self.current.is_none() {
+            self.start_segment()?;
+        }
+
+        let segment_bytes = self.segment_bytes;
+
+        let Some(segment) = self.current.as_mut() else {
+            return Ok(0);
+        };
+
+        let room = (segment_bytes - segment.written) as usize;
+        let written = segment.encoder.write(&buf[..buf.len().min(room)])?;
+
+        segment.written += written as u64;
+
+        if segment.written >= segment_bytes {
+            self.finish_segment()?;
+        }
+
+        Ok(written)
+    }
+
+    fn flush(&mut self) -> std::io::Result<()> {
+        match self.current.as_mut() {
+            Some(segment) => segment.encoder.flush(),
+            None => Ok(()),
+        }
+    }
+}
+
+impl Drop for SegmentWriter {
+    fn drop(&mut self) {
+        if let Err(error) = self.finish_segment() {
+            warn!("failed to finish the last segment: {:?}", error);
+        }
+    }
+}
@@ -0,0 +1,350 @@
+use std::io::{ErrorKind, Read, Write};
+use std::path::PathBuf;
+use std::time::Duration;
+
+use crate::audio::{wav_header, UNKNOWN_WAV_LENGTH};
+use crate::codec::RecordingCodec;
+use crate::convert::{PCM_BITS_PER_SAMPLE, PCM_CHANNELS, PCM_SAMPLE_RATE};
+use crate::encryption::{self, StorageKey};
+use crate::error::CoreError;
+use crate::meeting::Meeting;
+use crate::platform::RecordingStore;
+use crate::segment::{SegmentManifest, SegmentWriter, MANIFEST_FILE};
+
+// Works on anything with a std filesystem: the SD card mounted at /sdcard on the device, or a directory on a laptop
+#[derive(Debug, Clone)]
+pub struct FileStore {
+    root: PathBuf,
+    codec: RecordingCodec,
+    segment_duration: Option<Duration>,
+    key: Option<StorageKey>,
+}
+
+impl FileStore {
+    pub fn new<P: Into<PathBuf>>(root: P) -> Self {
+        Self {
+            root: root.into(),
+            codec: RecordingCodec::Pcm,
+            segment_duration: None,
+            key: None,
+        }
+    }
+
+    // New recordings are written with `codec`, existing ones are read with whatever they were recorded with
+    pub fn with_codec(mut self, codec: RecordingCodec) -> Self {
+        self.codec = codec;
+        self
+    }
+
+    pub fn codec(&self) -> RecordingCodec {
+        self.codec
+    }
+
+    // New recordings are split into files of `duration` listed in a manifest, instead of one file per meeting
+    pub fn with_segments(mut self, duration: Duration) -> Self {
+        self.segment_duration = Some(duration);
+        self
+    }
+
+    pub fn segment_duration(&self) -> Option<Duration> {
+        self.segment_duration
+    }
+
+    // Meetings and recordings are written encrypted with `key`, unencrypted ones written before are still read
+    pub fn with_encryption(mut self, key: StorageKey) -> Self {
+        self.key = Some(key);
+        self
+    }
+
+    pub fn key(&self) -> Option<&StorageKey> {
+        self.key.as_ref()
+    }
+
+    pub fn root(&self) -> &PathBuf {
+        &self.root
+    }
+
+    pub fn meetings_directory(&self) -> PathBuf {
+        self.root.join("meetings")
+    }
+
+    pub fn meeting_directory(&self, id: &str) -> PathBuf {
+        self.meetings_directory().join(id)
+    }
+
+    pub fn recording_path(&self, id: &str) -> PathBuf {
+        self.recording_path_with(id, self.codec)
+    }
+
+    pub fn recording_path_with(&self, id: &str, codec: RecordingCodec) -> PathBuf {
+        self.meeting_directory(id).join(format!("recording.{}", codec.extension()))
+    }
+
+    pub fn channel_recording_path(&self, id: &str, channel: usize) -> PathBuf {
+        self.meeting_directory(id).join(format!("channel-{}.{}", channel, self.codec.extension()))
+    }
+
+    pub fn manifest_path(&self, id: &str) -> PathBuf {
+        self.meeting_directory(id).join(MANIFEST_FILE)
+    }
+
+    pub fn load_manifest(&self, id: &str) -> Result<SegmentManifest, CoreError> {
+        Ok(SegmentManifest::load(&self.manifest_path(Self::checked_id(id)?))?)
+    }
+
+    // Segmented or not, the files of a meeting's recording in the order they are played
+    pub fn stored_recording(&self, id: &str) -> Option<StoredRecording> {
+        if let Ok(manifest) = self.load_manifest(id) {
+            let directory = self.meeting_directory(id);
+
+            return Some(StoredRecording {
+                codec: manifest.codec,
+                files: manifest.segments.iter().map(|segment| directory.join(&segment.file)).collect(),
+                samples: manifest.samples(),
+                key: self.key.clone(),
+            });
+        }
+
+        self.find_recording(id).map(|(codec, path)| StoredRecording {
+            codec,
+            files: vec![path],
+            samples: None,
+            key: self.key.clone(),
+        })
+    }
+
+    // A single segment of a segmented recording, on its own
+    pub fn stored_segment(&self, id: &str, index: usize) -> Option<StoredRecording> {
+        let manifest = self.load_manifest(id).ok()?;
+        let segment = manifest.segments.get(index)?;
+
+        Some(StoredRecording {
+            codec: manifest.codec,
+            files: vec![self.meeting_directory(id).join(&segment.file)],
+            samples: segment.complete.then_some(segment.samples),
+            key: self.key.clone(),
+        })
+    }
+
+    pub fn meeting_ids(&self) -> Result<Vec<String>, CoreError> {
+        let entries = match std::fs::read_dir(self.meetings_directory()) {
+            Ok(entries) => entries,
+            Err(error) if error.kind() == ErrorKind::NotFound => return Ok(vec![]),
+            Err(error) => return Err(error.into()),
+        };
+
+        let mut ids = vec![];
+
+        for entry in entries {
+            let entry = entry?;
+
+            if let (true, Some(id)) = (entry.file_type()?.is_dir(), entry.file_name().to_str()) {
+                if Meeting::is_valid_id(id) {
+                    ids.push(id.to_string());
+                }
+            }
+        }
+
+        Ok(ids)
+    }
+
+    // Everything stored for a meeting: the meeting itself, its recording and segments, kept channels
+    pub fn meeting_size(&self, id: &str) -> Result<u64, CoreError> {
+        fn size(path: &std::path::Path) -> std::io::Result<u64> {
+            let metadata = std::fs::metadata(path)?;
+
+            if metadata.is_dir() == false {
+                return Ok(metadata.len());
+            }
+
+            std::fs::read_dir(path)?.try_fold(0, |total, entry| Ok(total + size(&entry?.path())?))
+        }
+
+        Ok(size(&self.meeting_directory(Self::checked_id(id)?))?)
+    }
+
+    pub fn delete_meeting(&self, id: &str) -> Result<(), CoreError> {
+        Ok(std::fs::remove_dir_all(self.meeting_directory(Self::checked_id(id)?))?)
+    }
+
+    // The single file recording of a meeting and the codec it was recorded with, which may not be the current one
+    pub fn find_recording(&self, id: &str) -> Option<(RecordingCodec, PathBuf)> {
+        let id = Self::checked_id(id).ok()?;
+
+        std::iter::once(self.codec)
+            .chain(RecordingCodec::ALL)
+            .map(|codec| (codec, self.recording_path_with(id, codec)))
+            .find(|(_, path)| path.is_file())
+    }
+
+    fn existing_recording(&self, id: &str) -> Result<StoredRecording, CoreError> {
+        self.stored_recording(id)
+            .ok_or_else(|| CoreError::IOError(std::io::Error::new(ErrorKind::NotFound, format!("no recording of {}", id))))
+    }
+
+    fn checked_id(id: &str) -> Result<&str, CoreError> {
+        match Meeting::is_valid_id(id) {
+            true => Ok(id),
+            false => Err(CoreError::MeetingNotFound(id.to_string())),
+        }
+    }
+}
+
+impl RecordingStore for FileStore {
+    fn save_meeting(&self, meeting: &Meeting) -> Result<(), CoreError> {
+        let directory = self.meeting_directory(Self::checked_id(&meeting.id)?);
+
+        std::fs::create_dir_all(&directory)?;
+
+        Ok(encryption::write(
+            &directory.join("meeting.json"),
+            self.key.as_ref(),
+            &serde_json::to_vec(meeting)?,
+        )?)
+    }
+
+    fn load_meeting(&self, id: &str) -> Result<Meeting, CoreError> {
+        let path = self.meeting_directory(Self::checked_id(id)?).join("meeting.json");
+
+        let bytes = match encryption::read(&path, self.key.as_ref()) {
+            Ok(bytes) => bytes,
+            Err(error) if error.kind() == ErrorKind::NotFound => return Err(CoreError::MeetingNotFound(id.to_string())),
+            Err(error) => return Err(error.into()),
+        };
+
+        Ok(serde_json::from_slice(&bytes)?)
+    }
+
+    fn create_recording(&self, id: &str) -> Result<Box<dyn Write + Send>, CoreError> {
+        let directory = self.meeting_directory(Self::checked_id(id)?);
+
+        if let Some(duration) = self.segment_duration {
+            return Ok(Box::new(SegmentWriter::create_with_key(
+                directory,
+                self.codec,
+                PCM_SAMPLE_RATE,
+                duration,
+                self.key.clone(),
+            )?));
+        }
+
+        std::fs::create_dir_all(directory)?;
+
+        let file = encryption::create(&self.recording_path(id), self.key.as_ref())?;
+
+        Ok(self.codec.encoder(file, PCM_SAMPLE_RATE))
+    }
+
+    fn create_channel_recording(&self, id: &str, channel: usize) -> Result<Box<dyn Write + Send>, CoreError> {
+        std::fs::create_dir_all(self.meeting_directory(Self::checked_id(id)?))?;
+
+        let file = encryption::create(&self.channel_recording_path(id, channel), self.key.as_ref())?;
+
+        Ok(self.codec.encoder(file, PCM_SAMPLE_RATE))
+    }
+
+    fn open_recording(&self, id: &str) -> Result<Box<dyn Read + Send>, CoreError> {
+        let recording = self.existing_recording(id)?;
+
+        recording.decode(&recording.lengths()?)
+    }
+
+    fn recording_length(&self, id: &str) -> Result<u64, CoreError> {
+        Ok(self.existing_recording(id)?.lengths()?.iter().sum())
+    }
+}
+
+// The files a recording is stored in, in playing order: just the one, or the segments listed in its manifest
+#[derive(Debug, Clone, PartialEq)]
+pub struct StoredRecording {
+    pub codec: RecordingCodec,
+    pub files: Vec<PathBuf>,
+    // Known once every segment is complete
+    pub samples: Option<u64>,
+    // Of the store the recording is read from, the files themselves may or may not be encrypted
+    pub key: Option<StorageKey>,
+}
+
+// One stream of a whole recording, `header` goes in front of what `reader` returns
+pub struct RecordingDownload {
+    pub codec: RecordingCodec,
+    pub header: Vec<u8>,
+    // Without the header, None while a decoded recording's length isn't known yet
+    pub length: Option<u64>,
+    pub reader: Box<dyn Read + Send>,
+}
+
+impl StoredRecording {
+    // Headerless PCM and ADPCM cut after whole blocks can be appended to each other, FLAC streams can't
+    pub fn is_stitchable(&self) -> bool {
+        self.files.len() < 2 || self.codec != RecordingCodec::Flac
+    }
+
+    // Taken once up front, so a recording that is still growing is read consistently. Of what the files hold, after
+    // decrypting them
+    pub fn lengths(&self) -> Result<Vec<u64>, CoreError> {
+        Ok(self
+            .files
+            .iter()
+            .map(|path| encryption::readable_length(path))
+            .collect::<Result<Vec<_>, _>>()?)
+    }
+
+    // The stored bytes of every file, decrypted, one after the other
+    pub fn open(&self, lengths: &[u64]) -> Result<Box<dyn Read + Send>, CoreError> {
+        let mut reader: Box<dyn Read + Send> = Box::new(std::io::empty());
+
+        for (path, length) in self.files.iter().zip(lengths) {
+            reader = Box::new(reader.chain(encryption::open(path, self.key.as_ref())?.take(*length)));
+        }
+
+        Ok(reader)
+    }
+
+    // Decoded to PCM, file after file
+    pub fn decode(&self, lengths: &[u64]) -> Result<Box<dyn Read + Send>, CoreError> {
+        let mut reader: Box<dyn Read + Send> = Box::new(std::io::empty());
+
+        for (path, length) in self.files.iter().zip(lengths) {
+            let file = encryption::open(path, self.key.as_ref())?.take(*length);
+            reader = Box::new(reader.chain(self.codec.decoder(file)));
+        }
+
+        Ok(reader)
+    }
+
+    pub fn pcm_length(&self, lengths: &[u64]) -> Option<u64> {
+        match (self.samples, self.is_stitchable()) {
+            (Some(samples), _) => Some(samples * 2),
+            (None, true) => self.codec.pcm_length(lengths.iter().sum()),
+            (None, false) => None,
+        }
+    }
+
+    // In the codec it was stored with, or decoded into a PCM WAV when asked to or when the files can't be stitched
+    pub fn download(&self, lengths: &[u64], decode: bool, sample_rate: u32) -> Result<RecordingDownload, CoreError> {
+        match (decode && self.codec != RecordingCodec::Pcm) || self.is_stitchable() == false {
+            true => {
+                let length = self.pcm_length(lengths);
+                let data_length = length.map_or(UNKNOWN_WAV_LENGTH, |length| length as u32);
+
+                Ok(RecordingDownload {
+                    codec: RecordingCodec::Pcm,
+                    header: wav_header(data_length, sample_rate, PCM_CHANNELS, PCM_BITS_PER_SAMPLE).to_vec(),
+                    length,
+                    reader: self.decode(lengths)?,
+                })
+            }
+            false => {
+                let length = lengths.iter().sum();
+
+                Ok(RecordingDownload {
+                    codec: self.codec,
+                    header: self.codec.header(length, sample_rate),
+                    length: Some(length),
+                    reader: self.open(lengths)?,
+                })
+            }
+        }
+    }
+}
@@ -0,0 +1,83 @@
+use std::path::Path;
+
+use log::warn;
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Escape {
+    Html,
+    None,
+}
+
+pub struct Template {
+    source: String,
+    escape: Escape,
+}
+
+// Escapes text for use in html content and quoted attribute values
+pub fn escape_html(value: &str) -> String {
+    let mut output = String::with_capacity(value.len());
+
+    for character in value.chars() {
+        match character {
+            '&' => output.push_str("&amp;"),
+            '<' => output.push_str("&lt;"),
+            '>' => output.push_str("&gt;"),
+            '"' => output.push_str("&quot;"),
+            '\'' => output.push_str("&#39;"),
+            _ => output.push(character),
+        }
+    }
+
+    output
+}
+
+impl Template {
+    // A template at `path` takes precedence over `default`, so branding can be customized without a new firmware
+    pub fn load<P: AsRef<Path>>(path: P, default: &str, escape: Escape) -> Self {
+        let source = match std::fs::read_to_string(path) {
+            Ok(source) => source,
+            Err(_) => default.to_string(),
+        };
+
+        Self { source, escape }
+    }
+
+    pub fn from_str(source: &str, escape: Escape) -> Self {
+        Self {
+            source: source.to_string(),
+            escape,
+        }
+    }
+
+    // `{{ key }}` is escaped according to the template, `{{{ key }}}` is inserted as is and must only be used
+    // for fragments that were built from already escaped values
+    pub fn render(&self, variables: &[(&str, &str)]) -> String {
+        let mut output = String::with_capacity(self.source.len());
+        let mut rest = self.source.as_str();
+
+        while let Some(start) = rest.find("{{") {
+            output.push_str(&rest[..start]);
+
+            let raw = rest[start..].starts_with("{{{");
+            let (open, close) = if raw { ("{{{", "}}}") } else { ("{{", "}}") };
+
+            let Some(end) = rest[start + open.len()..].find(close) else {
+                rest = &rest[start..];
+                break;
+            };
+
+            let key = rest[start + open.len()..start + open.len() + end].trim();
+
+            match variables.iter().find(|(name, _)| *name == key) {
+                Some((_, value)) if raw || self.escape == Escape::None => output.push_str(value),
+                Some((_, value)) => output.push_str(&escape_html(value)),
+                None => warn!("template variable {} is not defined", key),
+            }
+
+            rest = &rest[start + open.len() + end + close.len()..];
+        }
+
+        output.push_str(rest);
+        output
+    }
+}
@@ -0,0 +1,378 @@
+use std::collections::VecDeque;
+use std::sync::{Arc, Mutex};
+
+use crate::meeting::{Meeting, SilentRegion, TalkTime};
+
+pub type SharedTimeline = Arc<Mutex<Timeline>>;
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct VadConfig {
+    // Nothing quieter than this counts as speech, however quiet the room is
+    pub threshold_dbfs: f32,
+    // Speech has to be this much louder than the tracked noise floor, which follows the AGC lifting quiet rooms
+    pub margin_db: f32,
+    // Frames crossing zero more often than this are noise-like (hiss, fans) and need another 6 dB to count as speech
+    pub max_zero_crossing_rate: f32,
+    // Keeps the detector active after the last speech frame, so pauses between words don't cut the stream
+    pub hangover_ms: u64,
+    // When false everything is streamed and the detector only feeds the talk time statistics
+    pub pause_streaming: bool,
+    // While paused a short burst of digital silence is sent this often, so the realtime session isn't closed as idle
+    pub keep_alive_ms: u64,
+    // Audio from right before speech resumes that is sent along, the detector needs a frame or two to react
+    pub pre_roll_ms: u64,
+    // Shorter pauses are not worth listing in the meeting's silent regions
+    pub min_silent_region_ms: u64,
+}
+
+impl Default for VadConfig {
+    fn default() -> Self {
+        Self {
+            threshold_dbfs: -55.0,
+            margin_db: 10.0,
+            max_zero_crossing_rate: 0.35,
+            hangover_ms: 1000,
+            pause_streaming: true,
+            keep_alive_ms: 5000,
+            pre_roll_ms: 300,
+            min_silent_region_ms: 2000,
+        }
+    }
+}
+
+// Energy and zero crossing detector with a noise floor that drops immediately and rises at most 6 dB per second
+pub struct Vad {
+    sample_rate: u32,
+    threshold: f32,
+    margin: f32,
+    max_zero_crossing_rate: f32,
+    hangover_samples: u64,
+    noise_floor: f32,
+    silent_samples: u64,
+    active: bool,
+}
+
+impl Vad {
+    const NOISE_FLOOR_RISE_DB_PER_SECOND: f32 = 6.0;
+    const NOISE_LIKE_PENALTY_DB: f32 = 6.0;
+
+    pub fn new(sample_rate: u32, config: &VadConfig) -> Self {
+        let threshold = from_dbfs(config.threshold_dbfs);
+
+        Self {
+            sample_rate,
+            threshold,
+            margin: from_db(config.margin_db),
+            max_zero_crossing_rate: config.max_zero_crossing_rate,
+            hangover_samples: config.hangover_ms * sample_rate as u64 / 1000,
+            noise_floor: threshold,
+            silent_samples: 0,
+            active: false,
+        }
+    }
+
+    pub fn is_active(&self) -> bool {
+        self.active
+    }
+
+    pub fn noise_floor_dbfs(&self) -> f32 {
+        20.0 * (self.noise_floor / i16::MAX as f32).log10()
+    }
+
+    // Classifies a frame, including the hangover, so it stays true for a while after the last speech frame
+    pub fn is_speech(&mut self, samples: &[i16]) -> bool {
+        if samples.is_empty() {
+            return self.active;
+        }
+
+        let rms = rms(samples);
+        let mut threshold = self.threshold.max(self.noise_floor * self.margin);
+
+        if zero_crossing_rate(samples) > self.max_zero_crossing_rate {
+            threshold *= from_db(Self::NOISE_LIKE_PENALTY_DB);
+        }
+
+        let seconds = samples.len() as f32 / self.sample_rate as f32;
+        let rise = from_db(Self::NOISE_FLOOR_RISE_DB_PER_SECOND * seconds);
+
+        self.noise_floor = rms.max(1.0).min(self.noise_floor * rise);
+
+        if rms >= threshold {
+            self.active = true;
+            self.silent_samples = 0;
+        } else {
+            self.silent_samples += samples.len() as u64;
+            self.active = self.active && self.silent_samples <= self.hangover_samples;
+        }
+
+        self.active
+    }
+}
+
+#[derive(Debug, Clone, Copy)]
+struct Segment {
+    stream: u64,
+    audio: u64,
+}
+
+// Maps positions in what was streamed back to positions in the microphone audio, since paused silence is never sent
+// and the realtime API only knows about the audio it received
+#[derive(Debug)]
+pub struct Timeline {
+    sample_rate: u32,
+    segments: Vec<Segment>,
+    streamed: u64,
+    audio_end: Option<u64>,
+}
+
+impl Timeline {
+    pub fn new(sample_rate: u32) -> Self {
+        Self {
+            sample_rate,
+            segments: vec![],
+            streamed: 0,
+            audio_end: None,
+        }
+    }
+
+    pub fn shared(sample_rate: u32) -> SharedTimeline {
+        Arc::new(Mutex::new(Self::new(sample_rate)))
+    }
+
+    fn record(&mut self, audio: u64, samples: u64) {
+        if self.audio_end != Some(audio) {
+            self.segments.push(Segment { stream: self.streamed, audio });
+        }
+
+        self.streamed += samples;
+        self.audio_end = Some(audio + samples);
+    }
+
+    // Keep-alive silence isn't part of the microphone audio, it is pinned to where it was sent
+    fn record_keep_alive(&mut self, audio: u64, samples: u64) {
+        self.segments.push(Segment { stream: self.streamed, audio });
+        self.streamed += samples;
+        self.audio_end = None;
+    }
+
+    pub fn streamed_ms(&self) -> u64 {
+        self.streamed * 1000 / self.sample_rate as u64
+    }
+
+    pub fn to_audio_ms(&self, stream_ms: u64) -> u64 {
+        let stream = stream_ms * self.sample_rate as u64 / 1000;
+        let index = self.segments.partition_point(|segment| segment.stream <= stream);
+
+        let Some(segment) = index.checked_sub(1).map(|index| self.segments[index]) else {
+            return stream_ms;
+        };
+
+        (segment.audio + stream - segment.stream) * 1000 / self.sample_rate as u64
+    }
+}
+
+// Sits in front of the realtime websocket and decides which frames are worth paying for
+pub struct VoiceGate {
+    vad: Vad,
+    config: VadConfig,
+    sample_rate: u32,
+    timeline: SharedTimeline,
+    pre_roll: VecDeque<(u64, Vec<u8>)>,
+    position: u64,
+    since_sent: u64,
+    speaking: bool,
+    silence_start: Option<u64>,
+    pending_regions: Vec<SilentRegion>,
+    // Counted in samples, so rounding doesn't add up over hours of 31 ms frames
+    speech_samples: u64,
+    silence_samples: u64,
+    streamed_samples: u64,
+}
+
+impl VoiceGate {
+    // 100 ms, the shortest chunk the realtime API accepts
+    const KEEP_ALIVE_MS: u64 = 100;
+    // Talk time is handed to the meeting at least this often, besides whenever a silent region ends
+    const UPDATE_INTERVAL_MS: u64 = 5000;
+
+    pub fn new(sample_rate: u32, config: VadConfig) -> Self {
+        Self {
+            vad: Vad::new(sample_rate, &config),
+            config,
+            sample_rate,
+            timeline: Timeline::shared(sample_rate),
+            pre_roll: VecDeque::new(),
+            position: 0,
+            since_sent: 0,
+            speaking: false,
+            silence_start: Some(0),
+            pending_regions: vec![],
+            speech_samples: 0,
+            silence_samples: 0,
+            streamed_samples: 0,
+        }
+    }
+
+    pub fn timeline(&self) -> SharedTimeline {
+        self.timeline.clone()
+    }
+
+    pub fn is_streaming(&self) -> bool {
+        self.speaking || self.config.pause_streaming == false
+    }
+
+    // Takes a frame of little endian PCM and returns what should go out on the websocket, possibly nothing
+    pub fn process(&mut self, frame: &[u8]) -> Vec<Vec<u8>> {
+        let samples = frame
+            .chunks_exact(2)
+            .map(|sample| i16::from_le_bytes([sample[0], sample[1]]))
+            .collect::<Vec<_>>();
+
+        let start = self.position;
+        let length = samples.len() as u64;
+        let speech = self.vad.is_speech(&samples);
+
+        self.position += length;
+
+        match speech {
+            true => self.speech_samples += length,
+            false => self.silence_samples += length,
+        }
+
+        if speech && self.speaking == false {
+            let resumed_at = self.pre_roll.front().map(|(position, _)| *position).unwrap_or(start);
+            self.close_silent_region(resumed_at);
+        } else if speech == false && self.speaking {
+            self.silence_start = Some(start);
+        }
+
+        self.speaking = speech;
+
+        if speech || self.config.pause_streaming == false {
+            let mut frames = vec![];
+
+            for (position, frame) in std::mem::take(&mut self.pre_roll) {
+                frames.push(self.send(position, frame));
+            }
+
+            frames.push(self.send(start, frame.to_vec()));
+
+            return frames;
+        }
+
+        self.pre_roll.push_back((start, frame.to_vec()));
+
+        let pre_roll_samples = self.config.pre_roll_ms * self.sample_rate as u64 / 1000;
+
+        while self.pre_roll.iter().map(|(_, frame)| frame.len() as u64 / 2).sum::<u64>() > pre_roll_samples {
+            self.pre_roll.pop_front();
+        }
+
+        self.since_sent += length;
+
+        if self.since_sent < self.config.keep_alive_ms * self.sample_rate as u64 / 1000 {
+            return vec![];
+        }
+
+        let keep_alive_samples = Self::KEEP_ALIVE_MS * self.sample_rate as u64 / 1000;
+
+        if let Ok(mut timeline) = self.timeline.lock() {
+            timeline.record_keep_alive(start, keep_alive_samples);
+        }
+
+        self.since_sent = 0;
+        self.streamed_samples += keep_alive_samples;
+
+        vec![vec![0u8; keep_alive_samples as usize * 2]]
+    }
+
+    // Closes a silence that lasts until the end of the audio
+    pub fn finish(&mut self) {
+        if self.speaking == false {
+            self.close_silent_region(self.position);
+        }
+    }
+
+    pub fn is_due(&self) -> bool {
+        self.pending_regions.is_empty() == false
+            || self.to_ms(self.speech_samples + self.silence_samples) >= Self::UPDATE_INTERVAL_MS
+    }
+
+    // Adds everything since the last call to the meeting, whichever meeting is current at that point. True when a
+    // silent region was added, which is worth saving the meeting for
+    pub fn update(&mut self, meeting: &mut Meeting) -> bool {
+        let regions = self.pending_regions.is_empty() == false;
+
+        meeting.silent_regions.append(&mut self.pending_regions);
+        meeting.talk_time.add(&TalkTime {
+            speech_ms: Self::take_ms(&mut self.speech_samples, self.sample_rate),
+            silence_ms: Self::take_ms(&mut self.silence_samples, self.sample_rate),
+            streamed_ms: Self::take_ms(&mut self.streamed_samples, self.sample_rate),
+        });
+
+        regions
+    }
+
+    fn send(&mut self, position: u64, frame: Vec<u8>) -> Vec<u8> {
+        let length = frame.len() as u64 / 2;
+
+        if let Ok(mut timeline) = self.timeline.lock() {
+            timeline.record(position, length);
+        }
+
+        self.since_sent = 0;
+        self.streamed_samples += length;
+
+        frame
+    }
+
+    fn close_silent_region(&mut self, end: u64) {
+        let Some(start) = self.silence_start.take() else {
+            return;
+        };
+
+        let region = SilentRegion {
+            start_ms: self.to_ms(start),
+            end_ms: self.to_ms(end),
+        };
+
+        if region.end_ms - region.start_ms >= self.config.min_silent_region_ms {
+            self.pending_regions.push(region);
+        }
+    }
+
+    // Leaves the remainder of a millisecond behind for the next update
+    fn take_ms(samples: &mut u64, sample_rate: u32) -> u64 {
+        let milliseconds = *samples * 1000 / sample_rate as u64;
+        *samples -= milliseconds * sample_rate as u64 / 1000;
+
+        milliseconds
+    }
+
+    fn to_ms(&self, samples: u64) -> u64 {
+        samples * 1000 / self.sample_rate as u64
+    }
+}
+
+fn rms(samples: &[i16]) -> f32 {
+    let sum = samples.iter().map(|sample| (*sample as f32).powi(2)).sum::<f32>();
+
+    (sum / samples.len() as f32).sqrt()
+}
+
+fn zero_crossing_rate(samples: &[i16]) -> f32 {
+    let crossings = samples
+        .windows(2)
+        .filter(|pair| (pair[0] >= 0) != (pair[1] >= 0))
+        .count();
+
+    crossings as f32 / samples.len().max(2).saturating_sub(1) as f32
+}
+
+fn from_db(db: f32) -> f32 {
+    10f32.powf(db / 20.0)
+}
+
+fn from_dbfs(dbfs: f32) -> f32 {
+    from_db(dbfs) * i16::MAX as f32
+}
@@ -0,0 +1,238 @@
+mod common;
+
+use std::f32::consts::PI;
+use std::io::{Read, Write};
+use std::sync::{Arc, Mutex};
+
+use echosense_core::codec::{
+    adpcm_samples, adpcm_wav_header, AdpcmDecoder, AdpcmEncoder, FlacDecoder, FlacEncoder, RecordingCodec,
+    ADPCM_BLOCK_ALIGN, ADPCM_SAMPLES_PER_BLOCK,
+};
+use echosense_core::platform::RecordingStore;
+use echosense_core::store::FileStore;
+
+use common::{read_all, record, temporary_directory};
+
+// A vowel-like tone with some noise on top, compresses about as well as speech does
+fn speech_like(samples: usize) -> Vec<i16> {
+    let mut state = 7u32;
+
+    (0..samples)
+        .map(|index| {
+            let time = index as f32 / 16000.0;
+            state = state.wrapping_mul(1664525).wrapping_add(1013904223);
+
+            let noise = ((state >> 16) as f32 / 65535.0 - 0.5) * 200.0;
+            let voice = 6000.0 * (2.0 * PI * 180.0 * time).sin() + 2500.0 * (2.0 * PI * 720.0 * time).sin();
+
+            (voice * (0.6 + 0.4 * (2.0 * PI * 3.0 * time).sin()) + noise) as i16
+        })
+        .collect()
+}
+
+fn to_bytes(samples: &[i16]) -> Vec<u8> {
+    samples.iter().flat_map(|sample| sample.to_le_bytes()).collect()
+}
+
+fn to_samples(bytes: &[u8]) -> Vec<i16> {
+    bytes.chunks_exact(2).map(|sample| i16::from_le_bytes([sample[0], sample[1]])).collect()
+}
+
+fn encode(codec: RecordingCodec, pcm: &[u8]) -> Vec<u8> {
+    let buffer = Arc::new(Mutex::new(vec![]));
+
+    {
+        let mut encoder = codec.encoder(SharedBuffer(buffer.clone()), 16000);
+
+        // Odd sized writes, so samples split across writes are exercised
+        for chunk in pcm.chunks(333) {
+            encoder.write_all(chunk).unwrap();
+        }
+
+        encoder.flush().unwrap();
+    }
+
+    let encoded = buffer.lock().unwrap().clone();
+    encoded
+}
+
+fn decode(codec: RecordingCodec, encoded: Vec<u8>) -> Vec<u8> {
+    let mut decoded = vec![];
+    codec.decoder(std::io::Cursor::new(encoded)).read_to_end(&mut decoded).unwrap();
+
+    decoded
+}
+
+// The encoders own their writer, this lets the test look at what was written once they are dropped
+#[derive(Clone)]
+struct SharedBuffer(Arc<Mutex<Vec<u8>>>);
+
+impl Write for SharedBuffer {
+    fn write(&mut self, buf: &[u8]) -> std::io::Result<usize> {
+        self.0.lock().unwrap().extend_from_slice(buf);
+        Ok(buf.len())
+    }
+
+    fn flush(&mut self) -> std::io::Result<()> {
+        Ok(())
+    }
+}
+
+#[test]
+fn codec_names() {
+    assert_eq!(RecordingCodec::from_name("PCM"), Some(RecordingCodec::Pcm));
+    assert_eq!(RecordingCodec::from_name("adpcm"), Some(RecordingCodec::ImaAdpcm));
+    assert_eq!(RecordingCodec::from_name("ima-adpcm"), Some(RecordingCodec::ImaAdpcm));
+    assert_eq!(RecordingCodec::from_name("flac"), Some(RecordingCodec::Flac));
+    assert_eq!(RecordingCodec::from_name("mp3"), None);
+}
+
+#[test]
+fn adpcm_is_a_quarter_of_the_size_and_close_to_the_original() {
+    // Three full blocks and a shorter one
+    let samples = speech_like(ADPCM_SAMPLES_PER_BLOCK * 3 + 101);
+    let encoded = encode(RecordingCodec::ImaAdpcm, &to_bytes(&samples));
+
+    assert_eq!(encoded.len(), ADPCM_BLOCK_ALIGN * 3 + 4 + 50);
+    assert_eq!(adpcm_samples(encoded.len()), samples.len());
+
+    let decoded = to_samples(&decode(RecordingCodec::ImaAdpcm, encoded));
+
+    assert_eq!(decoded.len(), samples.len());
+
+    let signal = samples.iter().map(|sample| (*sample as f64).powi(2)).sum::<f64>();
+    let error = samples
+        .iter()
+        .zip(decoded.iter())
+        .map(|(sample, decoded)| (*sample as f64 - *decoded as f64).powi(2))
+        .sum::<f64>();
+
+    // IMA ADPCM manages a little over 20 dB on speech
+    assert!(10.0 * (signal / error).log10() > 20.0);
+
+    // Every block starts with its first sample as is
+    assert_eq!(decoded[ADPCM_SAMPLES_PER_BLOCK], samples[ADPCM_SAMPLES_PER_BLOCK]);
+}
+
+#[test]
+fn adpcm_wav_header_describes_the_blocks() {
+    let header = adpcm_wav_header(ADPCM_BLOCK_ALIGN as u32 * 10, 16000);
+
+    assert_eq!(&header[0..4], b"RIFF");
+    assert_eq!(u32::from_le_bytes(header[4..8].try_into().unwrap()), 52 + 2560);
+    assert_eq!(u16::from_le_bytes([header[20], header[21]]), 0x0011);
+    assert_eq!(u16::from_le_bytes([header[32], header[33]]) as usize, ADPCM_BLOCK_ALIGN);
+    assert_eq!(u16::from_le_bytes([header[38], header[39]]) as usize, ADPCM_SAMPLES_PER_BLOCK);
+    assert_eq!(u32::from_le_bytes(header[48..52].try_into().unwrap()) as usize, ADPCM_SAMPLES_PER_BLOCK * 10);
+    assert_eq!(&header[52..56], b"data");
+    assert_eq!(u32::from_le_bytes(header[56..60].try_into().unwrap()), 2560);
+}
+
+#[test]
+fn adpcm_encoder_writes_the_last_block_when_dropped() {
+    let buffer = Arc::new(Mutex::new(vec![]));
+
+    {
+        let mut encoder = AdpcmEncoder::new(SharedBuffer(buffer.clone()));
+        encoder.write_all(&to_bytes(&speech_like(11))).unwrap();
+        encoder.flush().unwrap();
+
+        // A flush in the middle of a block doesn't cut it short
+        assert!(buffer.lock().unwrap().is_empty());
+    }
+
+    let encoded = buffer.lock().unwrap().clone();
+    let mut decoded = vec![];
+    AdpcmDecoder::new(encoded.as_slice()).read_to_end(&mut decoded).unwrap();
+
+    assert_eq!(encoded.len(), 4 + 5);
+    assert_eq!(decoded.len(), 11 * 2);
+}
+
+#[test]
+fn flac_is_lossless() {
+    for length in [0, 1, 5, 4096, 4096 * 3 + 17] {
+        let pcm = to_bytes(&speech_like(length));
+        let encoded = encode(RecordingCodec::Flac, &pcm);
+
+        assert_eq!(&encoded[0..4], b"fLaC");
+        assert_eq!(decode(RecordingCodec::Flac, encoded), pcm, "{} samples", length);
+    }
+}
+
+#[test]
+fn flac_compresses_speech_and_silence() {
+    let pcm = to_bytes(&speech_like(16000 * 5));
+    let encoded = encode(RecordingCodec::Flac, &pcm);
+
+    assert!(encoded.len() < pcm.len() * 2 / 3, "{} of {} bytes", encoded.len(), pcm.len());
+
+    // Digital silence ends up as a handful of bytes per frame
+    let silence = vec![0u8; 16000 * 2 * 5];
+    let encoded = encode(RecordingCodec::Flac, &silence);
+
+    assert!(encoded.len() < 500);
+    assert_eq!(decode(RecordingCodec::Flac, encoded), silence);
+}
+
+#[test]
+fn flac_falls_back_to_verbatim_for_noise() {
+    let mut state = 3u32;
+    let noise = (0..8192)
+        .map(|_| {
+            state = state.wrapping_mul(1664525).wrapping_add(1013904223);
+            (state >> 16) as i16
+        })
+        .collect::<Vec<_>>();
+
+    let pcm = to_bytes(&noise);
+    let encoded = encode(RecordingCodec::Flac, &pcm);
+
+    assert!(encoded.len() <= pcm.len() + 100);
+    assert_eq!(decode(RecordingCodec::Flac, encoded), pcm);
+}
+
+#[test]
+fn flac_stream_cut_off_mid_frame_keeps_the_complete_frames() {
+    let pcm = to_bytes(&speech_like(4096 * 3));
+    let buffer = Arc::new(Mutex::new(vec![]));
+
+    drop(FlacEncoder::new(SharedBuffer(buffer.clone()), 16000).write_all(&pcm));
+
+    let encoded = buffer.lock().unwrap().clone();
+    let mut decoded = vec![];
+
+    FlacDecoder::new(&encoded[..encoded.len() - 100]).read_to_end(&mut decoded).unwrap();
+
+    assert_eq!(decoded, pcm[..4096 * 2 * 2]);
+}
+
+#[test]
+fn flac_decoder_rejects_other_files() {
+    let mut decoded = vec![];
+
+    assert!(FlacDecoder::new(&b"RIFF....WAVE"[..]).read_to_end(&mut decoded).is_err());
+}
+
+#[test]
+fn recordings_are_stored_compressed_and_read_back_as_pcm() {
+    let directory = temporary_directory("store");
+    let store = FileStore::new(&directory).with_codec(RecordingCodec::Flac);
+    let pcm = to_bytes(&speech_like(16000 * 2));
+
+    record(&store, "cafe0003", &pcm);
+
+    assert!(store.recording_path("cafe0003").ends_with("recording.flac"));
+    assert!(store.recording_length("cafe0003").unwrap() < pcm.len() as u64 * 2 / 3);
+
+    assert_eq!(read_all(store.open_recording("cafe0003").unwrap()), pcm);
+
+    // Switching codecs doesn't lose track of meetings recorded before
+    let store = FileStore::new(&directory).with_codec(RecordingCodec::ImaAdpcm);
+
+    assert_eq!(store.find_recording("cafe0003").unwrap().0, RecordingCodec::Flac);
+    assert!(store.find_recording("cafe0004").is_none());
+    assert!(store.open_recording("cafe0004").is_err());
+
+    std::fs::remove_dir_all(&directory).ok();
+}
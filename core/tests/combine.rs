@@ -0,0 +1,272 @@
+mod common;
+
+use std::sync::{Arc, Mutex};
+
+use crossbeam_channel::unbounded;
+use echosense_core::combine::{CombineMode, Combiner};
+use echosense_core::convert::{CaptureFormat, ChannelSelection, Converter, SampleWidth};
+use echosense_core::dsp::{DspChain, DspConfig};
+use echosense_core::error::CoreError;
+use echosense_core::meeting::Meeting;
+use echosense_core::pipeline::{record_channels, record_microphone};
+use echosense_core::platform::AudioSource;
+use echosense_core::store::FileStore;
+
+use common::{rms, temporary_directory};
+
+const FRAME: usize = 500;
+
+// Broadband, so the cross-correlation has a single clear peak
+fn noise(length: usize, seed: u32) -> Vec<f32> {
+    let mut state = seed;
+
+    (0..length)
+        .map(|_| {
+            state = state.wrapping_mul(1664525).wrapping_add(1013904223);
+            ((state >> 16) as f32 / 65535.0 - 0.5) * 8000.0
+        })
+        .collect()
+}
+
+fn interleave(left: &[f32], right: &[f32]) -> Vec<i16> {
+    left.iter().zip(right.iter()).flat_map(|(left, right)| [*left as i16, *right as i16]).collect()
+}
+
+// The same signal reaching the right microphone `delay` samples after the left one, negative for the other way round
+fn delayed_pair(signal: &[f32], delay: isize) -> Vec<i16> {
+    let shifted = |shift: isize| {
+        (0..signal.len())
+            .map(|index| match index as isize - shift {
+                position if position >= 0 => signal[position as usize],
+                _ => 0.0,
+            })
+            .collect::<Vec<_>>()
+    };
+
+    interleave(&shifted((-delay).max(0)), &shifted(delay.max(0)))
+}
+
+fn run(combiner: &mut Combiner, interleaved: &[i16]) -> Vec<i16> {
+    interleaved
+        .chunks(FRAME * 2)
+        .flat_map(|frame| combiner.combine(frame, 2))
+        .collect()
+}
+
+struct StereoSource {
+    frames: Vec<Vec<u8>>,
+    current: Vec<u8>,
+}
+
+impl AudioSource for StereoSource {
+    fn sample_rate(&self) -> u32 {
+        16000
+    }
+
+    fn channels(&self) -> u16 {
+        2
+    }
+
+    fn sample(&mut self) -> Result<Option<&[u8]>, CoreError> {
+        match self.frames.is_empty() {
+            true => Ok(None),
+            false => {
+                self.current = self.frames.remove(0);
+                Ok(Some(&self.current))
+            }
+        }
+    }
+}
+
+#[test]
+fn mono_frames_pass_through() {
+    let mut combiner = Combiner::new(CombineMode::DelayAndSum, 3);
+    let frame = [1u8, 2, 3, 4, 5, 6];
+
+    assert_eq!(combiner.combine_pcm(&frame, 1), frame.to_vec());
+}
+
+#[test]
+fn mix_averages_both_channels() {
+    let mut combiner = Combiner::new(CombineMode::Mix, 1);
+
+    assert_eq!(combiner.combine(&[1000, 3000, -2000, 0, i16::MAX, i16::MAX], 2), vec![2000, -1000, i16::MAX]);
+}
+
+#[test]
+fn mode_names() {
+    assert_eq!(CombineMode::from_name("Mix"), Some(CombineMode::Mix));
+    assert_eq!(CombineMode::from_name("louder"), Some(CombineMode::Louder));
+    assert_eq!(CombineMode::from_name("beamform"), Some(CombineMode::DelayAndSum));
+    assert_eq!(CombineMode::from_name("delay_and_sum"), Some(CombineMode::DelayAndSum));
+    assert_eq!(CombineMode::from_name("both"), None);
+}
+
+#[test]
+fn louder_switches_only_once_the_other_channel_is_clearly_louder() {
+    let mut combiner = Combiner::new(CombineMode::Louder, 1);
+    let frame = |left: i16, right: i16| (0..FRAME).flat_map(|_| [left, right]).collect::<Vec<_>>();
+
+    combiner.combine(&frame(1000, 600), 2);
+    assert_eq!(combiner.louder_channel(), 0);
+
+    // 1.2 times as loud isn't enough to switch
+    combiner.combine(&frame(1000, 1200), 2);
+    assert_eq!(combiner.louder_channel(), 0);
+
+    let output = combiner.combine(&frame(1000, 2000), 2);
+    assert_eq!(combiner.louder_channel(), 1);
+
+    // Crossfaded from the old channel to the new one over the frame
+    assert!(output[0] < 1100);
+    assert_eq!(*output.last().unwrap(), 2000);
+
+    let output = combiner.combine(&frame(1000, 2000), 2);
+    assert!(output.iter().all(|sample| *sample == 2000));
+}
+
+#[test]
+fn delay_and_sum_finds_which_microphone_hears_the_talker_first() {
+    let signal = noise(FRAME * 8, 1);
+
+    let mut combiner = Combiner::new(CombineMode::DelayAndSum, 3);
+    run(&mut combiner, &delayed_pair(&signal, 2));
+    assert_eq!(combiner.delay(), 2);
+
+    let mut combiner = Combiner::new(CombineMode::DelayAndSum, 3);
+    run(&mut combiner, &delayed_pair(&signal, -3));
+    assert_eq!(combiner.delay(), -3);
+}
+
+#[test]
+fn delay_and_sum_adds_the_talker_up_in_phase() {
+    let signal = noise(FRAME * 8, 2);
+    let pair = delayed_pair(&signal, 2);
+
+    let steered = run(&mut Combiner::new(CombineMode::DelayAndSum, 3), &pair);
+    let mixed = run(&mut Combiner::new(CombineMode::Mix, 3), &pair);
+
+    // Past the first frame, where the steering settles, the talker comes through at full level
+    let input = rms(&signal.iter().map(|sample| *sample as i16).collect::<Vec<_>>()[FRAME * 2..]);
+
+    assert!((rms(&steered[FRAME * 2..]) - input).abs() < input * 0.02);
+    assert!(rms(&mixed[FRAME * 2..]) < input * 0.85);
+}
+
+#[test]
+fn delay_and_sum_holds_the_steering_through_silence() {
+    let mut combiner = Combiner::for_spacing(CombineMode::DelayAndSum, 16000, 60);
+    let signal = noise(FRAME * 4, 3);
+
+    run(&mut combiner, &delayed_pair(&signal, -2));
+    run(&mut combiner, &vec![0i16; FRAME * 2 * 4]);
+
+    assert_eq!(combiner.delay(), -2);
+}
+
+#[test]
+fn spacing_limits_the_delay() {
+    // 60 mm is 2.8 samples at 16 kHz
+    let mut combiner = Combiner::for_spacing(CombineMode::DelayAndSum, 16000, 60);
+    let signal = noise(FRAME * 4, 4);
+
+    run(&mut combiner, &delayed_pair(&signal, 6));
+
+    assert!(combiner.delay().abs() <= 3);
+}
+
+#[test]
+fn converter_keeps_both_channels_interleaved() {
+    let format = CaptureFormat {
+        sample_rate: 32000,
+        width: SampleWidth::Bits32,
+        channels: 2,
+        selection: ChannelSelection::Both,
+    };
+
+    assert_eq!(format.output_channels(), 2);
+
+    let mut converter = Converter::new(format);
+    let raw = (0..3200)
+        .flat_map(|_| [(1000i32 << 16).to_le_bytes(), (-2000i32 << 16).to_le_bytes()])
+        .flatten()
+        .collect::<Vec<_>>();
+
+    let samples = converter
+        .convert(&raw)
+        .chunks_exact(2)
+        .map(|sample| i16::from_le_bytes([sample[0], sample[1]]))
+        .collect::<Vec<_>>();
+
+    assert_eq!(samples.len(), 3200);
+    // Past the anti-aliasing filter's settling
+    assert!(samples[1000..].chunks_exact(2).all(|pair| pair[0] == 1000 && pair[1] == -2000));
+}
+
+#[test]
+fn record_microphone_combines_before_the_fan_out() {
+    let frames = (0..3)
+        .map(|_| [1000i16, 3000].repeat(FRAME).iter().flat_map(|sample| sample.to_le_bytes()).collect())
+        .collect();
+
+    let mut source = StereoSource { frames, current: vec![] };
+    let mut combiner = Combiner::new(CombineMode::Mix, 1);
+    let mut dsp = DspChain::new(&DspConfig::disabled(), 16000);
+
+    let (sender, receiver) = unbounded::<Vec<u8>>();
+    let (raw_sender, raw_receiver) = unbounded::<Vec<u8>>();
+
+    record_microphone(vec![sender], &mut source, &mut combiner, &mut dsp, Some(raw_sender)).unwrap();
+
+    let combined = receiver.iter().collect::<Vec<_>>();
+    let raw = raw_receiver.iter().collect::<Vec<_>>();
+
+    assert_eq!(combined.len(), 3);
+    assert_eq!(combined[0].len(), FRAME * 2);
+    assert!(combined[0].chunks_exact(2).all(|sample| i16::from_le_bytes([sample[0], sample[1]]) == 2000));
+
+    assert_eq!(raw.len(), 3);
+    assert_eq!(raw[0].len(), FRAME * 4);
+}
+
+#[test]
+fn record_microphone_keeps_feeding_the_others_when_a_consumer_is_gone() {
+    let frames = (0..3)
+        .map(|_| [1000i16, 3000].repeat(FRAME).iter().flat_map(|sample| sample.to_le_bytes()).collect())
+        .collect();
+
+    let mut source = StereoSource { frames, current: vec![] };
+    let mut combiner = Combiner::new(CombineMode::Mix, 1);
+    let mut dsp = DspChain::new(&DspConfig::disabled(), 16000);
+
+    let (sender, receiver) = unbounded::<Vec<u8>>();
+    let (gone, gone_receiver) = unbounded::<Vec<u8>>();
+    drop(gone_receiver);
+
+    record_microphone(vec![gone, sender], &mut source, &mut combiner, &mut dsp, None).unwrap();
+
+    assert_eq!(receiver.iter().count(), 3);
+}
+
+#[test]
+fn record_channels_writes_one_file_per_microphone() {
+    let directory = temporary_directory("channels");
+    let store = FileStore::new(&directory);
+    let meeting = Arc::new(Mutex::new(Meeting::new("cafe0002")));
+
+    let (sender, receiver) = unbounded::<Vec<u8>>();
+
+    sender.send([1i16, -1, 2, -2].iter().flat_map(|sample| sample.to_le_bytes()).collect()).unwrap();
+    sender.send([3i16, -3].iter().flat_map(|sample| sample.to_le_bytes()).collect()).unwrap();
+    drop(sender);
+
+    record_channels(receiver, 2, meeting, &store).unwrap();
+
+    let left = std::fs::read(store.channel_recording_path("cafe0002", 0)).unwrap();
+    let right = std::fs::read(store.channel_recording_path("cafe0002", 1)).unwrap();
+
+    assert_eq!(left, [1i16, 2, 3].iter().flat_map(|sample| sample.to_le_bytes()).collect::<Vec<_>>());
+    assert_eq!(right, [-1i16, -2, -3].iter().flat_map(|sample| sample.to_le_bytes()).collect::<Vec<_>>());
+
+    std::fs::remove_dir_all(&directory).ok();
+}
@@ -0,0 +1,58 @@
+// Every test file compiles its own copy of these and uses only some of them
+#![allow(dead_code)]
+
+use std::f32::consts::PI;
+use std::io::Read;
+use std::path::PathBuf;
+use std::sync::{Arc, Mutex};
+
+use crossbeam_channel::unbounded;
+use echosense_core::meeting::Meeting;
+use echosense_core::pipeline::record_audio;
+use echosense_core::store::FileStore;
+
+// Test binaries run in processes of their own, the name only has to be unique within one file
+pub fn temporary_directory(name: &str) -> PathBuf {
+    let directory = std::env::temp_dir().join(format!("echosense-{}-{}", name, std::process::id()));
+
+    std::fs::remove_dir_all(&directory).ok();
+    directory
+}
+
+pub fn sine(frequency: f32, amplitude: f32, sample_rate: u32, seconds: f32) -> Vec<i16> {
+    (0..(seconds * sample_rate as f32) as usize)
+        .map(|index| (amplitude * (2.0 * PI * frequency * index as f32 / sample_rate as f32).sin()) as i16)
+        .collect()
+}
+
+pub fn rms(samples: &[i16]) -> f32 {
+    (samples.iter().map(|sample| (*sample as f32).powi(2)).sum::<f32>() / samples.len() as f32).sqrt()
+}
+
+// Little endian pcm, for what only needs some audio that isn't silence
+pub fn tone(samples: usize) -> Vec<u8> {
+    (0..samples)
+        .map(|index| ((index as f32 * 0.07).sin() * 8000.0) as i16)
+        .flat_map(|sample| sample.to_le_bytes())
+        .collect()
+}
+
+// Feeds the pcm through the pipeline the way the microphone does, in frames of 1000 bytes
+pub fn record(store: &FileStore, id: &str, pcm: &[u8]) {
+    let meeting = Arc::new(Mutex::new(Meeting::new(id)));
+    let (sender, receiver) = unbounded::<Vec<u8>>();
+
+    for frame in pcm.chunks(1000) {
+        sender.send(frame.to_vec()).unwrap();
+    }
+
+    drop(sender);
+    record_audio(receiver, meeting, store).unwrap();
+}
+
+pub fn read_all(mut reader: Box<dyn Read + Send>) -> Vec<u8> {
+    let mut bytes = vec![];
+    reader.read_to_end(&mut bytes).unwrap();
+
+    bytes
+}
@@ -0,0 +1,154 @@
+mod common;
+
+use std::sync::Arc;
+
+use echosense_core::audio::wav_header;
+use echosense_core::convert::{CaptureFormat, ChannelSelection, Converter, SampleWidth, PCM_SAMPLE_RATE};
+use echosense_core::host::{SystemClock, WavFileSource};
+use echosense_core::platform::AudioSource;
+
+use common::{rms, sine};
+
+fn format(sample_rate: u32, width: SampleWidth, channels: u16, selection: ChannelSelection) -> CaptureFormat {
+    CaptureFormat {
+        sample_rate,
+        width,
+        channels,
+        selection,
+    }
+}
+
+fn encode(value: i32, width: SampleWidth) -> Vec<u8> {
+    match width {
+        SampleWidth::Bits16 => (value as i16).to_le_bytes().to_vec(),
+        SampleWidth::Bits24 => value.to_le_bytes()[..3].to_vec(),
+        SampleWidth::Bits32 => value.to_le_bytes().to_vec(),
+    }
+}
+
+fn decode(pcm: &[u8]) -> Vec<i16> {
+    pcm.chunks_exact(2).map(|sample| i16::from_le_bytes([sample[0], sample[1]])).collect()
+}
+
+fn resample(from: u32, samples: &[i16]) -> Vec<i16> {
+    let mut converter = Converter::new(format(from, SampleWidth::Bits16, 1, ChannelSelection::Left));
+    let mut output = vec![];
+
+    for chunk in samples.chunks(333) {
+        let raw = chunk.iter().flat_map(|sample| sample.to_le_bytes()).collect::<Vec<_>>();
+        output.extend(decode(converter.convert(&raw)));
+    }
+
+    output
+}
+
+#[test]
+fn passes_16_khz_mono_through() {
+    let mut converter = Converter::new(CaptureFormat::pcm());
+    let raw = (0..1000).map(|index| index as u8).collect::<Vec<_>>();
+
+    assert!(converter.is_passthrough());
+    assert_eq!(converter.convert(&raw), raw.as_slice());
+}
+
+#[test]
+fn scales_wider_samples_down_to_16_bits() {
+    // Full scale, half scale negative and a value only visible in the upper 16 bits
+    let values = [(0x7f_ffff, 0x7fff), (-0x40_0000, -0x4000), (0x12_3400, 0x1234)];
+
+    for width in [SampleWidth::Bits24, SampleWidth::Bits32] {
+        let shift = match width {
+            SampleWidth::Bits32 => 8,
+            _ => 0,
+        };
+
+        let raw = values
+            .iter()
+            .flat_map(|(value, _)| encode(value << shift, width))
+            .collect::<Vec<_>>();
+
+        let mut converter = Converter::new(format(PCM_SAMPLE_RATE, width, 1, ChannelSelection::Left));
+        let expected = values.iter().map(|(_, expected)| *expected as i16).collect::<Vec<_>>();
+
+        assert_eq!(decode(converter.convert(&raw)), expected, "{:?}", width);
+    }
+}
+
+#[test]
+fn selects_or_mixes_stereo_channels() {
+    let raw = [(1000, 3000), (-2000, 2000)]
+        .iter()
+        .flat_map(|(left, right)| [encode(*left, SampleWidth::Bits16), encode(*right, SampleWidth::Bits16)].concat())
+        .collect::<Vec<_>>();
+
+    let expected = [
+        (ChannelSelection::Left, vec![1000, -2000]),
+        (ChannelSelection::Right, vec![3000, 2000]),
+        (ChannelSelection::Mix, vec![2000, 0]),
+    ];
+
+    for (selection, expected) in expected {
+        let mut converter = Converter::new(format(PCM_SAMPLE_RATE, SampleWidth::Bits16, 2, selection));
+        assert_eq!(decode(converter.convert(&raw)), expected, "{:?}", selection);
+    }
+}
+
+#[test]
+fn keeps_partial_frames_for_the_next_call() {
+    let mut converter = Converter::new(format(PCM_SAMPLE_RATE, SampleWidth::Bits24, 2, ChannelSelection::Right));
+    let raw = [encode(0x10_0000, SampleWidth::Bits24), encode(0x20_0000, SampleWidth::Bits24)].concat();
+
+    assert!(converter.convert(&raw[..4]).is_empty());
+    assert_eq!(decode(converter.convert(&raw[4..])), vec![0x2000]);
+}
+
+#[test]
+fn downsamples_48_khz_to_16_khz() {
+    let speech = resample(48000, &sine(1000.0, 10000.0, 48000, 1.0));
+
+    assert!((speech.len() as i32 - 16000).abs() <= 1);
+    assert!((rms(&speech[8000..]) - 10000.0 / 2f32.sqrt()).abs() < 300.0);
+
+    // 11 kHz would fold back to 5 kHz without the anti-aliasing filter
+    let alias = resample(48000, &sine(11000.0, 10000.0, 48000, 1.0));
+
+    assert!(rms(&alias[8000..]) < 10000.0 / 2f32.sqrt() / 10.0);
+}
+
+#[test]
+fn upsamples_8_khz_to_16_khz() {
+    let speech = resample(8000, &sine(500.0, 10000.0, 8000, 1.0));
+
+    assert!((speech.len() as i32 - 16000).abs() <= 1);
+    assert!((rms(&speech[8000..]) - 10000.0 / 2f32.sqrt()).abs() < 300.0);
+}
+
+#[test]
+fn plays_24_bit_stereo_wav_files_at_16_khz() {
+    let path = std::env::temp_dir().join(format!("echosense-convert-{}.wav", std::process::id()));
+
+    let mut samples = vec![];
+
+    for sample in sine(1000.0, 10000.0, 48000, 1.0) {
+        samples.extend(encode((sample as i32) << 8, SampleWidth::Bits24));
+        samples.extend(encode(0, SampleWidth::Bits24));
+    }
+
+    let mut file = wav_header(samples.len() as u32, 48000, 2, 24).to_vec();
+    file.extend(samples);
+    std::fs::write(&path, file).unwrap();
+
+    let mut source = WavFileSource::open(&path, 1000, Arc::new(SystemClock::new()), false).unwrap();
+    let mut output = vec![];
+
+    while let Some(frame) = source.sample().unwrap() {
+        assert!(frame.len() <= 1000);
+        output.extend(decode(frame));
+    }
+
+    std::fs::remove_file(&path).ok();
+
+    assert_eq!(source.sample_rate(), PCM_SAMPLE_RATE);
+    assert!((output.len() as i32 - 16000).abs() <= 1);
+    assert!((rms(&output[8000..]) - 10000.0 / 2f32.sqrt()).abs() < 300.0);
+}
@@ -0,0 +1,173 @@
+mod common;
+
+use echosense_core::dsp::{Agc, AgcConfig, DcBlocker, DspChain, DspConfig, HighPass, NoiseGate, NoiseGateConfig, Stage};
+
+use common::{rms, sine};
+
+const SAMPLE_RATE: u32 = 16000;
+const FRAME: usize = 500;
+
+// Runs the stage frame by frame like record_microphone does, so state carried across frames is exercised too
+fn run<S: Stage>(stage: &mut S, mut samples: Vec<i16>) -> Vec<i16> {
+    for frame in samples.chunks_mut(FRAME) {
+        stage.process(frame);
+    }
+
+    samples
+}
+
+fn mean(samples: &[i16]) -> f32 {
+    samples.iter().map(|sample| *sample as f32).sum::<f32>() / samples.len() as f32
+}
+
+fn peak(samples: &[i16]) -> i32 {
+    samples.iter().map(|sample| (*sample as i32).abs()).max().unwrap_or(0)
+}
+
+fn dbfs(value: f32) -> f32 {
+    20.0 * (value / i16::MAX as f32).log10()
+}
+
+fn tail(samples: &[i16]) -> &[i16] {
+    &samples[samples.len() / 2..]
+}
+
+#[test]
+fn dc_blocker_removes_the_offset() {
+    let input = sine(440.0, 3000.0, SAMPLE_RATE, 2.0).into_iter().map(|sample| sample + 2000).collect::<Vec<_>>();
+    let output = run(&mut DcBlocker::new(SAMPLE_RATE), input);
+
+    assert!(mean(tail(&output)).abs() < 20.0, "mean {}", mean(tail(&output)));
+    assert!((rms(tail(&output)) - 3000.0 / 2f32.sqrt()).abs() < 100.0);
+}
+
+#[test]
+fn high_pass_attenuates_rumble_and_keeps_speech() {
+    let mut rumble = HighPass::new(SAMPLE_RATE, 120.0);
+    let mut speech = HighPass::new(SAMPLE_RATE, 120.0);
+
+    let rumble = run(&mut rumble, sine(30.0, 8000.0, SAMPLE_RATE, 1.0));
+    let speech = run(&mut speech, sine(1000.0, 8000.0, SAMPLE_RATE, 1.0));
+
+    // Two octaves below the corner of a second order filter is about -24 dB
+    assert!(rms(tail(&rumble)) < 8000.0 / 2f32.sqrt() / 10.0);
+    assert!((rms(tail(&speech)) - 8000.0 / 2f32.sqrt()).abs() < 200.0);
+}
+
+#[test]
+fn agc_raises_quiet_speech_to_the_target() {
+    let config = AgcConfig::default();
+    let mut agc = Agc::new(SAMPLE_RATE, &config);
+
+    // -40 dBFS, a talker far from the microphone
+    let output = run(&mut agc, sine(300.0, 327.0 * 2f32.sqrt(), SAMPLE_RATE, 8.0));
+
+    assert!((dbfs(rms(tail(&output))) - config.target_dbfs).abs() < 2.0, "{} dBFS", dbfs(rms(tail(&output))));
+    assert!(agc.gain_db() <= config.max_gain_db + 0.01);
+}
+
+#[test]
+fn agc_never_exceeds_the_maximum_gain() {
+    let config = AgcConfig {
+        max_gain_db: 12.0,
+        ..AgcConfig::default()
+    };
+    let mut agc = Agc::new(SAMPLE_RATE, &config);
+
+    // -60 dBFS input would need 40 dB to reach the target
+    let input = sine(300.0, 46.0, SAMPLE_RATE, 6.0);
+    let output = run(&mut agc, input.clone());
+
+    assert!(rms(tail(&output)) <= rms(tail(&input)) * 4.0 + 1.0);
+}
+
+#[test]
+fn agc_leaves_silence_alone() {
+    let mut agc = Agc::new(SAMPLE_RATE, &AgcConfig::default());
+
+    let input = sine(300.0, 10.0, SAMPLE_RATE, 4.0);
+    let output = run(&mut agc, input.clone());
+
+    assert_eq!(output, input);
+}
+
+#[test]
+fn limiter_keeps_loud_bursts_below_the_ceiling() {
+    let config = AgcConfig::default();
+    let mut agc = Agc::new(SAMPLE_RATE, &config);
+
+    // Quiet long enough for the gain to go all the way up, then a shout at full scale
+    let mut input = sine(300.0, 200.0, SAMPLE_RATE, 6.0);
+    input.extend(sine(300.0, 32000.0, SAMPLE_RATE, 0.5));
+
+    let output = run(&mut agc, input);
+    let ceiling = (10f32.powf(config.ceiling_dbfs / 20.0) * i16::MAX as f32) as i32;
+
+    assert!(peak(&output) <= ceiling + 1, "peak {} above {}", peak(&output), ceiling);
+}
+
+#[test]
+fn noise_gate_attenuates_background_noise() {
+    let config = NoiseGateConfig {
+        threshold_dbfs: -40.0,
+        attenuation_db: 20.0,
+        hold_ms: 100.0,
+    };
+
+    let noise = run(&mut NoiseGate::new(SAMPLE_RATE, &config), sine(2000.0, 100.0, SAMPLE_RATE, 1.0));
+    let speech = run(&mut NoiseGate::new(SAMPLE_RATE, &config), sine(300.0, 5000.0, SAMPLE_RATE, 1.0));
+
+    assert!(rms(tail(&noise)) < 100.0 / 2f32.sqrt() / 9.0);
+    assert!((rms(tail(&speech)) - 5000.0 / 2f32.sqrt()).abs() < 50.0);
+}
+
+#[test]
+fn noise_gate_holds_after_speech_ends() {
+    let config = NoiseGateConfig {
+        threshold_dbfs: -40.0,
+        attenuation_db: 20.0,
+        hold_ms: 200.0,
+    };
+
+    let mut input = sine(300.0, 5000.0, SAMPLE_RATE, 0.5);
+    input.extend(sine(300.0, 100.0, SAMPLE_RATE, 1.0));
+
+    let output = run(&mut NoiseGate::new(SAMPLE_RATE, &config), input.clone());
+
+    // 100 ms after the speech the gate is still open, a second later it has closed
+    let held = 8000 + 1600..8000 + 2400;
+    let closed = input.len() - 1600..input.len();
+
+    assert!((rms(&output[held.clone()]) - rms(&input[held])).abs() < 2.0);
+    assert!(rms(&output[closed.clone()]) < rms(&input[closed]) / 5.0);
+}
+
+#[test]
+fn disabled_chain_passes_audio_through() {
+    let mut chain = DspChain::new(&DspConfig::disabled(), SAMPLE_RATE);
+    let mut pcm = (0..1001).map(|index| index as u8).collect::<Vec<_>>();
+    let original = pcm.clone();
+
+    chain.process_pcm(&mut pcm);
+
+    assert!(chain.is_empty());
+    assert_eq!(pcm, original);
+}
+
+#[test]
+fn chain_processes_little_endian_pcm() {
+    let mut chain = DspChain::new(&DspConfig::default(), SAMPLE_RATE);
+    let input = sine(300.0, 300.0, SAMPLE_RATE, 6.0).into_iter().map(|sample| sample + 1500).collect::<Vec<_>>();
+
+    let mut output = vec![];
+
+    for frame in input.chunks(FRAME) {
+        let mut pcm = frame.iter().flat_map(|sample| sample.to_le_bytes()).collect::<Vec<_>>();
+        chain.process_pcm(&mut pcm);
+
+        output.extend(pcm.chunks_exact(2).map(|sample| i16::from_le_bytes([sample[0], sample[1]])));
+    }
+
+    assert!(mean(tail(&output)).abs() < 50.0);
+    assert!((dbfs(rms(tail(&output))) - AgcConfig::default().target_dbfs).abs() < 2.0);
+}
@@ -0,0 +1,292 @@
+mod common;
+
+use std::io::{ErrorKind, Read, Write};
+use std::time::Duration;
+
+use echosense_core::codec::RecordingCodec;
+use echosense_core::encryption::{
+    self, decode_hex, decrypt_directory, is_encrypted, DecryptingReader, EncryptingWriter, StorageKey, CHUNK_LENGTH, HEADER_LENGTH,
+    TAG_LENGTH,
+};
+use echosense_core::maintenance::check;
+use echosense_core::meeting::Meeting;
+use echosense_core::platform::RecordingStore;
+use echosense_core::segment::SegmentWriter;
+use echosense_core::store::FileStore;
+
+use common::{read_all, record, temporary_directory, tone};
+
+const SECRET: &str = "000102030405060708090a0b0c0d0e0f101112131415161718191a1b1c1d1e1f";
+
+fn key() -> StorageKey {
+    StorageKey::from_hex_secret(SECRET).unwrap()
+}
+
+fn encrypted(bytes: &[u8]) -> Vec<u8> {
+    let mut writer = EncryptingWriter::new(vec![], &key()).unwrap();
+    writer.write_all(bytes).unwrap();
+
+    writer.finish().unwrap()
+}
+
+fn decrypted(bytes: Vec<u8>, key: &StorageKey) -> std::io::Result<Vec<u8>> {
+    let mut plaintext = vec![];
+    DecryptingReader::new(std::io::Cursor::new(bytes), key)?.read_to_end(&mut plaintext)?;
+
+    Ok(plaintext)
+}
+
+#[test]
+fn keys_are_derived_from_hex_secrets() {
+    assert_eq!(
+        StorageKey::from_hex_secret(SECRET),
+        StorageKey::from_hex_secret(&SECRET.to_uppercase())
+    );
+    assert!(StorageKey::from_hex_secret(SECRET) != StorageKey::from_hex_secret(&SECRET.replace('f', "e")));
+    assert_eq!(StorageKey::from_hex_secret("abc"), None);
+    assert_eq!(StorageKey::from_hex_secret("zz"), None);
+    assert_eq!(StorageKey::from_hex_secret(""), None);
+    assert_eq!(format!("{:?}", key()), "StorageKey(..)");
+}
+
+#[test]
+fn hex_is_decoded_in_pairs_of_digits() {
+    assert_eq!(decode_hex(" 00ff7A\n"), Some(vec![0x00, 0xff, 0x7a]));
+    assert_eq!(decode_hex("+f"), None);
+    assert_eq!(decode_hex("0g"), None);
+    assert_eq!(decode_hex("éa"), None);
+    assert_eq!(decode_hex(""), None);
+}
+
+#[test]
+fn files_round_trip_across_chunk_boundaries() {
+    for length in [
+        0,
+        1,
+        CHUNK_LENGTH - 1,
+        CHUNK_LENGTH,
+        CHUNK_LENGTH + 1,
+        CHUNK_LENGTH * 3 + 17,
+    ] {
+        let plaintext = tone(length / 2 + 1)[..length].to_vec();
+        let ciphertext = encrypted(&plaintext);
+
+        assert_eq!(&ciphertext[..8], b"ECHOENC1");
+        assert_eq!(encryption::plaintext_length(ciphertext.len() as u64), length as u64);
+        assert_eq!(decrypted(ciphertext, &key()).unwrap(), plaintext);
+    }
+
+    // Every file gets a nonce of its own
+    assert!(encrypted(b"same") != encrypted(b"same"));
+}
+
+#[test]
+fn a_wrong_key_or_a_damaged_chunk_is_an_error() {
+    let ciphertext = encrypted(&tone(CHUNK_LENGTH));
+    let wrong = StorageKey::derive(b"another device");
+
+    assert_eq!(
+        decrypted(ciphertext.clone(), &wrong).unwrap_err().kind(),
+        ErrorKind::InvalidData
+    );
+
+    let mut damaged = ciphertext.clone();
+    damaged[HEADER_LENGTH + CHUNK_LENGTH + TAG_LENGTH + 10] ^= 0x01;
+
+    assert_eq!(decrypted(damaged, &key()).unwrap_err().kind(), ErrorKind::InvalidData);
+
+    // Chunks can't be swapped either, their index is part of the nonce
+    let mut swapped = ciphertext[..HEADER_LENGTH].to_vec();
+    swapped.extend_from_slice(&ciphertext[HEADER_LENGTH + CHUNK_LENGTH + TAG_LENGTH..]);
+    swapped.extend_from_slice(&ciphertext[HEADER_LENGTH..HEADER_LENGTH + CHUNK_LENGTH + TAG_LENGTH]);
+
+    assert!(decrypted(swapped, &key()).is_err());
+}
+
+#[test]
+fn unencrypted_files_are_read_as_they_are() {
+    let directory = temporary_directory("plaintext");
+    std::fs::create_dir_all(&directory).unwrap();
+
+    let plain = directory.join("plain.json");
+    let sealed = directory.join("sealed.json");
+
+    std::fs::write(&plain, b"{}").unwrap();
+    encryption::write(&sealed, Some(&key()), b"{\"id\":1}").unwrap();
+
+    assert!(is_encrypted(&plain).unwrap() == false);
+    assert!(is_encrypted(&sealed).unwrap());
+    assert_eq!(encryption::read(&plain, Some(&key())).unwrap(), b"{}");
+    assert_eq!(encryption::read(&sealed, Some(&key())).unwrap(), b"{\"id\":1}");
+    assert_eq!(encryption::readable_length(&sealed).unwrap(), 8);
+    assert_eq!(
+        encryption::read(&sealed, None).unwrap_err().kind(),
+        ErrorKind::PermissionDenied
+    );
+
+    std::fs::remove_dir_all(&directory).ok();
+}
+
+#[test]
+fn appending_rewrites_encrypted_files() {
+    let directory = temporary_directory("append");
+    std::fs::create_dir_all(&directory).unwrap();
+
+    let log = directory.join("log.jsonl");
+
+    encryption::append(&log, Some(&key()), b"first\n").unwrap();
+    encryption::append(&log, Some(&key()), b"second\n").unwrap();
+
+    assert!(is_encrypted(&log).unwrap());
+    assert_eq!(encryption::read(&log, Some(&key())).unwrap(), b"first\nsecond\n");
+
+    std::fs::remove_dir_all(&directory).ok();
+}
+
+#[test]
+fn an_encrypted_store_keeps_meetings_and_recordings_unreadable_without_the_key() {
+    let directory = temporary_directory("store");
+    let store = FileStore::new(&directory).with_encryption(key());
+    let pcm = tone(16000 + 123);
+
+    let mut meeting = Meeting::new("cafe0040");
+    meeting.title = Some("Quarterly numbers".to_string());
+    store.save_meeting(&meeting).unwrap();
+    record(&store, "cafe0040", &pcm);
+
+    let stored = std::fs::read(store.meeting_directory("cafe0040").join("meeting.json")).unwrap();
+
+    assert!(String::from_utf8_lossy(&stored).contains("Quarterly") == false);
+    assert!(is_encrypted(&store.recording_path("cafe0040")).unwrap());
+    assert_eq!(
+        store.load_meeting("cafe0040").unwrap().title.as_deref(),
+        Some("Quarterly numbers")
+    );
+
+    let recording = store.stored_recording("cafe0040").unwrap();
+    let download = recording.download(&recording.lengths().unwrap(), false, 16000).unwrap();
+
+    assert_eq!(download.length, Some(pcm.len() as u64));
+    assert_eq!(read_all(download.reader), pcm);
+
+    // The same card in a device with another secret
+    let other = FileStore::new(&directory).with_encryption(StorageKey::derive(b"another device"));
+
+    assert!(other.load_meeting("cafe0040").is_err());
+    assert!(FileStore::new(&directory).load_meeting("cafe0040").is_err());
+
+    std::fs::remove_dir_all(&directory).ok();
+}
+
+#[test]
+fn encrypted_segments_download_and_verify_in_every_codec() {
+    for codec in [RecordingCodec::Pcm, RecordingCodec::ImaAdpcm, RecordingCodec::Flac] {
+        let directory = temporary_directory(codec.extension());
+        let store = FileStore::new(&directory)
+            .with_codec(codec)
+            .with_segments(Duration::from_secs(1))
+            .with_encryption(key());
+        let pcm = tone(16000 * 2 + 1000);
+
+        record(&store, "cafe0041", &pcm);
+
+        let manifest = store.load_manifest("cafe0041").unwrap();
+        let meeting = store.meeting_directory("cafe0041");
+
+        assert!(manifest.is_complete());
+        assert!(manifest.verify(&meeting).is_empty());
+        assert!(manifest
+            .segments
+            .iter()
+            .all(|segment| is_encrypted(&meeting.join(&segment.file)).unwrap()));
+
+        let recording = store.stored_recording("cafe0041").unwrap();
+        let download = recording.download(&recording.lengths().unwrap(), true, 16000).unwrap();
+        let decoded = read_all(download.reader);
+
+        assert_eq!(decoded.len(), manifest.samples().unwrap() as usize * 2);
+
+        if codec == RecordingCodec::Pcm {
+            assert_eq!(decoded, pcm);
+        }
+
+        std::fs::remove_dir_all(&directory).ok();
+    }
+}
+
+#[test]
+fn an_interrupted_encrypted_segment_is_recovered_to_its_whole_chunks() {
+    let directory = temporary_directory("recover");
+    let store = FileStore::new(&directory).with_encryption(key());
+
+    store.save_meeting(&Meeting::new("cafe0042")).unwrap();
+
+    let mut writer = SegmentWriter::create_with_key(
+        store.meeting_directory("cafe0042"),
+        RecordingCodec::Pcm,
+        16000,
+        Duration::from_secs(1),
+        Some(key()),
+    )
+    .unwrap();
+
+    // The last chunk of the second segment is still in memory when the power goes
+    writer.write_all(&tone(16000 + 3000)).unwrap();
+    std::mem::forget(writer);
+
+    let report = check(&store, &[]);
+    let manifest = store.load_manifest("cafe0042").unwrap();
+
+    assert!(report.problems.is_empty());
+    assert_eq!(report.repaired.len(), 1);
+    assert!(manifest.is_complete());
+    assert_eq!(manifest.segments[1].samples, CHUNK_LENGTH as u64 / 2);
+
+    let recording = store.stored_recording("cafe0042").unwrap();
+
+    assert_eq!(
+        read_all(recording.decode(&recording.lengths().unwrap()).unwrap()).len() as u64,
+        manifest.samples().unwrap() * 2
+    );
+
+    std::fs::remove_dir_all(&directory).ok();
+}
+
+#[test]
+fn a_pulled_card_is_decrypted_into_a_plain_store() {
+    let directory = temporary_directory("card");
+    let output = temporary_directory("card-decrypted");
+    let store = FileStore::new(&directory)
+        .with_segments(Duration::from_secs(1))
+        .with_encryption(key());
+    let pcm = tone(16000 + 500);
+
+    store.save_meeting(&Meeting::new("cafe0043")).unwrap();
+    record(&store, "cafe0043", &pcm);
+    std::fs::write(directory.join("notes.txt"), b"plain").unwrap();
+
+    let report = decrypt_directory(&directory, &output, &key()).unwrap();
+
+    // The meeting and both segments, the manifest and the notes weren't encrypted
+    assert_eq!(report.decrypted, 3);
+    assert_eq!(report.copied, 2);
+    assert!(report.failed.is_empty());
+
+    let plain = FileStore::new(&output);
+    let manifest = plain.load_manifest("cafe0043").unwrap();
+
+    assert_eq!(plain.load_meeting("cafe0043").unwrap().id, "cafe0043");
+    assert!(manifest.verify(&plain.meeting_directory("cafe0043")).is_empty());
+
+    let recording = plain.stored_recording("cafe0043").unwrap();
+
+    assert_eq!(read_all(recording.open(&recording.lengths().unwrap()).unwrap()), pcm);
+
+    let report = decrypt_directory(&directory, &output, &StorageKey::derive(b"another device")).unwrap();
+
+    assert_eq!(report.decrypted, 0);
+    assert_eq!(report.failed.len(), 3);
+
+    std::fs::remove_dir_all(&directory).ok();
+    std::fs::remove_dir_all(&output).ok();
+}
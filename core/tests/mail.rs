@@ -0,0 +1,19 @@
+use echosense_core::mail::{date_header, message_id};
+
+#[test]
+fn date_header_follows_rfc_5322() {
+    assert_eq!(date_header(0), "Thu, 1 Jan 1970 00:00:00 +0000");
+    assert_eq!(date_header(1_760_862_600), "Sun, 19 Oct 2025 08:30:00 +0000");
+}
+
+#[test]
+fn date_header_handles_leap_days_and_the_end_of_the_year() {
+    assert_eq!(date_header(951_782_400), "Tue, 29 Feb 2000 00:00:00 +0000");
+    assert_eq!(date_header(1_735_689_599), "Tue, 31 Dec 2024 23:59:59 +0000");
+}
+
+#[test]
+fn message_id_uses_the_domain_of_the_sender() {
+    assert_eq!(message_id("1a2b3c4d", "device@example.com"), "<1a2b3c4d@example.com>");
+    assert_eq!(message_id("1a2b3c4d", "not an address"), "<1a2b3c4d@echosense.local>");
+}
@@ -0,0 +1,193 @@
+mod common;
+
+use std::io::Write;
+use std::time::Duration;
+
+use echosense_core::codec::RecordingCodec;
+use echosense_core::framebuffer::{pixel, render};
+use echosense_core::maintenance::{
+    benchmark, check, storage_info, BenchmarkResult, Confirmation, MaintenanceAction, MaintenanceResult, BENCHMARK_FILE,
+};
+use echosense_core::meeting::Meeting;
+use echosense_core::platform::{DrawState, RecordingStore};
+use echosense_core::retention::{StorageMedium, StorageUsage};
+use echosense_core::segment::SegmentWriter;
+use echosense_core::store::FileStore;
+
+use common::temporary_directory;
+
+// A segmented recording whose last segment was never finished, as after a power loss
+fn interrupted_recording(store: &FileStore, id: &str) {
+    store.save_meeting(&Meeting::new(id)).unwrap();
+
+    let mut writer = SegmentWriter::create(
+        store.meeting_directory(id),
+        RecordingCodec::Pcm,
+        16000,
+        Duration::from_secs(1),
+    )
+    .unwrap();
+
+    writer.write_all(&vec![0u8; (16000 + 300) * 2]).unwrap();
+    std::mem::forget(writer);
+}
+
+#[test]
+fn check_counts_files_and_finds_broken_meetings() {
+    let directory = temporary_directory("check");
+    let store = FileStore::new(&directory);
+
+    store.save_meeting(&Meeting::new("cafe0030")).unwrap();
+    std::fs::write(store.recording_path("cafe0030"), vec![0u8; 1000]).unwrap();
+
+    std::fs::create_dir_all(store.meeting_directory("cafe0031")).unwrap();
+    std::fs::write(store.meeting_directory("cafe0031").join("meeting.json"), "{\"id\":").unwrap();
+
+    let report = check(&store, &[]);
+
+    assert_eq!(report.files, 3);
+    assert!(report.bytes > 1000);
+    assert_eq!(report.problems.len(), 1);
+    assert!(report.problems[0].contains("cafe0031"));
+    assert!(report.repaired.is_empty());
+
+    std::fs::remove_dir_all(&directory).ok();
+}
+
+#[test]
+fn check_removes_leftover_files_and_closes_interrupted_recordings() {
+    let directory = temporary_directory("repair");
+    let store = FileStore::new(&directory);
+
+    interrupted_recording(&store, "cafe0032");
+    interrupted_recording(&store, "cafe0033");
+
+    std::fs::write(store.meeting_directory("cafe0032").join("meeting.json.tmp"), "{").unwrap();
+    std::fs::write(store.meeting_directory("cafe0033").join("manifest.json.tmp"), "{").unwrap();
+
+    // The second one is still being recorded and left alone
+    let report = check(&store, &["cafe0033".to_string()]);
+
+    assert!(report.problems.is_empty());
+    assert_eq!(report.repaired.len(), 2);
+    assert!(store.meeting_directory("cafe0032").join("meeting.json.tmp").exists() == false);
+    assert!(store.meeting_directory("cafe0033").join("manifest.json.tmp").exists());
+
+    assert!(store.load_manifest("cafe0032").unwrap().is_complete());
+    assert!(store.load_manifest("cafe0033").unwrap().is_complete() == false);
+
+    // Nothing left to do the second time
+    assert!(check(&store, &["cafe0033".to_string()]).repaired.is_empty());
+
+    std::fs::remove_dir_all(&directory).ok();
+}
+
+#[test]
+fn check_reports_damaged_segments() {
+    let directory = temporary_directory("damaged");
+    let store = FileStore::new(&directory);
+
+    interrupted_recording(&store, "cafe0034");
+    check(&store, &[]);
+
+    let manifest = store.load_manifest("cafe0034").unwrap();
+    let segment = store.meeting_directory("cafe0034").join(&manifest.segments[0].file);
+
+    std::fs::write(&segment, vec![1u8; 100]).unwrap();
+
+    let report = check(&store, &[]);
+
+    assert_eq!(
+        report.problems,
+        vec!["meeting cafe0034 segment 0 is damaged".to_string()]
+    );
+
+    std::fs::remove_dir_all(&directory).ok();
+}
+
+#[test]
+fn benchmark_writes_reads_and_removes_its_file() {
+    let directory = temporary_directory("benchmark");
+    std::fs::create_dir_all(&directory).unwrap();
+
+    let result = benchmark(&directory, 100_000).unwrap();
+
+    assert_eq!(result.bytes, 100_000);
+    assert!(directory.join(BENCHMARK_FILE).exists() == false);
+
+    let result = BenchmarkResult {
+        bytes: 1024 * 1024,
+        write_ms: 2000,
+        read_ms: 500,
+    };
+
+    assert_eq!(result.write_kbps(), 512);
+    assert_eq!(result.read_kbps(), 2048);
+
+    std::fs::remove_dir_all(&directory).ok();
+}
+
+#[test]
+fn confirmation_takes_the_code_until_it_expires() {
+    let confirmation = Confirmation::new("4711".to_string(), Duration::from_secs(15));
+
+    assert!(confirmation.confirms("4711"));
+    assert!(confirmation.confirms(" 4711\n"));
+    assert!(confirmation.confirms("4712") == false);
+
+    let expired = Confirmation::new("4711".to_string(), Duration::ZERO);
+
+    assert!(expired.is_expired());
+    assert!(expired.confirms("4711") == false);
+}
+
+#[test]
+fn actions_are_found_by_name() {
+    assert_eq!(MaintenanceAction::from_name("check"), Some(MaintenanceAction::Check));
+    assert_eq!(MaintenanceAction::from_name("Format"), Some(MaintenanceAction::Format));
+    assert_eq!(MaintenanceAction::from_name("repair"), None);
+}
+
+#[test]
+fn info_lists_the_medium_usage_and_meetings() {
+    let directory = temporary_directory("info");
+    let store = FileStore::new(&directory);
+
+    store.save_meeting(&Meeting::new("cafe0035")).unwrap();
+
+    let info = storage_info(
+        &store,
+        StorageMedium::SdCard,
+        Some(StorageUsage {
+            total_bytes: 4 * 1024 * 1024,
+            used_bytes: 1024 * 1024,
+        }),
+    );
+
+    assert_eq!(
+        MaintenanceResult::Info(info).lines(),
+        vec!["SD card", "1.0 MB / 4.0 MB", "25% used", "1 meetings"]
+    );
+
+    std::fs::remove_dir_all(&directory).ok();
+}
+
+#[test]
+fn text_is_drawn_line_by_line() {
+    let framebuffer = render(&DrawState::Text(vec![
+        "Check OK".to_string(),
+        String::new(),
+        "#".to_string(),
+    ]))
+    .unwrap();
+
+    let lit = |top: usize| (0..128).any(|x| (top..top + 10).any(|y| pixel(&framebuffer, x, y)));
+
+    assert!(lit(0));
+    assert!(lit(10) == false);
+    assert!(lit(20));
+    assert!((30..64).all(|y| (0..128).all(|x| pixel(&framebuffer, x, y) == false)));
+
+    // Text past the edge of the display is cut off
+    render(&DrawState::Text(vec!["#".repeat(40); 10])).unwrap();
+}
@@ -0,0 +1,215 @@
+mod common;
+
+use std::time::Duration;
+
+use echosense_core::framebuffer::{pixel, render};
+use echosense_core::meeting::Meeting;
+use echosense_core::platform::{DrawState, RecordingStore};
+use echosense_core::retention::{
+    EvictionReason, MeetingUsage, RetentionPolicy, StorageLevel, StorageManager, StorageUsage,
+};
+use echosense_core::store::FileStore;
+
+use common::temporary_directory;
+
+const MB: u64 = 1024 * 1024;
+const DAY: u64 = 24 * 60 * 60;
+const NOW: u64 = 1_760_000_000;
+
+fn meeting(id: &str, megabytes: u64, days_ago: Option<u64>, starred: bool) -> MeetingUsage {
+    MeetingUsage {
+        id: id.to_string(),
+        bytes: megabytes * MB,
+        started_at: days_ago.map(|days| NOW - days * DAY),
+        starred,
+    }
+}
+
+fn usage(total_mb: u64, used_mb: u64) -> StorageUsage {
+    StorageUsage {
+        total_bytes: total_mb * MB,
+        used_bytes: used_mb * MB,
+    }
+}
+
+fn policy() -> RetentionPolicy {
+    RetentionPolicy {
+        min_free_bytes: 0,
+        ..RetentionPolicy::default()
+    }
+}
+
+#[test]
+fn level_follows_the_thresholds() {
+    let policy = RetentionPolicy::default();
+
+    assert_eq!(policy.level(&usage(1000, 500)), StorageLevel::Ok);
+    assert_eq!(policy.level(&usage(1000, 800)), StorageLevel::Warning);
+    assert_eq!(policy.level(&usage(1000, 960)), StorageLevel::Critical);
+    assert_eq!(policy.level(&usage(0, 0)), StorageLevel::Critical);
+}
+
+#[test]
+fn meetings_older_than_the_max_age_are_evicted_unless_starred() {
+    let policy = RetentionPolicy {
+        max_age: Some(Duration::from_secs(30 * DAY)),
+        ..policy()
+    };
+
+    let meetings = [
+        meeting("a", 10, Some(40), false),
+        meeting("b", 10, Some(35), true),
+        meeting("c", 10, Some(20), false),
+        meeting("d", 10, None, false),
+    ];
+
+    assert_eq!(
+        policy.evictions(&meetings, &usage(1000, 100), &[], Some(NOW)),
+        vec![("a".to_string(), EvictionReason::MaxAge)]
+    );
+
+    // Without a clock nothing is too old
+    assert!(policy.evictions(&meetings, &usage(1000, 100), &[], None).is_empty());
+}
+
+#[test]
+fn the_oldest_meetings_go_first_to_stay_under_the_max_total_size() {
+    let policy = RetentionPolicy {
+        max_total_bytes: Some(25 * MB),
+        ..policy()
+    };
+
+    let meetings = [
+        meeting("new", 10, Some(1), false),
+        meeting("old", 10, Some(9), false),
+        meeting("unknown", 10, None, false),
+        meeting("current", 10, Some(0), false),
+    ];
+
+    // Meetings without a start time count as the oldest, the current one is never touched
+    assert_eq!(
+        policy.evictions(&meetings, &usage(1000, 100), &["current".to_string()], Some(NOW)),
+        vec![
+            ("unknown".to_string(), EvictionReason::MaxTotalSize),
+            ("old".to_string(), EvictionReason::MaxTotalSize),
+        ]
+    );
+}
+
+#[test]
+fn low_space_evicts_until_the_minimum_is_free() {
+    let policy = RetentionPolicy {
+        min_free_bytes: 50 * MB,
+        ..RetentionPolicy::default()
+    };
+
+    let meetings = [
+        meeting("a", 30, Some(3), false),
+        meeting("b", 30, Some(2), true),
+        meeting("c", 30, Some(1), false),
+        meeting("d", 30, Some(0), false),
+    ];
+
+    assert_eq!(
+        policy.evictions(&meetings, &usage(1000, 990), &[], Some(NOW)),
+        vec![
+            ("a".to_string(), EvictionReason::LowSpace),
+            ("c".to_string(), EvictionReason::LowSpace),
+        ]
+    );
+
+    // With keep_starred off the starred meeting is just the next oldest
+    let policy = RetentionPolicy {
+        keep_starred: false,
+        ..policy
+    };
+
+    assert_eq!(policy.evictions(&meetings, &usage(1000, 990), &[], Some(NOW))[1].0, "b");
+}
+
+#[test]
+fn storage_manager_deletes_evicted_meetings() {
+    let directory = temporary_directory("manager");
+    let store = FileStore::new(&directory);
+
+    for (id, started_at, starred) in [
+        ("cafe0020", 100, false),
+        ("cafe0021", 200, true),
+        ("cafe0022", 300, false),
+    ] {
+        let mut meeting = Meeting::new(id);
+        meeting.started_at = Some(started_at);
+        meeting.starred = starred;
+
+        store.save_meeting(&meeting).unwrap();
+        std::fs::write(store.recording_path(id), vec![0u8; 1000]).unwrap();
+    }
+
+    assert_eq!(
+        store.meeting_size("cafe0020").unwrap(),
+        1000 + std::fs::metadata(store.meeting_directory("cafe0020").join("meeting.json"))
+            .unwrap()
+            .len()
+    );
+
+    let mut manager = StorageManager::new(
+        store.clone(),
+        RetentionPolicy {
+            min_free_bytes: 0,
+            max_total_bytes: Some(2500),
+            ..RetentionPolicy::default()
+        },
+    );
+
+    let report = manager
+        .check(
+            StorageUsage {
+                total_bytes: 10000,
+                used_bytes: 9000,
+            },
+            &["cafe0022".to_string()],
+            None,
+        )
+        .unwrap();
+
+    assert_eq!(report.evicted, vec!["cafe0020".to_string()]);
+    assert!(report.usage.used_bytes < 8000);
+    assert_eq!(report.level, StorageLevel::Ok);
+
+    let mut remaining = store.meeting_ids().unwrap();
+    remaining.sort();
+
+    assert_eq!(remaining, vec!["cafe0021".to_string(), "cafe0022".to_string()]);
+
+    std::fs::remove_dir_all(&directory).ok();
+}
+
+#[test]
+fn storage_warning_draws_a_gauge_next_to_the_qr_code() {
+    let framebuffer = render(&DrawState::StorageWarning {
+        content: "http://echosense.local".to_string(),
+        used_percent: 50,
+    })
+    .unwrap();
+
+    // Outline, the lower half filled and the upper half empty
+    assert!(pixel(&framebuffer, 8, 30));
+    assert!(pixel(&framebuffer, 15, 50));
+    assert!(pixel(&framebuffer, 15, 10) == false);
+
+    // The QR code itself is unchanged
+    let qrcode = render(&DrawState::QRCode("http://echosense.local".to_string())).unwrap();
+
+    assert!((30..128).all(|x| (0..64).all(|y| pixel(&framebuffer, x, y) == pixel(&qrcode, x, y))));
+}
+
+#[test]
+fn no_card_crosses_out_a_card_next_to_the_qr_code() {
+    let framebuffer = render(&DrawState::NoCard("http://echosense.local".to_string())).unwrap();
+
+    // The card's outline and the middle of the cross, with nothing drawn above or below the card
+    assert!(pixel(&framebuffer, 8, 30));
+    assert!(pixel(&framebuffer, 23, 30));
+    assert!(pixel(&framebuffer, 15, 31) || pixel(&framebuffer, 16, 31));
+    assert!((8..24).all(|x| pixel(&framebuffer, x, 10) == false && pixel(&framebuffer, x, 52) == false));
+}
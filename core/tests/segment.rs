@@ -0,0 +1,198 @@
+mod common;
+
+use std::io::Write;
+use std::time::Duration;
+
+use echosense_core::codec::{RecordingCodec, ADPCM_BLOCK_ALIGN, ADPCM_SAMPLES_PER_BLOCK};
+use echosense_core::platform::RecordingStore;
+use echosense_core::segment::{checksum_file, Crc32, SegmentManifest, SegmentWriter};
+use echosense_core::store::FileStore;
+
+use common::{read_all, record, temporary_directory, tone};
+
+#[test]
+fn crc32_matches_the_reference_value() {
+    let mut crc32 = Crc32::new();
+    crc32.update(b"1234");
+    crc32.update(b"56789");
+
+    assert_eq!(crc32.value(), 0xcbf43926);
+    assert_eq!(crc32.hex(), "cbf43926");
+}
+
+#[test]
+fn recordings_are_split_into_segments_listed_in_the_manifest() {
+    let directory = temporary_directory("pcm");
+    let store = FileStore::new(&directory).with_segments(Duration::from_secs(1));
+    let pcm = tone(16000 * 3 + 4000);
+
+    record(&store, "cafe0010", &pcm);
+
+    let manifest = store.load_manifest("cafe0010").unwrap();
+
+    assert_eq!(manifest.codec, RecordingCodec::Pcm);
+    assert_eq!(manifest.segment_ms, 1000);
+    assert_eq!(manifest.segments.len(), 4);
+    assert_eq!(manifest.samples(), Some(16000 * 3 + 4000));
+
+    for (index, segment) in manifest.segments.iter().enumerate() {
+        let (length, crc32) = checksum_file(&store.meeting_directory("cafe0010").join(&segment.file)).unwrap();
+
+        assert_eq!(segment.index, index);
+        assert_eq!(segment.file, format!("segments/segment-{:04}.raw", index));
+        assert_eq!(segment.start_sample, index as u64 * 16000);
+        assert_eq!(segment.start_ms, index as u64 * 1000);
+        assert!(segment.complete);
+        assert_eq!((segment.length, segment.crc32.clone()), (length, crc32));
+    }
+
+    assert_eq!(manifest.segments[3].duration_ms, 250);
+    assert_eq!(store.recording_length("cafe0010").unwrap(), pcm.len() as u64);
+    assert_eq!(read_all(store.open_recording("cafe0010").unwrap()), pcm);
+
+    std::fs::remove_dir_all(&directory).ok();
+}
+
+#[test]
+fn adpcm_segments_end_on_whole_blocks_and_stitch_by_appending() {
+    let directory = temporary_directory("adpcm");
+    let store = FileStore::new(&directory)
+        .with_codec(RecordingCodec::ImaAdpcm)
+        .with_segments(Duration::from_secs(1));
+
+    record(&store, "cafe0011", &tone(16000 * 2 + 300));
+
+    let manifest = store.load_manifest("cafe0011").unwrap();
+    let blocks = 16000 / ADPCM_SAMPLES_PER_BLOCK;
+
+    assert_eq!(manifest.segments[0].samples as usize, blocks * ADPCM_SAMPLES_PER_BLOCK);
+    assert_eq!(manifest.segments[0].length as usize, blocks * ADPCM_BLOCK_ALIGN);
+    assert_eq!(manifest.segments[1].start_sample, manifest.segments[0].samples);
+
+    let recording = store.stored_recording("cafe0011").unwrap();
+    let download = recording.download(&recording.lengths().unwrap(), false, 16000).unwrap();
+
+    assert!(recording.is_stitchable());
+    assert_eq!(download.codec, RecordingCodec::ImaAdpcm);
+    assert_eq!(download.length, Some(store.recording_length("cafe0011").unwrap()));
+
+    // The appended blocks decode to the same audio as the segments one by one
+    let stitched = read_all(RecordingCodec::ImaAdpcm.decoder(std::io::Cursor::new(read_all(download.reader))));
+
+    assert_eq!(stitched, read_all(store.open_recording("cafe0011").unwrap()));
+    assert_eq!(stitched.len() as u64, manifest.samples().unwrap() * 2);
+
+    std::fs::remove_dir_all(&directory).ok();
+}
+
+#[test]
+fn flac_segments_play_on_their_own_and_download_decoded_as_a_whole() {
+    let directory = temporary_directory("flac");
+    let store = FileStore::new(&directory)
+        .with_codec(RecordingCodec::Flac)
+        .with_segments(Duration::from_secs(1));
+    let pcm = tone(16000 * 2 + 1000);
+
+    record(&store, "cafe0012", &pcm);
+
+    let second = store.stored_segment("cafe0012", 1).unwrap();
+    let download = second.download(&second.lengths().unwrap(), false, 16000).unwrap();
+
+    assert_eq!(download.codec, RecordingCodec::Flac);
+    assert!(download.header.is_empty());
+
+    let segment = read_all(download.reader);
+    let start = store.load_manifest("cafe0012").unwrap().segments[1].start_sample as usize * 2;
+
+    assert_eq!(&segment[0..4], b"fLaC");
+    assert_eq!(
+        read_all(RecordingCodec::Flac.decoder(std::io::Cursor::new(segment))),
+        pcm[start..start + 12288 * 2]
+    );
+
+    let recording = store.stored_recording("cafe0012").unwrap();
+    let download = recording.download(&recording.lengths().unwrap(), false, 16000).unwrap();
+
+    assert!(recording.is_stitchable() == false);
+    assert_eq!(download.codec, RecordingCodec::Pcm);
+    assert_eq!(download.length, Some(pcm.len() as u64));
+    assert_eq!(read_all(download.reader), pcm);
+
+    std::fs::remove_dir_all(&directory).ok();
+}
+
+#[test]
+fn the_segment_being_written_is_listed_before_it_is_complete() {
+    let directory = temporary_directory("open");
+    let manifest_path = directory.join("manifest.json");
+
+    {
+        let mut writer = SegmentWriter::create(&directory, RecordingCodec::Pcm, 16000, Duration::from_secs(1)).unwrap();
+
+        writer.write_all(&tone(16000 + 100)).unwrap();
+        writer.flush().unwrap();
+
+        let manifest = SegmentManifest::load(&manifest_path).unwrap();
+
+        assert_eq!(manifest.segments.len(), 2);
+        assert!(manifest.segments[0].complete);
+        assert!(manifest.segments[1].complete == false);
+        assert_eq!(manifest.samples(), None);
+        assert!(manifest.verify(&directory).is_empty());
+    }
+
+    let manifest = SegmentManifest::load(&manifest_path).unwrap();
+
+    assert!(manifest.is_complete());
+    assert_eq!(manifest.segments[1].samples, 100);
+
+    std::fs::remove_dir_all(&directory).ok();
+}
+
+#[test]
+fn verify_finds_damaged_and_missing_segments() {
+    let directory = temporary_directory("verify");
+    let store = FileStore::new(&directory).with_segments(Duration::from_secs(1));
+
+    record(&store, "cafe0013", &tone(16000 * 3));
+
+    let manifest = store.load_manifest("cafe0013").unwrap();
+    let meeting = store.meeting_directory("cafe0013");
+
+    assert!(manifest.verify(&meeting).is_empty());
+
+    let mut damaged = std::fs::read(meeting.join(&manifest.segments[0].file)).unwrap();
+    damaged[100] ^= 0x40;
+    std::fs::write(meeting.join(&manifest.segments[0].file), damaged).unwrap();
+    std::fs::remove_file(meeting.join(&manifest.segments[2].file)).unwrap();
+
+    assert_eq!(manifest.verify(&meeting), vec![0, 2]);
+
+    std::fs::remove_dir_all(&directory).ok();
+}
+
+#[test]
+fn a_recording_created_again_carries_on_after_a_segment_that_was_cut_short() {
+    let directory = temporary_directory("resume");
+
+    let mut writer = SegmentWriter::create(&directory, RecordingCodec::Pcm, 16000, Duration::from_secs(1)).unwrap();
+    writer.write_all(&tone(16000 + 301)[..(16000 + 301) * 2 - 1]).unwrap();
+
+    // As if the card filled up or the power went, the second segment is never finished
+    std::mem::forget(writer);
+
+    let mut writer = SegmentWriter::create(&directory, RecordingCodec::Pcm, 16000, Duration::from_secs(1)).unwrap();
+    writer.write_all(&tone(100)).unwrap();
+    drop(writer);
+
+    let manifest = SegmentManifest::load(&directory.join("manifest.json")).unwrap();
+
+    assert_eq!(manifest.segments.len(), 3);
+    assert!(manifest.is_complete());
+    assert_eq!(manifest.segments[1].samples, 300);
+    assert_eq!(manifest.segments[1].length, 600);
+    assert_eq!(manifest.segments[2].start_sample, 16300);
+    assert!(manifest.verify(&directory).is_empty());
+
+    std::fs::remove_dir_all(&directory).ok();
+}
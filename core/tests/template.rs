@@ -0,0 +1,60 @@
+use echosense_core::template::{escape_html, Escape, Template};
+
+#[test]
+fn html_is_escaped() {
+    assert_eq!(
+        escape_html("<script>alert('hi') && \"bye\"</script>"),
+        "&lt;script&gt;alert(&#39;hi&#39;) &amp;&amp; &quot;bye&quot;&lt;/script&gt;"
+    );
+    assert_eq!(escape_html("Grüße, plain text"), "Grüße, plain text");
+}
+
+#[test]
+fn variables_are_escaped_unless_raw() {
+    let template = Template::from_str("<p title=\"{{ title }}\">{{title}}</p>{{{ fragment }}}", Escape::Html);
+
+    assert_eq!(
+        template.render(&[("title", "\"Q3\" <b>"), ("fragment", "<li>kept</li>")]),
+        "<p title=\"&quot;Q3&quot; &lt;b&gt;\">&quot;Q3&quot; &lt;b&gt;</p><li>kept</li>"
+    );
+}
+
+#[test]
+fn plain_text_templates_are_not_escaped() {
+    let template = Template::from_str("Summary: {{summary}}", Escape::None);
+
+    assert_eq!(template.render(&[("summary", "a < b & c")]), "Summary: a < b & c");
+}
+
+#[test]
+fn undefined_variables_are_left_out() {
+    let template = Template::from_str("[{{missing}}]", Escape::Html);
+
+    assert_eq!(template.render(&[]), "[]");
+}
+
+#[test]
+fn unterminated_braces_are_kept_as_they_are() {
+    let template = Template::from_str("{{title}} and {{ title", Escape::Html);
+
+    assert_eq!(template.render(&[("title", "<x>")]), "&lt;x&gt; and {{ title");
+    assert_eq!(
+        Template::from_str("{{{raw}} rest", Escape::Html).render(&[("raw", "<x>")]),
+        "{{{raw}} rest"
+    );
+}
+
+#[test]
+fn a_template_file_takes_precedence_over_the_default() {
+    let directory = std::env::temp_dir().join(format!("echosense-template-{}", std::process::id()));
+    std::fs::create_dir_all(&directory).unwrap();
+    std::fs::write(directory.join("report.txt"), "custom {{name}}").unwrap();
+
+    let custom = Template::load(directory.join("report.txt"), "default {{name}}", Escape::None);
+    let default = Template::load(directory.join("missing.txt"), "default {{name}}", Escape::None);
+
+    assert_eq!(custom.render(&[("name", "x")]), "custom x");
+    assert_eq!(default.render(&[("name", "x")]), "default x");
+
+    std::fs::remove_dir_all(&directory).ok();
+}
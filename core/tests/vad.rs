@@ -0,0 +1,223 @@
+mod common;
+
+use echosense_core::meeting::{Meeting, SilentRegion, TalkTime};
+use echosense_core::vad::{Timeline, VadConfig, Vad, VoiceGate};
+
+use common::sine;
+
+const SAMPLE_RATE: u32 = 16000;
+// 31.25 ms, the device's frame size
+const FRAME: usize = 500;
+
+// Deterministic white noise, which crosses zero about every other sample
+fn noise(amplitude: f32, seconds: f32) -> Vec<i16> {
+    let mut state = 0x2545_f491u32;
+
+    (0..(seconds * SAMPLE_RATE as f32) as usize)
+        .map(|_| {
+            state = state.wrapping_mul(1_664_525).wrapping_add(1_013_904_223);
+            ((state >> 16) as f32 / 32768.0 - 1.0) * amplitude
+        })
+        .map(|sample| sample as i16)
+        .collect()
+}
+
+fn silence(seconds: f32) -> Vec<i16> {
+    vec![0; (seconds * SAMPLE_RATE as f32) as usize]
+}
+
+fn frames(samples: &[i16]) -> Vec<Vec<u8>> {
+    samples
+        .chunks(FRAME)
+        .map(|frame| frame.iter().flat_map(|sample| sample.to_le_bytes()).collect())
+        .collect()
+}
+
+fn detect(vad: &mut Vad, samples: &[i16]) -> Vec<bool> {
+    samples.chunks(FRAME).map(|frame| vad.is_speech(frame)).collect()
+}
+
+#[test]
+fn detects_speech_and_ignores_silence() {
+    let mut vad = Vad::new(SAMPLE_RATE, &VadConfig::default());
+
+    assert!(detect(&mut vad, &silence(1.0)).iter().all(|speech| *speech == false));
+    assert!(detect(&mut vad, &sine(300.0, 3000.0, SAMPLE_RATE, 1.0)).iter().all(|speech| *speech));
+}
+
+#[test]
+fn hangover_bridges_short_pauses() {
+    let config = VadConfig {
+        hangover_ms: 500,
+        ..VadConfig::default()
+    };
+
+    let mut vad = Vad::new(SAMPLE_RATE, &config);
+    detect(&mut vad, &sine(300.0, 3000.0, SAMPLE_RATE, 1.0));
+
+    let pause = detect(&mut vad, &silence(1.0));
+
+    // 500 ms are 16 frames
+    assert!(pause[..16].iter().all(|speech| *speech));
+    assert!(pause[17..].iter().all(|speech| *speech == false));
+}
+
+#[test]
+fn adapts_to_steady_background_noise() {
+    let mut vad = Vad::new(SAMPLE_RATE, &VadConfig::default());
+
+    // A fan at -35 dBFS that the AGC lifted well above the fixed threshold
+    let fan = detect(&mut vad, &noise(1000.0, 6.0));
+
+    assert!(fan[fan.len() * 2 / 3..].iter().all(|speech| *speech == false));
+    assert!(vad.noise_floor_dbfs() > -45.0);
+
+    // Speech is still picked up on top of it
+    let mut talking = noise(1000.0, 1.0);
+
+    for (sample, voice) in talking.iter_mut().zip(sine(300.0, 8000.0, SAMPLE_RATE, 1.0)) {
+        *sample = sample.saturating_add(voice);
+    }
+
+    assert!(detect(&mut vad, &talking).iter().all(|speech| *speech));
+}
+
+#[test]
+fn noise_like_frames_need_to_be_louder() {
+    let config = VadConfig::default();
+
+    // Both are 3 dB above the threshold plus margin over a floor at the threshold, only the tone passes
+    let level = 10f32.powf((config.threshold_dbfs + config.margin_db + 3.0) / 20.0) * i16::MAX as f32;
+
+    let mut vad = Vad::new(SAMPLE_RATE, &config);
+    assert!(vad.is_speech(&sine(300.0, level * 2f32.sqrt(), SAMPLE_RATE, 0.03125)));
+
+    let mut vad = Vad::new(SAMPLE_RATE, &config);
+    assert!(vad.is_speech(&noise(level * 3f32.sqrt(), 0.03125)) == false);
+}
+
+#[test]
+fn gate_holds_back_silence_and_sends_the_pre_roll() {
+    let mut gate = VoiceGate::new(SAMPLE_RATE, VadConfig::default());
+
+    let silent = frames(&silence(2.0))
+        .iter()
+        .map(|frame| gate.process(frame).len())
+        .sum::<usize>();
+
+    assert_eq!(silent, 0);
+    assert!(gate.is_streaming() == false);
+
+    let frame = frames(&sine(300.0, 3000.0, SAMPLE_RATE, 0.03125)).remove(0);
+    let sent = gate.process(&frame);
+
+    // 300 ms of pre-roll fit nine frames, plus the frame that triggered the detector
+    assert_eq!(sent.len(), 10);
+    assert_eq!(sent.last(), Some(&frame));
+    assert!(gate.is_streaming());
+}
+
+#[test]
+fn gate_sends_keep_alive_during_long_silences() {
+    let config = VadConfig {
+        keep_alive_ms: 2000,
+        ..VadConfig::default()
+    };
+
+    let mut gate = VoiceGate::new(SAMPLE_RATE, config);
+    let mut keep_alives = vec![];
+
+    for frame in frames(&silence(10.0)) {
+        keep_alives.extend(gate.process(&frame));
+    }
+
+    // 100 ms of digital silence every two seconds
+    assert_eq!(keep_alives.len(), 5);
+    assert!(keep_alives.iter().all(|frame| frame.len() == 3200 && frame.iter().all(|byte| *byte == 0)));
+}
+
+#[test]
+fn gate_streams_everything_when_pausing_is_disabled() {
+    let config = VadConfig {
+        pause_streaming: false,
+        ..VadConfig::default()
+    };
+
+    let mut gate = VoiceGate::new(SAMPLE_RATE, config);
+    let mut meeting = Meeting::new("cafe0003");
+
+    let mut samples = silence(3.0);
+    samples.extend(sine(300.0, 3000.0, SAMPLE_RATE, 1.0));
+
+    let sent = frames(&samples).iter().map(|frame| gate.process(frame).len()).sum::<usize>();
+    gate.finish();
+    gate.update(&mut meeting);
+
+    assert_eq!(sent, 128);
+    assert_eq!(meeting.talk_time.streamed_ms, 4000);
+    assert_eq!(meeting.silent_regions, vec![SilentRegion { start_ms: 0, end_ms: 3000 }]);
+}
+
+#[test]
+fn gate_records_silent_regions_and_talk_time() {
+    let config = VadConfig {
+        hangover_ms: 500,
+        ..VadConfig::default()
+    };
+
+    let mut gate = VoiceGate::new(SAMPLE_RATE, config);
+    let mut meeting = Meeting::new("cafe0003");
+
+    // Speech, a pause too short to list, speech and silence until the end, the hangover counts as speech
+    let mut samples = sine(300.0, 3000.0, SAMPLE_RATE, 2.0);
+    samples.extend(silence(1.5));
+    samples.extend(sine(300.0, 3000.0, SAMPLE_RATE, 2.0));
+    samples.extend(silence(5.0));
+
+    for frame in frames(&samples) {
+        gate.process(&frame);
+
+        if gate.is_due() {
+            gate.update(&mut meeting);
+        }
+    }
+
+    gate.finish();
+    gate.update(&mut meeting);
+
+    assert_eq!(meeting.silent_regions, vec![SilentRegion { start_ms: 6000, end_ms: 10500 }]);
+    assert_eq!(meeting.talk_time.speech_ms + meeting.talk_time.silence_ms, 10500);
+    assert_eq!(meeting.talk_time.speech_ms, 5000);
+    assert!((meeting.talk_time.speech_ratio() - 0.476).abs() < 0.01);
+}
+
+#[test]
+fn timeline_maps_transcripts_back_onto_the_recording() {
+    let mut gate = VoiceGate::new(SAMPLE_RATE, VadConfig::default());
+    let timeline = gate.timeline();
+
+    assert_eq!(timeline.lock().unwrap().to_audio_ms(1234), 1234);
+
+    let mut samples = silence(4.0);
+    samples.extend(sine(300.0, 3000.0, SAMPLE_RATE, 1.0));
+
+    for frame in frames(&samples) {
+        gate.process(&frame);
+    }
+
+    let timeline = timeline.lock().unwrap();
+
+    // The stream starts with the pre-roll, 281.25 ms before the tone
+    assert_eq!(timeline.streamed_ms(), 1281);
+    assert_eq!(timeline.to_audio_ms(0), 3718);
+    assert_eq!(timeline.to_audio_ms(1000), 4718);
+}
+
+#[test]
+fn timeline_without_pauses_is_the_identity() {
+    let timeline = Timeline::new(SAMPLE_RATE);
+
+    assert_eq!(timeline.to_audio_ms(0), 0);
+    assert_eq!(timeline.to_audio_ms(90_000), 90_000);
+    assert_eq!(TalkTime::default().speech_ratio(), 0.0);
+}
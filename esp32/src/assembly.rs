@@ -1,20 +1,23 @@
 use std::sync::mpsc::{channel, Receiver};
 use std::time::Duration;
 
+use echosense_core::assembly::ResponseBuffer;
+use echosense_core::error::CoreError;
+use echosense_core::platform::{HttpClient, HttpResponse, Method, WsClient};
+use echosense_core::store::StoredRecording;
 use esp_idf_svc::hal::delay::FreeRtos;
 use esp_idf_svc::http::client::{Configuration, Connection, EspHttpConnection};
-use esp_idf_svc::http::Method;
 use esp_idf_svc::io::{Read, Write};
-use esp_idf_svc::sys::EspError;
 use esp_idf_svc::tls::X509;
 use esp_idf_svc::ws::client::{EspWebSocketClient, EspWebSocketClientConfig, WebSocketEventType};
+use esp_idf_svc::ws::FrameType;
 use log::{error, info};
-use serde::de::DeserializeOwned;
-use serde::{Deserialize, Serialize};
+
+pub use echosense_core::assembly::{AssemblyResponse, SummarizeRequest, UploadResponse};
 
 use crate::custom_error::CustomError;
-use crate::file_server::Transcription;
-use crate::stream_audio_writer::AudioWriter;
+use crate::stream_audio_writer::{AudioWriter, PCM_BITS_PER_SAMPLE, PCM_CHANNELS};
+use crate::SAMPLE_RATE_HZ;
 
 const SERVER_ROOT_CERT: &[u8] = b"
 -----BEGIN CERTIFICATE-----
@@ -44,268 +47,118 @@ slI2yayq0n2TXoHyNCLEH8rpsJRVILFsg0jc7BaFrMnF462+ajSehgj12IidNeRN
 4zl+EoNaWdpnWndvSpAEkq2P
 -----END CERTIFICATE-----\0";
 
-#[derive(Debug, Serialize, Deserialize)]
-#[serde(tag = "message_type")]
-pub enum AssemblyResponse {
-    PartialTranscript { text: String, created: String },
-    FinalTranscript { text: String, created: String },
-    SessionBegins { session_id: String },
-    SessionInformation { audio_duration_seconds: f32 },
-    SessionTerminated,
-}
-
-#[derive(Debug, Serialize)]
-pub struct SummarizeRequest {
-    pub context: String,
-    pub final_model: String,
-    pub max_output_size: u16,
-    pub temperature: f32,
-    pub input_text: String,
-    pub answer_format: String,
-}
-
-#[derive(Debug, Deserialize)]
-pub struct SummarizeResponse {
-    pub request_id: String,
-    pub response: String,
-}
-
-impl Default for SummarizeRequest {
-    fn default() -> Self {
-        SummarizeRequest {
-            context: String::new(),
-            final_model: "anthropic/claude-3-5-sonnet".to_string(),
-            max_output_size: 3000,
-            temperature: 0.0,
-            input_text: String::new(),
-            answer_format: "bullet points".to_string(),
-        }
-    }
-}
-
-#[derive(Debug, Serialize, Deserialize)]
-pub struct TokenResponse {
-    token: String,
-}
-
-#[derive(Debug, Serialize, Deserialize)]
-pub struct UploadResponse {
-    pub upload_url: String,
-}
-
-#[derive(Debug, Serialize)]
-pub struct TranscribeRequest {
-    audio_url: String,
-}
+const HTTP_RESPONSE_CHUNK: usize = 1000;
 
-#[derive(Debug, Serialize)]
-pub struct AskQuestionQuestion {
-    question: String,
-    answer_format: String,
-}
+pub type Assembly = echosense_core::assembly::Assembly<EspHttpClient>;
 
-#[derive(Debug, Serialize)]
-pub struct AskQuestionRequest {
-    questions: Vec<AskQuestionQuestion>,
-    input_text: String,
-}
+#[derive(Clone, Copy, Default)]
+pub struct EspHttpClient;
 
-#[derive(Debug, Deserialize)]
-pub struct AskQuestionResponseItem {
-    pub question: String,
-    pub answer: String,
-}
-
-#[derive(Debug, Deserialize)]
-pub struct AskQuestionResponse {
-    pub request_id: String,
-    pub response: Vec<AskQuestionResponseItem>,
-}
+impl EspHttpClient {
+    fn connect() -> Result<EspHttpConnection, CustomError> {
+        let mut configuration = Configuration::default();
+        configuration.timeout = Some(Duration::from_secs(30));
 
-#[derive(Debug, Deserialize, PartialEq)]
-#[serde(rename_all = "snake_case")]
-pub enum TranscriptionStatus {
-    Queued,
-    Processing,
-    Completed,
-    Error,
-}
+        Ok(EspHttpConnection::new(&configuration)?)
+    }
 
-#[derive(Debug, Deserialize)]
-pub struct TranscribeResponse {
-    pub id: String,
-    pub status: TranscriptionStatus,
-    pub text: Option<String>,
-}
+    fn read_response(client: &mut EspHttpConnection) -> Result<HttpResponse, CustomError> {
+        let mut buffer = [0; HTTP_RESPONSE_CHUNK];
+        let mut body = vec![];
 
-#[derive(Clone)]
-pub struct Assembly {
-    api_key: String,
-}
+        while let Ok(length) = client.read(&mut buffer) {
+            if length == 0 {
+                break;
+            }
+            body.extend_from_slice(&buffer[..length])
+        }
 
-impl Assembly {
-    pub fn new<S: Into<String>>(api_key: S) -> Result<Self, EspError> {
-        Ok(Assembly {
-            api_key: api_key.into(),
+        Ok(HttpResponse {
+            status: client.status(),
+            body,
         })
     }
+}
 
-    pub fn summarize_transcripts(
+impl HttpClient for EspHttpClient {
+    fn request(
         &mut self,
-        transcription: Vec<Transcription>,
-    ) -> Result<SummarizeResponse, CustomError> {
-        let headers = [
-            ("Authorization", self.api_key.as_str()),
-            ("Content-Type", "application/json"),
-        ];
+        method: Method,
+        url: &str,
+        headers: &[(&str, &str)],
+        body: Option<&[u8]>,
+    ) -> Result<HttpResponse, CoreError> {
+        let method = match method {
+            Method::Get => esp_idf_svc::http::Method::Get,
+            Method::Post => esp_idf_svc::http::Method::Post,
+        };
 
-        let mut request = SummarizeRequest::default();
+        let mut client = Self::connect()?;
 
-        request.input_text = transcription
-            .into_iter()
-            .map(|transcription| transcription.text)
-            .collect::<Vec<_>>()
-            .join("\n");
+        client.initiate_request(method, url, headers).map_err(CustomError::from)?;
 
-        let mut configuration = Configuration::default();
-        configuration.timeout = Some(Duration::from_secs(30));
+        if let Some(body) = body {
+            client.write_all(body).map_err(CustomError::from)?;
+        }
 
-        let mut client = EspHttpConnection::new(&configuration)?;
+        client.flush().map_err(CustomError::from)?;
+        client.initiate_response().map_err(CustomError::from)?;
 
-        client.initiate_request(
-            Method::Post,
-            "https://api.assemblyai.com/lemur/v3/generate/summary",
-            &headers,
-        )?;
-        client.write_all(&serde_json::to_vec(&request)?)?;
-        client.flush()?;
-        client.initiate_response()?;
-        self.handle_request::<SummarizeResponse, 5000>(client)
+        Ok(Self::read_response(&mut client)?)
     }
+}
 
-    pub fn get_transcript<T: Into<String>>(
-        &mut self,
-        transcript_id: T,
-    ) -> Result<TranscribeResponse, CustomError> {
-        let headers = [("Authorization", self.api_key.as_str())];
-
-        let url = format!(
-            "https://api.assemblyai.com/v2/transcript/{}",
-            transcript_id.into()
-        );
-        let mut client = EspHttpConnection::new(&Default::default())?;
-
-        client.initiate_request(Method::Get, url.as_str(), &headers)?;
-        client.flush()?;
-        client.initiate_response()?;
+pub struct EspWsClient(EspWebSocketClient<'static>);
 
-        self.handle_request::<TranscribeResponse, 1000>(client)
+impl WsClient for EspWsClient {
+    fn send_binary(&mut self, data: &[u8]) -> Result<(), CoreError> {
+        Ok(self.0.send(FrameType::Binary(false), data).map_err(CustomError::from)?)
     }
 
-    pub fn ask_question<T: Into<String>>(
-        &mut self,
-        question: T,
-        transcription: Vec<Transcription>,
-    ) -> Result<AskQuestionResponse, CustomError> {
-        let headers = [
-            ("Authorization", self.api_key.as_str()),
-            ("Content-Type", "application/json"),
-        ];
-
-        let mut client = EspHttpConnection::new(&Default::default())?;
-
-        client.initiate_request(
-            Method::Post,
-            "https://api.assemblyai.com/lemur/v3/generate/question-answer",
-            &headers,
-        )?;
-
-        let request = AskQuestionRequest {
-            questions: vec![AskQuestionQuestion {
-                question: question.into(),
-                answer_format: "short sentence".to_string(),
-            }],
-            input_text: transcription
-                .into_iter()
-                .map(|transcription| transcription.text)
-                .collect::<Vec<_>>()
-                .join("\n"),
-        };
-
-        client.write_all(&serde_json::to_vec(&request)?)?;
-        client.flush()?;
-        client.initiate_response()?;
-
-        self.handle_request::<AskQuestionResponse, 1000>(client)
+    fn send_text(&mut self, text: &str) -> Result<(), CoreError> {
+        Ok(self.0.send(FrameType::Text(false), text.as_bytes()).map_err(CustomError::from)?)
     }
+}
 
-    pub fn transcribe<T: Into<String>>(
+// The parts of the SDK that stream straight from/to the esp-idf clients and can't go through HttpClient
+pub trait AssemblyStreaming {
+    fn upload<T: std::io::Read, const BUFFER_SIZE: usize>(
         &mut self,
-        audio_url: T,
-    ) -> Result<TranscribeResponse, CustomError> {
-        let headers = [
-            ("Authorization", self.api_key.as_str()),
-            ("Content-Type", "application/json"),
-        ];
-
-        let mut client = EspHttpConnection::new(&Default::default())?;
-
-        client.initiate_request(
-            Method::Post,
-            "https://api.assemblyai.com/v2/transcript",
-            &headers,
-        )?;
-
-        let request = TranscribeRequest {
-            audio_url: audio_url.into(),
-        };
-
-        client.write_all(&serde_json::to_vec(&request)?)?;
-        client.flush()?;
-        client.initiate_response()?;
-
-        self.handle_request::<TranscribeResponse, 1000>(client)
-    }
+        file: &mut T,
+    ) -> Result<UploadResponse, CustomError>;
 
-    pub fn transcribe_wait<T: Into<String>>(
+    // Sends a stored recording as it is on the card, its segments one after the other, with the header of its codec
+    // in front. Segments that can't be stitched are decoded to PCM on the way
+    fn upload_recording<const BUFFER_SIZE: usize>(
         &mut self,
-        audio_url: T,
-    ) -> Result<TranscribeResponse, CustomError> {
-        let response = self.transcribe(audio_url)?;
+        recording: &StoredRecording,
+    ) -> Result<UploadResponse, CustomError>;
 
-        loop {
-            if let Ok(response) = self.get_transcript(&response.id) {
-                match response.status {
-                    TranscriptionStatus::Completed => break Ok(response),
-                    TranscriptionStatus::Queued | TranscriptionStatus::Processing => {
-                        FreeRtos::delay_ms(100)
-                    }
-                    TranscriptionStatus::Error => panic!("failed to transcribe audio..."),
-                }
-            }
-        }
-    }
+    fn stream(&mut self, sample_rate: u32) -> Result<(EspWsClient, Receiver<AssemblyResponse>), CustomError>;
+}
 
-    pub fn upload<T: std::io::Read, const BUFFER_SIZE: usize>(
+impl AssemblyStreaming for Assembly {
+    fn upload<T: std::io::Read, const BUFFER_SIZE: usize>(
         &mut self,
         file: &mut T,
     ) -> Result<UploadResponse, CustomError> {
         let headers = [
-            ("Authorization", self.api_key.as_str()),
+            ("Authorization", self.api_key()),
             ("Content-Type", "application/octet-stream"),
         ];
 
         let mut client = EspHttpConnection::new(&Default::default())?;
 
         client.initiate_request(
-            Method::Post,
-            "https://api.assemblyai.com/v2/upload",
+            esp_idf_svc::http::Method::Post,
+            &self.url("/v2/upload"),
             &headers,
         )?;
 
         {
             let mut buffer = [0u8; BUFFER_SIZE];
-            let mut audio = AudioWriter::initialize::<BUFFER_SIZE>(&mut client)?;
+            let mut audio =
+                AudioWriter::initialize::<BUFFER_SIZE>(&mut client, PCM_CHANNELS, SAMPLE_RATE_HZ, PCM_BITS_PER_SAMPLE)?;
 
             while let Ok(length) = file.read(&mut buffer) {
                 if length == 0 {
@@ -323,33 +176,49 @@ impl Assembly {
         client.flush()?;
         client.initiate_response()?;
 
-        self.handle_request::<UploadResponse, 500>(client)
+        Ok(EspHttpClient::read_response(&mut client)?.json()?)
     }
 
-    pub fn create_temporary_token(&mut self) -> Result<TokenResponse, CustomError> {
+    fn upload_recording<const BUFFER_SIZE: usize>(
+        &mut self,
+        recording: &StoredRecording,
+    ) -> Result<UploadResponse, CustomError> {
+        let mut download = recording.download(&recording.lengths()?, false, SAMPLE_RATE_HZ)?;
+
         let headers = [
-            ("Authorization", self.api_key.as_str()),
-            ("Content-Type", "application/json"),
+            ("Authorization", self.api_key()),
+            ("Content-Type", download.codec.content_type()),
         ];
 
         let mut client = EspHttpConnection::new(&Default::default())?;
 
         client.initiate_request(
-            Method::Post,
-            "https://api.assemblyai.com/v2/realtime/token",
+            esp_idf_svc::http::Method::Post,
+            &self.url("/v2/upload"),
             &headers,
         )?;
 
-        let payload = b"{\"expires_in\": 3600}";
+        client.write_all(&download.header)?;
+
+        let mut buffer = [0u8; BUFFER_SIZE];
+
+        loop {
+            let read = std::io::Read::read(&mut download.reader, &mut buffer)?;
+
+            if read == 0 {
+                break;
+            }
+
+            client.write_all(&buffer[..read])?;
+        }
 
-        client.write_all(payload)?;
         client.flush()?;
         client.initiate_response()?;
 
-        self.handle_request::<TokenResponse, 100>(client)
+        Ok(EspHttpClient::read_response(&mut client)?.json()?)
     }
 
-    pub fn stream(&mut self, sample_rate: u32) -> Result<(EspWebSocketClient<'static>, Receiver<AssemblyResponse>), CustomError> {
+    fn stream(&mut self, sample_rate: u32) -> Result<(EspWsClient, Receiver<AssemblyResponse>), CustomError> {
         let token = self.create_temporary_token()?.token;
 
         let config = EspWebSocketClientConfig {
@@ -359,12 +228,12 @@ impl Assembly {
         };
 
         let timeout = Duration::from_secs(30);
-        let endpoint = format!("wss://api.assemblyai.com/v2/realtime/ws?sample_rate={}&enable_extra_session_information=true&token={}", sample_rate, token);
+        let endpoint = self.realtime_url(sample_rate, &token);
 
         let (sender, receiver) = channel::<AssemblyResponse>();
-        let mut response_buffer = vec![];
+        let mut response_buffer = ResponseBuffer::default();
 
-        let mut client = EspWebSocketClient::new(&endpoint, &config, timeout, move |event| {
+        let client = EspWebSocketClient::new(&endpoint, &config, timeout, move |event| {
             if let Ok(event) = event {
                 match event.event_type {
                     WebSocketEventType::BeforeConnect => info!("BeforeConnect!"),
@@ -373,11 +242,7 @@ impl Assembly {
                     WebSocketEventType::Close(_) => {}
                     WebSocketEventType::Closed => info!("Closed!"),
                     WebSocketEventType::Text(text) => {
-                        response_buffer.extend_from_slice(text.as_bytes());
-
-                        if let Ok(response) = serde_json::from_slice::<AssemblyResponse>(response_buffer.as_slice()) {
-                            response_buffer.clear();
-
+                        if let Some(response) = response_buffer.push(text.as_bytes()) {
                             if let Err(error) = sender.send(response) {
                                 error!("failed to send response: {}", error);
                             }
@@ -394,23 +259,6 @@ impl Assembly {
             FreeRtos::delay_ms(10);
         }
 
-        Ok((client, receiver))
-    }
-
-    fn handle_request<T: DeserializeOwned, const BUFFER_SIZE: usize>(
-        &self,
-        mut client: EspHttpConnection,
-    ) -> Result<T, CustomError> {
-        let mut buffer = [0; BUFFER_SIZE];
-        let mut response = vec![];
-
-        while let Ok(length) = client.read(&mut buffer) {
-            if length == 0 {
-                break;
-            }
-            response.extend_from_slice(&buffer[..length])
-        }
-
-        Ok(serde_json::from_slice::<T>(&response)?)
+        Ok((EspWsClient(client), receiver))
     }
 }
@@ -0,0 +1,13 @@
+use std::time::{SystemTime, UNIX_EPOCH};
+
+// The clock starts in 1970 until SNTP sets it, anything before this (2024-01-01) means it isn't set yet
+const CLOCK_SET_AFTER: u64 = 1_704_067_200;
+
+// Unix seconds, once SNTP has set the clock
+pub fn unix_time() -> Option<u64> {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .ok()
+        .map(|time| time.as_secs())
+        .filter(|seconds| *seconds >= CLOCK_SET_AFTER)
+}
@@ -5,9 +5,9 @@ use std::string::FromUtf8Error;
 use std::sync::mpsc::{SendError, Sender};
 use std::sync::{MutexGuard, PoisonError};
 
+use echosense_core::error::CoreError;
 use esp_idf_svc::io::{EspIOError, ReadExactError};
 use esp_idf_svc::sys::EspError;
-use qrcode_generator::QRCodeError;
 use riff_wave::WriteError;
 
 use crate::file_server::WebsocketMessage;
@@ -25,10 +25,15 @@ pub enum CustomError {
     FromUtf8Error(FromUtf8Error),
     WebsocketSendError(SendError<WebsocketMessage>),
     ChannelSendError,
-    QRCodeError(QRCodeError),
     DisplayError(display_interface::DisplayError),
     WriteWavFileError(WriteError),
-    FailedToSendEmail(String)
+    FailedToSendEmail(String),
+    FirmwareUpdateError(String),
+    MeetingNotFound(String),
+    // Neither an SD card nor the internal flash is mounted
+    NoStorage,
+    WebhookDeliveryError(u16),
+    CoreError(CoreError),
 }
 
 impl Display for CustomError {
@@ -123,8 +128,18 @@ impl From<display_interface::DisplayError> for CustomError {
     }
 }
 
-impl From<QRCodeError> for CustomError {
-    fn from(error: QRCodeError) -> Self {
-        CustomError::QRCodeError(error)
+impl From<CoreError> for CustomError {
+    fn from(error: CoreError) -> Self {
+        CustomError::CoreError(error)
     }
-}
\ No newline at end of file
+}
+
+// Lets the esp-idf implementations of the core traits use `?` on anything that already converts into a CustomError
+impl From<CustomError> for CoreError {
+    fn from(error: CustomError) -> Self {
+        match error {
+            CustomError::CoreError(error) => error,
+            error => CoreError::PlatformError(format!("{:?}", error)),
+        }
+    }
+}
@@ -1,7 +1,4 @@
 use crate::custom_error::CustomError;
-use crate::images::dev_to_logo::DEV_TO_LOGO;
-use crate::images::wifi_icon::WIFI_ICON;
-use crate::qrcode::QRCode;
 use embedded_graphics::image::{Image, ImageRaw};
 use embedded_graphics::pixelcolor::BinaryColor;
 use embedded_graphics::prelude::Point;
@@ -13,21 +10,17 @@ use esp_idf_svc::hal::peripheral::Peripheral;
 use ssd1306::mode::BufferedGraphicsMode;
 use ssd1306::prelude::*;
 use ssd1306::{I2CDisplayInterface, Ssd1306};
-use crate::images::done_icon::DONE_ICON;
+use echosense_core::error::CoreError;
+use echosense_core::framebuffer::{render, FRAMEBUFFER_WIDTH};
+use echosense_core::platform::StatusDisplay;
+
+pub use echosense_core::platform::DrawState;
 
 pub struct Display<'d> {
     state: DrawState,
     driver: Ssd1306<I2CInterface<I2cDriver<'d>>, DisplaySize128x64, BufferedGraphicsMode<DisplaySize128x64>>,
 }
 
-#[derive(PartialEq)]
-pub enum DrawState {
-    Initializing,
-    Wifi,
-    Done,
-    QRCode(String),
-}
-
 impl<'d> Display<'d> {
     pub fn new<I2C: I2c>(
         i2c: impl Peripheral<P=I2C> + 'd,
@@ -58,16 +51,11 @@ impl<'d> Display<'d> {
             return Ok(());
         }
 
-        let byte = match &state {
-            DrawState::Initializing => DEV_TO_LOGO.to_vec(),
-            DrawState::Wifi => WIFI_ICON.to_vec(),
-            DrawState::Done => DONE_ICON.to_vec(),
-            DrawState::QRCode(content) => QRCode::new(content, 128, 64, 2, (20, 3))?.to_vec(),
-        };
+        let byte = render(&state)?;
 
         self.driver.clear_buffer();
 
-        let raw: ImageRaw<BinaryColor> = ImageRaw::new(&byte, 128);
+        let raw: ImageRaw<BinaryColor> = ImageRaw::new(&byte, FRAMEBUFFER_WIDTH as u32);
         let image = Image::new(&raw, Point::new(0, 0));
 
         image.draw(&mut self.driver)?;
@@ -78,4 +66,10 @@ impl<'d> Display<'d> {
 
         Ok(())
     }
-}
\ No newline at end of file
+}
+
+impl<'d> StatusDisplay for Display<'d> {
+    fn draw(&mut self, state: DrawState) -> Result<(), CoreError> {
+        Ok(Display::draw(self, state)?)
+    }
+}
@@ -0,0 +1,99 @@
+use std::sync::OnceLock;
+
+use echosense_core::encryption::{decode_hex, StorageKey};
+use esp_idf_svc::nvs::{EspDefaultNvsPartition, EspNvs};
+use esp_idf_svc::sys::esp_fill_random;
+use log::{info, warn};
+
+use crate::custom_error::CustomError;
+
+const STORAGE_ENCRYPTION: Option<&str> = option_env!("STORAGE_ENCRYPTION");
+// Only taken when NVS holds no secret yet, e.g. to give a replacement device the secret of the one it replaces
+const STORAGE_SECRET: Option<&str> = option_env!("STORAGE_SECRET");
+const NVS_NAMESPACE: &str = "storage";
+const NVS_SECRET: &str = "secret";
+const SECRET_LENGTH: usize = 32;
+
+// The secret and the key derived from it
+static SECRET: OnceLock<(Vec<u8>, StorageKey)> = OnceLock::new();
+
+// With STORAGE_ENCRYPTION=true everything written under /sdcard is encrypted with a key derived from a secret kept in
+// NVS. It is created on the first boot and survives firmware updates, erasing the flash loses it and with it the card
+pub fn initialize(nvs: EspDefaultNvsPartition) -> Result<(), CustomError> {
+    if matches!(STORAGE_ENCRYPTION, Some("true" | "on" | "1")) == false {
+        info!("storage isn't encrypted");
+        return Ok(());
+    }
+
+    let mut storage = EspNvs::new(nvs, NVS_NAMESPACE, true)?;
+    let mut buffer = [0u8; SECRET_LENGTH];
+
+    let secret = match storage.get_raw(NVS_SECRET, &mut buffer)? {
+        Some(secret) if secret.len() == SECRET_LENGTH => secret.to_vec(),
+        _ => {
+            let secret = match STORAGE_SECRET
+                .filter(|secret| secret.is_empty() == false)
+                .map(|secret| decode_hex(secret).filter(|secret| secret.len() == SECRET_LENGTH))
+            {
+                Some(Some(secret)) => secret,
+                Some(None) => {
+                    warn!(
+                        "STORAGE_SECRET isn't {} hex encoded bytes, a random one is used",
+                        SECRET_LENGTH
+                    );
+                    random_secret()
+                }
+                None => random_secret(),
+            };
+
+            storage.set_raw(NVS_SECRET, &secret)?;
+            info!("created the storage secret");
+
+            secret
+        }
+    };
+
+    let key = StorageKey::derive(&secret);
+    SECRET.set((secret, key)).ok();
+    info!("storage is encrypted");
+
+    Ok(())
+}
+
+fn random_secret() -> Vec<u8> {
+    let mut secret = vec![0u8; SECRET_LENGTH];
+
+    unsafe { esp_fill_random(secret.as_mut_ptr() as *mut _, secret.len()) };
+
+    secret
+}
+
+// None while the storage isn't encrypted
+pub fn storage_key() -> Option<StorageKey> {
+    SECRET.get().map(|(_, key)| key.clone())
+}
+
+// What `echosense-decrypt` needs to read a card pulled from this device
+pub fn secret_hex() -> Option<String> {
+    SECRET
+        .get()
+        .map(|(secret, _)| secret.iter().map(|byte| format!("{:02x}", byte)).collect())
+}
+
+// The secret as the display shows it, 16 digits per line in groups of 4 to copy it down without losing track
+pub fn secret_lines() -> Vec<String> {
+    let Some(secret) = secret_hex() else {
+        return vec!["Storage isn't".to_string(), "encrypted".to_string()];
+    };
+
+    let digits = secret.chars().collect::<Vec<_>>();
+
+    std::iter::once("Storage secret".to_string())
+        .chain(digits.chunks(16).map(|line| {
+            line.chunks(4)
+                .map(|group| group.iter().collect::<String>())
+                .collect::<Vec<_>>()
+                .join(" ")
+        }))
+        .collect()
+}
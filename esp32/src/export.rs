@@ -0,0 +1,190 @@
+use serde::Deserialize;
+
+use crate::custom_error::CustomError;
+use crate::meeting::Meeting;
+use crate::template::escape_html;
+
+// Cues without timing information from the API are shown for this long
+const DEFAULT_CUE_DURATION_MS: u64 = 2000;
+
+#[derive(Debug, Clone, Copy, PartialEq, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum ExportFormat {
+    Srt,
+    Vtt,
+    Markdown,
+    Txt,
+    Json,
+}
+
+impl ExportFormat {
+    pub fn from_name(name: &str) -> Option<Self> {
+        match name.to_ascii_lowercase().as_str() {
+            "srt" => Some(ExportFormat::Srt),
+            "vtt" | "webvtt" => Some(ExportFormat::Vtt),
+            "md" | "markdown" => Some(ExportFormat::Markdown),
+            "txt" | "text" => Some(ExportFormat::Txt),
+            "json" => Some(ExportFormat::Json),
+            _ => None,
+        }
+    }
+
+    pub fn extension(&self) -> &'static str {
+        match self {
+            ExportFormat::Srt => "srt",
+            ExportFormat::Vtt => "vtt",
+            ExportFormat::Markdown => "md",
+            ExportFormat::Txt => "txt",
+            ExportFormat::Json => "json",
+        }
+    }
+
+    pub fn content_type(&self) -> &'static str {
+        match self {
+            ExportFormat::Srt => "application/x-subrip",
+            ExportFormat::Vtt => "text/vtt",
+            ExportFormat::Markdown => "text/markdown",
+            ExportFormat::Txt => "text/plain",
+            ExportFormat::Json => "application/json",
+        }
+    }
+
+    pub fn filename(&self, meeting: &Meeting) -> String {
+        format!("meeting-{}.{}", meeting.id, self.extension())
+    }
+}
+
+pub fn render(meeting: &Meeting, format: ExportFormat) -> Result<String, CustomError> {
+    Ok(match format {
+        ExportFormat::Srt => render_srt(meeting),
+        ExportFormat::Vtt => render_vtt(meeting),
+        ExportFormat::Markdown => render_markdown(meeting),
+        ExportFormat::Txt => render_text(meeting),
+        ExportFormat::Json => serde_json::to_string_pretty(meeting)?,
+    })
+}
+
+fn render_srt(meeting: &Meeting) -> String {
+    let mut output = String::new();
+
+    for (index, transcription) in meeting.transcriptions.iter().enumerate() {
+        let (start, end) = cue_timing(transcription.audio_start, transcription.audio_end);
+
+        output.push_str(&format!(
+            "{}\n{} --> {}\n{}\n\n",
+            index + 1,
+            format_timestamp(start, ','),
+            format_timestamp(end, ','),
+            transcription.text
+        ));
+    }
+
+    output
+}
+
+fn render_vtt(meeting: &Meeting) -> String {
+    let mut output = String::from("WEBVTT\n\n");
+
+    if let Some(summary) = &meeting.summary {
+        // WebVTT comments can't contain "-->"
+        output.push_str(&format!("NOTE Summary\n{}\n\n", summary.replace("-->", "->")));
+    }
+
+    for (index, transcription) in meeting.transcriptions.iter().enumerate() {
+        let (start, end) = cue_timing(transcription.audio_start, transcription.audio_end);
+
+        // Cue text is parsed for markup, so spoken "<" or "&" must not turn into tags
+        output.push_str(&format!(
+            "{}\n{} --> {}\n{}\n\n",
+            index + 1,
+            format_timestamp(start, '.'),
+            format_timestamp(end, '.'),
+            escape_html(&transcription.text)
+        ));
+    }
+
+    output
+}
+
+fn render_markdown(meeting: &Meeting) -> String {
+    let mut output = format!("# {}\n\n", meeting.title());
+
+    if let Some(summary) = &meeting.summary {
+        output.push_str(&format!("## Summary\n\n{}\n\n", summary));
+    }
+
+    if let Some(action_items) = &meeting.action_items {
+        output.push_str(&format!("## Action Items\n\n{}\n\n", action_items));
+    }
+
+    if meeting.questions.is_empty() == false {
+        output.push_str("## Questions & Answers\n\n");
+
+        for question in meeting.questions.iter() {
+            output.push_str(&format!("**Q:** {}\n\n**A:** {}\n\n", question.question, question.answer));
+        }
+    }
+
+    output.push_str("## Transcript\n\n");
+
+    for transcription in meeting.transcriptions.iter() {
+        output.push_str(&format!(
+            "- **{}** {}\n",
+            format_timestamp(transcription.audio_start, '.'),
+            transcription.text
+        ));
+    }
+
+    output
+}
+
+fn render_text(meeting: &Meeting) -> String {
+    let mut output = format!("{}\n\n", meeting.title());
+
+    if let Some(summary) = &meeting.summary {
+        output.push_str(&format!("Summary:\n{}\n\n", summary));
+    }
+
+    if let Some(action_items) = &meeting.action_items {
+        output.push_str(&format!("Action Items:\n{}\n\n", action_items));
+    }
+
+    if meeting.questions.is_empty() == false {
+        output.push_str("Questions & Answers:\n");
+
+        for question in meeting.questions.iter() {
+            output.push_str(&format!("Q: {}\nA: {}\n\n", question.question, question.answer));
+        }
+    }
+
+    output.push_str("Transcript:\n");
+
+    for transcription in meeting.transcriptions.iter() {
+        output.push_str(&format!(
+            "[{}] {}\n",
+            format_timestamp(transcription.audio_start, '.'),
+            transcription.text
+        ));
+    }
+
+    output
+}
+
+fn cue_timing(start: u64, end: u64) -> (u64, u64) {
+    if end > start {
+        (start, end)
+    } else {
+        (start, start + DEFAULT_CUE_DURATION_MS)
+    }
+}
+
+fn format_timestamp(milliseconds: u64, separator: char) -> String {
+    format!(
+        "{:02}:{:02}:{:02}{}{:03}",
+        milliseconds / 3_600_000,
+        (milliseconds / 60_000) % 60,
+        (milliseconds / 1000) % 60,
+        separator,
+        milliseconds % 1000
+    )
+}
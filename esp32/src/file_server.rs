@@ -1,31 +1,95 @@
-use std::collections::HashMap;
+use std::collections::{BTreeMap, HashMap, HashSet};
 use std::ffi::CStr;
+use std::fs::File;
+use std::io::Read;
+use std::path::Path;
 use std::sync::mpsc::{channel, Sender};
 use std::sync::{Arc, Mutex};
 use std::thread::spawn;
 use std::time::Duration;
 
+use esp_idf_svc::hal::delay::FreeRtos;
+use esp_idf_svc::hal::reset::restart;
 use esp_idf_svc::handle::RawHandle;
 use esp_idf_svc::http::server::{Configuration, EspHttpServer};
 use esp_idf_svc::http::Method;
 use esp_idf_svc::io::Write;
 use esp_idf_svc::sys::EspError;
 use esp_idf_svc::ws::FrameType;
-use log::info;
+use log::{error, info};
 use serde::{Deserialize, Serialize};
+use echosense_core::encryption;
+use echosense_core::maintenance::{MaintenanceAction, MaintenanceResult};
+use echosense_core::retention::StorageReport;
+
+pub use echosense_core::meeting::Transcription;
 
 use crate::custom_error::CustomError;
+use crate::encryption::storage_key;
+use crate::export::{render, ExportFormat};
+use crate::maintenance::{authorized, MaintenanceRequest};
+use crate::meeting::{store, Meeting, MeetingFiles, SharedMeeting};
+use crate::ota::Ota;
+use crate::outbox::{Outbox, OutboxEntry};
+use crate::recipients::{RecipientStatus, Recipients};
+use crate::storage::{SharedMedium, StorageMedium};
+use crate::webhooks::{WebhookEvent, WebhookTarget, WEBHOOK_LOG_FILE};
+use crate::{OTA_TOKEN, SAMPLE_RATE_HZ};
+
+const OTA_UPLOAD_CHUNK: usize = 4096;
+const RECORDING_DOWNLOAD_CHUNK: usize = 4096;
+// Checking a large, full card reads all of it
+const MAINTENANCE_TIMEOUT: Duration = Duration::from_secs(600);
 
 pub type Sessions = Arc<Mutex<HashMap<i32, Sender<WebsocketMessage>>>>;
+pub type Monitors = Arc<Mutex<HashSet<i32>>>;
 
 #[derive(Debug, Deserialize)]
 pub enum Command {
+    StartMeeting,
     GetSummary,
     AskQuestion { id: String, question: String },
     SendTranscriptionViaEmail {
         email: Option<String>,
         with_audio: bool,
-    }
+        #[serde(default)]
+        formats: Vec<ExportFormat>,
+        #[serde(default)]
+        recipients: Recipients,
+        #[serde(default)]
+        lists: Vec<String>,
+    },
+    GetDistributionLists,
+    SaveDistributionList { name: String, recipients: Recipients },
+    DeleteDistributionList { name: String },
+    GetWebhooks,
+    // Every transcript goes to a webhook target, so changing them takes the maintenance token
+    SaveWebhook {
+        #[serde(default)]
+        token: String,
+        url: String,
+        secret: String,
+        #[serde(default)]
+        events: Vec<WebhookEvent>,
+    },
+    DeleteWebhook {
+        #[serde(default)]
+        token: String,
+        url: String,
+    },
+    SetMeetingTitle { title: String },
+    SetMeetingStarred {
+        #[serde(default)]
+        id: Option<String>,
+        starred: bool,
+    },
+    MonitorAudio { enabled: bool },
+    Maintenance {
+        token: String,
+        action: MaintenanceAction,
+        #[serde(default)]
+        code: Option<String>,
+    },
 }
 
 #[derive(Debug, Deserialize)]
@@ -35,6 +99,7 @@ struct Action {
 
 pub struct Server {
     sessions: Sessions,
+    monitors: Monitors,
     inner: EspHttpServer<'static>,
 }
 
@@ -42,15 +107,27 @@ const INDEX_HTML: &'static [u8] = include_bytes!("../../frontend/dist/index.html
 
 #[derive(Debug, Serialize)]
 pub enum WebsocketMessage {
+    MeetingId(String),
     PartialTranscription(Transcription),
     FinalTranscription(Transcription),
     Summary(String),
     AnswerQuestion { id: String, answer: String },
+    AudioLevel { rms: f32, peak: f32 },
+    Audio(Vec<u8>),
+    EmailDelivery(Vec<RecipientStatus>),
+    DistributionLists(BTreeMap<String, Recipients>),
+    Outbox(Vec<OutboxEntry>),
+    Webhooks(Vec<WebhookTarget>),
+    Storage(StorageReport),
+    StorageMedium(StorageMedium),
+    Maintenance(MaintenanceResult),
+    RecordingStalled(bool),
 }
 
 impl Into<Payload> for WebsocketMessage {
     fn into(self) -> Payload {
         match self {
+            WebsocketMessage::MeetingId(id) => Payload::MeetingId(id),
             WebsocketMessage::PartialTranscription(transcription) => {
                 Payload::PartialTranscription(transcription)
             }
@@ -61,40 +138,62 @@ impl Into<Payload> for WebsocketMessage {
             WebsocketMessage::AnswerQuestion { id, answer } => {
                 Payload::AnswerQuestion { id, answer }
             }
+            WebsocketMessage::AudioLevel { rms, peak } => Payload::AudioLevel { rms, peak },
+            WebsocketMessage::EmailDelivery(statuses) => Payload::EmailDelivery(statuses),
+            WebsocketMessage::DistributionLists(lists) => Payload::DistributionLists(lists),
+            WebsocketMessage::Outbox(entries) => Payload::Outbox(entries),
+            WebsocketMessage::Webhooks(targets) => Payload::Webhooks(targets),
+            WebsocketMessage::Storage(report) => Payload::Storage(report),
+            WebsocketMessage::StorageMedium(medium) => Payload::StorageMedium(medium),
+            WebsocketMessage::RecordingStalled(stalled) => Payload::RecordingStalled(stalled),
+            WebsocketMessage::Maintenance(result) => Payload::Maintenance(result),
+            WebsocketMessage::Audio(_) => unreachable!("audio frames are sent as binary frames"),
         }
     }
 }
 
-#[derive(Debug, Clone, Serialize)]
-pub struct Transcription {
-    pub text: String,
-    pub timestamp: String,
-}
-
 #[derive(Debug, Serialize)]
 enum Payload {
     Summary(String),
     SessionId(i32),
+    MeetingId(String),
     AnswerQuestion { id: String, answer: String },
     Transcriptions(Vec<Transcription>),
     PartialTranscription(Transcription),
     FinalTranscription(Transcription),
+    AudioLevel { rms: f32, peak: f32 },
+    MonitoringAudio(bool),
+    EmailDelivery(Vec<RecipientStatus>),
+    DistributionLists(BTreeMap<String, Recipients>),
+    Outbox(Vec<OutboxEntry>),
+    Webhooks(Vec<WebhookTarget>),
+    Storage(StorageReport),
+    StorageMedium(StorageMedium),
+    Maintenance(MaintenanceResult),
+    RecordingStalled(bool),
 }
 
 impl Server {
     pub fn new() -> Result<Self, CustomError> {
         Ok(Server {
             sessions: Arc::new(Mutex::new(HashMap::new())),
-            inner: EspHttpServer::new(&Configuration::default())?,
+            monitors: Arc::new(Mutex::new(HashSet::new())),
+            inner: EspHttpServer::new(&Configuration {
+                uri_match_wildcard: true,
+                ..Default::default()
+            })?,
         })
     }
 
     pub fn initialize_websocket(
         &mut self,
         frontend_command_sender: Sender<Command>,
-        transcriptions: Arc<Mutex<Vec<Transcription>>>,
+        meeting: SharedMeeting,
+        medium: SharedMedium,
+        maintenance: Sender<MaintenanceRequest>,
     ) -> Result<Sessions, CustomError> {
         let sessions = self.sessions.clone();
+        let monitors = self.monitors.clone();
 
         self.inner.ws_handler("/connect", move |socket| {
             let (sender, receiver) = channel::<WebsocketMessage>();
@@ -108,10 +207,38 @@ impl Server {
                 info!("new session: {:?}", socket.session());
 
                 {
-                    let transcriptions = transcriptions.lock().unwrap();
+                    let meeting = meeting.lock().unwrap();
+
+                    let message = Payload::MeetingId(meeting.id.clone());
+                    let message = serde_json::to_string::<Payload>(&message.into()).unwrap();
+
+                    socket.send(FrameType::Text(false), message.as_bytes())?;
 
-                    if transcriptions.is_empty() == false {
-                        let message = Payload::Transcriptions(transcriptions.clone());
+                    if meeting.transcriptions.is_empty() == false {
+                        let message = Payload::Transcriptions(meeting.transcriptions.clone());
+                        let message = serde_json::to_string::<Payload>(&message.into()).unwrap();
+
+                        socket.send(FrameType::Text(false), message.as_bytes())?;
+                    }
+                }
+
+                // Only worth mentioning when there is no card, new sessions learn about changes as they happen
+                {
+                    let medium = *medium.lock().unwrap();
+
+                    if medium != StorageMedium::SdCard {
+                        let message = Payload::StorageMedium(medium);
+                        let message = serde_json::to_string::<Payload>(&message.into()).unwrap();
+
+                        socket.send(FrameType::Text(false), message.as_bytes())?;
+                    }
+                }
+
+                {
+                    let entries = Outbox::entries();
+
+                    if entries.is_empty() == false {
+                        let message = Payload::Outbox(entries);
                         let message = serde_json::to_string::<Payload>(&message.into()).unwrap();
 
                         socket.send(FrameType::Text(false), message.as_bytes())?;
@@ -129,11 +256,17 @@ impl Server {
 
                 spawn::<_, Result<(), CustomError>>(move || {
                     loop {
-                        if let Ok(message) = receiver.recv_timeout(Duration::from_millis(1000)) {
-                            dettached.send(
-                                FrameType::Text(false),
-                                serde_json::to_string::<Payload>(&message.into())?.as_bytes(),
-                            )?;
+                        match receiver.recv_timeout(Duration::from_millis(1000)) {
+                            Ok(WebsocketMessage::Audio(samples)) => {
+                                dettached.send(FrameType::Binary(false), samples.as_slice())?;
+                            }
+                            Ok(message) => {
+                                dettached.send(
+                                    FrameType::Text(false),
+                                    serde_json::to_string::<Payload>(&message.into())?.as_bytes(),
+                                )?;
+                            }
+                            Err(_) => {}
                         }
 
                         if dettached.is_closed() {
@@ -152,6 +285,7 @@ impl Server {
                 let session_id = socket.session();
 
                 sessions.remove(&session_id);
+                monitors.lock().unwrap().remove(&session_id);
 
                 info!("closed websocket session {:?}", session_id);
 
@@ -170,6 +304,40 @@ impl Server {
             let message = message.to_str().unwrap();
             let message: Action = serde_json::from_str(message).unwrap();
 
+            // Monitoring is per session, so it is handled here rather than by the command thread
+            if let Command::MonitorAudio { enabled } = message.command {
+                let mut monitors = monitors.lock().unwrap();
+
+                if enabled {
+                    monitors.insert(socket.session());
+                } else {
+                    monitors.remove(&socket.session());
+                }
+
+                let message = Payload::MonitoringAudio(enabled);
+                let message = serde_json::to_string::<Payload>(&message).unwrap();
+
+                socket.send(FrameType::Text(false), message.as_bytes())?;
+
+                return Ok(());
+            }
+
+            // Goes straight to the main loop, which owns the storage. The result is sent to every session
+            if let Command::Maintenance { token, action, code } = message.command {
+                if authorized(&token) == false {
+                    let message = Payload::Maintenance(MaintenanceResult::Failed("Unauthorized".to_string()));
+                    let message = serde_json::to_string::<Payload>(&message).unwrap();
+
+                    socket.send(FrameType::Text(false), message.as_bytes())?;
+
+                    return Ok(());
+                }
+
+                maintenance.send(MaintenanceRequest { action, code, reply: None }).unwrap();
+
+                return Ok(());
+            }
+
             frontend_command_sender.send(message.command).unwrap();
 
             Ok::<(), EspError>(())
@@ -178,6 +346,226 @@ impl Server {
         Ok(self.sessions.clone())
     }
 
+    pub fn monitors(&self) -> Monitors {
+        self.monitors.clone()
+    }
+
+    pub fn initialize_ota(&mut self) -> Result<(), CustomError> {
+        self.inner.fn_handler::<CustomError, _>("/api/ota", Method::Post, |mut request| {
+            let token = format!("Bearer {}", OTA_TOKEN);
+
+            if OTA_TOKEN.is_empty() || request.header("Authorization") != Some(token.as_str()) {
+                request.into_status_response(401)?.write_all(b"Unauthorized")?;
+                return Ok(());
+            }
+
+            let length = request.content_len();
+
+            if let Err(error) = Ota::update::<_, OTA_UPLOAD_CHUNK>(&mut request, length) {
+                error!("firmware update failed: {:?}", error);
+
+                request.into_status_response(400)?.write_all(format!("{:?}", error).as_bytes())?;
+                return Ok(());
+            }
+
+            request.into_ok_response()?.write_all(b"Firmware updated, restarting...")?;
+
+            spawn(|| {
+                FreeRtos::delay_ms(1000);
+                restart();
+            });
+
+            Ok(())
+        })?;
+
+        Ok(())
+    }
+
+    // POST /api/storage/{info|check|format|benchmark}[?code=...], answered once the main loop carried it out
+    pub fn initialize_maintenance_api(&mut self, maintenance: Sender<MaintenanceRequest>) -> Result<(), CustomError> {
+        self.inner.fn_handler::<CustomError, _>("/api/storage/*", Method::Post, move |request| {
+            let authorization = request.header("Authorization").unwrap_or_default();
+
+            if authorized(authorization.trim_start_matches("Bearer ")) == false {
+                request.into_status_response(401)?.write_all(b"Unauthorized")?;
+                return Ok(());
+            }
+
+            let uri = request.uri().to_string();
+            let (path, query) = uri.split_once('?').unwrap_or((uri.as_str(), ""));
+
+            let Some(action) = MaintenanceAction::from_name(path.trim_start_matches("/api/storage/")) else {
+                request.into_status_response(400)?.write_all(b"Expected /api/storage/info|check|format|benchmark")?;
+                return Ok(());
+            };
+
+            let code = query
+                .split('&')
+                .filter_map(|parameter| parameter.split_once('='))
+                .find(|(key, _)| *key == "code")
+                .map(|(_, value)| value.to_string());
+
+            let (reply, result) = channel::<MaintenanceResult>();
+
+            maintenance
+                .send(MaintenanceRequest { action, code, reply: Some(reply) })
+                .map_err(|_| CustomError::ChannelSendError)?;
+
+            let Ok(result) = result.recv_timeout(MAINTENANCE_TIMEOUT) else {
+                request.into_status_response(504)?.write_all(b"Timed out")?;
+                return Ok(());
+            };
+
+            let status = match result {
+                MaintenanceResult::Failed(_) => 500,
+                _ => 200,
+            };
+
+            request
+                .into_response(status, None, &[("Content-Type", "application/json")])?
+                .write_all(&serde_json::to_vec(&result)?)?;
+
+            Ok(())
+        })?;
+
+        Ok(())
+    }
+
+    pub fn initialize_meetings_api(&mut self, meeting: SharedMeeting) -> Result<(), CustomError> {
+        self.inner.fn_handler::<CustomError, _>("/api/meetings/*", Method::Get, move |request| {
+            let uri = request.uri().to_string();
+            let (path, query) = uri.split_once('?').unwrap_or((uri.as_str(), ""));
+            let segments = path
+                .trim_start_matches("/api/meetings/")
+                .split('/')
+                .collect::<Vec<_>>();
+
+            let format = query
+                .split('&')
+                .filter_map(|parameter| parameter.split_once('='))
+                .find(|(key, _)| *key == "format")
+                .and_then(|(_, value)| ExportFormat::from_name(value));
+
+            let (id, format) = match (segments.as_slice(), format) {
+                ([id, "export"], Some(format)) => (id.to_string(), format),
+                ([id, "recording", "manifest"], _) if Meeting::is_valid_id(id) => {
+                    let Ok(manifest) = store().load_manifest(id) else {
+                        request.into_status_response(404)?.write_all(b"Recording is not segmented")?;
+                        return Ok(());
+                    };
+
+                    request
+                        .into_response(200, Some("OK"), &[("Content-Type", "application/json")])?
+                        .write_all(&serde_json::to_vec(&manifest)?)?;
+
+                    return Ok(());
+                }
+                ([id, "recording", rest @ ..], _) if Meeting::is_valid_id(id) => {
+                    let recording = match rest {
+                        [] => Meeting::recording_of(id).map(|recording| (format!("meeting-{}", id), recording)),
+                        ["segments", index] => index.parse::<usize>().ok().and_then(|index| {
+                            let segment = store().stored_segment(id, index)?;
+                            Some((format!("meeting-{}-{:04}", id, index), segment))
+                        }),
+                        _ => None,
+                    };
+
+                    // ?format=wav decodes a compressed recording to plain PCM on the way out, for players that don't
+                    // know ADPCM or FLAC. Segmented FLAC is always decoded, its streams can't simply be appended
+                    let decode = query.split('&').any(|parameter| parameter == "format=wav");
+
+                    // The lengths are taken when the download starts, the recording of the current meeting keeps growing
+                    let Some((name, mut download)) = recording.and_then(|(name, recording)| {
+                        let download = recording.download(&recording.lengths().ok()?, decode, SAMPLE_RATE_HZ).ok()?;
+                        Some((name, download))
+                    }) else {
+                        request.into_status_response(404)?.write_all(b"Recording not found")?;
+                        return Ok(());
+                    };
+
+                    let content_type = download.codec.content_type();
+                    let disposition = format!("attachment; filename=\"{}.{}\"", name, download.codec.file_extension());
+                    let mut response = request.into_response(
+                        200,
+                        Some("OK"),
+                        &[("Content-Type", content_type), ("Content-Disposition", disposition.as_str())],
+                    )?;
+
+                    response.write_all(&download.header)?;
+
+                    let mut buffer = [0u8; RECORDING_DOWNLOAD_CHUNK];
+
+                    loop {
+                        let read = download.reader.read(&mut buffer)?;
+
+                        if read == 0 {
+                            break;
+                        }
+
+                        response.write_all(&buffer[..read])?;
+                    }
+
+                    return Ok(());
+                }
+                _ => {
+                    request.into_status_response(400)?.write_all(b"Expected /api/meetings/{id}/export?format=srt|vtt|markdown|txt|json or /api/meetings/{id}/recording[/manifest|/segments/{index}][?format=wav]")?;
+                    return Ok(());
+                }
+            };
+
+            let meeting = {
+                let current = meeting.lock()?;
+
+                if current.id == id {
+                    current.clone()
+                } else {
+                    drop(current);
+
+                    match Meeting::load(&id) {
+                        Ok(meeting) => meeting,
+                        Err(_) => {
+                            request.into_status_response(404)?.write_all(b"Meeting not found")?;
+                            return Ok(());
+                        }
+                    }
+                }
+            };
+
+            let content = render(&meeting, format)?;
+            let disposition = format!("attachment; filename=\"{}\"", format.filename(&meeting));
+
+            request
+                .into_response(
+                    200,
+                    Some("OK"),
+                    &[
+                        ("Content-Type", format.content_type()),
+                        ("Content-Disposition", disposition.as_str()),
+                    ],
+                )?
+                .write_all(content.as_bytes())?;
+
+            Ok(())
+        })?;
+
+        Ok(())
+    }
+
+    pub fn initialize_webhooks_api(&mut self) -> Result<(), CustomError> {
+        self.inner.fn_handler::<CustomError, _>("/api/webhooks/log", Method::Get, |request| {
+            // One JSON object per line, the most recent delivery attempts last
+            let content = encryption::read(Path::new(WEBHOOK_LOG_FILE), storage_key().as_ref()).unwrap_or_default();
+
+            request
+                .into_response(200, Some("OK"), &[("Content-Type", "application/x-ndjson")])?
+                .write_all(&content)?;
+
+            Ok(())
+        })?;
+
+        Ok(())
+    }
+
     pub fn initialize_static_file_server(&mut self) -> Result<(), CustomError> {
         self.inner.fn_handler("/", Method::Get, |request| {
             request
@@ -0,0 +1,56 @@
+use esp_idf_svc::fs::littlefs::{LittleFsInfo, Littlefs};
+use esp_idf_svc::io::vfs::MountedLittlefs;
+use esp_idf_svc::sys::EspError;
+use echosense_core::retention::StorageUsage;
+use log::warn;
+
+use crate::custom_error::CustomError;
+use crate::meeting::STORAGE_ROOT;
+
+// The `storage` partition in partitions.csv
+const PARTITION: &str = "storage";
+
+// 128KB of flash standing in for the SD card, enough for transcripts and settings but not for audio
+pub struct InternalFlash {
+    filesystem: MountedLittlefs<Littlefs<()>>,
+}
+
+impl InternalFlash {
+    // The partition starts out erased, it is formatted the first time it doesn't mount
+    pub fn new() -> Result<Self, CustomError> {
+        let littlefs = unsafe { Littlefs::new_partition(PARTITION)? };
+
+        let filesystem = match MountedLittlefs::mount(littlefs, STORAGE_ROOT) {
+            Ok(filesystem) => filesystem,
+            Err(error) => {
+                warn!("formatting the internal flash: {:?}", error);
+                return Self::format();
+            }
+        };
+
+        Ok(InternalFlash { filesystem })
+    }
+
+    // Wipes the partition, only while nothing else is mounted at STORAGE_ROOT
+    pub fn format() -> Result<Self, CustomError> {
+        let mut littlefs = unsafe { Littlefs::new_partition(PARTITION)? };
+        littlefs.format()?;
+
+        Ok(InternalFlash {
+            filesystem: MountedLittlefs::mount(littlefs, STORAGE_ROOT)?,
+        })
+    }
+
+    pub fn info(&self) -> Result<LittleFsInfo, EspError> {
+        self.filesystem.info()
+    }
+
+    pub fn usage(&self) -> Result<StorageUsage, EspError> {
+        let info = self.info()?;
+
+        Ok(StorageUsage {
+            total_bytes: info.total_bytes as u64,
+            used_bytes: info.used_bytes as u64,
+        })
+    }
+}
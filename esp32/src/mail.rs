@@ -0,0 +1,205 @@
+use std::io::Read;
+
+use base64::engine::general_purpose::STANDARD;
+use base64::Engine;
+use echosense_core::store::StoredRecording;
+
+use crate::custom_error::CustomError;
+use crate::export::{render, ExportFormat};
+use crate::meeting::{Meeting, MeetingFiles};
+use crate::recipients::{RecipientStatus, Recipients};
+use crate::report::Report;
+use crate::template::{Escape, Template};
+use crate::SAMPLE_RATE_HZ;
+
+// 57 bytes encode to exactly one 76 character MIME line, and being a multiple of 3 no chunk is ever padded
+const BASE64_CHUNK: usize = 57 * 12;
+const BASE64_LINE: usize = 76;
+
+pub trait MailTransport {
+    // Largest message the provider accepts, including the base64 overhead of attachments
+    fn max_message_size(&self) -> usize;
+
+    fn send(&mut self, message: &Message) -> Result<Vec<RecipientStatus>, CustomError>;
+}
+
+pub enum AttachmentContent {
+    Bytes(Vec<u8>),
+    // Recordings don't fit in RAM, they are streamed from the SD card while the message is sent. `lengths` are the
+    // stored sizes when the message was put together, the recording of the current meeting keeps growing. `length`
+    // includes the header
+    Recording {
+        recording: StoredRecording,
+        lengths: Vec<u64>,
+        length: usize,
+    },
+}
+
+pub struct Attachment {
+    pub filename: String,
+    pub content_type: String,
+    pub content: AttachmentContent,
+}
+
+impl Attachment {
+    pub fn from_export(meeting: &Meeting, format: ExportFormat) -> Result<Self, CustomError> {
+        Ok(Self {
+            filename: format.filename(meeting),
+            content_type: format.content_type().to_string(),
+            content: AttachmentContent::Bytes(render(meeting, format)?.into_bytes()),
+        })
+    }
+
+    pub fn length(&self) -> usize {
+        match &self.content {
+            AttachmentContent::Bytes(bytes) => bytes.len(),
+            AttachmentContent::Recording { length, .. } => *length,
+        }
+    }
+
+    pub fn encoded_length(&self) -> usize {
+        (self.length() + 2) / 3 * 4
+    }
+
+    pub fn reader(&self) -> Result<Box<dyn Read + '_>, CustomError> {
+        Ok(match &self.content {
+            AttachmentContent::Bytes(bytes) => Box::new(bytes.as_slice()),
+            AttachmentContent::Recording { recording, lengths, .. } => {
+                let download = recording.download(lengths, false, SAMPLE_RATE_HZ)?;
+
+                Box::new(std::io::Cursor::new(download.header).chain(download.reader))
+            }
+        })
+    }
+
+    // Streams the attachment as base64, optionally wrapped into CRLF terminated lines as MIME requires
+    pub fn write_base64<F: FnMut(&[u8]) -> Result<(), CustomError>>(
+        &self,
+        wrap_lines: bool,
+        mut sink: F,
+    ) -> Result<(), CustomError> {
+        let mut source = self.reader()?;
+        let mut buffer = [0u8; BASE64_CHUNK];
+        let mut encoded = [0u8; BASE64_CHUNK / 3 * 4];
+
+        loop {
+            // Fill the whole chunk so padding only ever happens at the very end
+            let mut filled = 0;
+
+            while filled < buffer.len() {
+                match source.read(&mut buffer[filled..])? {
+                    0 => break,
+                    length => filled += length,
+                }
+            }
+
+            if filled == 0 {
+                break;
+            }
+
+            let length = STANDARD
+                .encode_slice(&buffer[..filled], &mut encoded)
+                .map_err(|error| CustomError::FailedToSendEmail(error.to_string()))?;
+
+            if wrap_lines {
+                for line in encoded[..length].chunks(BASE64_LINE) {
+                    sink(line)?;
+                    sink(b"\r\n")?;
+                }
+            } else {
+                sink(&encoded[..length])?;
+            }
+
+            if filled < buffer.len() {
+                break;
+            }
+        }
+
+        Ok(())
+    }
+}
+
+pub struct Message {
+    pub from: String,
+    pub recipients: Recipients,
+    pub subject: String,
+    pub text: String,
+    pub html: String,
+    pub attachments: Vec<Attachment>,
+}
+
+impl Message {
+    pub fn meeting_report(
+        from: &str,
+        recipients: Recipients,
+        meeting: &Meeting,
+        device_url: &str,
+        formats: &[ExportFormat],
+        with_audio: bool,
+        max_message_size: usize,
+    ) -> Result<Self, CustomError> {
+        let report = Report::new(meeting, device_url);
+
+        let mut message = Message {
+            from: from.to_string(),
+            recipients,
+            subject: report.subject(),
+            text: report.text(),
+            html: report.html(),
+            attachments: formats
+                .iter()
+                .map(|format| Attachment::from_export(meeting, *format))
+                .collect::<Result<Vec<_>, _>>()?,
+        };
+
+        let recording = match with_audio {
+            true => meeting.recording().and_then(|recording| {
+                let lengths = recording.lengths().ok()?;
+                let download = recording.download(&lengths, false, SAMPLE_RATE_HZ).ok()?;
+                let length = download.length.map(|length| download.header.len() + length as usize);
+
+                Some((recording, lengths, download.codec, length))
+            }),
+            false => None,
+        };
+
+        let Some((recording, lengths, codec, length)) = recording else {
+            return Ok(message);
+        };
+
+        // Segmented FLAC that is still being recorded has no known length yet, it's linked like one that is too large
+        let attachment = length
+            .map(|length| Attachment {
+                filename: format!("meeting-{}.{}", meeting.id, codec.file_extension()),
+                content_type: codec.content_type().to_string(),
+                content: AttachmentContent::Recording { recording, lengths, length },
+            })
+            .filter(|attachment| message.estimated_size() + attachment.encoded_length() <= max_message_size);
+
+        if let Some(attachment) = attachment {
+            message.attachments.push(attachment);
+        } else {
+            let url = format!("{}/api/meetings/{}/recording", device_url, meeting.id);
+
+            message.html.push_str(
+                &Template::from_str(
+                    "<p>The recording is too large to be attached, download it from <a href=\"{{url}}\">{{url}}</a>.</p>",
+                    Escape::Html,
+                )
+                .render(&[("url", url.as_str())]),
+            );
+            message
+                .text
+                .push_str(&format!("\nThe recording is too large to be attached, download it from {}\n", url));
+        }
+
+        Ok(message)
+    }
+
+    pub fn estimated_size(&self) -> usize {
+        self.subject.len()
+            + self.text.len()
+            + self.html.len()
+            + self.attachments.iter().map(|attachment| attachment.encoded_length()).sum::<usize>()
+    }
+}
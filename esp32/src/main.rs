@@ -3,6 +3,15 @@
 extern crate core;
 
 use button_driver::{Button, ButtonConfig};
+use echosense_core::combine::{CombineMode, Combiner};
+use echosense_core::convert::{ChannelSelection, SampleWidth, PCM_SAMPLE_RATE};
+use echosense_core::dsp::{AgcConfig, DspChain, DspConfig, NoiseGateConfig};
+use echosense_core::maintenance::{MaintenanceAction, MaintenanceResult};
+use echosense_core::pipeline::{answer_question, handle_transcript, summarize, TranscriptEvent};
+use echosense_core::platform::AudioSource;
+use echosense_core::retention::{StorageLevel, StorageManager, StorageReport};
+use echosense_core::store::StoredRecording;
+use echosense_core::vad::{SharedTimeline, VadConfig, VoiceGate};
 use crossbeam::channel::Sender;
 use embedded_graphics::draw_target::DrawTarget;
 use embedded_graphics::prelude::Primitive;
@@ -14,13 +23,13 @@ use esp_idf_svc::hal::i2s::I2sRx;
 use esp_idf_svc::hal::peripheral::Peripheral;
 use esp_idf_svc::hal::peripherals::Peripherals;
 use esp_idf_svc::log::EspLogger;
-use esp_idf_svc::sys::esp_random;
+use esp_idf_svc::nvs::EspDefaultNvsPartition;
+use esp_idf_svc::sntp::EspSntp;
 use esp_idf_svc::wifi::{AsyncWifi, AuthMethod, ClientConfiguration, Configuration, EspWifi};
-use esp_idf_svc::ws::client::EspWebSocketClient;
-use esp_idf_svc::ws::FrameType;
 use log::{debug, error, info, warn};
+use serde_json::json;
 use std::cell::{OnceCell, RefCell};
-use std::collections::VecDeque;
+use std::collections::{HashMap, VecDeque};
 use std::fs::File;
 use std::io::{Read, Seek, SeekFrom, Write};
 use std::ops::Deref;
@@ -28,49 +37,132 @@ use std::sync::atomic::{AtomicBool, Ordering};
 use std::sync::mpsc::{channel, Receiver};
 use std::sync::{Arc, Mutex};
 use std::thread::spawn;
-use std::time::Instant;
+use std::time::{Duration, Instant};
 
-use crate::assembly::{Assembly, AssemblyResponse, SummarizeRequest};
+use crate::clock::unix_time;
+use crate::assembly::{Assembly, AssemblyResponse, AssemblyStreaming, EspHttpClient, EspWsClient, SummarizeRequest};
 use crate::custom_error::CustomError;
 use crate::display::{Display, DrawState};
-use crate::file_server::{Command, Server, Sessions, Transcription, WebsocketMessage};
-use crate::microphone::Microphone;
-use crate::mini_sdcard::MiniSDCard;
+use crate::encryption::{secret_hex, secret_lines};
+use crate::mail::{MailTransport, Message};
+use crate::maintenance::{authorized, Maintenance, MaintenanceRequest};
+use crate::file_server::{Command, Monitors, Server, Sessions, Transcription, WebsocketMessage};
+use crate::mdns::Mdns;
+use crate::mqtt::Mqtt;
+use crate::meeting::{new_meeting, store, Meeting, MeetingFiles, SharedMeeting};
+use crate::microphone::{AudioLevel, Microphone, MicrophoneConfig, SlotStandard};
+use crate::mini_sdcard::SdCardPins;
 use crate::network::Network;
+use crate::recipients::{DistributionLists, RecipientStatus};
+use crate::ota::Ota;
+use crate::outbox::{Notification, Outbox, OutboxEntry};
 use crate::sendgrid::SendGrid;
+use crate::smtp::{Smtp, SmtpSecurity};
+use crate::storage::{storage_manager, SharedMedium, Storage, StorageMedium};
+use crate::webhooks::{WebhookEvent, WebhookTarget, WebhookTargets, Webhooks};
 
 mod assembly;
+mod clock;
 mod custom_error;
 mod file_server;
+mod internal_flash;
+mod mdns;
+mod mqtt;
+mod meeting;
 mod microphone;
 mod mini_sdcard;
 mod network;
-mod qrcode;
+mod ota;
+mod outbox;
+mod recipients;
+mod report;
 mod stream_audio_writer;
 mod display;
-mod images;
+mod encryption;
+mod export;
+mod mail;
+mod maintenance;
 mod sendgrid;
+mod smtp;
+mod storage;
+mod template;
+mod webhooks;
 
 const WIFI_SSID: &str = env!("WIFI_SSID");
 const WIFI_PASSWORD: &str = env!("WIFI_PASSWORD");
 const ASSEMBLY_APIKEY: &str = env!("ASSEMBLY_APIKEY");
 const SENDGRID_APIKEY: &str = env!("SENDGRID_APIKEY");
 const SENDGRID_FROM: &str = env!("SENDGRID_FROM");
+const SMTP_HOST: Option<&str> = option_env!("SMTP_HOST");
+const SMTP_PORT: Option<&str> = option_env!("SMTP_PORT");
+const SMTP_SECURITY: Option<&str> = option_env!("SMTP_SECURITY");
+const SMTP_USERNAME: Option<&str> = option_env!("SMTP_USERNAME");
+const SMTP_PASSWORD: Option<&str> = option_env!("SMTP_PASSWORD");
+const SMTP_FROM: Option<&str> = option_env!("SMTP_FROM");
+const MQTT_URL: Option<&str> = option_env!("MQTT_URL");
+const MQTT_USERNAME: Option<&str> = option_env!("MQTT_USERNAME");
+const MQTT_PASSWORD: Option<&str> = option_env!("MQTT_PASSWORD");
+const MQTT_TOPIC_PREFIX: Option<&str> = option_env!("MQTT_TOPIC_PREFIX");
+const DSP_DC_BLOCK: Option<&str> = option_env!("DSP_DC_BLOCK");
+const DSP_HIGH_PASS_HZ: Option<&str> = option_env!("DSP_HIGH_PASS_HZ");
+const DSP_NOISE_GATE_DBFS: Option<&str> = option_env!("DSP_NOISE_GATE_DBFS");
+const DSP_AGC: Option<&str> = option_env!("DSP_AGC");
+const DSP_AGC_TARGET_DBFS: Option<&str> = option_env!("DSP_AGC_TARGET_DBFS");
+const DSP_AGC_MAX_GAIN_DB: Option<&str> = option_env!("DSP_AGC_MAX_GAIN_DB");
+const MIC_BITS: Option<&str> = option_env!("MIC_BITS");
+const MIC_STANDARD: Option<&str> = option_env!("MIC_STANDARD");
+const MIC_CHANNEL: Option<&str> = option_env!("MIC_CHANNEL");
+const MIC_SAMPLE_RATE_HZ: Option<&str> = option_env!("MIC_SAMPLE_RATE_HZ");
+const MIC_COMBINE: Option<&str> = option_env!("MIC_COMBINE");
+const MIC_SPACING_MM: Option<&str> = option_env!("MIC_SPACING_MM");
+const MIC_KEEP_CHANNELS: Option<&str> = option_env!("MIC_KEEP_CHANNELS");
+const VAD_PAUSE_STREAMING: Option<&str> = option_env!("VAD_PAUSE_STREAMING");
+const VAD_THRESHOLD_DBFS: Option<&str> = option_env!("VAD_THRESHOLD_DBFS");
+const VAD_HANGOVER_MS: Option<&str> = option_env!("VAD_HANGOVER_MS");
+const FIRMWARE_VERSION: &str = env!("CARGO_PKG_VERSION");
+const OTA_TOKEN: &str = env!("OTA_TOKEN");
+const OTA_HEALTH_TIMEOUT: Duration = Duration::from_secs(120);
 
 const SUMMARY_UPLOAD_CHUNK: usize = 1000;
+const SEGMENT_POLL_INTERVAL_MS: u32 = 5000;
 const MICROPHONE_RECORD_BUFFER_SIZE: usize = 1000;
-const SAMPLE_RATE_HZ: u32 = 16000;
+// The rate everything after the microphone runs at, the mic itself may run at MIC_SAMPLE_RATE_HZ
+const SAMPLE_RATE_HZ: u32 = PCM_SAMPLE_RATE;
+const MONITOR_LEVEL_INTERVAL: usize = 3;
+const OUTBOX_IDLE_INTERVAL: Duration = Duration::from_secs(60);
+const MQTT_STATUS_INTERVAL: Duration = Duration::from_secs(30);
+const STORAGE_CHECK_INTERVAL: Duration = Duration::from_secs(60);
+const RECORDING_RETRY_INTERVAL: Duration = Duration::from_secs(5);
+// A few retries without the recording stalling again and it counts as running
+const RECORDING_STALL_TIMEOUT: Duration = Duration::from_secs(15);
+// Without a card detect pin, a card inserted later is only found by trying to talk to it
+const CARD_DETECT_INTERVAL: Duration = Duration::from_secs(5);
+// Holding the button this long asks to format the storage, holding it as long again confirms it
+const FORMAT_HOLD: Duration = Duration::from_secs(5);
+// How long a maintenance result stays on the display before the QR code comes back
+const MAINTENANCE_DISPLAY_TIME: Duration = Duration::from_secs(10);
+// Long enough to copy 64 hex digits down
+const SECRET_DISPLAY_TIME: Duration = Duration::from_secs(60);
+const SECRET_CLICKS: usize = 5;
 
 fn main() -> Result<(), CustomError> {
     EspLogger::initialize_default();
 
     info!("initializing...");
 
+    let ota = Ota::new(OTA_HEALTH_TIMEOUT)?;
     let peripherals = Peripherals::take()?;
+    let nvs = EspDefaultNvsPartition::take()?;
+
+    // Before anything is read from or written to the storage
+    encryption::initialize(nvs.clone())?;
+
     let mut toggle = Arc::new(AtomicBool::new(false));
     let toggle_a = toggle.clone();
     let toggle_b = toggle.clone();
     let toggle_c = toggle.clone();
+    let recording_stalled = Arc::new(AtomicBool::new(false));
+    let recording_stalled_a = recording_stalled.clone();
 
     // Initialize Display
     let mut display = Display::new(
@@ -85,17 +177,23 @@ fn main() -> Result<(), CustomError> {
     let pin = PinDriver::input(peripherals.pins.gpio5)?;
     let mut button = Button::<_, Instant>::new(pin, ButtonConfig::default());
 
-    // Initialize SD Card / Mount SD Card
-    let sdcard = MiniSDCard::new(
-        peripherals.spi2,
-        peripherals.pins.gpio2, // sck
-        peripherals.pins.gpio3, // mosi
-        peripherals.pins.gpio1, // miso
-        peripherals.pins.gpio4, // cs
+    // Initialize SD Card / Mount SD Card, the internal flash stands in for a missing or unreadable card
+    let mut storage = Storage::mount(
+        SdCardPins {
+            spi: peripherals.spi2,
+            sck: peripherals.pins.gpio2,
+            mosi: peripherals.pins.gpio3,
+            miso: peripherals.pins.gpio1,
+            cs: peripherals.pins.gpio4,
+        },
         false,
-    )?;
+    );
 
-    info!("SD Card: {:?}", sdcard.info()?);
+    let medium = storage.shared_medium();
+    let medium_a = medium.clone();
+    let medium_b = medium.clone();
+
+    info!("Storage: {:?} {:?}", storage.medium(), storage.info());
 
     // Initialize microphone
     let microphone = Microphone::<_, MICROPHONE_RECORD_BUFFER_SIZE>::new(
@@ -103,75 +201,229 @@ fn main() -> Result<(), CustomError> {
         peripherals.pins.gpio8, // sck
         peripherals.pins.gpio6, // sd
         peripherals.pins.gpio7, // ws
-        SAMPLE_RATE_HZ,
+        microphone_config(),
     )?;
 
     // Initialize Wifi network
-    let network = Network::new(peripherals.modem, WIFI_SSID, WIFI_PASSWORD)?.connect()?;
+    let network = Network::new(peripherals.modem, WIFI_SSID, WIFI_PASSWORD, nvs)?.connect()?;
+
+    // Meetings get a start time once the clock is set, the retention rules need it to tell their age
+    let _sntp = EspSntp::new_default()?;
 
     // Initialize AssemblyAI SDK
-    let mut assembly = Assembly::new(ASSEMBLY_APIKEY)?;
+    let mut assembly = Assembly::new(ASSEMBLY_APIKEY, EspHttpClient);
 
     // Initialize WebServer
     let mut file_server = Server::new()?;
 
+    let meeting = Arc::new(Mutex::new(new_meeting()));
+    let meeting_a = meeting.clone();
+    let meeting_b = meeting.clone();
+    let meeting_c = meeting.clone();
+    let meeting_d = meeting.clone();
+    let meeting_e = meeting.clone();
+    let meeting_f = meeting.clone();
+    let meeting_g = meeting.clone();
+
     file_server.initialize_static_file_server()?;
+    file_server.initialize_ota()?;
+    file_server.initialize_meetings_api(meeting_c)?;
+    file_server.initialize_webhooks_api()?;
 
     info!("Static file server initialized");
 
+    let meeting_id = meeting.lock()?.id.clone();
+
     let (frontend_command_sender, frontend_command_receiver) = channel::<Command>();
+    let (maintenance_sender, maintenance_receiver) = channel::<MaintenanceRequest>();
     let (transcription_uploaded_notifier, transcription_uploader_receiver) = std::sync::mpsc::channel::<String>();
 
-    let transcriptions = Arc::new(Mutex::new(vec![]));
-    let transcriptions_a = transcriptions.clone();
-    let transcriptions_b = transcriptions.clone();
+    let mqtt_command_sender = frontend_command_sender.clone();
+    file_server.initialize_maintenance_api(maintenance_sender.clone())?;
 
-    let sessions = file_server.initialize_websocket(frontend_command_sender, transcriptions_a)?;
+    let sessions = file_server.initialize_websocket(frontend_command_sender, meeting_a, medium, maintenance_sender)?;
     let sessions_a = sessions.clone();
     let sessions_b = sessions.clone();
     let sessions_c = sessions.clone();
+    let sessions_d = sessions.clone();
+    let sessions_e = sessions.clone();
+    let sessions_f = sessions.clone();
+    let monitors = file_server.monitors();
 
     info!("Websocket initialized.");
 
     let ip_info = network.ip_info()?;
-    let address = format!("http://{}", ip_info.ip);
-
-    info!("Address: {:?}", address);
 
-    display.draw(DrawState::QRCode(address))?;
+    // Advertise the device as echosense-<id>.local so the address survives DHCP lease changes
+    let mdns = Mdns::new(network.mac()?, 80)?;
+    let address = mdns.address();
+
+    info!("Address: {:?} ({})", address, ip_info.ip);
+
+    display.draw(qrcode_state(storage.medium(), address.clone()))?;
+
+    // Initialize outgoing webhooks
+    let webhooks = Webhooks::new(address.as_str())?;
+    let webhooks_a = webhooks.clone();
+    let webhooks_b = webhooks.clone();
+
+    // Initialize MQTT, only when a broker is configured
+    let mqtt = match MQTT_URL.filter(|url| url.is_empty() == false) {
+        Some(url) => Mqtt::new(
+            url,
+            MQTT_USERNAME.filter(|username| username.is_empty() == false),
+            MQTT_PASSWORD.filter(|password| password.is_empty() == false),
+            MQTT_TOPIC_PREFIX
+                .filter(|prefix| prefix.is_empty() == false)
+                .map(|prefix| prefix.trim_end_matches('/').to_string())
+                .unwrap_or_else(|| format!("echosense/{}", mdns.hostname())),
+            mqtt_command_sender,
+            sessions_e,
+        )?,
+        None => Mqtt::disabled(),
+    };
+
+    let mut last_status: Option<Instant> = None;
+    let mut last_storage_check: Option<Instant> = None;
+    let mut last_card_detect = Instant::now();
+    let mut last_stall: Option<Instant> = None;
+    let mut recording_is_stalled = false;
+    let mut retention = storage_manager(storage.medium());
+    let mut maintenance = Maintenance::new();
+    // When a maintenance result or the secret went up on the display, and for how long it stays
+    let mut maintenance_shown: Option<(Instant, Duration)> = None;
+    let qrcode = address.clone();
 
     {
         let (live_transcription_websocket_notifier, receiver) = assembly.stream(SAMPLE_RATE_HZ)?;
+        let gate = VoiceGate::new(SAMPLE_RATE_HZ, vad_config());
+        let timeline = gate.timeline();
 
         // spawn(move || generate_summary(transcription_uploader_receiver, sessions_a));
-        spawn(move || handle_transcription_thread(receiver, sessions_b, transcriptions));
+        spawn(move || handle_transcription_thread(receiver, sessions_b, meeting, webhooks_a, timeline));
+
+        let (outbox_sender, outbox_receiver) = crossbeam::channel::unbounded::<()>();
+
         spawn(move || {
-            handle_frontend_sent_commands(frontend_command_receiver, transcriptions_b, sessions_c, toggle_a)
+            handle_frontend_sent_commands(
+                frontend_command_receiver,
+                meeting_b,
+                sessions_c,
+                toggle_a,
+                outbox_sender,
+                webhooks_b,
+            )
         });
 
-        let (sender, receiver) = crossbeam::channel::unbounded::<Vec<u8>>();
+        webhooks.emit(WebhookEvent::MeetingStarted, &meeting_id, json!({}));
+        mqtt.publish("meeting", true, &json!({ "meeting_id": meeting_id, "state": "started" }));
 
-        let receiver_a = receiver.clone();
-        let receiver_b = receiver.clone();
+        spawn(move || process_outbox(outbox_receiver, sessions_a, address));
+
+        // Every consumer gets its own channel, cloning a crossbeam receiver would split the frames between them
+        let (sender_a, receiver_a) = crossbeam::channel::unbounded::<Vec<u8>>();
+        let (sender_b, receiver_b) = crossbeam::channel::unbounded::<Vec<u8>>();
+        let (sender_c, receiver_c) = crossbeam::channel::unbounded::<Vec<u8>>();
 
         std::thread::Builder::new()
             // .stack_size(20000)
-            .spawn(move || record_microphone(sender, microphone))?;
+            .spawn(move || record_microphone(vec![sender_a, sender_b, sender_c], microphone, meeting_g, medium_a))?;
 
         std::thread::Builder::new()
             // .stack_size(20000)
             .spawn(move || {
-                start_live_transcription(receiver_a, live_transcription_websocket_notifier)
+                start_live_transcription(receiver_a, live_transcription_websocket_notifier, gate, meeting_f)
             })?;
 
         std::thread::Builder::new()
             // .stack_size(20000)
-            .spawn(move || record_audio_from_microphone_to_the_sdcard(receiver_b))?;
+            .spawn(move || {
+                record_audio_from_microphone_to_the_sdcard(receiver_b, meeting_e, medium_b, recording_stalled_a)
+            })?;
+
+        std::thread::Builder::new()
+            .spawn(move || monitor_audio(receiver_c, sessions_d, monitors))?;
+
+        // Everything came up (wifi, web server and the live transcription stream), keep this firmware
+        ota.mark_healthy()?;
 
         loop {
             button.tick();
 
+            // The storage secret never goes over the network, with the plain HTTP and websocket of the device anyone on
+            // the Wi-Fi could read it. It is only shown on the display and the serial console, to whoever has the device
+            if button.clicks() == SECRET_CLICKS {
+                if let Some(secret) = secret_hex() {
+                    info!("storage secret: {}", secret);
+                }
+
+                display.draw(DrawState::Text(secret_lines()))?;
+                maintenance_shown = Some((Instant::now(), SECRET_DISPLAY_TIME));
+            }
+
+            // More clicks than the one that finishes the meeting, and long holds, are storage maintenance
+            let action = match button.clicks() {
+                2 => Some(MaintenanceAction::Info),
+                3 => Some(MaintenanceAction::Check),
+                4 => Some(MaintenanceAction::Benchmark),
+                _ if button.held_time().is_some_and(|held| held >= FORMAT_HOLD) => Some(MaintenanceAction::Format),
+                _ => None,
+            };
+
+            let requests = action
+                .map(|action| MaintenanceRequest {
+                    action,
+                    code: maintenance.pending_code(),
+                    reply: None,
+                })
+                .into_iter()
+                .chain(maintenance_receiver.try_iter());
+
+            for request in requests.collect::<Vec<_>>() {
+                let active = vec![meeting_d.lock()?.id.clone()];
+                let previous = storage.medium();
+
+                display.draw(DrawState::Text(vec![format!("{:?}...", request.action)]))?;
+
+                let result = maintenance.run(&mut storage, request.action, request.code.as_deref(), &active);
+
+                if result == MaintenanceResult::Formatted || storage.medium() != previous {
+                    storage_changed(&storage, &meeting_d, &sessions_f);
+                    retention = storage_manager(storage.medium());
+                    last_storage_check = None;
+                }
+
+                display.draw(DrawState::Text(result.lines()))?;
+                maintenance_shown = Some((Instant::now(), MAINTENANCE_DISPLAY_TIME));
+
+                for (_, notifier) in sessions_f.lock()?.iter() {
+                    notifier.send(WebsocketMessage::Maintenance(result.clone())).ok();
+                }
+
+                if let Some(reply) = request.reply {
+                    reply.send(result).ok();
+                }
+            }
+
+            if maintenance_shown.is_some_and(|(shown, time)| shown.elapsed() >= time) {
+                maintenance_shown = None;
+
+                match (retention.level(), storage.usage()) {
+                    (StorageLevel::Ok, _) | (_, Err(_)) => display.draw(qrcode_state(storage.medium(), qrcode.clone()))?,
+                    (_, Ok(usage)) => display.draw(DrawState::StorageWarning {
+                        content: qrcode.clone(),
+                        used_percent: usage.used_percent(),
+                    })?,
+                }
+            }
+
             if button.is_clicked() {
+                let meeting = meeting_d.lock()?;
+                let overview = meeting_overview(&meeting);
+
+                webhooks.emit(WebhookEvent::MeetingFinished, &meeting.id, overview.clone());
+                mqtt.publish("meeting", true, &json!({ "meeting_id": meeting.id, "state": "finished", "meeting": overview }));
+
                 display.draw(DrawState::Initializing)?;
                 break Ok(());
             }
@@ -180,6 +432,94 @@ fn main() -> Result<(), CustomError> {
                 display.draw(DrawState::Done)?;
             }
 
+            if storage.medium() != StorageMedium::SdCard && last_card_detect.elapsed() >= CARD_DETECT_INTERVAL {
+                last_card_detect = Instant::now();
+
+                if storage.detect_card() {
+                    storage_changed(&storage, &meeting_d, &sessions_f);
+                    retention = storage_manager(storage.medium());
+                    last_storage_check = None;
+
+                    display.draw(qrcode_state(storage.medium(), qrcode.clone()))?;
+                }
+            }
+
+            let stall = recording_stalled.swap(false, Ordering::Relaxed);
+
+            if stall {
+                last_stall = Some(Instant::now());
+            }
+
+            // The recording thread keeps raising the flag while it waits, once it stops the audio is recorded again
+            let is_stalled = last_stall.is_some_and(|last_stall| last_stall.elapsed() < RECORDING_STALL_TIMEOUT);
+
+            if is_stalled != recording_is_stalled {
+                recording_is_stalled = is_stalled;
+
+                for (_, notifier) in sessions_f.lock()?.iter() {
+                    notifier.send(WebsocketMessage::RecordingStalled(is_stalled)).ok();
+                }
+            }
+
+            // Right away when the recording stalled, because the card is full or was pulled
+            if stall || last_storage_check.map_or(true, |last_check| last_check.elapsed() >= STORAGE_CHECK_INTERVAL) {
+                last_storage_check = Some(Instant::now());
+
+                let previous = retention.level();
+
+                match check_storage(&mut retention, &storage, &meeting_d) {
+                    Ok(report) => {
+                        if report.level != previous {
+                            warn!("storage {:?}, {}% used", report.level, report.usage.used_percent());
+
+                            match report.level {
+                                StorageLevel::Ok => display.draw(qrcode_state(storage.medium(), qrcode.clone()))?,
+                                _ => display.draw(DrawState::StorageWarning {
+                                    content: qrcode.clone(),
+                                    used_percent: report.usage.used_percent(),
+                                })?,
+                            }
+                        }
+
+                        // A session that just went away must not take the main loop down
+                        for (_, notifier) in sessions_f.lock()?.iter() {
+                            notifier.send(WebsocketMessage::Storage(report.clone())).ok();
+                        }
+                    }
+                    Err(error) if storage.medium() == StorageMedium::SdCard => {
+                        error!("storage check failed, the SD card is gone: {:?}", error);
+
+                        storage.card_removed();
+                        storage_changed(&storage, &meeting_d, &sessions_f);
+                        retention = storage_manager(storage.medium());
+                        last_card_detect = Instant::now();
+
+                        display.draw(qrcode_state(storage.medium(), qrcode.clone()))?;
+                    }
+                    Err(error) => debug!("storage check skipped: {:?}", error),
+                }
+            }
+
+            if last_status.map_or(true, |last_status| last_status.elapsed() >= MQTT_STATUS_INTERVAL) {
+                last_status = Some(Instant::now());
+
+                let info = storage.info();
+
+                mqtt.publish(
+                    "status",
+                    true,
+                    &json!({
+                        "firmware": FIRMWARE_VERSION,
+                        "meeting_id": meeting_d.lock()?.id,
+                        "rssi": network.rssi().ok(),
+                        "storage_medium": storage.medium(),
+                        "sd_total_bytes": info.as_ref().map(|info| info.total_bytes),
+                        "sd_used_bytes": info.as_ref().map(|info| info.used_bytes),
+                        "storage_level": retention.level(),
+                    }),
+                );
+            }
+
             button.reset();
 
             FreeRtos::delay_ms(1);
@@ -188,27 +528,25 @@ fn main() -> Result<(), CustomError> {
 }
 
 fn process_uploading_task_queue(
-    filenames_b: Arc<Mutex<VecDeque<String>>>,
+    segments: Arc<Mutex<VecDeque<StoredRecording>>>,
     transcription_uploaded_notifier: std::sync::mpsc::Sender<String>,
 ) -> Result<(), CustomError> {
     loop {
-        let mut filename = {
-            let mut filenames = filenames_b.lock()?;
-            filenames.pop_front()
+        let segment = {
+            let mut segments = segments.lock()?;
+            segments.pop_front()
         };
 
-        if let Some(filename) = filename {
+        if let Some(segment) = segment {
             warn!("uploading...");
 
-            let mut file = File::open(&filename)?;
-
-            if file.stream_len()? == 0 {
-                warn!("file length is zero, skipping...");
+            if segment.lengths()?.iter().sum::<u64>() == 0 {
+                warn!("segment is empty, skipping...");
                 continue;
             }
 
-            let mut assembly = Assembly::new(ASSEMBLY_APIKEY)?;
-            let response = assembly.upload::<_, SUMMARY_UPLOAD_CHUNK>(&mut file)?;
+            let mut assembly = Assembly::new(ASSEMBLY_APIKEY, EspHttpClient);
+            let response = assembly.upload_recording::<SUMMARY_UPLOAD_CHUNK>(&segment)?;
             let response = assembly.transcribe_wait(&response.upload_url)?;
 
             info!("uploaded successfully, transcription id: {}", response.id);
@@ -220,131 +558,788 @@ fn process_uploading_task_queue(
     }
 }
 
-fn record_audio_from_microphone_to_the_sdcard(receiver: crossbeam::channel::Receiver<Vec<u8>>) -> Result<(), CustomError> {
-    let random_number = unsafe { esp_random() };
-    let filename = format!("/sdcard/{}.wav", random_number);
-
-    let mut audio_file = File::create(&filename)?;
+// A full card fails the write. With segments the recording carries on in a new segment once the storage check made
+// room, the audio captured in between is dropped. A single file would be truncated by starting over, so it isn't.
+// Without a card nothing is recorded, the internal flash is far too small for audio
+fn record_audio_from_microphone_to_the_sdcard(
+    receiver: crossbeam::channel::Receiver<Vec<u8>>,
+    meeting: SharedMeeting,
+    medium: SharedMedium,
+    stalled: Arc<AtomicBool>,
+) -> Result<(), CustomError> {
+    let store = store();
 
     loop {
-        if let Ok(microphone_data) = receiver.recv() {
-            audio_file.write_all(microphone_data.as_slice())?;
+        if *medium.lock()? != StorageMedium::SdCard {
+            drain(&receiver, RECORDING_RETRY_INTERVAL);
+            continue;
+        }
+
+        if let Err(error) = echosense_core::pipeline::record_audio(receiver.clone(), meeting.clone(), &store) {
+            error!("recording stalled: {:?}", error);
+
+            stalled.store(true, Ordering::Relaxed);
+            drain(&receiver, RECORDING_RETRY_INTERVAL);
+
+            // A single file can't be carried on, creating it again would truncate it. What it has is kept, and the
+            // recording starts over with the next meeting. Segmented recordings carry on in a new segment right away
+            if store.segment_duration().is_none() {
+                let stalled_id = meeting.lock()?.id.clone();
+
+                while meeting.lock()?.id == stalled_id {
+                    stalled.store(true, Ordering::Relaxed);
+                    drain(&receiver, RECORDING_RETRY_INTERVAL);
+                }
+            }
+
+            continue;
+        }
+
+        return Ok(());
+    }
+}
+
+// Drops the frames that come in meanwhile, so they don't pile up in memory
+fn drain(receiver: &crossbeam::channel::Receiver<Vec<u8>>, duration: Duration) {
+    let until = Instant::now() + duration;
+
+    while Instant::now() < until {
+        receiver.try_iter().for_each(drop);
+        FreeRtos::delay_ms(100);
+    }
+}
+
+// The QR code, with the no card indicator while the internal flash or memory stands in for the card
+fn qrcode_state(medium: StorageMedium, address: String) -> DrawState {
+    match medium {
+        StorageMedium::SdCard => DrawState::QRCode(address),
+        _ => DrawState::NoCard(address),
+    }
+}
+
+// The current meeting is saved to the new storage, so what it has so far isn't only kept in memory
+fn storage_changed(storage: &Storage, meeting: &SharedMeeting, sessions: &Sessions) {
+    let medium = storage.medium();
+
+    info!("storage is now {:?}", medium);
+
+    if let Err(error) = meeting.lock().map_err(CustomError::from).and_then(|meeting| meeting.save()) {
+        warn!("failed to save the meeting to {:?}: {:?}", medium, error);
+    }
+
+    if let Ok(sessions) = sessions.lock() {
+        for (_, notifier) in sessions.iter() {
+            notifier.send(WebsocketMessage::StorageMedium(medium)).ok();
+        }
+    }
+}
+
+// Applies the retention rules, never to the meeting being recorded or to those the outbox still has to send
+fn check_storage(
+    retention: &mut StorageManager,
+    storage: &Storage,
+    meeting: &SharedMeeting,
+) -> Result<StorageReport, CustomError> {
+    let usage = storage.usage()?;
+    let now = unix_time();
+
+    let current = {
+        let mut meeting = meeting.lock()?;
+
+        // Started before SNTP set the clock, this is close enough
+        if meeting.started_at.is_none() && now.is_some() {
+            meeting.started_at = now;
+            meeting.save()?;
+        }
+
+        meeting.id.clone()
+    };
+
+    let mut protected = vec![current];
+
+    for entry in Outbox::entries() {
+        match entry.notification {
+            Notification::MeetingReport { meeting_id, .. } => protected.push(meeting_id),
         }
     }
+
+    Ok(retention.check(usage, &protected, now)?)
 }
 
+// Queues the segments the recording thread completes, instead of writing chunks of its own. A meeting is followed until
+// its last segment is complete, which happens only after the next meeting has started
 fn periodically_upload_transcriptions(
-    receiver: crossbeam::channel::Receiver<Vec<u8>>,
+    meeting: SharedMeeting,
     transcription_uploaded_notifier: std::sync::mpsc::Sender<String>,
 ) -> Result<(), CustomError> {
-    let mut filenames = Arc::new(Mutex::new(VecDeque::<String>::new()));
-    let filenames_a = filenames.clone();
-    let filenames_b = filenames.clone();
+    let segments = Arc::new(Mutex::new(VecDeque::<StoredRecording>::new()));
+    let segments_a = segments.clone();
 
     std::thread::Builder::new().spawn::<_, Result<(), CustomError>>(move || {
-        process_uploading_task_queue(filenames_b, transcription_uploaded_notifier)
+        process_uploading_task_queue(segments_a, transcription_uploaded_notifier)
     })?;
 
+    // Meeting ids and how many of their segments are queued already
+    let mut followed = Vec::<(String, usize)>::new();
+    let store = store();
+
     loop {
-        let random_number = unsafe { esp_random() };
-        let filename = format!("/sdcard/{}.wav", random_number);
+        let current = meeting.lock()?.id.clone();
 
-        let mut audio_file = File::create(&filename)?;
-        let mut chunks = 0;
+        if followed.iter().any(|(id, _)| *id == current) == false {
+            followed.push((current.clone(), 0));
+        }
 
-        loop {
-            if let Ok(microphone_data) = receiver.recv() {
-                audio_file.write_all(microphone_data.as_slice())?;
-                chunks += 1;
-            }
+        for (id, queued) in followed.iter_mut() {
+            let Ok(manifest) = store.load_manifest(id) else {
+                continue;
+            };
 
-            if chunks >= SUMMARY_UPLOAD_CHUNK {
-                let mut filenames = filenames_a.lock()?;
-                filenames.push_back(filename);
+            let complete = manifest.segments.iter().take_while(|segment| segment.complete).count();
 
-                break drop(audio_file);
+            for index in *queued..complete {
+                if let Some(segment) = store.stored_segment(id, index) {
+                    segments.lock()?.push_back(segment);
+                }
             }
+
+            *queued = complete.max(*queued);
         }
+
+        followed.retain(|(id, queued)| {
+            *id == current || store.load_manifest(id).map_or(false, |manifest| *queued < manifest.segments.len())
+        });
+
+        FreeRtos::delay_ms(SEGMENT_POLL_INTERVAL_MS)
     }
 }
 
 fn start_live_transcription(
     receiver: crossbeam::channel::Receiver<Vec<u8>>,
-    mut live_transcription_websocket_notifier: EspWebSocketClient,
+    mut live_transcription_websocket_notifier: EspWsClient,
+    mut gate: VoiceGate,
+    meeting: SharedMeeting,
 ) -> Result<(), CustomError> {
-    loop {
-        if let Ok(microphone_data) = receiver.recv() {
-            live_transcription_websocket_notifier.send(FrameType::Binary(false), microphone_data.as_slice())?;
-        }
+    Ok(echosense_core::pipeline::start_live_transcription(
+        receiver,
+        &mut live_transcription_websocket_notifier,
+        &mut gate,
+        &meeting,
+        &store(),
+    )?)
+}
+
+// Silence is held back from the realtime API by default, VAD_PAUSE_STREAMING=false only measures talk time
+fn vad_config() -> VadConfig {
+    let defaults = VadConfig::default();
+
+    VadConfig {
+        pause_streaming: VAD_PAUSE_STREAMING
+            .map(|value| matches!(value, "false" | "off" | "0") == false)
+            .unwrap_or(defaults.pause_streaming),
+        threshold_dbfs: VAD_THRESHOLD_DBFS
+            .and_then(|value| value.parse().ok())
+            .unwrap_or(defaults.threshold_dbfs),
+        hangover_ms: VAD_HANGOVER_MS
+            .and_then(|value| value.parse().ok())
+            .unwrap_or(defaults.hangover_ms),
+        ..defaults
     }
 }
 
 fn record_microphone(
-    sender: Sender<Vec<u8>>,
+    senders: Vec<Sender<Vec<u8>>>,
     mut microphone: Microphone<I2sRx, MICROPHONE_RECORD_BUFFER_SIZE>,
+    meeting: SharedMeeting,
+    medium: SharedMedium,
 ) -> Result<(), CustomError> {
+    let config = dsp_config();
+
+    info!("audio processing: {:?}", config);
+
+    let mut dsp = DspChain::new(&config, SAMPLE_RATE_HZ);
+    let mut combiner = combiner();
+    let channels = microphone.channels();
+
+    // Each microphone on its own next to the combined recording, MIC_KEEP_CHANNELS=true
+    let raw = match (MIC_KEEP_CHANNELS, channels) {
+        (Some("true" | "on" | "1"), 2..) => {
+            let (sender, receiver) = crossbeam::channel::unbounded::<Vec<u8>>();
+
+            std::thread::Builder::new().spawn(move || record_channels_to_the_sdcard(receiver, channels, meeting, medium))?;
+
+            Some(sender)
+        }
+        _ => None,
+    };
+
+    Ok(echosense_core::pipeline::record_microphone(senders, &mut microphone, &mut combiner, &mut dsp, raw)?)
+}
+
+// Starts once there is a card. After a failure the frames are only drained, a microphone that can't hand them over would
+// stop the whole recording
+fn record_channels_to_the_sdcard(
+    receiver: crossbeam::channel::Receiver<Vec<u8>>,
+    channels: u16,
+    meeting: SharedMeeting,
+    medium: SharedMedium,
+) -> Result<(), CustomError> {
+    while *medium.lock()? != StorageMedium::SdCard {
+        drain(&receiver, RECORDING_RETRY_INTERVAL);
+    }
+
+    if let Err(error) = echosense_core::pipeline::record_channels(receiver.clone(), channels, meeting, &store()) {
+        error!("stopped recording the separate channels: {:?}", error);
+
+        while receiver.recv().is_ok() {}
+    }
+
+    Ok(())
+}
+
+// Only used with MIC_COMBINE, MIC_SPACING_MM is the distance between the two mics (60 mm by default)
+fn combiner() -> Combiner {
+    let mode = MIC_COMBINE.and_then(CombineMode::from_name).unwrap_or(CombineMode::Mix);
+    let spacing_mm = MIC_SPACING_MM.and_then(|value| value.parse().ok()).unwrap_or(60);
+
+    info!("microphones combined: {:?}, {} mm apart", mode, spacing_mm);
+
+    Combiner::for_spacing(mode, SAMPLE_RATE_HZ, spacing_mm)
+}
+
+// Defaults to a 16-bit mono mic at 16 kHz, e.g. MIC_BITS=32 for an INMP441 or MIC_CHANNEL=mix for a dual-mic board.
+// MIC_COMBINE=mix|louder|beamform captures both mics and leaves combining them to the Combiner instead
+fn microphone_config() -> MicrophoneConfig {
+    let defaults = MicrophoneConfig::default();
+
+    let config = MicrophoneConfig {
+        sample_rate_hz: MIC_SAMPLE_RATE_HZ
+            .and_then(|value| value.parse().ok())
+            .unwrap_or(defaults.sample_rate_hz),
+        width: MIC_BITS
+            .and_then(|value| value.parse().ok())
+            .and_then(SampleWidth::from_bits)
+            .unwrap_or(defaults.width),
+        standard: MIC_STANDARD
+            .and_then(SlotStandard::from_name)
+            .unwrap_or(defaults.standard),
+        selection: match MIC_COMBINE.and_then(CombineMode::from_name) {
+            Some(_) => ChannelSelection::Both,
+            None => MIC_CHANNEL
+                .and_then(ChannelSelection::from_name)
+                .unwrap_or(defaults.selection),
+        },
+    };
+
+    info!("microphone: {:?}", config);
+
+    config
+}
+
+// Every stage can be tuned or switched off from .env, e.g. DSP_AGC=false or DSP_HIGH_PASS_HZ=off
+fn dsp_config() -> DspConfig {
+    let defaults = DspConfig::default();
+    let disabled = |value: &str| matches!(value, "false" | "off" | "0");
+
+    let agc = match DSP_AGC.filter(|value| disabled(value)) {
+        Some(_) => None,
+        None => {
+            let agc = AgcConfig::default();
+
+            Some(AgcConfig {
+                target_dbfs: DSP_AGC_TARGET_DBFS
+                    .and_then(|value| value.parse().ok())
+                    .unwrap_or(agc.target_dbfs),
+                max_gain_db: DSP_AGC_MAX_GAIN_DB
+                    .and_then(|value| value.parse().ok())
+                    .unwrap_or(agc.max_gain_db),
+                ..agc
+            })
+        }
+    };
+
+    DspConfig {
+        dc_block: DSP_DC_BLOCK.map(|value| disabled(value) == false).unwrap_or(defaults.dc_block),
+        high_pass_hz: match DSP_HIGH_PASS_HZ {
+            Some(value) if disabled(value) => None,
+            Some(value) => value.parse().ok().or(defaults.high_pass_hz),
+            None => defaults.high_pass_hz,
+        },
+        noise_gate: DSP_NOISE_GATE_DBFS
+            .and_then(|value| value.parse().ok())
+            .map(|threshold_dbfs| NoiseGateConfig {
+                threshold_dbfs,
+                ..NoiseGateConfig::default()
+            }),
+        agc,
+    }
+}
+
+fn monitor_audio(
+    receiver: crossbeam::channel::Receiver<Vec<u8>>,
+    sessions: Sessions,
+    monitors: Monitors,
+) -> Result<(), CustomError> {
+    let mut frames = 0usize;
+
     loop {
-        sender.send(microphone.sample()?.to_vec())?;
+        if let Ok(microphone_data) = receiver.recv() {
+            let monitors = {
+                let monitors = monitors.lock()?;
+                monitors.clone()
+            };
+
+            if monitors.is_empty() {
+                continue;
+            }
+
+            frames += 1;
+
+            let level = if frames % MONITOR_LEVEL_INTERVAL == 0 {
+                Some(AudioLevel::measure(microphone_data.as_slice()))
+            } else {
+                None
+            };
+
+            let sessions = sessions.lock()?;
+
+            // A tab that was just closed may not be removed yet, it mustn't end the monitor and with it the microphone
+            for (session_id, notifier) in sessions.iter() {
+                if monitors.contains(session_id) == false {
+                    continue;
+                }
+
+                if notifier.send(WebsocketMessage::Audio(microphone_data.clone())).is_err() {
+                    warn!("monitoring session {} is gone", session_id);
+                    continue;
+                }
+
+                if let Some(level) = level {
+                    notifier.send(WebsocketMessage::AudioLevel { rms: level.rms, peak: level.peak }).ok();
+                }
+            }
+        }
     }
 }
 
+fn meeting_overview(meeting: &Meeting) -> serde_json::Value {
+    json!({
+        "title": meeting.title(),
+        "duration_ms": meeting.duration_ms(),
+        "transcripts": meeting.transcriptions.len(),
+        "talk_time": meeting.talk_time,
+    })
+}
+
+// SMTP takes over from SendGrid as soon as a host is configured at build time
+fn create_mail_transport() -> Result<(&'static str, Box<dyn MailTransport>), CustomError> {
+    let from = SMTP_FROM.filter(|from| from.is_empty() == false).unwrap_or(SENDGRID_FROM);
+
+    let Some(host) = SMTP_HOST.filter(|host| host.is_empty() == false) else {
+        return Ok((SENDGRID_FROM, Box::new(SendGrid::new(SENDGRID_APIKEY)?)));
+    };
+
+    let security = SMTP_SECURITY
+        .and_then(SmtpSecurity::from_name)
+        .unwrap_or(SmtpSecurity::StartTls);
+
+    let port = SMTP_PORT
+        .and_then(|port| port.parse::<u16>().ok())
+        .unwrap_or(match security {
+            SmtpSecurity::StartTls => 587,
+            SmtpSecurity::Implicit => 465,
+            SmtpSecurity::None => 25,
+        });
+
+    let smtp = Smtp::new(
+        host,
+        port,
+        security,
+        SMTP_USERNAME.unwrap_or(""),
+        SMTP_PASSWORD.unwrap_or(""),
+    );
+
+    Ok((from, Box::new(smtp)))
+}
+
 fn handle_frontend_sent_commands(
     frontend_command_receiver: Receiver<Command>,
-    transcriptions: Arc<Mutex<Vec<Transcription>>>,
+    meeting: SharedMeeting,
     sessions: Sessions,
     trigger: Arc<AtomicBool>,
+    outbox: crossbeam::channel::Sender<()>,
+    webhooks: Webhooks,
 ) -> Result<(), CustomError> {
     while let Ok(command) = frontend_command_receiver.recv() {
         info!("received command: {:?}", command);
 
-        let mut assembly = Assembly::new(ASSEMBLY_APIKEY)?;
-        let transcriptions = {
-            let transcriptions = transcriptions.lock()?;
-            transcriptions.clone()
-        };
+        // A failing command (no connectivity, full SD card...) must never take the command loop down with it
+        if let Err(error) = handle_frontend_sent_command(command, &meeting, &sessions, &trigger, &outbox, &webhooks) {
+            error!("failed to handle command: {:?}", error);
+        }
+    }
 
-        match command {
-            Command::GetSummary => {
-                let response = assembly.summarize_transcripts(transcriptions)?;
+    Ok(())
+}
 
-                info!("summary: {:?}", response);
+fn handle_frontend_sent_command(
+    command: Command,
+    meeting: &SharedMeeting,
+    sessions: &Sessions,
+    trigger: &Arc<AtomicBool>,
+    outbox: &crossbeam::channel::Sender<()>,
+    webhooks: &Webhooks,
+) -> Result<(), CustomError> {
+    let mut assembly = Assembly::new(ASSEMBLY_APIKEY, EspHttpClient);
+    let snapshot = {
+        let meeting = meeting.lock()?;
+        meeting.clone()
+    };
+
+    match command {
+        Command::StartMeeting => {
+            let (previous, current) = {
+                let mut meeting = meeting.lock()?;
+                let previous = std::mem::replace(&mut *meeting, new_meeting());
+                meeting.save()?;
+
+                (previous, meeting.id.clone())
+            };
+
+            info!("finished meeting {}, started meeting {}", previous.id, current);
+
+            webhooks.emit(WebhookEvent::MeetingFinished, &previous.id, meeting_overview(&previous));
+            webhooks.emit(WebhookEvent::MeetingStarted, &current, json!({}));
 
+            trigger.store(false, Ordering::Relaxed);
+
+            let sessions = sessions.lock()?;
+
+            for (_, notifier) in sessions.iter() {
+                notifier.send(WebsocketMessage::MeetingId(current.clone()))?;
+            }
+        }
+        Command::GetSummary => {
+            let summary = summarize(&mut assembly, meeting, &store())?;
+
+            webhooks.emit(WebhookEvent::SummaryReady, &snapshot.id, json!({ "summary": summary }));
+
+            let sessions = sessions.lock()?;
+
+            for (_, notifier) in sessions.iter() {
+                notifier.send(WebsocketMessage::Summary(summary.clone()))?;
+            }
+        }
+        Command::AskQuestion { id, question } => {
+            let question_answer = answer_question(&mut assembly, meeting, &store(), id, question)?;
+
+            webhooks.emit(WebhookEvent::QuestionAnswered, &snapshot.id, json!(question_answer));
+
+            let sessions = sessions.lock()?;
+
+            for (_, notifier) in sessions.iter() {
+                notifier.send(WebsocketMessage::AnswerQuestion {
+                    id: question_answer.id.clone(),
+                    answer: question_answer.answer.clone(),
+                })?;
+            }
+        }
+        Command::SendTranscriptionViaEmail { email, with_audio, formats, recipients, lists } => {
+            let mut requested = recipients;
+
+            if let Some(email) = email {
+                requested.to.push(email);
+            }
+
+            let distribution_lists = DistributionLists::load();
+
+            for name in lists.iter() {
+                match distribution_lists.get(name) {
+                    Some(list) => requested.merge(list.clone()),
+                    None => warn!("distribution list {} does not exist", name),
+                }
+            }
+
+            let (recipients, mut statuses) = requested.validate();
+
+            if recipients.to.is_empty() && recipients.is_empty() == false {
+                for email in recipients.all() {
+                    statuses.push(RecipientStatus::failed(email, "at least one valid \"to\" recipient is required"));
+                }
+            }
+
+            if recipients.to.is_empty() == false {
+                let mut snapshot = snapshot;
+
+                // Fill in what the report needs but wasn't requested during the meeting, a failure only leaves the section empty
+                if snapshot.transcriptions.is_empty() == false {
+                    if snapshot.summary.is_none() {
+                        snapshot.summary = assembly
+                            .summarize_transcripts(snapshot.transcriptions.clone())
+                            .map(|response| response.response)
+                            .ok();
+                    }
+
+                    if snapshot.action_items.is_none() {
+                        snapshot.action_items = assembly
+                            .action_items(snapshot.transcriptions.clone())
+                            .map(|response| response.response)
+                            .ok();
+                    }
+
+                    let mut meeting = meeting.lock()?;
+                    meeting.summary = snapshot.summary.clone();
+                    meeting.action_items = snapshot.action_items.clone();
+                }
+
+                // The outbox loads the meeting from the SD card, make sure it is there even without transcripts
+                meeting.lock()?.save()?;
+
+                // Queued on the SD card first, the outbox thread takes care of sending and retrying
+                let entry = OutboxEntry::new(Notification::MeetingReport {
+                    meeting_id: snapshot.id.clone(),
+                    recipients,
+                    formats,
+                    with_audio,
+                });
+
+                entry.save()?;
+                outbox.send(())?;
+            }
+
+            if statuses.is_empty() == false {
                 let sessions = sessions.lock()?;
 
                 for (_, notifier) in sessions.iter() {
-                    notifier.send(WebsocketMessage::Summary(response.response.clone()))?;
+                    notifier.send(WebsocketMessage::EmailDelivery(statuses.clone()))?;
                 }
             }
-            Command::AskQuestion { id, question } => {
-                let response = assembly.ask_question(question, transcriptions)?;
-                let sessions = sessions.lock()?;
 
-                info!("{:?}", response);
+            trigger.store(true, Ordering::Relaxed);
+        }
+        Command::GetDistributionLists => {
+            let lists = DistributionLists::load();
+            let sessions = sessions.lock()?;
 
-                for (_, notifier) in sessions.iter() {
-                    notifier.send(WebsocketMessage::AnswerQuestion {
-                        id: id.clone(),
-                        answer: response.response[0].answer.clone(),
-                    })?;
+            for (_, notifier) in sessions.iter() {
+                notifier.send(WebsocketMessage::DistributionLists(lists.all().clone()))?;
+            }
+        }
+        Command::SaveDistributionList { name, recipients } => {
+            let (recipients, rejected) = recipients.validate();
+            let mut lists = DistributionLists::load();
+
+            if rejected.is_empty() == false {
+                warn!("dropped invalid addresses from list {}: {:?}", name, rejected);
+            }
+
+            lists.insert(name, recipients);
+            lists.save()?;
+
+            let sessions = sessions.lock()?;
+
+            for (_, notifier) in sessions.iter() {
+                notifier.send(WebsocketMessage::DistributionLists(lists.all().clone()))?;
+            }
+        }
+        Command::DeleteDistributionList { name } => {
+            let mut lists = DistributionLists::load();
+
+            lists.remove(&name);
+            lists.save()?;
+
+            let sessions = sessions.lock()?;
+
+            for (_, notifier) in sessions.iter() {
+                notifier.send(WebsocketMessage::DistributionLists(lists.all().clone()))?;
+            }
+        }
+        Command::GetWebhooks => {
+            let targets = WebhookTargets::load();
+            let sessions = sessions.lock()?;
+
+            for (_, notifier) in sessions.iter() {
+                notifier.send(WebsocketMessage::Webhooks(targets.redacted()))?;
+            }
+        }
+        Command::SaveWebhook { token, .. } | Command::DeleteWebhook { token, .. } if authorized(token) == false => {
+            warn!("rejected a webhook change without a valid token");
+        }
+        Command::SaveWebhook { url, secret, events, .. } => {
+            let target = WebhookTarget { url, secret, events };
+            let mut targets = WebhookTargets::load();
+
+            if target.is_valid() {
+                targets.insert(target);
+                targets.save()?;
+            } else {
+                warn!("rejected webhook {} without an http(s) url or a secret", target.url);
+            }
+
+            let sessions = sessions.lock()?;
+
+            for (_, notifier) in sessions.iter() {
+                notifier.send(WebsocketMessage::Webhooks(targets.redacted()))?;
+            }
+        }
+        Command::DeleteWebhook { url, .. } => {
+            let mut targets = WebhookTargets::load();
+
+            targets.remove(&url);
+            targets.save()?;
+
+            let sessions = sessions.lock()?;
+
+            for (_, notifier) in sessions.iter() {
+                notifier.send(WebsocketMessage::Webhooks(targets.redacted()))?;
+            }
+        }
+        Command::SetMeetingTitle { title } => {
+            let mut meeting = meeting.lock()?;
+            meeting.title = Some(title);
+            meeting.save()?;
+        }
+        Command::SetMeetingStarred { id, starred } => {
+            let mut current = meeting.lock()?;
+
+            // Starred meetings are kept by the retention rules, the current one unless another id is given
+            match id.filter(|id| *id != current.id) {
+                Some(id) => {
+                    let mut meeting = Meeting::load(&id)?;
+                    meeting.starred = starred;
+                    meeting.save()?;
+                }
+                None => {
+                    current.starred = starred;
+                    current.save()?;
                 }
             }
-            Command::SendTranscriptionViaEmail { email, with_audio } => {
-                let mut sendgrid = SendGrid::new(SENDGRID_APIKEY)?;
+        }
+        // Handled per session by the websocket handler
+        Command::MonitorAudio { .. } => {}
+        // Sent to the main loop by the websocket handler
+        Command::Maintenance { .. } => {}
+    }
+
+    Ok(())
+}
+
+fn process_outbox(
+    wakeup: crossbeam::channel::Receiver<()>,
+    sessions: Sessions,
+    address: String,
+) -> Result<(), CustomError> {
+    // Retry times only live in memory, whatever survived a reboot is attempted right away
+    let mut schedule = HashMap::<String, Instant>::new();
+
+    loop {
+        if let Err(error) = process_outbox_entries(&mut schedule, &sessions, &address) {
+            error!("failed to process the outbox: {:?}", error);
+        }
+
+        let timeout = schedule
+            .values()
+            .min()
+            .map(|due| due.saturating_duration_since(Instant::now()))
+            .unwrap_or(OUTBOX_IDLE_INTERVAL);
+
+        // Woken up early whenever something new is queued
+        let _ = wakeup.recv_timeout(timeout);
+    }
+}
+
+fn process_outbox_entries(
+    schedule: &mut HashMap<String, Instant>,
+    sessions: &Sessions,
+    address: &str,
+) -> Result<(), CustomError> {
+    let mut changed = false;
+
+    for mut entry in Outbox::entries() {
+        if schedule.get(&entry.id).is_some_and(|due| *due > Instant::now()) {
+            continue;
+        }
+
+        changed = true;
+
+        let statuses = match deliver_notification(&entry.notification, address) {
+            Ok(statuses) => {
+                info!("delivered outbox entry {}", entry.id);
+
+                schedule.remove(&entry.id);
+                entry.remove()?;
+
+                statuses
+            }
+            Err(error) => {
+                entry.attempts += 1;
+                entry.last_error = Some(format!("{:?}", error));
 
-                if let Some(email) = email {
-                    sendgrid.send_email(email, transcriptions)?
+                if entry.is_exhausted() == false {
+                    warn!("outbox entry {} failed (attempt {}): {:?}", entry.id, entry.attempts, error);
+
+                    schedule.insert(entry.id.clone(), Instant::now() + entry.backoff());
+                    entry.save()?;
+
+                    continue;
                 }
 
-                trigger.store(true, Ordering::Relaxed);
+                error!("giving up on outbox entry {} after {} attempts", entry.id, entry.attempts);
+
+                schedule.remove(&entry.id);
+                entry.remove()?;
+
+                match &entry.notification {
+                    Notification::MeetingReport { recipients, .. } => recipients
+                        .all()
+                        .map(|email| RecipientStatus::failed(email, format!("{:?}", error)))
+                        .collect(),
+                }
             }
+        };
+
+        let sessions = sessions.lock()?;
+
+        for (_, notifier) in sessions.iter() {
+            notifier.send(WebsocketMessage::EmailDelivery(statuses.clone()))?;
+        }
+    }
+
+    if changed {
+        let entries = Outbox::entries();
+        let sessions = sessions.lock()?;
+
+        for (_, notifier) in sessions.iter() {
+            notifier.send(WebsocketMessage::Outbox(entries.clone()))?;
         }
     }
 
     Ok(())
 }
 
+fn deliver_notification(notification: &Notification, address: &str) -> Result<Vec<RecipientStatus>, CustomError> {
+    match notification {
+        Notification::MeetingReport { meeting_id, recipients, formats, with_audio } => {
+            let meeting = Meeting::load(meeting_id)?;
+            let (from, mut transport) = create_mail_transport()?;
+
+            let message = Message::meeting_report(
+                from,
+                recipients.clone(),
+                &meeting,
+                address,
+                formats,
+                *with_audio,
+                transport.max_message_size(),
+            )?;
+
+            transport.send(&message)
+        }
+    }
+}
+
 fn generate_summary(receiver: Receiver<String>, sessions: Sessions) -> Result<(), CustomError> {
-    let mut assembly = Assembly::new(ASSEMBLY_APIKEY)?;
+    let mut assembly = Assembly::new(ASSEMBLY_APIKEY, EspHttpClient);
     let mut uploaded_transcriptions_ids = vec![];
 
     loop {
@@ -372,52 +1367,32 @@ fn generate_summary(receiver: Receiver<String>, sessions: Sessions) -> Result<()
 fn handle_transcription_thread(
     receiver: Receiver<AssemblyResponse>,
     sessions: Sessions,
-    transcriptions: Arc<Mutex<Vec<Transcription>>>,
+    meeting: SharedMeeting,
+    webhooks: Webhooks,
+    timeline: SharedTimeline,
 ) -> Result<(), CustomError> {
+    let store = store();
+
     loop {
         if let Ok(message) = receiver.recv() {
-            match message {
-                AssemblyResponse::PartialTranscript { text, created } => {
-                    if text.is_empty() == false {
-                        let transcription = Transcription {
-                            text: text.clone(),
-                            timestamp: created.clone(),
-                        };
-
-                        let sessions = sessions.lock()?;
-
-                        for (_, notifier) in sessions.iter() {
-                            // info!("P: ...{}", &text[text.len().saturating_sub(100)..]);
-                            notifier.send(WebsocketMessage::PartialTranscription(
-                                transcription.clone(),
-                            ))?
-                        }
+            match handle_transcript(message, &meeting, &store, &timeline)? {
+                Some(TranscriptEvent::Partial(transcription)) => {
+                    let sessions = sessions.lock()?;
+
+                    for (_, notifier) in sessions.iter() {
+                        notifier.send(WebsocketMessage::PartialTranscription(transcription.clone()))?
                     }
                 }
-                AssemblyResponse::FinalTranscript { text, created } => {
-                    println!("{:?}", created);
-                    if text.is_empty() == false {
-                        let transcription = Transcription {
-                            text: text.clone(),
-                            timestamp: created.clone(),
-                        };
-
-                        {
-                            let mut transcriptions = transcriptions.lock()?;
-                            transcriptions.push(transcription.clone())
-                        }
+                Some(TranscriptEvent::Final { meeting_id, transcription }) => {
+                    webhooks.emit(WebhookEvent::FinalTranscript, &meeting_id, json!(transcription));
 
-                        let sessions = sessions.lock()?;
-                        for (_, notifier) in sessions.iter() {
-                            // warn!("F: ...{}", &text[text.len().saturating_sub(100)..]);
-                            notifier
-                                .send(WebsocketMessage::FinalTranscription(transcription.clone()))?
-                        }
+                    let sessions = sessions.lock()?;
+
+                    for (_, notifier) in sessions.iter() {
+                        notifier.send(WebsocketMessage::FinalTranscription(transcription.clone()))?
                     }
                 }
-                AssemblyResponse::SessionBegins { .. } => {}
-                AssemblyResponse::SessionInformation { .. } => {}
-                AssemblyResponse::SessionTerminated => {}
+                None => {}
             }
         }
     }
@@ -0,0 +1,108 @@
+use std::path::Path;
+use std::sync::mpsc::Sender;
+use std::time::Duration;
+
+use echosense_core::maintenance::{benchmark, check, storage_info, Confirmation, MaintenanceAction, MaintenanceResult};
+use esp_idf_svc::sys::esp_random;
+use log::{info, warn};
+
+use crate::meeting::{store, STORAGE_ROOT};
+use crate::storage::{Storage, StorageMedium};
+use crate::OTA_TOKEN;
+
+const MAINTENANCE_TOKEN: Option<&str> = option_env!("MAINTENANCE_TOKEN");
+const FORMAT_CONFIRMATION_TIMEOUT: Duration = Duration::from_secs(15);
+// Large enough to get past the card's write cache, small enough for a card that is almost full
+const SD_CARD_BENCHMARK_BYTES: usize = 1024 * 1024;
+const INTERNAL_FLASH_BENCHMARK_BYTES: usize = 16 * 1024;
+
+// Formatting wipes every meeting and webhooks get a copy of every transcript, so without a MAINTENANCE_TOKEN of its own
+// it takes the OTA_TOKEN
+pub fn authorized(token: &str) -> bool {
+    let expected = MAINTENANCE_TOKEN
+        .filter(|token| token.is_empty() == false)
+        .unwrap_or(OTA_TOKEN);
+
+    expected.is_empty() == false && token == expected
+}
+
+// Carried out by the main loop, which owns the storage
+pub struct MaintenanceRequest {
+    pub action: MaintenanceAction,
+    // Confirms the format requested before
+    pub code: Option<String>,
+    // Where an HTTP request waits for the result, every websocket session gets it either way
+    pub reply: Option<Sender<MaintenanceResult>>,
+}
+
+#[derive(Default)]
+pub struct Maintenance {
+    confirmation: Option<Confirmation>,
+}
+
+impl Maintenance {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    // The code of the format waiting to be confirmed, the button confirms it by being held again
+    pub fn pending_code(&self) -> Option<String> {
+        self.confirmation
+            .as_ref()
+            .filter(|confirmation| confirmation.is_expired() == false)
+            .map(|confirmation| confirmation.code().to_string())
+    }
+
+    // `active` are the meetings still being recorded, the check leaves their recording open
+    pub fn run(
+        &mut self,
+        storage: &mut Storage,
+        action: MaintenanceAction,
+        code: Option<&str>,
+        active: &[String],
+    ) -> MaintenanceResult {
+        info!("storage maintenance: {:?}", action);
+
+        let medium = storage.medium();
+
+        match action {
+            MaintenanceAction::Info => MaintenanceResult::Info(storage_info(&store(), medium, storage.usage().ok())),
+            _ if medium == StorageMedium::Memory => MaintenanceResult::Failed("No storage mounted".to_string()),
+            MaintenanceAction::Check => MaintenanceResult::Check(check(&store(), active)),
+            MaintenanceAction::Benchmark => {
+                let bytes = match medium {
+                    StorageMedium::SdCard => SD_CARD_BENCHMARK_BYTES,
+                    _ => INTERNAL_FLASH_BENCHMARK_BYTES,
+                };
+
+                match benchmark(Path::new(STORAGE_ROOT), bytes) {
+                    Ok(result) => MaintenanceResult::Benchmark(result),
+                    Err(error) => MaintenanceResult::Failed(format!("{:?}", error)),
+                }
+            }
+            // A wrong code uses the confirmation up, the format has to be requested again
+            MaintenanceAction::Format => match (self.confirmation.take(), code) {
+                (Some(confirmation), Some(code)) if confirmation.confirms(code) => match storage.format() {
+                    Ok(()) => MaintenanceResult::Formatted,
+                    Err(error) => {
+                        warn!("failed to format the {:?}: {:?}", medium, error);
+                        MaintenanceResult::Failed(format!("{:?}", error))
+                    }
+                },
+                _ => {
+                    let random_number = unsafe { esp_random() };
+                    let confirmation =
+                        Confirmation::new(format!("{:04}", random_number % 10000), FORMAT_CONFIRMATION_TIMEOUT);
+
+                    let result = MaintenanceResult::ConfirmFormat {
+                        code: confirmation.code().to_string(),
+                        expires_in_s: FORMAT_CONFIRMATION_TIMEOUT.as_secs(),
+                    };
+
+                    self.confirmation = Some(confirmation);
+                    result
+                }
+            },
+        }
+    }
+}
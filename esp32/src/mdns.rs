@@ -0,0 +1,41 @@
+use esp_idf_svc::mdns::EspMdns;
+
+use crate::custom_error::CustomError;
+use crate::FIRMWARE_VERSION;
+
+pub struct Mdns {
+    hostname: String,
+    inner: EspMdns,
+}
+
+impl Mdns {
+    pub fn new(mac: [u8; 6], port: u16) -> Result<Self, CustomError> {
+        // Use the last 3 bytes of the MAC so the name is stable across reboots and DHCP leases
+        let hostname = format!("echosense-{:02x}{:02x}{:02x}", mac[3], mac[4], mac[5]);
+
+        let mut mdns = EspMdns::take()?;
+
+        mdns.set_hostname(&hostname)?;
+        mdns.set_instance_name("EchoSense")?;
+        mdns.add_service(
+            Some("EchoSense"),
+            "_http",
+            "_tcp",
+            port,
+            &[("version", FIRMWARE_VERSION), ("path", "/")],
+        )?;
+
+        Ok(Self {
+            hostname,
+            inner: mdns,
+        })
+    }
+
+    pub fn hostname(&self) -> &str {
+        self.hostname.as_str()
+    }
+
+    pub fn address(&self) -> String {
+        format!("http://{}.local", self.hostname)
+    }
+}
@@ -0,0 +1,86 @@
+use echosense_core::codec::RecordingCodec;
+use echosense_core::error::CoreError;
+use echosense_core::platform::RecordingStore;
+use echosense_core::store::{FileStore, StoredRecording};
+use esp_idf_svc::sys::esp_random;
+use std::time::Duration;
+
+pub use echosense_core::meeting::{Meeting, QuestionAnswer, SharedMeeting};
+
+use crate::clock::unix_time;
+use crate::custom_error::CustomError;
+use crate::encryption::storage_key;
+
+pub const STORAGE_ROOT: &str = "/sdcard";
+
+const RECORDING_CODEC: Option<&str> = option_env!("RECORDING_CODEC");
+const RECORDING_SEGMENT_SECONDS: Option<&str> = option_env!("RECORDING_SEGMENT_SECONDS");
+const DEFAULT_SEGMENT_SECONDS: u64 = 300;
+
+// New recordings use RECORDING_CODEC (pcm, adpcm or flac), raw PCM when it isn't set, and are split into segments of
+// RECORDING_SEGMENT_SECONDS, 0 keeps a single file per meeting. Encrypted when STORAGE_ENCRYPTION is on
+pub fn store() -> FileStore {
+    let codec = RECORDING_CODEC
+        .and_then(RecordingCodec::from_name)
+        .unwrap_or(RecordingCodec::Pcm);
+
+    let segment_seconds = RECORDING_SEGMENT_SECONDS
+        .and_then(|seconds| seconds.parse().ok())
+        .unwrap_or(DEFAULT_SEGMENT_SECONDS);
+
+    let store = FileStore::new(STORAGE_ROOT).with_codec(codec);
+
+    let store = match segment_seconds {
+        0 => store,
+        seconds => store.with_segments(Duration::from_secs(seconds)),
+    };
+
+    match storage_key() {
+        Some(key) => store.with_encryption(key),
+        None => store,
+    }
+}
+
+pub fn new_meeting() -> Meeting {
+    let random_number = unsafe { esp_random() };
+
+    let mut meeting = Meeting::new(format!("{:08x}", random_number));
+    meeting.started_at = unix_time();
+
+    meeting
+}
+
+// Meetings live on the SD card, under /sdcard/meetings/<id>
+pub trait MeetingFiles: Sized {
+    fn load(id: &str) -> Result<Self, CustomError>;
+
+    fn save(&self) -> Result<(), CustomError>;
+
+    // Written by the recording thread at SAMPLE_RATE_HZ, raw 16-bit little endian PCM or compressed with the codec
+    // it comes with, in one file or in segments
+    fn recording(&self) -> Option<StoredRecording>;
+
+    fn recording_of(id: &str) -> Option<StoredRecording>;
+}
+
+impl MeetingFiles for Meeting {
+    fn load(id: &str) -> Result<Self, CustomError> {
+        match store().load_meeting(id) {
+            Ok(meeting) => Ok(meeting),
+            Err(CoreError::MeetingNotFound(id)) => Err(CustomError::MeetingNotFound(id)),
+            Err(error) => Err(error.into()),
+        }
+    }
+
+    fn save(&self) -> Result<(), CustomError> {
+        Ok(store().save_meeting(self)?)
+    }
+
+    fn recording(&self) -> Option<StoredRecording> {
+        Self::recording_of(&self.id)
+    }
+
+    fn recording_of(id: &str) -> Option<StoredRecording> {
+        store().stored_recording(id)
+    }
+}
@@ -1,20 +1,104 @@
 use std::io::Write;
 
 use esp_idf_svc::hal::gpio::{AnyIOPin, InputPin, OutputPin};
-use esp_idf_svc::hal::i2s::config::SlotMode::Mono;
 use esp_idf_svc::hal::i2s::config::{
-    ClockSource, Config, DataBitWidth, MclkMultiple, StdClkConfig, StdConfig, StdGpioConfig,
-    StdSlotConfig,
+    ClockSource, Config, DataBitWidth, MclkMultiple, SlotMode, StdClkConfig, StdConfig, StdGpioConfig,
+    StdSlotConfig, StdSlotMask,
 };
 use esp_idf_svc::hal::i2s::{I2sDriver, I2sRx};
 use esp_idf_svc::hal::peripheral::Peripheral;
 use esp_idf_svc::io::Read;
 use esp_idf_svc::sys::EspError;
+use echosense_core::convert::{CaptureFormat, ChannelSelection, Converter, SampleWidth, PCM_SAMPLE_RATE};
+use echosense_core::error::CoreError;
+use echosense_core::platform::AudioSource;
+
+pub use echosense_core::audio::AudioLevel;
+
 use crate::custom_error::CustomError;
 
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum SlotStandard {
+    Philips,
+    // Left justified
+    Msb,
+    Pcm,
+}
+
+impl SlotStandard {
+    pub fn from_name(name: &str) -> Option<Self> {
+        match name.to_lowercase().as_str() {
+            "philips" | "i2s" => Some(SlotStandard::Philips),
+            "msb" | "left_justified" => Some(SlotStandard::Msb),
+            "pcm" => Some(SlotStandard::Pcm),
+            _ => None,
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct MicrophoneConfig {
+    pub sample_rate_hz: u32,
+    pub width: SampleWidth,
+    pub standard: SlotStandard,
+    // Left or Right reads a single slot, Mix captures both and averages them, Both hands them out for the Combiner
+    pub selection: ChannelSelection,
+}
+
+impl Default for MicrophoneConfig {
+    // A single 16-bit mic with L/R tied low, what the board shipped with
+    fn default() -> Self {
+        Self {
+            sample_rate_hz: PCM_SAMPLE_RATE,
+            width: SampleWidth::Bits16,
+            standard: SlotStandard::Philips,
+            selection: ChannelSelection::Left,
+        }
+    }
+}
+
+impl MicrophoneConfig {
+    pub fn capture_format(&self) -> CaptureFormat {
+        CaptureFormat {
+            sample_rate: self.sample_rate_hz,
+            width: self.width,
+            channels: match self.selection {
+                ChannelSelection::Mix | ChannelSelection::Both => 2,
+                _ => 1,
+            },
+            selection: self.selection,
+        }
+    }
+
+    fn slot_config(&self) -> StdSlotConfig {
+        let width = match self.width {
+            SampleWidth::Bits16 => DataBitWidth::Bits16,
+            SampleWidth::Bits24 => DataBitWidth::Bits24,
+            SampleWidth::Bits32 => DataBitWidth::Bits32,
+        };
+
+        let (mode, mask) = match self.selection {
+            ChannelSelection::Left => (SlotMode::Mono, StdSlotMask::Left),
+            ChannelSelection::Right => (SlotMode::Mono, StdSlotMask::Right),
+            ChannelSelection::Mix | ChannelSelection::Both => (SlotMode::Stereo, StdSlotMask::Both),
+        };
+
+        let config = match self.standard {
+            SlotStandard::Philips => StdSlotConfig::philips_slot_default(width, mode),
+            SlotStandard::Msb => StdSlotConfig::msb_slot_default(width, mode),
+            SlotStandard::Pcm => StdSlotConfig::pcm_slot_default(width, mode),
+        };
+
+        config.slot_mask(mask)
+    }
+}
+
+// Reads the I2S peripheral in whatever format the mic uses, and hands out 16 kHz 16-bit frames of about BUFFER_SIZE
+// bytes per channel, mono unless both channels are kept
 pub struct Microphone<'d, T, const BUFFER_SIZE: usize> {
     device: I2sDriver<'d, T>,
-    buffer: [u8; BUFFER_SIZE],
+    buffer: Vec<u8>,
+    converter: Converter,
 }
 
 impl<'d, const BUFFER_SIZE: usize> Microphone<'d, I2sRx, BUFFER_SIZE> {
@@ -23,17 +107,24 @@ impl<'d, const BUFFER_SIZE: usize> Microphone<'d, I2sRx, BUFFER_SIZE> {
         sck: impl Peripheral<P=impl OutputPin + InputPin> + 'd,
         sd: impl Peripheral<P=impl InputPin> + 'd,
         ws: impl Peripheral<P=impl OutputPin + InputPin> + 'd,
-        sample_rate_hz: u32,
+        config: MicrophoneConfig,
     ) -> Result<Self, CustomError> {
+        let format = config.capture_format();
+        let raw_size = format.raw_bytes_for(BUFFER_SIZE * format.output_channels() as usize);
+
         let channel_config = Config::new()
             .dma_buffer_count(2)
-            .frames_per_buffer((BUFFER_SIZE * 2) as u32);
+            .frames_per_buffer((raw_size / format.frame_bytes() * 2) as u32);
+
+        // 24-bit data needs an MCLK multiple divisible by 3
+        let multiple = match config.width {
+            SampleWidth::Bits24 => MclkMultiple::M384,
+            _ => MclkMultiple::M512,
+        };
 
-        let clock_config =
-            StdClkConfig::new(sample_rate_hz, ClockSource::Pll160M, MclkMultiple::M512);
-        let slot_config = StdSlotConfig::philips_slot_default(DataBitWidth::Bits16, Mono);
+        let clock_config = StdClkConfig::new(config.sample_rate_hz, ClockSource::Pll160M, multiple);
         let gpio_cfg = StdGpioConfig::new(false, false, false);
-        let i2s_std_config = StdConfig::new(channel_config, clock_config, slot_config, gpio_cfg);
+        let i2s_std_config = StdConfig::new(channel_config, clock_config, config.slot_config(), gpio_cfg);
 
         let mut i2s = I2sDriver::new_std_rx(i2s, &i2s_std_config, sck, sd, None::<AnyIOPin>, ws)?;
 
@@ -41,14 +132,15 @@ impl<'d, const BUFFER_SIZE: usize> Microphone<'d, I2sRx, BUFFER_SIZE> {
 
         Ok(Microphone {
             device: i2s,
-            buffer: [0; BUFFER_SIZE],
+            buffer: vec![0; raw_size],
+            converter: Converter::new(format),
         })
     }
 
     pub fn sample(&mut self) -> Result<&[u8], CustomError> {
         self.device.read_exact(&mut self.buffer)?;
 
-        Ok(self.buffer.as_slice())
+        Ok(self.converter.convert(&self.buffer))
     }
 
     pub fn start(&mut self) -> Result<(), EspError> {
@@ -59,3 +151,19 @@ impl<'d, const BUFFER_SIZE: usize> Microphone<'d, I2sRx, BUFFER_SIZE> {
         self.device.rx_disable()
     }
 }
+
+impl<'d, const BUFFER_SIZE: usize> AudioSource for Microphone<'d, I2sRx, BUFFER_SIZE> {
+    // Always the converted rate, whatever the I2S clock runs at
+    fn sample_rate(&self) -> u32 {
+        PCM_SAMPLE_RATE
+    }
+
+    fn channels(&self) -> u16 {
+        self.converter.format().output_channels()
+    }
+
+    // The I2S peripheral never runs dry, a frame is always returned
+    fn sample(&mut self) -> Result<Option<&[u8]>, CoreError> {
+        Ok(Some(Microphone::sample(self)?))
+    }
+}
@@ -1,19 +1,23 @@
 use std::fs::File;
 
 use esp_idf_svc::fs::littlefs::{LittleFsInfo, Littlefs};
-use esp_idf_svc::hal::gpio::{AnyIOPin, InputPin, OutputPin};
+use esp_idf_svc::hal::gpio::{AnyIOPin, Gpio1, Gpio2, Gpio3, Gpio4, InputPin, OutputPin};
 use esp_idf_svc::hal::peripheral::Peripheral;
 use esp_idf_svc::hal::sd::spi::SdSpiHostDriver;
 use esp_idf_svc::hal::sd::{SdCardConfiguration, SdCardDriver};
 use esp_idf_svc::hal::spi::config::DriverConfig;
-use esp_idf_svc::hal::spi::{Dma, SpiAnyPins, SpiDriver};
+use esp_idf_svc::hal::spi::{Dma, SpiAnyPins, SpiDriver, SPI2};
 use esp_idf_svc::io::vfs::MountedLittlefs;
 use esp_idf_svc::sys::EspError;
+use echosense_core::retention::StorageUsage;
 
 use crate::custom_error::CustomError;
+use crate::meeting::STORAGE_ROOT;
+
+pub type CardFilesystem<'d> = Littlefs<SdCardDriver<SdSpiHostDriver<'d, SpiDriver<'d>>>>;
 
 pub struct MiniSDCard<'d> {
-    filesystem: MountedLittlefs<Littlefs<SdCardDriver<SdSpiHostDriver<'d, SpiDriver<'d>>>>>,
+    filesystem: MountedLittlefs<CardFilesystem<'d>>,
 }
 
 impl<'d> MiniSDCard<'d> {
@@ -25,6 +29,23 @@ impl<'d> MiniSDCard<'d> {
         cs: impl Peripheral<P = impl OutputPin> + 'd,
         format_on_boot: bool,
     ) -> Result<Self, CustomError> {
+        let mut littlefs = Self::detect(spi, sck, mosi, miso, cs)?;
+
+        if format_on_boot {
+            littlefs.format()?;
+        }
+
+        Self::mount(littlefs)
+    }
+
+    // Initializing the card fails when there is none, nothing is mounted yet
+    pub fn detect(
+        spi: impl Peripheral<P = impl SpiAnyPins> + 'd,
+        sck: impl Peripheral<P = impl OutputPin> + 'd,
+        mosi: impl Peripheral<P = impl OutputPin> + 'd,
+        miso: impl Peripheral<P = impl InputPin + OutputPin> + 'd,
+        cs: impl Peripheral<P = impl OutputPin> + 'd,
+    ) -> Result<CardFilesystem<'d>, CustomError> {
         let spi_driver = SpiDriver::new(
             spi,
             sck,
@@ -45,14 +66,12 @@ impl<'d> MiniSDCard<'d> {
             &SdCardConfiguration::new(),
         )?;
 
-        let mut littlefs = Littlefs::new_sdcard(sd_card_driver)?;
-
-        if format_on_boot {
-            littlefs.format()?;
-        }
+        Ok(Littlefs::new_sdcard(sd_card_driver)?)
+    }
 
+    pub fn mount(littlefs: CardFilesystem<'d>) -> Result<Self, CustomError> {
         Ok(MiniSDCard {
-            filesystem: MountedLittlefs::mount(littlefs, "/sdcard")?,
+            filesystem: MountedLittlefs::mount(littlefs, STORAGE_ROOT)?,
         })
     }
 
@@ -60,6 +79,15 @@ impl<'d> MiniSDCard<'d> {
         self.filesystem.info()
     }
 
+    pub fn usage(&self) -> Result<StorageUsage, EspError> {
+        let info = self.info()?;
+
+        Ok(StorageUsage {
+            total_bytes: info.total_bytes as u64,
+            used_bytes: info.used_bytes as u64,
+        })
+    }
+
     pub fn create_file<T: Into<String> + AsRef<std::path::Path>>(
         &self,
         name: &T,
@@ -67,3 +95,27 @@ impl<'d> MiniSDCard<'d> {
         Ok(File::create(name)?)
     }
 }
+
+// The bus and pins the card is wired to (see the pinout), kept to try again when a card is inserted later
+pub struct SdCardPins {
+    pub spi: SPI2,
+    pub sck: Gpio2,
+    pub mosi: Gpio3,
+    pub miso: Gpio1,
+    pub cs: Gpio4,
+}
+
+impl SdCardPins {
+    // Only called while no card is mounted, so the drivers of a previous attempt are gone and the peripherals free
+    pub fn detect(&mut self) -> Result<CardFilesystem<'static>, CustomError> {
+        unsafe {
+            MiniSDCard::detect(
+                self.spi.clone_unchecked(),
+                self.sck.clone_unchecked(),
+                self.mosi.clone_unchecked(),
+                self.miso.clone_unchecked(),
+                self.cs.clone_unchecked(),
+            )
+        }
+    }
+}
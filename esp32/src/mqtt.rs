@@ -0,0 +1,161 @@
+use std::sync::mpsc::{channel, Receiver, Sender};
+use std::sync::{Arc, Mutex};
+use std::thread::spawn;
+
+use esp_idf_svc::mqtt::client::{EspMqttClient, EspMqttConnection, EventPayload, LwtConfiguration, MqttClientConfiguration, QoS};
+use log::{error, info, warn};
+use serde::Serialize;
+use serde_json::json;
+
+use crate::custom_error::CustomError;
+use crate::file_server::{Command, Sessions, WebsocketMessage};
+
+// Websocket session ids are socket descriptors, so a negative id can never collide with a browser
+const MQTT_SESSION_ID: i32 = -1;
+
+#[derive(Clone)]
+pub struct Mqtt {
+    client: Option<Arc<Mutex<EspMqttClient<'static>>>>,
+    prefix: String,
+}
+
+impl Mqtt {
+    // Publishing on a disabled client is a no-op, so callers don't have to care whether MQTT is configured
+    pub fn disabled() -> Self {
+        Self {
+            client: None,
+            prefix: String::new(),
+        }
+    }
+
+    pub fn new<S: Into<String>>(
+        url: &str,
+        username: Option<&str>,
+        password: Option<&str>,
+        prefix: S,
+        commands: Sender<Command>,
+        sessions: Sessions,
+    ) -> Result<Self, CustomError> {
+        let prefix = prefix.into();
+        let availability = format!("{}/availability", prefix);
+
+        let configuration = MqttClientConfiguration {
+            username,
+            password,
+            lwt: Some(LwtConfiguration {
+                topic: availability.as_str(),
+                payload: b"offline",
+                qos: QoS::AtLeastOnce,
+                retain: true,
+            }),
+            ..Default::default()
+        };
+
+        let (client, connection) = EspMqttClient::new(url, &configuration)?;
+
+        let mqtt = Self {
+            client: Some(Arc::new(Mutex::new(client))),
+            prefix,
+        };
+
+        let mqtt_a = mqtt.clone();
+        let mqtt_b = mqtt.clone();
+
+        std::thread::Builder::new()
+            .stack_size(8192)
+            .spawn(move || mqtt_a.handle_events(connection, commands))?;
+
+        // Subscribing like a websocket session gets MQTT every message the frontend receives, for free
+        let (sender, receiver) = channel::<WebsocketMessage>();
+
+        sessions.lock()?.insert(MQTT_SESSION_ID, sender);
+
+        spawn(move || mqtt_b.forward(receiver));
+
+        Ok(mqtt)
+    }
+
+    pub fn topic(&self, name: &str) -> String {
+        format!("{}/{}", self.prefix, name)
+    }
+
+    pub fn publish<T: Serialize>(&self, name: &str, retain: bool, payload: &T) {
+        let Some(client) = &self.client else {
+            return;
+        };
+
+        let result = serde_json::to_vec(payload)
+            .map_err(CustomError::from)
+            .and_then(|payload| {
+                let mut client = client.lock()?;
+
+                // Enqueued rather than published, so a slow broker never blocks the caller
+                client.enqueue(&self.topic(name), QoS::AtLeastOnce, retain, &payload)?;
+
+                Ok(())
+            });
+
+        if let Err(error) = result {
+            warn!("failed to publish {}: {:?}", name, error);
+        }
+    }
+
+    fn handle_events(&self, mut connection: EspMqttConnection, commands: Sender<Command>) -> Result<(), CustomError> {
+        let command_topic = self.topic("command");
+
+        while let Ok(event) = connection.next() {
+            match event.payload() {
+                EventPayload::Connected(_) => {
+                    info!("MQTT connected, listening on {}", command_topic);
+
+                    if let Some(client) = &self.client {
+                        let mut client = client.lock()?;
+
+                        client.subscribe(&command_topic, QoS::AtLeastOnce)?;
+                        client.enqueue(&self.topic("availability"), QoS::AtLeastOnce, true, b"online")?;
+                    }
+                }
+                EventPayload::Disconnected => warn!("MQTT disconnected"),
+                EventPayload::Received { topic, data, .. } => {
+                    if topic != Some(command_topic.as_str()) {
+                        continue;
+                    }
+
+                    // Same JSON as the "command" field of websocket messages, e.g. "GetSummary" or {"AskQuestion": {...}}
+                    match serde_json::from_slice::<Command>(data) {
+                        Ok(command) => commands.send(command)?,
+                        Err(error) => warn!("ignoring invalid MQTT command: {:?}", error),
+                    }
+                }
+                EventPayload::Error(error) => error!("MQTT error: {:?}", error),
+                _ => {}
+            }
+        }
+
+        Ok(())
+    }
+
+    fn forward(&self, receiver: Receiver<WebsocketMessage>) -> Result<(), CustomError> {
+        while let Ok(message) = receiver.recv() {
+            match message {
+                WebsocketMessage::PartialTranscription(transcription) => {
+                    self.publish("transcripts/partial", false, &transcription)
+                }
+                WebsocketMessage::FinalTranscription(transcription) => {
+                    self.publish("transcripts/final", false, &transcription)
+                }
+                WebsocketMessage::Summary(summary) => self.publish("summary", true, &json!({ "summary": summary })),
+                WebsocketMessage::AnswerQuestion { id, answer } => {
+                    self.publish("answers", false, &json!({ "id": id, "answer": answer }))
+                }
+                WebsocketMessage::MeetingId(meeting_id) => {
+                    self.publish("meeting", true, &json!({ "meeting_id": meeting_id, "state": "started" }))
+                }
+                WebsocketMessage::EmailDelivery(statuses) => self.publish("email", false, &statuses),
+                _ => {}
+            }
+        }
+
+        Ok(())
+    }
+}
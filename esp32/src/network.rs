@@ -4,7 +4,7 @@ use esp_idf_svc::hal::modem::WifiModemPeripheral;
 use esp_idf_svc::hal::peripheral::Peripheral;
 use esp_idf_svc::ipv4::IpInfo;
 use esp_idf_svc::nvs::EspDefaultNvsPartition;
-use esp_idf_svc::sys::EspError;
+use esp_idf_svc::sys::{esp, esp_wifi_sta_get_ap_info, wifi_ap_record_t, EspError};
 use esp_idf_svc::wifi::{AuthMethod, BlockingWifi, ClientConfiguration, Configuration, EspWifi};
 use log::info;
 use std::marker::PhantomData;
@@ -24,9 +24,9 @@ impl Network<Disconnected> {
         modem: impl Peripheral<P = M> + 'static,
         ssid: S,
         password: S,
+        nvs: EspDefaultNvsPartition,
     ) -> Result<Network<Disconnected>, CustomError> {
         let sys_loop = EspSystemEventLoop::take()?;
-        let nvs = EspDefaultNvsPartition::take()?;
 
         let mut wifi =
             BlockingWifi::wrap(EspWifi::new(modem, sys_loop.clone(), Some(nvs))?, sys_loop)?;
@@ -68,6 +68,18 @@ impl Network<Connected> {
         self.device.wifi().sta_netif().get_ip_info()
     }
 
+    pub fn mac(&self) -> Result<[u8; 6], EspError> {
+        self.device.wifi().sta_netif().get_mac()
+    }
+
+    pub fn rssi(&self) -> Result<i8, EspError> {
+        let mut record = wifi_ap_record_t::default();
+
+        esp!(unsafe { esp_wifi_sta_get_ap_info(&mut record) })?;
+
+        Ok(record.rssi)
+    }
+
     pub fn disconnect(&mut self) -> Result<(), EspError> {
         self.device.disconnect()
     }
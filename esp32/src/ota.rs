@@ -0,0 +1,114 @@
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Arc;
+use std::thread::spawn;
+use std::time::Duration;
+
+use esp_idf_svc::hal::delay::FreeRtos;
+use esp_idf_svc::io::Write;
+use esp_idf_svc::ota::{EspOta, SlotState};
+use esp_idf_svc::sys::esp_ota_get_next_update_partition;
+use log::{error, info, warn};
+
+use crate::custom_error::CustomError;
+
+pub struct Ota {
+    healthy: Arc<AtomicBool>,
+}
+
+impl Ota {
+    // A freshly flashed slot boots as "unverified" and has until `timeout` to confirm itself,
+    // otherwise it is marked invalid and the bootloader falls back to the previous slot.
+    pub fn new(timeout: Duration) -> Result<Self, CustomError> {
+        let ota = EspOta::new()?;
+        let slot = ota.get_running_slot()?;
+        let healthy = Arc::new(AtomicBool::new(slot.state != SlotState::Unverified));
+
+        info!("running firmware from slot {} ({:?})", slot.label, slot.state);
+
+        if slot.state == SlotState::Unverified {
+            let healthy = healthy.clone();
+
+            spawn(move || {
+                FreeRtos::delay_ms(timeout.as_millis() as u32);
+
+                if healthy.load(Ordering::Relaxed) == false {
+                    error!("firmware did not confirm itself as healthy, rolling back...");
+
+                    if let Ok(mut ota) = EspOta::new() {
+                        ota.mark_running_slot_invalid_and_reboot();
+                    }
+                }
+            });
+        }
+
+        Ok(Self { healthy })
+    }
+
+    pub fn mark_healthy(&self) -> Result<(), CustomError> {
+        if self.healthy.swap(true, Ordering::Relaxed) == false {
+            EspOta::new()?.mark_running_slot_valid()?;
+
+            info!("firmware marked as healthy");
+        }
+
+        Ok(())
+    }
+
+    pub fn update<R: esp_idf_svc::io::Read, const BUFFER_SIZE: usize>(
+        source: &mut R,
+        expected_length: Option<u64>,
+    ) -> Result<(), CustomError> {
+        // The slots only take 1920KB of the 4MB flash, so say so before overwriting half of one
+        let slot_size = unsafe { esp_ota_get_next_update_partition(std::ptr::null()).as_ref() }
+            .map(|partition| partition.size as u64);
+
+        if let (Some(length), Some(slot_size)) = (expected_length, slot_size) {
+            if length > slot_size {
+                return Err(CustomError::FirmwareUpdateError(format!(
+                    "image of {} bytes doesn't fit the {} bytes of the update slot",
+                    length, slot_size
+                )));
+            }
+        }
+
+        let mut ota = EspOta::new()?;
+        let mut update = ota.initiate_update()?;
+        let mut buffer = [0u8; BUFFER_SIZE];
+        let mut written = 0u64;
+
+        loop {
+            let length = match source.read(&mut buffer) {
+                Ok(length) => length,
+                Err(_) => {
+                    update.abort()?;
+                    return Err(CustomError::FirmwareUpdateError("connection interrupted".to_string()));
+                }
+            };
+
+            if length == 0 {
+                break;
+            }
+
+            if let Err(error) = update.write_all(&buffer[..length]) {
+                update.abort()?;
+                return Err(CustomError::FirmwareUpdateError(format!("failed to write image: {:?}", error)));
+            }
+
+            written += length as u64;
+        }
+
+        if written == 0 || expected_length.is_some_and(|length| length != written) {
+            update.abort()?;
+            return Err(CustomError::FirmwareUpdateError(format!("incomplete image, received {} bytes", written)));
+        }
+
+        // Validates the image checksum and switches the boot partition to the new slot
+        update
+            .complete()
+            .map_err(|error| CustomError::FirmwareUpdateError(format!("image verification failed: {}", error)))?;
+
+        warn!("firmware update of {} bytes completed", written);
+
+        Ok(())
+    }
+}
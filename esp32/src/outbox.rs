@@ -0,0 +1,103 @@
+use std::path::Path;
+use std::time::Duration;
+
+use echosense_core::encryption;
+use esp_idf_svc::sys::esp_random;
+use log::warn;
+use serde::{Deserialize, Serialize};
+
+use crate::custom_error::CustomError;
+use crate::encryption::storage_key;
+use crate::export::ExportFormat;
+use crate::recipients::Recipients;
+
+pub const OUTBOX_DIRECTORY: &str = "/sdcard/outbox";
+pub const OUTBOX_MAX_ATTEMPTS: u32 = 10;
+
+const OUTBOX_INITIAL_BACKOFF: Duration = Duration::from_secs(30);
+const OUTBOX_MAX_BACKOFF: Duration = Duration::from_secs(60 * 60);
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub enum Notification {
+    MeetingReport {
+        meeting_id: String,
+        recipients: Recipients,
+        formats: Vec<ExportFormat>,
+        with_audio: bool,
+    },
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct OutboxEntry {
+    pub id: String,
+    pub notification: Notification,
+    pub attempts: u32,
+    pub last_error: Option<String>,
+}
+
+impl OutboxEntry {
+    pub fn new(notification: Notification) -> Self {
+        let random_number = unsafe { esp_random() };
+
+        Self {
+            id: format!("{:08x}", random_number),
+            notification,
+            attempts: 0,
+            last_error: None,
+        }
+    }
+
+    fn path(&self) -> String {
+        format!("{}/{}.json", OUTBOX_DIRECTORY, self.id)
+    }
+
+    // Written before the first attempt so a reboot or a crash never loses a queued notification
+    pub fn save(&self) -> Result<(), CustomError> {
+        std::fs::create_dir_all(OUTBOX_DIRECTORY)?;
+
+        let path = self.path();
+
+        Ok(encryption::write(Path::new(&path), storage_key().as_ref(), &serde_json::to_vec(self)?)?)
+    }
+
+    pub fn remove(&self) -> Result<(), CustomError> {
+        Ok(std::fs::remove_file(self.path())?)
+    }
+
+    pub fn is_exhausted(&self) -> bool {
+        self.attempts >= OUTBOX_MAX_ATTEMPTS
+    }
+
+    // 30s, 1m, 2m, 4m... capped at an hour
+    pub fn backoff(&self) -> Duration {
+        let exponent = self.attempts.saturating_sub(1).min(16);
+
+        (OUTBOX_INITIAL_BACKOFF * 2u32.pow(exponent)).min(OUTBOX_MAX_BACKOFF)
+    }
+}
+
+pub struct Outbox;
+
+impl Outbox {
+    pub fn entries() -> Vec<OutboxEntry> {
+        let Ok(directory) = std::fs::read_dir(OUTBOX_DIRECTORY) else {
+            return vec![];
+        };
+
+        directory
+            .filter_map(|entry| entry.ok())
+            .filter_map(|entry| {
+                let path = entry.path();
+                let bytes = encryption::read(&path, storage_key().as_ref()).ok()?;
+
+                match serde_json::from_slice::<OutboxEntry>(&bytes) {
+                    Ok(entry) => Some(entry),
+                    Err(error) => {
+                        warn!("skipping unreadable outbox entry {:?}: {:?}", path, error);
+                        None
+                    }
+                }
+            })
+            .collect()
+    }
+}
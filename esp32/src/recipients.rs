@@ -0,0 +1,165 @@
+use std::collections::{BTreeMap, HashSet};
+use std::path::Path;
+
+use echosense_core::encryption;
+use serde::{Deserialize, Serialize};
+
+use crate::custom_error::CustomError;
+use crate::encryption::storage_key;
+
+pub const CONFIG_DIRECTORY: &str = "/sdcard/config";
+const DISTRIBUTION_LISTS_FILE: &str = "/sdcard/config/distribution_lists.json";
+
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct Recipients {
+    #[serde(default)]
+    pub to: Vec<String>,
+    #[serde(default)]
+    pub cc: Vec<String>,
+    #[serde(default)]
+    pub bcc: Vec<String>,
+    #[serde(default)]
+    pub reply_to: Option<String>,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct RecipientStatus {
+    pub email: String,
+    pub delivered: bool,
+    pub error: Option<String>,
+}
+
+impl RecipientStatus {
+    pub fn failed<S: Into<String>>(email: &str, error: S) -> Self {
+        Self {
+            email: email.to_string(),
+            delivered: false,
+            error: Some(error.into()),
+        }
+    }
+}
+
+impl Recipients {
+    pub fn is_empty(&self) -> bool {
+        self.to.is_empty() && self.cc.is_empty() && self.bcc.is_empty()
+    }
+
+    pub fn all(&self) -> impl Iterator<Item = &String> {
+        self.to.iter().chain(self.cc.iter()).chain(self.bcc.iter())
+    }
+
+    pub fn merge(&mut self, other: Recipients) {
+        self.to.extend(other.to);
+        self.cc.extend(other.cc);
+        self.bcc.extend(other.bcc);
+
+        if self.reply_to.is_none() {
+            self.reply_to = other.reply_to;
+        }
+    }
+
+    // Splits into the addresses that can be handed to the provider and a failure status for every rejected one.
+    // Addresses are unique across to, cc and bcc, as providers reject duplicates within the same message.
+    pub fn validate(self) -> (Recipients, Vec<RecipientStatus>) {
+        let mut seen = HashSet::new();
+        let mut rejected = vec![];
+
+        let mut filter = |addresses: Vec<String>| {
+            addresses
+                .into_iter()
+                .map(|address| address.trim().to_string())
+                .filter(|address| address.is_empty() == false)
+                .filter(|address| {
+                    if is_valid_email(address) == false {
+                        rejected.push(RecipientStatus::failed(address, "invalid email address"));
+                        return false;
+                    }
+
+                    seen.insert(address.to_ascii_lowercase())
+                })
+                .collect::<Vec<_>>()
+        };
+
+        let mut recipients = Recipients {
+            to: filter(self.to),
+            cc: filter(self.cc),
+            bcc: filter(self.bcc),
+            reply_to: None,
+        };
+
+        if let Some(reply_to) = self.reply_to.map(|address| address.trim().to_string()) {
+            if is_valid_email(&reply_to) {
+                recipients.reply_to = Some(reply_to);
+            } else if reply_to.is_empty() == false {
+                rejected.push(RecipientStatus::failed(&reply_to, "invalid reply-to address"));
+            }
+        }
+
+        (recipients, rejected)
+    }
+}
+
+pub fn is_valid_email(address: &str) -> bool {
+    if address.len() > 254 || address.chars().any(|character| character.is_whitespace() || character.is_control()) {
+        return false;
+    }
+
+    let Some((local, domain)) = address.rsplit_once('@') else {
+        return false;
+    };
+
+    if local.is_empty() || local.len() > 64 || local.contains('@') || local.starts_with('.') || local.ends_with('.') {
+        return false;
+    }
+
+    let labels = domain.split('.').collect::<Vec<_>>();
+
+    labels.len() >= 2
+        && labels.iter().all(|label| {
+            label.is_empty() == false
+                && label.len() <= 63
+                && label.starts_with('-') == false
+                && label.ends_with('-') == false
+                && label.chars().all(|character| character.is_ascii_alphanumeric() || character == '-')
+        })
+}
+
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct DistributionLists {
+    lists: BTreeMap<String, Recipients>,
+}
+
+impl DistributionLists {
+    pub fn load() -> Self {
+        encryption::read(Path::new(DISTRIBUTION_LISTS_FILE), storage_key().as_ref())
+            .ok()
+            .and_then(|bytes| serde_json::from_slice(&bytes).ok())
+            .unwrap_or_default()
+    }
+
+    pub fn save(&self) -> Result<(), CustomError> {
+        std::fs::create_dir_all(CONFIG_DIRECTORY)?;
+
+        Ok(encryption::write(
+            Path::new(DISTRIBUTION_LISTS_FILE),
+            storage_key().as_ref(),
+            &serde_json::to_vec(self)?,
+        )?)
+    }
+
+    pub fn get(&self, name: &str) -> Option<&Recipients> {
+        self.lists.get(name)
+    }
+
+    pub fn insert(&mut self, name: String, recipients: Recipients) {
+        self.lists.insert(name, recipients);
+    }
+
+    pub fn remove(&mut self, name: &str) {
+        self.lists.remove(name);
+    }
+
+    pub fn all(&self) -> &BTreeMap<String, Recipients> {
+        &self.lists
+    }
+}
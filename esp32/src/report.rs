@@ -0,0 +1,130 @@
+use crate::meeting::Meeting;
+use crate::template::{escape_html, load_template, Escape, Template};
+
+const REPORT_HTML: &str = include_str!("../templates/report.html");
+const REPORT_TEXT: &str = include_str!("../templates/report.txt");
+
+pub struct Report<'a> {
+    meeting: &'a Meeting,
+    device_url: &'a str,
+}
+
+impl<'a> Report<'a> {
+    pub fn new(meeting: &'a Meeting, device_url: &'a str) -> Self {
+        Self { meeting, device_url }
+    }
+
+    pub fn subject(&self) -> String {
+        format!("Meeting report: {}", self.meeting.title())
+    }
+
+    pub fn html(&self) -> String {
+        let summary = match &self.meeting.summary {
+            Some(summary) => Self::html_list(summary),
+            None => "<p>No summary was generated.</p>".to_string(),
+        };
+
+        let action_items = match &self.meeting.action_items {
+            Some(action_items) => Self::html_list(action_items),
+            None => "<p>No action items.</p>".to_string(),
+        };
+
+        let questions = match self.meeting.questions.is_empty() {
+            true => "<p>No questions were asked.</p>".to_string(),
+            false => self
+                .meeting
+                .questions
+                .iter()
+                .map(|question| {
+                    Template::from_str("<p><b>{{question}}</b><br/>{{answer}}</p>", Escape::Html).render(&[
+                        ("question", question.question.as_str()),
+                        ("answer", question.answer.as_str()),
+                    ])
+                })
+                .collect::<Vec<_>>()
+                .join(""),
+        };
+
+        let transcript = self
+            .meeting
+            .transcriptions
+            .iter()
+            .map(|transcription| {
+                Template::from_str("<span><b>{{timestamp}}</b></span>: <span>{{text}}</span>", Escape::Html).render(&[
+                    ("timestamp", transcription.timestamp.as_str()),
+                    ("text", transcription.text.as_str()),
+                ])
+            })
+            .collect::<Vec<_>>()
+            .join("<hr/>");
+
+        load_template("report.html", REPORT_HTML, Escape::Html).render(&[
+            ("title", self.meeting.title().as_str()),
+            ("date", self.meeting.date().as_str()),
+            ("duration", self.duration().as_str()),
+            ("summary", summary.as_str()),
+            ("action_items", action_items.as_str()),
+            ("questions", questions.as_str()),
+            ("transcript", transcript.as_str()),
+            ("device_url", self.device_url),
+        ])
+    }
+
+    pub fn text(&self) -> String {
+        let summary = self.meeting.summary.clone().unwrap_or("No summary was generated.".to_string());
+        let action_items = self.meeting.action_items.clone().unwrap_or("No action items.".to_string());
+
+        let questions = match self.meeting.questions.is_empty() {
+            true => "No questions were asked.".to_string(),
+            false => self
+                .meeting
+                .questions
+                .iter()
+                .map(|question| format!("Q: {}\nA: {}", question.question, question.answer))
+                .collect::<Vec<_>>()
+                .join("\n\n"),
+        };
+
+        let transcript = self
+            .meeting
+            .transcriptions
+            .iter()
+            .map(|transcription| format!("[{}] {}", transcription.timestamp, transcription.text))
+            .collect::<Vec<_>>()
+            .join("\n");
+
+        load_template("report.txt", REPORT_TEXT, Escape::None).render(&[
+            ("title", self.meeting.title().as_str()),
+            ("date", self.meeting.date().as_str()),
+            ("duration", self.duration().as_str()),
+            ("summary", summary.as_str()),
+            ("action_items", action_items.as_str()),
+            ("questions", questions.as_str()),
+            ("transcript", transcript.as_str()),
+            ("device_url", self.device_url),
+        ])
+    }
+
+    fn duration(&self) -> String {
+        let seconds = self.meeting.duration_ms() / 1000;
+
+        match seconds {
+            0..=59 => format!("{}s", seconds),
+            60..=3599 => format!("{}m {:02}s", seconds / 60, seconds % 60),
+            _ => format!("{}h {:02}m", seconds / 3600, (seconds / 60) % 60),
+        }
+    }
+
+    // LeMUR answers with "- item" / "• item" lines, render them as a proper list
+    fn html_list(content: &str) -> String {
+        let items = content
+            .lines()
+            .map(|line| line.trim().trim_start_matches(['-', '•', '*']).trim())
+            .filter(|line| line.is_empty() == false)
+            .map(|line| format!("<li>{}</li>", escape_html(line)))
+            .collect::<Vec<_>>()
+            .join("");
+
+        format!("<ul>{}</ul>", items)
+    }
+}
@@ -4,12 +4,14 @@ use esp_idf_svc::http::client::{Configuration, Connection, EspHttpConnection};
 use esp_idf_svc::http::Method;
 use esp_idf_svc::io::{Read, Write};
 use esp_idf_svc::sys::EspError;
-use log::error;
 use serde::Serialize;
 
 use crate::custom_error::CustomError;
-use crate::file_server::Transcription;
-use crate::SENDGRID_FROM;
+use crate::mail::{MailTransport, Message};
+use crate::recipients::RecipientStatus;
+
+// SendGrid rejects messages over 30MB, including the base64 overhead of attachments
+const MAX_MESSAGE_SIZE: usize = 30 * 1024 * 1024;
 
 #[derive(Clone)]
 pub struct SendGrid {
@@ -21,40 +23,48 @@ struct Email {
     email: String,
 }
 
+impl Email {
+    fn list(addresses: &[String]) -> Vec<Email> {
+        addresses
+            .iter()
+            .map(|address| Email { email: address.clone() })
+            .collect()
+    }
+}
+
 #[derive(Debug, Serialize)]
 struct Content {
     r#type: String,
     value: String,
 }
 
-impl Content {
-    fn from_transcriptions(transcription: Vec<Transcription>) -> Self {
-        let content = transcription
-            .into_iter()
-            .map(|transcription| format!("<span><b>{}</b></span>: <span>{}</span>", transcription.timestamp, transcription.text))
-            .collect::<Vec<_>>()
-            .join("<hr/>");
-
-        let header = "<h2>Transcription:</h2>";
-
-        Self {
-            r#type: "text/html".to_string(),
-            value: format!("{}{}", header, content),
-        }
-    }
+#[derive(Debug, Serialize)]
+struct Attachment {
+    content: String,
+    filename: String,
+    r#type: String,
+    disposition: String,
 }
 
 #[derive(Debug, Serialize)]
 struct Personalization {
     to: Vec<Email>,
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    cc: Vec<Email>,
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    bcc: Vec<Email>,
 }
 
 #[derive(Debug, Serialize)]
 struct SendEmailRequest {
     from: Email,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    reply_to: Option<Email>,
     subject: String,
     personalizations: Vec<Personalization>,
     content: Vec<Content>,
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    attachments: Vec<Attachment>,
 }
 
 impl SendGrid {
@@ -64,45 +74,108 @@ impl SendGrid {
         })
     }
 
-    pub fn send_email<T: Into<String>>(&mut self, to: T, transcription: Vec<Transcription>) -> Result<(), CustomError> {
-        let token = format!("Bearer {}", self.api_key.as_str());
-        let headers = [
-            ("Authorization", token.as_str()),
-            ("Content-Type", "application/json"),
-        ];
+    // Attachments can be too big to be encoded in RAM, the request is serialized with a placeholder
+    // for each of them which is swapped for the base64 content while streaming the body
+    fn placeholder(index: usize) -> String {
+        format!("__ECHOSENSE_ATTACHMENT_{}__", index)
+    }
+}
+
+impl MailTransport for SendGrid {
+    fn max_message_size(&self) -> usize {
+        MAX_MESSAGE_SIZE
+    }
+
+    fn send(&mut self, message: &Message) -> Result<Vec<RecipientStatus>, CustomError> {
+        let recipients = &message.recipients;
 
         let request = SendEmailRequest {
             from: Email {
-                email: SENDGRID_FROM.to_string(),
+                email: message.from.clone(),
             },
-            subject: "Your transcription is ready!".to_string(),
+            reply_to: recipients.reply_to.clone().map(|email| Email { email }),
+            subject: message.subject.clone(),
             personalizations: vec![
                 Personalization {
-                    to: vec![
-                        Email {
-                            email: to.into()
-                        }
-                    ]
+                    to: Email::list(&recipients.to),
+                    cc: Email::list(&recipients.cc),
+                    bcc: Email::list(&recipients.bcc),
                 }
             ],
+            // SendGrid requires the plain text alternative to come before the html one
             content: vec![
-                Content::from_transcriptions(transcription)
+                Content {
+                    r#type: "text/plain".to_string(),
+                    value: message.text.clone(),
+                },
+                Content {
+                    r#type: "text/html".to_string(),
+                    value: message.html.clone(),
+                },
             ],
+            attachments: message
+                .attachments
+                .iter()
+                .enumerate()
+                .map(|(index, attachment)| Attachment {
+                    content: Self::placeholder(index),
+                    filename: attachment.filename.clone(),
+                    r#type: attachment.content_type.clone(),
+                    disposition: "attachment".to_string(),
+                })
+                .collect(),
         };
 
+        let body = serde_json::to_string(&request)?;
+
+        let content_length = body.len()
+            + message.attachments.iter().map(|attachment| attachment.encoded_length()).sum::<usize>()
+            - (0..message.attachments.len()).map(|index| Self::placeholder(index).len()).sum::<usize>();
+
+        let token = format!("Bearer {}", self.api_key.as_str());
+        let content_length = content_length.to_string();
+        let headers = [
+            ("Authorization", token.as_str()),
+            ("Content-Type", "application/json"),
+            ("Content-Length", content_length.as_str()),
+        ];
+
         let mut configuration = Configuration::default();
         configuration.timeout = Some(Duration::from_secs(30));
 
         let mut client = EspHttpConnection::new(&configuration)?;
 
         client.initiate_request(Method::Post, "https://api.sendgrid.com/v3/mail/send", &headers)?;
-        client.write_all(&serde_json::to_vec(&request)?)?;
+
+        let mut rest = body.as_str();
+
+        for (index, attachment) in message.attachments.iter().enumerate() {
+            // Matched with the quotes around it, which the subject or a transcript can only contain escaped
+            let placeholder = format!("\"content\":\"{}\"", Self::placeholder(index));
+
+            if let Some((prefix, suffix)) = rest.split_once(placeholder.as_str()) {
+                client.write_all(prefix.as_bytes())?;
+                client.write_all(b"\"content\":\"")?;
+                attachment.write_base64(false, |chunk| Ok(client.write_all(chunk)?))?;
+                client.write_all(b"\"")?;
+                rest = suffix;
+            }
+        }
+
+        client.write_all(rest.as_bytes())?;
         client.flush()?;
         client.initiate_response()?;
 
         let status = client.status();
         if status >= 200 && status < 300 {
-            return Ok(());
+            return Ok(recipients
+                .all()
+                .map(|email| RecipientStatus {
+                    email: email.clone(),
+                    delivered: true,
+                    error: None,
+                })
+                .collect());
         }
 
         let mut buffer = [0; 1000];
@@ -0,0 +1,383 @@
+use std::net::TcpStream;
+use std::time::Duration;
+
+use base64::engine::general_purpose::STANDARD;
+use base64::Engine;
+use echosense_core::mail::{date_header, message_id};
+use esp_idf_svc::sys::esp_random;
+use esp_idf_svc::tls::{Config, EspTls, Socket};
+use log::{info, warn};
+
+use crate::clock::unix_time;
+use crate::custom_error::CustomError;
+use crate::mail::{MailTransport, Message};
+use crate::recipients::RecipientStatus;
+
+const SMTP_TIMEOUT: Duration = Duration::from_secs(30);
+const MAX_MESSAGE_SIZE: usize = 20 * 1024 * 1024;
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum SmtpSecurity {
+    // Plain connection upgraded with STARTTLS, usually port 587
+    StartTls,
+    // TLS from the first byte, usually port 465
+    Implicit,
+    // No encryption at all, only meant for a local SMTP sink during development
+    None,
+}
+
+impl SmtpSecurity {
+    pub fn from_name(name: &str) -> Option<Self> {
+        match name.to_ascii_lowercase().as_str() {
+            "starttls" => Some(SmtpSecurity::StartTls),
+            "tls" | "ssl" | "implicit" => Some(SmtpSecurity::Implicit),
+            "none" | "plain" => Some(SmtpSecurity::None),
+            _ => None,
+        }
+    }
+}
+
+pub struct Smtp {
+    host: String,
+    port: u16,
+    security: SmtpSecurity,
+    username: String,
+    password: String,
+}
+
+trait SmtpStream {
+    fn read(&mut self, buffer: &mut [u8]) -> Result<usize, CustomError>;
+    fn write_all(&mut self, buffer: &[u8]) -> Result<(), CustomError>;
+}
+
+impl SmtpStream for TcpStream {
+    fn read(&mut self, buffer: &mut [u8]) -> Result<usize, CustomError> {
+        Ok(std::io::Read::read(self, buffer)?)
+    }
+
+    fn write_all(&mut self, buffer: &[u8]) -> Result<(), CustomError> {
+        Ok(std::io::Write::write_all(self, buffer)?)
+    }
+}
+
+impl<S: Socket> SmtpStream for EspTls<S> {
+    fn read(&mut self, buffer: &mut [u8]) -> Result<usize, CustomError> {
+        Ok(EspTls::read(self, buffer)?)
+    }
+
+    fn write_all(&mut self, buffer: &[u8]) -> Result<(), CustomError> {
+        Ok(EspTls::write_all(self, buffer)?)
+    }
+}
+
+struct Reply {
+    code: u16,
+    lines: Vec<String>,
+}
+
+struct Session<S: SmtpStream> {
+    stream: S,
+    buffer: Vec<u8>,
+}
+
+impl<S: SmtpStream> Session<S> {
+    fn new(stream: S) -> Self {
+        Self {
+            stream,
+            buffer: vec![],
+        }
+    }
+
+    fn read_line(&mut self) -> Result<String, CustomError> {
+        loop {
+            if let Some(position) = self.buffer.iter().position(|byte| *byte == b'\n') {
+                let line = self.buffer.drain(..=position).collect::<Vec<_>>();
+
+                return Ok(String::from_utf8(line)?.trim_end().to_string());
+            }
+
+            let mut chunk = [0u8; 256];
+            let length = self.stream.read(&mut chunk)?;
+
+            if length == 0 {
+                return Err(CustomError::FailedToSendEmail("connection closed by the SMTP server".to_string()));
+            }
+
+            self.buffer.extend_from_slice(&chunk[..length]);
+        }
+    }
+
+    // Multi-line replies use "250-" for every line but the last one, which is "250 "
+    fn read_reply(&mut self) -> Result<Reply, CustomError> {
+        let mut lines = vec![];
+
+        loop {
+            let line = self.read_line()?;
+
+            let code = line
+                .get(..3)
+                .and_then(|code| code.parse::<u16>().ok())
+                .ok_or_else(|| CustomError::FailedToSendEmail(format!("malformed SMTP reply: {}", line)))?;
+
+            let last = line.as_bytes().get(3) != Some(&b'-');
+
+            lines.push(line.get(4..).unwrap_or("").to_string());
+
+            if last {
+                break Ok(Reply { code, lines });
+            }
+        }
+    }
+
+    fn expect(&mut self, expected: &[u16]) -> Result<Reply, CustomError> {
+        let reply = self.read_reply()?;
+
+        if expected.contains(&reply.code) == false {
+            return Err(CustomError::FailedToSendEmail(format!("{} {}", reply.code, reply.lines.join(" "))));
+        }
+
+        Ok(reply)
+    }
+
+    fn command(&mut self, command: &str, expected: &[u16]) -> Result<Reply, CustomError> {
+        self.stream.write_all(command.as_bytes())?;
+        self.stream.write_all(b"\r\n")?;
+        self.expect(expected)
+    }
+
+    fn ehlo(&mut self) -> Result<Vec<String>, CustomError> {
+        Ok(self.command("EHLO echosense", &[250])?.lines)
+    }
+
+    fn authenticate(&mut self, capabilities: &[String], username: &str, password: &str) -> Result<(), CustomError> {
+        if username.is_empty() {
+            return Ok(());
+        }
+
+        let mechanisms = capabilities
+            .iter()
+            .find_map(|capability| capability.strip_prefix("AUTH "))
+            .unwrap_or("")
+            .split_whitespace()
+            .map(|mechanism| mechanism.to_ascii_uppercase())
+            .collect::<Vec<_>>();
+
+        if mechanisms.iter().any(|mechanism| mechanism == "PLAIN") {
+            let credentials = STANDARD.encode(format!("\0{}\0{}", username, password));
+
+            self.command(&format!("AUTH PLAIN {}", credentials), &[235])?;
+        } else if mechanisms.iter().any(|mechanism| mechanism == "LOGIN") {
+            self.command("AUTH LOGIN", &[334])?;
+            self.command(&STANDARD.encode(username), &[334])?;
+            self.command(&STANDARD.encode(password), &[235])?;
+        } else {
+            return Err(CustomError::FailedToSendEmail("SMTP server offers neither AUTH PLAIN nor AUTH LOGIN".to_string()));
+        }
+
+        Ok(())
+    }
+
+    fn deliver(&mut self, message: &Message) -> Result<Vec<RecipientStatus>, CustomError> {
+        self.command(&format!("MAIL FROM:<{}>", message.from), &[250])?;
+
+        let mut statuses = vec![];
+
+        for email in message.recipients.all() {
+            statuses.push(match self.command(&format!("RCPT TO:<{}>", email), &[250, 251]) {
+                Ok(_) => RecipientStatus {
+                    email: email.clone(),
+                    delivered: true,
+                    error: None,
+                },
+                Err(error) => RecipientStatus::failed(email, format!("{:?}", error)),
+            });
+        }
+
+        if statuses.iter().all(|status| status.delivered == false) {
+            self.command("RSET", &[250])?;
+            return Ok(statuses);
+        }
+
+        self.command("DATA", &[354])?;
+        self.write_mime(message)?;
+        self.stream.write_all(b"\r\n.\r\n")?;
+        self.expect(&[250])?;
+
+        Ok(statuses)
+    }
+
+    // Every body part is base64 encoded, so no line can ever start with a "." and need dot-stuffing
+    fn write_mime(&mut self, message: &Message) -> Result<(), CustomError> {
+        let random_number = unsafe { esp_random() };
+        let mixed = format!("echosense-mixed-{:08x}", random_number);
+        let alternative = format!("echosense-alternative-{:08x}", random_number);
+
+        let unique = format!("{:08x}{:08x}", random_number, unsafe { esp_random() });
+
+        let mut headers = vec![
+            format!("From: <{}>", message.from),
+            format!("Message-ID: {}", message_id(&unique, &message.from)),
+            format!("Subject: =?UTF-8?B?{}?=", STANDARD.encode(&message.subject)),
+            "MIME-Version: 1.0".to_string(),
+            format!("Content-Type: multipart/mixed; boundary=\"{}\"", mixed),
+        ];
+
+        // Until SNTP has set the clock the relay is left to add the date, rather than one from 1970
+        if let Some(now) = unix_time() {
+            headers.push(format!("Date: {}", date_header(now)));
+        }
+
+        // Bcc addresses only show up in the envelope
+        if message.recipients.to.is_empty() == false {
+            headers.push(format!("To: {}", Self::address_list(&message.recipients.to)));
+        }
+
+        if message.recipients.cc.is_empty() == false {
+            headers.push(format!("Cc: {}", Self::address_list(&message.recipients.cc)));
+        }
+
+        if let Some(reply_to) = &message.recipients.reply_to {
+            headers.push(format!("Reply-To: <{}>", reply_to));
+        }
+
+        for header in headers {
+            self.stream.write_all(header.as_bytes())?;
+            self.stream.write_all(b"\r\n")?;
+        }
+
+        self.stream.write_all(format!("\r\n--{}\r\n", mixed).as_bytes())?;
+        self.stream.write_all(format!("Content-Type: multipart/alternative; boundary=\"{}\"\r\n", alternative).as_bytes())?;
+
+        for (content_type, body) in [("text/plain", &message.text), ("text/html", &message.html)] {
+            self.stream.write_all(format!("\r\n--{}\r\n", alternative).as_bytes())?;
+            self.stream.write_all(format!("Content-Type: {}; charset=UTF-8\r\n", content_type).as_bytes())?;
+            self.stream.write_all(b"Content-Transfer-Encoding: base64\r\n\r\n")?;
+
+            for line in STANDARD.encode(body.as_bytes()).as_bytes().chunks(76) {
+                self.stream.write_all(line)?;
+                self.stream.write_all(b"\r\n")?;
+            }
+        }
+
+        self.stream.write_all(format!("\r\n--{}--\r\n", alternative).as_bytes())?;
+
+        for attachment in message.attachments.iter() {
+            self.stream.write_all(format!("\r\n--{}\r\n", mixed).as_bytes())?;
+            self.stream.write_all(format!("Content-Type: {}; name=\"{}\"\r\n", attachment.content_type, attachment.filename).as_bytes())?;
+            self.stream.write_all(format!("Content-Disposition: attachment; filename=\"{}\"\r\n", attachment.filename).as_bytes())?;
+            self.stream.write_all(b"Content-Transfer-Encoding: base64\r\n\r\n")?;
+
+            let stream = &mut self.stream;
+            attachment.write_base64(true, |chunk| stream.write_all(chunk))?;
+        }
+
+        self.stream.write_all(format!("\r\n--{}--", mixed).as_bytes())?;
+
+        Ok(())
+    }
+
+    fn address_list(addresses: &[String]) -> String {
+        addresses
+            .iter()
+            .map(|address| format!("<{}>", address))
+            .collect::<Vec<_>>()
+            .join(", ")
+    }
+}
+
+impl Smtp {
+    pub fn new<S: Into<String>>(host: S, port: u16, security: SmtpSecurity, username: S, password: S) -> Self {
+        Self {
+            host: host.into(),
+            port,
+            security,
+            username: username.into(),
+            password: password.into(),
+        }
+    }
+
+    fn tls_config(&self) -> Config {
+        Config {
+            common_name: Some(self.host.as_str()),
+            use_crt_bundle_attach: true,
+            timeout_ms: SMTP_TIMEOUT.as_millis() as u32,
+            ..Default::default()
+        }
+    }
+
+    fn connect(&self) -> Result<TcpStream, CustomError> {
+        let stream = TcpStream::connect((self.host.as_str(), self.port))?;
+
+        stream.set_read_timeout(Some(SMTP_TIMEOUT))?;
+        stream.set_write_timeout(Some(SMTP_TIMEOUT))?;
+
+        Ok(stream)
+    }
+
+    fn transaction<S: SmtpStream>(
+        &self,
+        session: &mut Session<S>,
+        capabilities: &[String],
+        message: &Message,
+    ) -> Result<Vec<RecipientStatus>, CustomError> {
+        session.authenticate(capabilities, &self.username, &self.password)?;
+
+        let statuses = session.deliver(message)?;
+
+        if let Err(error) = session.command("QUIT", &[221]) {
+            warn!("SMTP server did not acknowledge QUIT: {:?}", error);
+        }
+
+        Ok(statuses)
+    }
+}
+
+impl MailTransport for Smtp {
+    fn max_message_size(&self) -> usize {
+        MAX_MESSAGE_SIZE
+    }
+
+    fn send(&mut self, message: &Message) -> Result<Vec<RecipientStatus>, CustomError> {
+        info!("sending email through {}:{} ({:?})", self.host, self.port, self.security);
+
+        match self.security {
+            SmtpSecurity::Implicit => {
+                let mut tls = EspTls::new()?;
+                tls.connect(&self.host, self.port, &self.tls_config())?;
+
+                let mut session = Session::new(tls);
+                session.expect(&[220])?;
+
+                let capabilities = session.ehlo()?;
+                self.transaction(&mut session, &capabilities, message)
+            }
+            SmtpSecurity::StartTls => {
+                let mut plain = Session::new(self.connect()?);
+                plain.expect(&[220])?;
+
+                let capabilities = plain.ehlo()?;
+
+                if capabilities.iter().any(|capability| capability.eq_ignore_ascii_case("STARTTLS")) == false {
+                    return Err(CustomError::FailedToSendEmail("SMTP server does not support STARTTLS".to_string()));
+                }
+
+                plain.command("STARTTLS", &[220])?;
+
+                let mut tls = EspTls::adopt(plain.stream)?;
+                tls.negotiate(&self.host, &self.tls_config())?;
+
+                // Capabilities have to be discovered again once the connection is encrypted
+                let mut session = Session::new(tls);
+                let capabilities = session.ehlo()?;
+                self.transaction(&mut session, &capabilities, message)
+            }
+            SmtpSecurity::None => {
+                let mut session = Session::new(self.connect()?);
+                session.expect(&[220])?;
+
+                let capabilities = session.ehlo()?;
+                self.transaction(&mut session, &capabilities, message)
+            }
+        }
+    }
+}
@@ -0,0 +1,230 @@
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+
+use esp_idf_svc::fs::littlefs::LittleFsInfo;
+use echosense_core::retention::{RetentionPolicy, StorageManager, StorageUsage};
+use log::{info, warn};
+
+use crate::custom_error::CustomError;
+use crate::internal_flash::InternalFlash;
+use crate::meeting::store;
+use crate::mini_sdcard::{MiniSDCard, SdCardPins};
+
+pub use echosense_core::retention::StorageMedium;
+
+const STORAGE_MAX_AGE_DAYS: Option<&str> = option_env!("STORAGE_MAX_AGE_DAYS");
+const STORAGE_MAX_TOTAL_MB: Option<&str> = option_env!("STORAGE_MAX_TOTAL_MB");
+const STORAGE_KEEP_STARRED: Option<&str> = option_env!("STORAGE_KEEP_STARRED");
+const STORAGE_MIN_FREE_MB: Option<&str> = option_env!("STORAGE_MIN_FREE_MB");
+const STORAGE_WARNING_PERCENT: Option<&str> = option_env!("STORAGE_WARNING_PERCENT");
+const STORAGE_CRITICAL_PERCENT: Option<&str> = option_env!("STORAGE_CRITICAL_PERCENT");
+
+const MB: u64 = 1024 * 1024;
+// The internal flash only holds transcripts, they are small
+const INTERNAL_FLASH_MIN_FREE_BYTES: u64 = 16 * 1024;
+
+// Meetings are only deleted to keep STORAGE_MIN_FREE_MB free unless STORAGE_MAX_AGE_DAYS or STORAGE_MAX_TOTAL_MB are
+// set, starred ones are kept unless STORAGE_KEEP_STARRED=false
+pub fn retention_policy() -> RetentionPolicy {
+    let defaults = RetentionPolicy::default();
+
+    RetentionPolicy {
+        max_age: STORAGE_MAX_AGE_DAYS
+            .and_then(|days| days.parse::<u64>().ok())
+            .filter(|days| *days > 0)
+            .map(|days| Duration::from_secs(days * 24 * 60 * 60)),
+        max_total_bytes: STORAGE_MAX_TOTAL_MB
+            .and_then(|megabytes| megabytes.parse::<u64>().ok())
+            .filter(|megabytes| *megabytes > 0)
+            .map(|megabytes| megabytes * MB),
+        keep_starred: STORAGE_KEEP_STARRED
+            .map(|value| matches!(value, "false" | "off" | "0") == false)
+            .unwrap_or(defaults.keep_starred),
+        min_free_bytes: STORAGE_MIN_FREE_MB
+            .and_then(|megabytes| megabytes.parse::<u64>().ok())
+            .map(|megabytes| megabytes * MB)
+            .unwrap_or(defaults.min_free_bytes),
+        warning_percent: STORAGE_WARNING_PERCENT
+            .and_then(|percent| percent.parse().ok())
+            .unwrap_or(defaults.warning_percent),
+        critical_percent: STORAGE_CRITICAL_PERCENT
+            .and_then(|percent| percent.parse().ok())
+            .unwrap_or(defaults.critical_percent),
+    }
+}
+
+pub fn storage_manager(medium: StorageMedium) -> StorageManager {
+    let policy = match medium {
+        StorageMedium::InternalFlash => RetentionPolicy {
+            min_free_bytes: INTERNAL_FLASH_MIN_FREE_BYTES,
+            ..retention_policy()
+        },
+        _ => retention_policy(),
+    };
+
+    StorageManager::new(store(), policy)
+}
+
+pub type SharedMedium = Arc<Mutex<StorageMedium>>;
+
+enum Mounted {
+    SdCard(MiniSDCard<'static>),
+    InternalFlash(InternalFlash),
+    Memory,
+}
+
+// Mounts the SD card at STORAGE_ROOT, or the internal flash in its place when there is no card, so everything else
+// keeps using the same paths. A card inserted later takes over from the internal flash
+pub struct Storage {
+    pins: SdCardPins,
+    mounted: Mounted,
+    medium: SharedMedium,
+}
+
+impl Storage {
+    pub fn mount(mut pins: SdCardPins, format_on_boot: bool) -> Self {
+        let card = pins.detect().and_then(|mut littlefs| {
+            if format_on_boot {
+                littlefs.format()?;
+            }
+
+            MiniSDCard::mount(littlefs)
+        });
+
+        let mut storage = Storage {
+            pins,
+            mounted: Mounted::Memory,
+            medium: Arc::new(Mutex::new(StorageMedium::Memory)),
+        };
+
+        match card {
+            Ok(card) => storage.set(Mounted::SdCard(card)),
+            Err(error) => {
+                warn!("no SD card: {:?}", error);
+                storage.mount_internal_flash();
+            }
+        }
+
+        storage
+    }
+
+    pub fn medium(&self) -> StorageMedium {
+        match self.mounted {
+            Mounted::SdCard(_) => StorageMedium::SdCard,
+            Mounted::InternalFlash(_) => StorageMedium::InternalFlash,
+            Mounted::Memory => StorageMedium::Memory,
+        }
+    }
+
+    // For the threads that need to know whether there is a card to record to
+    pub fn shared_medium(&self) -> SharedMedium {
+        self.medium.clone()
+    }
+
+    pub fn info(&self) -> Option<LittleFsInfo> {
+        match &self.mounted {
+            Mounted::SdCard(card) => card.info().ok(),
+            Mounted::InternalFlash(flash) => flash.info().ok(),
+            Mounted::Memory => None,
+        }
+    }
+
+    // Fails once the card is pulled, since its filesystem can't be read anymore
+    pub fn usage(&self) -> Result<StorageUsage, CustomError> {
+        match &self.mounted {
+            Mounted::SdCard(card) => Ok(card.usage()?),
+            Mounted::InternalFlash(flash) => Ok(flash.usage()?),
+            Mounted::Memory => Err(CustomError::NoStorage),
+        }
+    }
+
+    // Looks for a card while there is none, true once one was found and mounted
+    pub fn detect_card(&mut self) -> bool {
+        if let Mounted::SdCard(_) = self.mounted {
+            return false;
+        }
+
+        let Ok(littlefs) = self.pins.detect() else {
+            return false;
+        };
+
+        // The card takes the internal flash's place at STORAGE_ROOT
+        self.set(Mounted::Memory);
+
+        match MiniSDCard::mount(littlefs) {
+            Ok(card) => {
+                info!("SD card inserted: {:?}", card.info());
+                self.set(Mounted::SdCard(card));
+                true
+            }
+            Err(error) => {
+                warn!("failed to mount the SD card: {:?}", error);
+                self.mount_internal_flash();
+                false
+            }
+        }
+    }
+
+    pub fn card_removed(&mut self) {
+        warn!("SD card removed");
+
+        self.set(Mounted::Memory);
+        self.mount_internal_flash();
+    }
+
+    // Unmounts whatever is mounted and formats it. Files still open, like the recording's, fail from then on
+    pub fn format(&mut self) -> Result<(), CustomError> {
+        let medium = self.medium();
+
+        warn!("formatting the {:?}", medium);
+
+        match medium {
+            StorageMedium::SdCard => {
+                self.set(Mounted::Memory);
+
+                let card = self.pins.detect().and_then(|mut littlefs| {
+                    littlefs.format()?;
+                    MiniSDCard::mount(littlefs)
+                });
+
+                match card {
+                    Ok(card) => self.set(Mounted::SdCard(card)),
+                    Err(error) => {
+                        self.mount_internal_flash();
+                        return Err(error);
+                    }
+                }
+            }
+            StorageMedium::InternalFlash => {
+                self.set(Mounted::Memory);
+
+                match InternalFlash::format() {
+                    Ok(flash) => self.set(Mounted::InternalFlash(flash)),
+                    Err(error) => {
+                        self.mount_internal_flash();
+                        return Err(error);
+                    }
+                }
+            }
+            StorageMedium::Memory => return Err(CustomError::NoStorage),
+        }
+
+        Ok(())
+    }
+
+    fn mount_internal_flash(&mut self) {
+        match InternalFlash::new() {
+            Ok(flash) => self.set(Mounted::InternalFlash(flash)),
+            Err(error) => warn!("no internal flash either, meetings are only kept in memory: {:?}", error),
+        }
+    }
+
+    // The previous filesystem is unmounted by dropping it
+    fn set(&mut self, mounted: Mounted) {
+        self.mounted = mounted;
+
+        if let Ok(mut medium) = self.medium.lock() {
+            *medium = self.medium();
+        }
+    }
+}
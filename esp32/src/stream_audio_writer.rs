@@ -4,7 +4,8 @@ use esp_idf_svc::http::client::EspHttpConnection;
 use esp_idf_svc::io::Write;
 use riff_wave::{WaveWriter, WriteResult};
 
-use crate::SAMPLE_RATE_HZ;
+pub use echosense_core::audio::{wav_header, WAV_HEADER_SIZE};
+pub use echosense_core::convert::{PCM_BITS_PER_SAMPLE, PCM_CHANNELS};
 
 pub struct AudioWriter<'a> {
     client: &'a mut EspHttpConnection,
@@ -21,8 +22,18 @@ impl<'a> AudioWriter<'a> {
         }
     }
 
-    pub fn initialize<const BUFFER_SIZE: usize>(client: &'a mut EspHttpConnection) -> WriteResult<WaveWriter<BufWriter<AudioWriter<'a>>>> {
-        WaveWriter::new(1, SAMPLE_RATE_HZ, 16, BufWriter::with_capacity(BUFFER_SIZE, Self::new(client)))
+    pub fn initialize<const BUFFER_SIZE: usize>(
+        client: &'a mut EspHttpConnection,
+        channels: u16,
+        sample_rate: u32,
+        bits_per_sample: u16,
+    ) -> WriteResult<WaveWriter<BufWriter<AudioWriter<'a>>>> {
+        WaveWriter::new(
+            channels,
+            sample_rate,
+            bits_per_sample,
+            BufWriter::with_capacity(BUFFER_SIZE, Self::new(client)),
+        )
     }
 }
 
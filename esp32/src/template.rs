@@ -0,0 +1,10 @@
+use std::path::Path;
+
+pub use echosense_core::template::{escape_html, Escape, Template};
+
+pub const TEMPLATES_DIRECTORY: &str = "/sdcard/templates";
+
+// Templates on the SD card take precedence over the ones built into the firmware
+pub fn load_template(name: &str, default: &str, escape: Escape) -> Template {
+    Template::load(Path::new(TEMPLATES_DIRECTORY).join(name), default, escape)
+}
@@ -0,0 +1,337 @@
+use std::path::Path;
+use std::time::{Duration, Instant};
+
+use crossbeam::channel::{unbounded, Receiver, RecvTimeoutError, Sender};
+use echosense_core::encryption;
+use esp_idf_svc::http::client::{Configuration, Connection, EspHttpConnection};
+use esp_idf_svc::http::Method;
+use esp_idf_svc::io::Write;
+use esp_idf_svc::sys::esp_random;
+use hmac::{Hmac, Mac};
+use log::{error, info, warn};
+use serde::{Deserialize, Serialize};
+use sha2::Sha256;
+
+use crate::custom_error::CustomError;
+use crate::encryption::storage_key;
+use crate::recipients::CONFIG_DIRECTORY;
+
+const WEBHOOKS_FILE: &str = "/sdcard/config/webhooks.json";
+pub const WEBHOOK_LOG_DIRECTORY: &str = "/sdcard/logs";
+pub const WEBHOOK_LOG_FILE: &str = "/sdcard/logs/webhooks.log";
+
+// The log is rotated once it grows past this size, keeping a single previous file around
+const WEBHOOK_LOG_MAX_SIZE: u64 = 64 * 1024;
+const WEBHOOK_MAX_ATTEMPTS: u32 = 5;
+const WEBHOOK_RETRY_BACKOFF: Duration = Duration::from_secs(5);
+const WEBHOOK_MAX_PENDING: usize = 100;
+const WEBHOOK_IDLE_INTERVAL: Duration = Duration::from_secs(60);
+
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum WebhookEvent {
+    MeetingStarted,
+    FinalTranscript,
+    SummaryReady,
+    QuestionAnswered,
+    MeetingFinished,
+}
+
+impl WebhookEvent {
+    pub fn name(&self) -> &'static str {
+        match self {
+            WebhookEvent::MeetingStarted => "meeting_started",
+            WebhookEvent::FinalTranscript => "final_transcript",
+            WebhookEvent::SummaryReady => "summary_ready",
+            WebhookEvent::QuestionAnswered => "question_answered",
+            WebhookEvent::MeetingFinished => "meeting_finished",
+        }
+    }
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct WebhookTarget {
+    pub url: String,
+    pub secret: String,
+    // No events means every event
+    #[serde(default)]
+    pub events: Vec<WebhookEvent>,
+}
+
+impl WebhookTarget {
+    // Without a secret anyone could compute the signature, so receivers would have nothing to verify
+    pub fn is_valid(&self) -> bool {
+        (self.url.starts_with("http://") || self.url.starts_with("https://")) && self.secret.trim().is_empty() == false
+    }
+
+    fn wants(&self, event: WebhookEvent) -> bool {
+        self.events.is_empty() || self.events.contains(&event)
+    }
+}
+
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct WebhookTargets {
+    targets: Vec<WebhookTarget>,
+}
+
+impl WebhookTargets {
+    pub fn load() -> Self {
+        encryption::read(Path::new(WEBHOOKS_FILE), storage_key().as_ref())
+            .ok()
+            .and_then(|bytes| serde_json::from_slice(&bytes).ok())
+            .unwrap_or_default()
+    }
+
+    pub fn save(&self) -> Result<(), CustomError> {
+        std::fs::create_dir_all(CONFIG_DIRECTORY)?;
+
+        Ok(encryption::write(
+            Path::new(WEBHOOKS_FILE),
+            storage_key().as_ref(),
+            &serde_json::to_vec(self)?,
+        )?)
+    }
+
+    // Targets are identified by their url, saving an existing one replaces it
+    pub fn insert(&mut self, target: WebhookTarget) {
+        self.remove(&target.url);
+        self.targets.push(target);
+    }
+
+    pub fn remove(&mut self, url: &str) {
+        self.targets.retain(|target| target.url != url);
+    }
+
+    // Secrets never leave the device once saved
+    pub fn redacted(&self) -> Vec<WebhookTarget> {
+        self.targets
+            .iter()
+            .map(|target| WebhookTarget {
+                secret: String::new(),
+                ..target.clone()
+            })
+            .collect()
+    }
+}
+
+#[derive(Debug, Serialize)]
+pub struct WebhookPayload {
+    pub id: String,
+    pub event: WebhookEvent,
+    pub meeting_id: String,
+    pub device_url: String,
+    pub data: serde_json::Value,
+}
+
+#[derive(Debug, Serialize)]
+struct DeliveryLogEntry<'a> {
+    id: &'a str,
+    event: WebhookEvent,
+    url: &'a str,
+    attempt: u32,
+    delivered: bool,
+    status: Option<u16>,
+    error: Option<String>,
+}
+
+struct PendingDelivery {
+    id: String,
+    event: WebhookEvent,
+    target: WebhookTarget,
+    body: String,
+    attempts: u32,
+    due: Instant,
+}
+
+#[derive(Clone)]
+pub struct Webhooks {
+    device_url: String,
+    sender: Sender<WebhookPayload>,
+}
+
+impl Webhooks {
+    pub fn new<S: Into<String>>(device_url: S) -> Result<Self, CustomError> {
+        let (sender, receiver) = unbounded::<WebhookPayload>();
+
+        std::thread::Builder::new().spawn(move || Self::dispatch(receiver))?;
+
+        Ok(Self {
+            device_url: device_url.into(),
+            sender,
+        })
+    }
+
+    // Never blocks the caller, delivery happens on the webhook thread
+    pub fn emit(&self, event: WebhookEvent, meeting_id: &str, data: serde_json::Value) {
+        let random_number = unsafe { esp_random() };
+
+        let payload = WebhookPayload {
+            id: format!("{:08x}", random_number),
+            event,
+            meeting_id: meeting_id.to_string(),
+            device_url: self.device_url.clone(),
+            data,
+        };
+
+        if let Err(error) = self.sender.send(payload) {
+            error!("failed to queue webhook {}: {:?}", event.name(), error);
+        }
+    }
+
+    fn dispatch(receiver: Receiver<WebhookPayload>) -> Result<(), CustomError> {
+        let mut pending = Vec::<PendingDelivery>::new();
+
+        loop {
+            let timeout = pending
+                .iter()
+                .map(|delivery| delivery.due)
+                .min()
+                .map(|due| due.saturating_duration_since(Instant::now()))
+                .unwrap_or(WEBHOOK_IDLE_INTERVAL);
+
+            match receiver.recv_timeout(timeout) {
+                Ok(payload) => {
+                    let body = serde_json::to_string(&payload)?;
+
+                    for target in WebhookTargets::load().targets {
+                        // Also skips targets saved with an empty secret before it was required
+                        if target.is_valid() == false || target.wants(payload.event) == false {
+                            continue;
+                        }
+
+                        if pending.len() >= WEBHOOK_MAX_PENDING {
+                            let dropped = pending.remove(0);
+                            warn!("webhook queue is full, dropping {} to {}", dropped.id, dropped.target.url);
+                        }
+
+                        pending.push(PendingDelivery {
+                            id: payload.id.clone(),
+                            event: payload.event,
+                            target,
+                            body: body.clone(),
+                            attempts: 0,
+                            due: Instant::now(),
+                        });
+                    }
+                }
+                Err(RecvTimeoutError::Disconnected) => break Ok(()),
+                Err(RecvTimeoutError::Timeout) => {}
+            }
+
+            let now = Instant::now();
+            let mut index = 0;
+
+            while index < pending.len() {
+                if pending[index].due > now {
+                    index += 1;
+                    continue;
+                }
+
+                let delivery = &mut pending[index];
+                delivery.attempts += 1;
+
+                let result = Self::post(delivery);
+
+                Self::log(delivery, &result);
+
+                match result {
+                    Ok(_) => {
+                        info!("delivered webhook {} to {}", delivery.id, delivery.target.url);
+                        pending.remove(index);
+                    }
+                    Err(_) if delivery.attempts >= WEBHOOK_MAX_ATTEMPTS => {
+                        error!("giving up on webhook {} to {}", delivery.id, delivery.target.url);
+                        pending.remove(index);
+                    }
+                    Err(error) => {
+                        warn!("webhook {} to {} failed: {:?}", delivery.id, delivery.target.url, error);
+
+                        // 5s, 10s, 20s, 40s
+                        delivery.due = now + WEBHOOK_RETRY_BACKOFF * 2u32.pow(delivery.attempts - 1);
+                        index += 1;
+                    }
+                }
+            }
+        }
+    }
+
+    fn post(delivery: &PendingDelivery) -> Result<u16, CustomError> {
+        let signature = format!("sha256={}", Self::sign(&delivery.target.secret, delivery.body.as_bytes())?);
+        let content_length = delivery.body.len().to_string();
+
+        let headers = [
+            ("Content-Type", "application/json"),
+            ("Content-Length", content_length.as_str()),
+            ("X-EchoSense-Event", delivery.event.name()),
+            ("X-EchoSense-Delivery", delivery.id.as_str()),
+            ("X-EchoSense-Signature", signature.as_str()),
+        ];
+
+        let mut configuration = Configuration::default();
+        configuration.timeout = Some(Duration::from_secs(10));
+
+        let mut client = EspHttpConnection::new(&configuration)?;
+
+        client.initiate_request(Method::Post, delivery.target.url.as_str(), &headers)?;
+        client.write_all(delivery.body.as_bytes())?;
+        client.flush()?;
+        client.initiate_response()?;
+
+        let status = client.status();
+
+        if status >= 200 && status < 300 {
+            return Ok(status);
+        }
+
+        Err(CustomError::WebhookDeliveryError(status))
+    }
+
+    // Receivers verify the request by computing the same HMAC over the raw body with their copy of the secret
+    fn sign(secret: &str, body: &[u8]) -> Result<String, CustomError> {
+        let mut mac = Hmac::<Sha256>::new_from_slice(secret.as_bytes())
+            .map_err(|error| CustomError::AnyhowError(anyhow::anyhow!(error)))?;
+
+        mac.update(body);
+
+        Ok(mac
+            .finalize()
+            .into_bytes()
+            .iter()
+            .map(|byte| format!("{:02x}", byte))
+            .collect())
+    }
+
+    fn log(delivery: &PendingDelivery, result: &Result<u16, CustomError>) {
+        let entry = DeliveryLogEntry {
+            id: delivery.id.as_str(),
+            event: delivery.event,
+            url: delivery.target.url.as_str(),
+            attempt: delivery.attempts,
+            delivered: result.is_ok(),
+            status: match result {
+                Ok(status) => Some(*status),
+                Err(CustomError::WebhookDeliveryError(status)) => Some(*status),
+                Err(_) => None,
+            },
+            error: result.as_ref().err().map(|error| format!("{:?}", error)),
+        };
+
+        if let Err(error) = Self::append_log(&entry) {
+            warn!("failed to write the webhook delivery log: {:?}", error);
+        }
+    }
+
+    fn append_log(entry: &DeliveryLogEntry) -> Result<(), CustomError> {
+        std::fs::create_dir_all(WEBHOOK_LOG_DIRECTORY)?;
+
+        if std::fs::metadata(WEBHOOK_LOG_FILE).is_ok_and(|metadata| metadata.len() > WEBHOOK_LOG_MAX_SIZE) {
+            std::fs::rename(WEBHOOK_LOG_FILE, format!("{}.1", WEBHOOK_LOG_FILE))?;
+        }
+
+        // Encrypted, the whole log is written again, it is rotated small enough for that
+        let mut line = serde_json::to_vec(entry)?;
+        line.push(b'\n');
+
+        Ok(encryption::append(Path::new(WEBHOOK_LOG_FILE), storage_key().as_ref(), &line)?)
+    }
+}
@@ -0,0 +1,6 @@
+// A local stand-in for the parts of AssemblyAI the device talks to, for tests and the simulator without network access
+pub mod script;
+pub mod server;
+
+pub use script::{Failure, Script};
+pub use server::{MockAssembly, RecordedRequest};
@@ -0,0 +1,15 @@
+use echosense_mock::{MockAssembly, Script};
+
+// Serves the default script, e.g. for `echosense-simulator --backend http://localhost:9000`
+fn main() -> std::io::Result<()> {
+    env_logger::Builder::from_env(env_logger::Env::default().default_filter_or("info")).init();
+
+    let address = std::env::args().nth(1).unwrap_or_else(|| "127.0.0.1:9000".to_string());
+    let mock = MockAssembly::bind(&address, Script::default())?;
+
+    println!("{}", mock.base_url());
+
+    loop {
+        std::thread::park();
+    }
+}
@@ -0,0 +1,87 @@
+use echosense_core::assembly::AssemblyResponse;
+
+#[derive(Debug, Clone)]
+pub struct Failure {
+    // Matched against the start of the request path, e.g. "/lemur/v3/generate/summary"
+    pub path: String,
+    pub status: u16,
+    // How many matching requests fail before the endpoint recovers
+    pub times: usize,
+}
+
+// What the mock answers with, everything is deterministic so tests can assert on it
+#[derive(Debug, Clone)]
+pub struct Script {
+    // When set, requests without this exact Authorization header are rejected with a 401
+    pub api_key: Option<String>,
+    // Realtime messages, sent in order once every `frames_per_message` binary frames
+    pub realtime: Vec<AssemblyResponse>,
+    pub frames_per_message: usize,
+    // Closes the first realtime session after this many binary frames, to exercise reconnects
+    pub drop_after_frames: Option<usize>,
+    // Number of times a transcript is reported as processing before it completes
+    pub transcript_polls: usize,
+    pub transcript_text: String,
+    pub summary: String,
+    pub action_items: String,
+    pub answer: String,
+    pub failures: Vec<Failure>,
+}
+
+impl Script {
+    pub fn final_transcripts<S: AsRef<str>>(sentences: &[S]) -> Vec<AssemblyResponse> {
+        let mut messages = vec![];
+        let mut audio_start = 0;
+
+        for sentence in sentences {
+            let sentence = sentence.as_ref();
+            let words = sentence.split(' ').collect::<Vec<_>>();
+            let audio_end = audio_start + words.len() as u64 * 400;
+
+            for index in 1..words.len() {
+                messages.push(AssemblyResponse::PartialTranscript {
+                    text: words[..index].join(" "),
+                    created: Self::created(messages.len()),
+                    audio_start,
+                    audio_end: audio_start + index as u64 * 400,
+                });
+            }
+
+            messages.push(AssemblyResponse::FinalTranscript {
+                text: sentence.to_string(),
+                created: Self::created(messages.len()),
+                audio_start,
+                audio_end,
+            });
+
+            audio_start = audio_end;
+        }
+
+        messages
+    }
+
+    fn created(index: usize) -> String {
+        format!("2024-12-01T10:{:02}:{:02}.000000", index / 60 % 60, index % 60)
+    }
+}
+
+impl Default for Script {
+    fn default() -> Self {
+        Self {
+            api_key: None,
+            realtime: Self::final_transcripts(&[
+                "Welcome everyone to the weekly sync",
+                "The release is planned for next Friday",
+                "Anna will prepare the changelog",
+            ]),
+            frames_per_message: 8,
+            drop_after_frames: None,
+            transcript_polls: 2,
+            transcript_text: "Welcome everyone to the weekly sync".to_string(),
+            summary: "- The release is planned for next Friday".to_string(),
+            action_items: "- Anna: prepare the changelog".to_string(),
+            answer: "Next Friday".to_string(),
+            failures: vec![],
+        }
+    }
+}
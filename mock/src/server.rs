@@ -0,0 +1,383 @@
+use std::collections::HashMap;
+use std::io::{Read, Write};
+use std::net::{SocketAddr, TcpListener, TcpStream};
+use std::sync::atomic::{AtomicU32, Ordering};
+use std::sync::{Arc, Mutex};
+use std::thread::spawn;
+
+use echosense_core::assembly::AssemblyResponse;
+use log::{info, warn};
+use serde_json::{json, Value};
+use tungstenite::handshake::server::{ErrorResponse, Request, Response};
+use tungstenite::http::StatusCode;
+use tungstenite::Message;
+
+use crate::script::Script;
+
+#[derive(Debug, Clone)]
+pub struct RecordedRequest {
+    pub method: String,
+    pub path: String,
+    pub content_type: Option<String>,
+    pub body: Vec<u8>,
+}
+
+#[derive(Default)]
+struct State {
+    requests: Vec<RecordedRequest>,
+    tokens: Vec<String>,
+    sessions: usize,
+    audio_bytes: usize,
+    uploads: usize,
+    // Transcript id to the number of polls left before it completes, None for transcripts that failed
+    transcripts: HashMap<String, Option<usize>>,
+    // Index into Script::failures to how many times it already failed
+    failures: HashMap<usize, usize>,
+}
+
+type SharedState = Arc<Mutex<State>>;
+
+pub struct MockAssembly {
+    address: SocketAddr,
+    state: SharedState,
+}
+
+impl MockAssembly {
+    // Binds a random port on localhost, the server lives as long as the process
+    pub fn start(script: Script) -> std::io::Result<Self> {
+        Self::bind("127.0.0.1:0", script)
+    }
+
+    pub fn bind(address: &str, script: Script) -> std::io::Result<Self> {
+        let listener = TcpListener::bind(address)?;
+        let address = listener.local_addr()?;
+        let state = SharedState::default();
+        let script = Arc::new(script);
+
+        {
+            let state = state.clone();
+
+            spawn(move || {
+                for stream in listener.incoming().flatten() {
+                    let (state, script) = (state.clone(), script.clone());
+
+                    spawn(move || {
+                        if let Err(error) = handle_connection(stream, &state, &script) {
+                            warn!("mock connection failed: {:?}", error);
+                        }
+                    });
+                }
+            });
+        }
+
+        info!("mock AssemblyAI listening on http://{}", address);
+
+        Ok(Self { address, state })
+    }
+
+    pub fn base_url(&self) -> String {
+        format!("http://{}", self.address)
+    }
+
+    pub fn requests(&self) -> Vec<RecordedRequest> {
+        self.state.lock().unwrap().requests.clone()
+    }
+
+    pub fn requests_to(&self, path: &str) -> usize {
+        self.requests().iter().filter(|request| request.path.starts_with(path)).count()
+    }
+
+    // Realtime sessions that completed the websocket handshake
+    pub fn sessions(&self) -> usize {
+        self.state.lock().unwrap().sessions
+    }
+
+    // Total bytes of binary audio frames received over all realtime sessions
+    pub fn audio_bytes(&self) -> usize {
+        self.state.lock().unwrap().audio_bytes
+    }
+}
+
+fn handle_connection(stream: TcpStream, state: &SharedState, script: &Script) -> std::io::Result<()> {
+    let mut buffer = [0u8; 2048];
+
+    // Peek so the websocket handshake still sees the whole request
+    let request = loop {
+        let length = stream.peek(&mut buffer)?;
+        let request = String::from_utf8_lossy(&buffer[..length]).to_string();
+
+        if request.contains("\r\n\r\n") || length == buffer.len() {
+            break request;
+        }
+
+        if length == 0 {
+            return Ok(());
+        }
+    };
+
+    match request.to_lowercase().contains("upgrade: websocket") {
+        true => handle_realtime(stream, state, script),
+        false => handle_http(stream, state, script),
+    }
+}
+
+fn handle_http(mut stream: TcpStream, state: &SharedState, script: &Script) -> std::io::Result<()> {
+    let mut request = vec![];
+    let mut buffer = [0u8; 4096];
+
+    let header_end = loop {
+        if let Some(position) = request.windows(4).position(|window| window == b"\r\n\r\n") {
+            break position + 4;
+        }
+
+        match stream.read(&mut buffer)? {
+            0 => return Ok(()),
+            length => request.extend_from_slice(&buffer[..length]),
+        }
+    };
+
+    let head = String::from_utf8_lossy(&request[..header_end]).to_string();
+    let mut lines = head.split("\r\n");
+    let mut request_line = lines.next().unwrap_or_default().split_whitespace();
+    let method = request_line.next().unwrap_or_default().to_string();
+    let path = request_line.next().unwrap_or_default().to_string();
+
+    let headers = lines
+        .filter_map(|line| line.split_once(':'))
+        .map(|(name, value)| (name.trim().to_lowercase(), value.trim().to_string()))
+        .collect::<HashMap<_, _>>();
+
+    let content_length = headers
+        .get("content-length")
+        .and_then(|length| length.parse::<usize>().ok())
+        .unwrap_or(0);
+
+    let mut body = request[header_end..].to_vec();
+
+    while body.len() < content_length {
+        match stream.read(&mut buffer)? {
+            0 => break,
+            length => body.extend_from_slice(&buffer[..length]),
+        }
+    }
+
+    let (status, response) = respond(
+        RecordedRequest {
+            method,
+            path,
+            content_type: headers.get("content-type").cloned(),
+            body,
+        },
+        headers.get("authorization").map(String::as_str),
+        state,
+        script,
+    );
+
+    let response = serde_json::to_vec(&response)?;
+
+    write!(
+        stream,
+        "HTTP/1.1 {} {}\r\nContent-Type: application/json\r\nContent-Length: {}\r\nConnection: close\r\n\r\n",
+        status,
+        StatusCode::from_u16(status).ok().and_then(|status| status.canonical_reason()).unwrap_or(""),
+        response.len()
+    )?;
+
+    stream.write_all(&response)?;
+    stream.flush()
+}
+
+fn respond(request: RecordedRequest, authorization: Option<&str>, state: &SharedState, script: &Script) -> (u16, Value) {
+    let mut state = state.lock().unwrap();
+    let body = serde_json::from_slice::<Value>(&request.body).unwrap_or(Value::Null);
+    let path = request.path.clone();
+    let method = request.method.clone();
+
+    state.requests.push(request);
+
+    if let Some(api_key) = &script.api_key {
+        if authorization != Some(api_key.as_str()) {
+            return (401, json!({ "error": "Authentication error, API token missing/invalid" }));
+        }
+    }
+
+    for (index, failure) in script.failures.iter().enumerate() {
+        let failed = state.failures.entry(index).or_insert(0);
+
+        if path.starts_with(&failure.path) && *failed < failure.times {
+            *failed += 1;
+            return (failure.status, json!({ "error": format!("scripted failure {}", failure.status) }));
+        }
+    }
+
+    match (method.as_str(), path.as_str()) {
+        ("POST", "/v2/realtime/token") => {
+            let token = format!("mock-token-{}", state.tokens.len() + 1);
+            state.tokens.push(token.clone());
+
+            (200, json!({ "token": token }))
+        }
+        ("POST", "/v2/upload") => {
+            state.uploads += 1;
+
+            (200, json!({ "upload_url": format!("https://cdn.mock/uploads/{}", state.uploads) }))
+        }
+        ("POST", "/v2/transcript") => match body.get("audio_url").and_then(Value::as_str) {
+            Some(audio_url) => {
+                let id = format!("transcript-{}", state.transcripts.len() + 1);
+
+                // Anything that wasn't uploaded fails, the same as AssemblyAI does for urls it can't download
+                let polls = match audio_url.starts_with("https://cdn.mock/uploads/") {
+                    true => Some(script.transcript_polls),
+                    false => None,
+                };
+
+                state.transcripts.insert(id.clone(), polls);
+
+                (200, json!({ "id": id, "status": "queued", "text": null }))
+            }
+            None => (400, json!({ "error": "audio_url is required" })),
+        },
+        ("GET", path) if path.starts_with("/v2/transcript/") => {
+            let id = path.trim_start_matches("/v2/transcript/");
+
+            match state.transcripts.get_mut(id) {
+                Some(None) => (200, json!({ "id": id, "status": "error", "text": null })),
+                Some(Some(0)) => (200, json!({ "id": id, "status": "completed", "text": script.transcript_text })),
+                Some(Some(polls)) => {
+                    *polls -= 1;
+
+                    (200, json!({ "id": id, "status": "processing", "text": null }))
+                }
+                None => (404, json!({ "error": "Transcript not found" })),
+            }
+        }
+        ("POST", "/lemur/v3/generate/summary") => (200, json!({ "request_id": "mock-summary", "response": script.summary })),
+        ("POST", "/lemur/v3/generate/action-items") => {
+            (200, json!({ "request_id": "mock-action-items", "response": script.action_items }))
+        }
+        ("POST", "/lemur/v3/generate/question-answer") => {
+            let answers = body
+                .get("questions")
+                .and_then(Value::as_array)
+                .map(|questions| {
+                    questions
+                        .iter()
+                        .map(|question| json!({ "question": question["question"], "answer": script.answer }))
+                        .collect::<Vec<_>>()
+                })
+                .unwrap_or_default();
+
+            (200, json!({ "request_id": "mock-question-answer", "response": answers }))
+        }
+        _ => (404, json!({ "error": "Not found" })),
+    }
+}
+
+// The handshake callback's error type is dictated by tungstenite
+#[allow(clippy::result_large_err)]
+fn handle_realtime(stream: TcpStream, state: &SharedState, script: &Script) -> std::io::Result<()> {
+    let sample_rate = Arc::new(AtomicU32::new(16000));
+
+    let session = {
+        let state = state.clone();
+        let sample_rate = sample_rate.clone();
+
+        move |request: &Request, response: Response| -> Result<Response, ErrorResponse> {
+            let uri = request.uri();
+            let parameter = |name: &str| {
+                uri.query()
+                    .unwrap_or_default()
+                    .split('&')
+                    .find_map(|parameter| parameter.strip_prefix(name)?.strip_prefix('='))
+                    .unwrap_or_default()
+                    .to_string()
+            };
+
+            let token = parameter("token");
+
+            if let Ok(rate) = parameter("sample_rate").parse::<u32>() {
+                sample_rate.store(rate, Ordering::Relaxed);
+            }
+
+            let mut state = state.lock().unwrap();
+
+            state.requests.push(RecordedRequest {
+                method: "GET".to_string(),
+                path: uri.to_string(),
+                content_type: None,
+                body: vec![],
+            });
+
+            if uri.path() != "/v2/realtime/ws" || state.tokens.contains(&token) == false {
+                let mut error = ErrorResponse::new(Some("invalid or missing temporary token".to_string()));
+                *error.status_mut() = StatusCode::UNAUTHORIZED;
+
+                return Err(error);
+            }
+
+            state.sessions += 1;
+
+            Ok(response)
+        }
+    };
+
+    let mut socket = tungstenite::accept_hdr(stream, session).map_err(|error| io_error(error))?;
+    let first_session = state.lock().unwrap().sessions == 1;
+
+    socket
+        .send(text(&AssemblyResponse::SessionBegins { session_id: "mock-session".to_string() }))
+        .map_err(io_error)?;
+
+    let mut frames = 0usize;
+    let mut audio_bytes = 0usize;
+    let mut script_position = 0usize;
+
+    loop {
+        let message = match socket.read() {
+            Ok(message) => message,
+            Err(tungstenite::Error::ConnectionClosed | tungstenite::Error::AlreadyClosed) => return Ok(()),
+            Err(error) => return Err(io_error(error)),
+        };
+
+        match message {
+            Message::Binary(audio) => {
+                frames += 1;
+                audio_bytes += audio.len();
+                state.lock().unwrap().audio_bytes += audio.len();
+
+                if first_session && Some(frames) == script.drop_after_frames {
+                    // No close frame, like a wifi drop
+                    return Ok(());
+                }
+
+                if frames % script.frames_per_message.max(1) == 0 && script_position < script.realtime.len() {
+                    socket.send(text(&script.realtime[script_position])).map_err(io_error)?;
+                    script_position += 1;
+                }
+            }
+            Message::Text(message) if message.contains("terminate_session") => {
+                let duration = audio_bytes as f32 / 2.0 / sample_rate.load(Ordering::Relaxed) as f32;
+
+                socket
+                    .send(text(&AssemblyResponse::SessionInformation { audio_duration_seconds: duration }))
+                    .map_err(io_error)?;
+                socket.send(text(&AssemblyResponse::SessionTerminated)).map_err(io_error)?;
+                socket.close(None).ok();
+                socket.flush().ok();
+
+                return Ok(());
+            }
+            Message::Close(_) => return Ok(()),
+            _ => {}
+        }
+    }
+}
+
+fn text(response: &AssemblyResponse) -> Message {
+    Message::Text(serde_json::to_string(response).unwrap_or_default())
+}
+
+fn io_error<E: ToString>(error: E) -> std::io::Error {
+    std::io::Error::other(error.to_string())
+}
@@ -0,0 +1,58 @@
+use std::path::PathBuf;
+
+use echosense_core::encryption::{decrypt_directory, StorageKey};
+
+use echosense_simulator::custom_error::CustomError;
+
+const USAGE: &str = "usage: echosense-decrypt --secret <hex> <card> <output>";
+
+// Decrypts a card pulled from a device with the secret exported from it, so its meetings can be used without the device
+fn main() -> Result<(), CustomError> {
+    env_logger::Builder::from_env(env_logger::Env::default().default_filter_or("info")).init();
+
+    let mut secret = std::env::var("STORAGE_SECRET").ok();
+    let mut paths = vec![];
+    let mut arguments = std::env::args().skip(1);
+
+    while let Some(argument) = arguments.next() {
+        match argument.as_str() {
+            "--secret" => secret = arguments.next(),
+            _ if argument.starts_with("--") => {
+                return Err(CustomError::InvalidArguments(format!(
+                    "unknown argument {}\n{}",
+                    argument, USAGE
+                )))
+            }
+            _ => paths.push(PathBuf::from(argument)),
+        }
+    }
+
+    let key = secret
+        .as_deref()
+        .and_then(StorageKey::from_hex_secret)
+        .ok_or_else(|| CustomError::InvalidArguments(format!("a hex storage secret is needed\n{}", USAGE)))?;
+
+    let [source, destination] = paths.as_slice() else {
+        return Err(CustomError::InvalidArguments(USAGE.to_string()));
+    };
+
+    let report = decrypt_directory(source, destination, &key)?;
+
+    println!(
+        "{} files decrypted, {} copied as they were, {} failed",
+        report.decrypted,
+        report.copied,
+        report.failed.len()
+    );
+
+    for failure in &report.failed {
+        println!("  {}", failure);
+    }
+
+    match report.failed.is_empty() {
+        true => Ok(()),
+        false => Err(CustomError::InvalidArguments(
+            "some files didn't decrypt, is it the secret of this card's device?".to_string(),
+        )),
+    }
+}
@@ -0,0 +1,77 @@
+use std::fmt::{Debug, Display, Formatter};
+use std::sync::{MutexGuard, PoisonError};
+
+use echosense_core::error::CoreError;
+
+#[derive(Debug)]
+pub enum CustomError {
+    CoreError(CoreError),
+    IOError(std::io::Error),
+    SerdeJsonError(serde_json::Error),
+    // Boxed, tungstenite's error is several times the size of every other variant
+    WebsocketError(Box<tungstenite::Error>),
+    PngError(png::EncodingError),
+    MutexLockError(String),
+    ChannelSendError,
+    InvalidArguments(String),
+}
+
+impl Display for CustomError {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        Debug::fmt(self, f)
+    }
+}
+
+impl std::error::Error for CustomError {}
+
+impl From<CoreError> for CustomError {
+    fn from(error: CoreError) -> Self {
+        CustomError::CoreError(error)
+    }
+}
+
+impl From<std::io::Error> for CustomError {
+    fn from(error: std::io::Error) -> Self {
+        CustomError::IOError(error)
+    }
+}
+
+impl From<serde_json::Error> for CustomError {
+    fn from(error: serde_json::Error) -> Self {
+        CustomError::SerdeJsonError(error)
+    }
+}
+
+impl From<tungstenite::Error> for CustomError {
+    fn from(error: tungstenite::Error) -> Self {
+        CustomError::WebsocketError(Box::new(error))
+    }
+}
+
+impl From<png::EncodingError> for CustomError {
+    fn from(error: png::EncodingError) -> Self {
+        CustomError::PngError(error)
+    }
+}
+
+impl<'a, T> From<PoisonError<MutexGuard<'a, T>>> for CustomError {
+    fn from(error: PoisonError<MutexGuard<T>>) -> Self {
+        CustomError::MutexLockError(error.to_string())
+    }
+}
+
+impl<T> From<crossbeam_channel::SendError<T>> for CustomError {
+    fn from(_: crossbeam_channel::SendError<T>) -> Self {
+        CustomError::ChannelSendError
+    }
+}
+
+// Lets the host implementations of the core traits use `?` on anything that already converts into a CustomError
+impl From<CustomError> for CoreError {
+    fn from(error: CustomError) -> Self {
+        match error {
+            CustomError::CoreError(error) => error,
+            error => CoreError::PlatformError(format!("{:?}", error)),
+        }
+    }
+}
@@ -0,0 +1,107 @@
+use std::fs::File;
+use std::io::BufWriter;
+use std::path::PathBuf;
+
+use echosense_core::error::CoreError;
+use echosense_core::framebuffer::{pixel, render, FRAMEBUFFER_HEIGHT, FRAMEBUFFER_WIDTH};
+use echosense_core::platform::{DrawState, StatusDisplay};
+
+use crate::custom_error::CustomError;
+
+const PNG_SCALE: usize = 4;
+
+// Two pixel rows per line of text, using half blocks
+pub struct TerminalDisplay {
+    state: Option<DrawState>,
+}
+
+impl TerminalDisplay {
+    pub fn new() -> Self {
+        Self { state: None }
+    }
+}
+
+impl Default for TerminalDisplay {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl StatusDisplay for TerminalDisplay {
+    fn draw(&mut self, state: DrawState) -> Result<(), CoreError> {
+        if self.state.as_ref() == Some(&state) {
+            return Ok(());
+        }
+
+        let framebuffer = render(&state)?;
+        let mut output = String::new();
+
+        for y in (0..FRAMEBUFFER_HEIGHT).step_by(2) {
+            for x in 0..FRAMEBUFFER_WIDTH {
+                output.push(match (pixel(&framebuffer, x, y), pixel(&framebuffer, x, y + 1)) {
+                    (true, true) => '█',
+                    (true, false) => '▀',
+                    (false, true) => '▄',
+                    (false, false) => ' ',
+                });
+            }
+
+            output.push('\n');
+        }
+
+        println!("{}", output);
+
+        self.state = Some(state);
+
+        Ok(())
+    }
+}
+
+// Overwrites the same image on every change, e.g. to keep it open in an image viewer that reloads
+pub struct PngDisplay {
+    path: PathBuf,
+    state: Option<DrawState>,
+}
+
+impl PngDisplay {
+    pub fn new<P: Into<PathBuf>>(path: P) -> Self {
+        Self {
+            path: path.into(),
+            state: None,
+        }
+    }
+
+    fn write(&self, framebuffer: &[u8]) -> Result<(), CustomError> {
+        let width = FRAMEBUFFER_WIDTH * PNG_SCALE;
+        let height = FRAMEBUFFER_HEIGHT * PNG_SCALE;
+
+        let mut encoder = png::Encoder::new(BufWriter::new(File::create(&self.path)?), width as u32, height as u32);
+        encoder.set_color(png::ColorType::Grayscale);
+        encoder.set_depth(png::BitDepth::Eight);
+
+        let mut image = vec![0u8; width * height];
+
+        for y in 0..height {
+            for x in 0..width {
+                if pixel(framebuffer, x / PNG_SCALE, y / PNG_SCALE) {
+                    image[x + y * width] = 0xFF;
+                }
+            }
+        }
+
+        Ok(encoder.write_header()?.write_image_data(&image)?)
+    }
+}
+
+impl StatusDisplay for PngDisplay {
+    fn draw(&mut self, state: DrawState) -> Result<(), CoreError> {
+        if self.state.as_ref() == Some(&state) {
+            return Ok(());
+        }
+
+        self.write(&render(&state)?)?;
+        self.state = Some(state);
+
+        Ok(())
+    }
+}
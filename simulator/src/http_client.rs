@@ -0,0 +1,64 @@
+use std::io::Read;
+use std::time::Duration;
+
+use echosense_core::error::CoreError;
+use echosense_core::platform::{HttpClient, HttpResponse, Method};
+use ureq::Agent;
+
+#[derive(Clone)]
+pub struct UreqClient {
+    agent: Agent,
+}
+
+impl UreqClient {
+    pub fn new() -> Self {
+        Self {
+            agent: ureq::AgentBuilder::new().timeout(Duration::from_secs(30)).build(),
+        }
+    }
+}
+
+impl Default for UreqClient {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl HttpClient for UreqClient {
+    fn request(
+        &mut self,
+        method: Method,
+        url: &str,
+        headers: &[(&str, &str)],
+        body: Option<&[u8]>,
+    ) -> Result<HttpResponse, CoreError> {
+        let method = match method {
+            Method::Get => "GET",
+            Method::Post => "POST",
+        };
+
+        let mut request = self.agent.request(method, url);
+
+        for (name, value) in headers {
+            request = request.set(name, value);
+        }
+
+        let result = match body {
+            Some(body) => request.send_bytes(body),
+            None => request.call(),
+        };
+
+        // Error statuses still carry a body worth reporting, only transport failures are errors here
+        let response = match result {
+            Ok(response) | Err(ureq::Error::Status(_, response)) => response,
+            Err(error) => return Err(CoreError::PlatformError(error.to_string())),
+        };
+
+        let status = response.status();
+        let mut body = vec![];
+
+        response.into_reader().read_to_end(&mut body)?;
+
+        Ok(HttpResponse { status, body })
+    }
+}
@@ -0,0 +1,6 @@
+pub mod custom_error;
+pub mod display;
+pub mod http_client;
+pub mod realtime;
+pub mod server;
+pub mod websocket;
@@ -0,0 +1,432 @@
+use std::path::PathBuf;
+use std::sync::{Arc, Mutex};
+use std::thread::spawn;
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+use crossbeam_channel::{unbounded, Receiver};
+use echosense_core::assembly::{Assembly, AssemblyResponse, ASSEMBLY_BASE_URL};
+use echosense_core::audio::AudioLevel;
+use echosense_core::codec::RecordingCodec;
+use echosense_core::combine::{CombineMode, Combiner};
+use echosense_core::dsp::{DspChain, DspConfig, NoiseGateConfig};
+use echosense_core::encryption::StorageKey;
+use echosense_core::host::{SystemClock, WavFileSource};
+use echosense_core::meeting::{Meeting, SharedMeeting};
+use echosense_core::pipeline::{
+    answer_question, handle_transcript, record_audio, record_channels, record_microphone, start_live_transcription,
+    summarize, TranscriptEvent,
+};
+use echosense_core::platform::{AudioSource, DrawState, RecordingStore, StatusDisplay};
+use echosense_core::store::FileStore;
+use echosense_core::vad::{SharedTimeline, VadConfig, VoiceGate};
+use log::{error, info, warn};
+
+use echosense_simulator::custom_error::CustomError;
+use echosense_simulator::display::{PngDisplay, TerminalDisplay};
+use echosense_simulator::http_client::UreqClient;
+use echosense_simulator::realtime::RealtimeSession;
+use echosense_simulator::server::{Command, Monitors, Server, Sessions, WebsocketMessage};
+
+const USAGE: &str = "usage: echosense-simulator --wav <file> [--port 8080] [--backend https://api.assemblyai.com] \
+[--storage simulator-data] [--display terminal|png:<file>] [--frontend frontend/dist/index.html] [--no-realtime] \
+[--no-dsp] [--noise-gate <dBFS>] [--stream-silence] [--combine mix|louder|beamform] [--mic-spacing <mm>] \
+[--keep-channels] [--codec pcm|adpcm|flac] [--segment-seconds <s>] [--storage-secret <hex>]";
+
+// Same frame size as the device's I2S buffer
+const MICROPHONE_FRAME_SIZE: usize = 1000;
+const MONITOR_LEVEL_INTERVAL: usize = 3;
+
+struct Options {
+    wav: PathBuf,
+    port: u16,
+    backend: String,
+    storage: PathBuf,
+    display: String,
+    frontend: PathBuf,
+    realtime: bool,
+    dsp: DspConfig,
+    vad: VadConfig,
+    combine: Option<CombineMode>,
+    mic_spacing_mm: u32,
+    keep_channels: bool,
+    codec: RecordingCodec,
+    segment_seconds: u64,
+    storage_key: Option<StorageKey>,
+}
+
+impl Options {
+    fn parse() -> Result<Self, CustomError> {
+        let mut options = Options {
+            wav: PathBuf::new(),
+            port: 8080,
+            backend: ASSEMBLY_BASE_URL.to_string(),
+            storage: PathBuf::from("simulator-data"),
+            display: "terminal".to_string(),
+            frontend: PathBuf::from("frontend/dist/index.html"),
+            realtime: true,
+            dsp: DspConfig::default(),
+            vad: VadConfig::default(),
+            combine: None,
+            mic_spacing_mm: 60,
+            keep_channels: false,
+            codec: RecordingCodec::Pcm,
+            segment_seconds: 0,
+            storage_key: None,
+        };
+
+        let mut arguments = std::env::args().skip(1);
+
+        while let Some(argument) = arguments.next() {
+            if argument == "--no-realtime" {
+                options.realtime = false;
+                continue;
+            }
+
+            if argument == "--stream-silence" {
+                options.vad.pause_streaming = false;
+                continue;
+            }
+
+            if argument == "--keep-channels" {
+                options.keep_channels = true;
+                continue;
+            }
+
+            if argument == "--no-dsp" {
+                options.dsp = DspConfig::disabled();
+                continue;
+            }
+
+            let value = arguments
+                .next()
+                .ok_or_else(|| CustomError::InvalidArguments(format!("{} needs a value\n{}", argument, USAGE)))?;
+
+            match argument.as_str() {
+                "--wav" => options.wav = PathBuf::from(value),
+                "--port" => {
+                    options.port = value
+                        .parse()
+                        .map_err(|_| CustomError::InvalidArguments(format!("invalid port {}", value)))?
+                }
+                "--backend" => options.backend = value,
+                "--storage" => options.storage = PathBuf::from(value),
+                "--display" => options.display = value,
+                "--frontend" => options.frontend = PathBuf::from(value),
+                "--noise-gate" => {
+                    let threshold_dbfs = value
+                        .parse()
+                        .map_err(|_| CustomError::InvalidArguments(format!("invalid noise gate threshold {}", value)))?;
+
+                    options.dsp.noise_gate = Some(NoiseGateConfig {
+                        threshold_dbfs,
+                        ..NoiseGateConfig::default()
+                    });
+                }
+                "--combine" => {
+                    options.combine = Some(
+                        CombineMode::from_name(&value)
+                            .ok_or_else(|| CustomError::InvalidArguments(format!("invalid combine mode {}", value)))?,
+                    )
+                }
+                "--mic-spacing" => {
+                    options.mic_spacing_mm = value
+                        .parse()
+                        .map_err(|_| CustomError::InvalidArguments(format!("invalid microphone spacing {}", value)))?
+                }
+                "--codec" => {
+                    options.codec = RecordingCodec::from_name(&value)
+                        .ok_or_else(|| CustomError::InvalidArguments(format!("invalid codec {}", value)))?
+                }
+                "--segment-seconds" => {
+                    options.segment_seconds = value
+                        .parse()
+                        .map_err(|_| CustomError::InvalidArguments(format!("invalid segment length {}", value)))?
+                }
+                "--storage-secret" => {
+                    options.storage_key = Some(
+                        StorageKey::from_hex_secret(&value)
+                            .ok_or_else(|| CustomError::InvalidArguments("the storage secret isn't hex".to_string()))?,
+                    )
+                }
+                _ => return Err(CustomError::InvalidArguments(format!("unknown argument {}\n{}", argument, USAGE))),
+            }
+        }
+
+        if options.wav.as_os_str().is_empty() {
+            return Err(CustomError::InvalidArguments(USAGE.to_string()));
+        }
+
+        Ok(options)
+    }
+}
+
+fn main() -> Result<(), CustomError> {
+    env_logger::Builder::from_env(env_logger::Env::default().default_filter_or("info")).init();
+
+    let options = Options::parse()?;
+
+    let mut display: Box<dyn StatusDisplay> = match options.display.strip_prefix("png:") {
+        Some(path) => Box::new(PngDisplay::new(path)),
+        None => Box::new(TerminalDisplay::new()),
+    };
+
+    display.draw(DrawState::Initializing)?;
+
+    let store = FileStore::new(&options.storage).with_codec(options.codec);
+    let store = match options.segment_seconds {
+        0 => store,
+        seconds => store.with_segments(Duration::from_secs(seconds)),
+    };
+    let store = Arc::new(match options.storage_key.clone() {
+        Some(key) => store.with_encryption(key),
+        None => store,
+    });
+    let clock = Arc::new(SystemClock::new());
+    let mut microphone = WavFileSource::open(&options.wav, MICROPHONE_FRAME_SIZE, clock, options.realtime)?;
+
+    // A stereo file stands in for the two microphones, without --combine only its first channel is used
+    if options.combine.is_some() {
+        microphone = microphone.with_both_channels();
+    }
+
+    // There is no wifi to join, the state is only shown for parity with the device
+    display.draw(DrawState::Wifi)?;
+
+    let api_key = std::env::var("ASSEMBLY_APIKEY").unwrap_or_default();
+    let assembly = Assembly::new(api_key, UreqClient::new()).with_base_url(&options.backend);
+
+    let server = Server::new(options.port, options.frontend.clone())?;
+    let sessions = server.sessions();
+    let monitors = server.monitors();
+
+    let meeting = Arc::new(Mutex::new(new_meeting()));
+
+    let (mut live_transcription, responses) = RealtimeSession::connect(assembly.clone(), microphone.sample_rate())?;
+
+    let address = format!("http://localhost:{}", options.port);
+
+    info!("Address: {} (frontend: {}/?ws=localhost:{})", address, address, options.port);
+
+    display.draw(DrawState::QRCode(address))?;
+
+    let (command_sender, command_receiver) = unbounded::<Command>();
+
+    let mut gate = VoiceGate::new(microphone.sample_rate(), options.vad.clone());
+
+    {
+        let (sessions, meeting, store, timeline) = (sessions.clone(), meeting.clone(), store.clone(), gate.timeline());
+        spawn(move || {
+            log_errors(handle_transcription_thread(responses, sessions, meeting, store.as_ref(), &timeline))
+        });
+    }
+
+    {
+        let (sessions, meeting, store) = (sessions.clone(), meeting.clone(), store.clone());
+        spawn(move || handle_frontend_sent_commands(command_receiver, assembly, sessions, meeting, store.as_ref()));
+    }
+
+    // Every consumer gets its own channel, cloning a crossbeam receiver would split the frames between them
+    let (sender_a, receiver_a) = unbounded::<Vec<u8>>();
+    let (sender_b, receiver_b) = unbounded::<Vec<u8>>();
+    let (sender_c, receiver_c) = unbounded::<Vec<u8>>();
+
+    let mut dsp = DspChain::new(&options.dsp, microphone.sample_rate());
+    let mut combiner = Combiner::for_spacing(
+        options.combine.unwrap_or(CombineMode::Mix),
+        microphone.sample_rate(),
+        options.mic_spacing_mm,
+    );
+
+    let raw = match (options.keep_channels, microphone.channels()) {
+        (true, channels @ 2..) => {
+            let (sender, receiver) = unbounded::<Vec<u8>>();
+            let (meeting, store) = (meeting.clone(), store.clone());
+
+            spawn(move || {
+                log_errors(record_channels(receiver, channels, meeting, store.as_ref()).map_err(CustomError::from))
+            });
+
+            Some(sender)
+        }
+        _ => None,
+    };
+
+    spawn(move || {
+        let result =
+            record_microphone(vec![sender_a, sender_b, sender_c], &mut microphone, &mut combiner, &mut dsp, raw);
+
+        log_errors(result.map_err(CustomError::from))
+    });
+
+    {
+        let (meeting, store) = (meeting.clone(), store.clone());
+
+        spawn(move || {
+            let result =
+                start_live_transcription(receiver_a, &mut live_transcription, &mut gate, &meeting, store.as_ref())
+                    .and_then(|_| live_transcription.terminate());
+
+            log_errors(result.map_err(CustomError::from))
+        });
+    }
+
+    {
+        let (meeting, store) = (meeting.clone(), store.clone());
+        spawn(move || log_errors(record_audio(receiver_b, meeting, store.as_ref()).map_err(CustomError::from)));
+    }
+
+    {
+        let sessions = sessions.clone();
+        spawn(move || log_errors(monitor_audio(receiver_c, sessions, monitors)));
+    }
+
+    server.run(command_sender, meeting)
+}
+
+fn new_meeting() -> Meeting {
+    let nanos = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|duration| duration.subsec_nanos() ^ duration.as_secs() as u32)
+        .unwrap_or_default();
+
+    Meeting::new(format!("{:08x}", nanos))
+}
+
+fn log_errors(result: Result<(), CustomError>) {
+    if let Err(error) = result {
+        error!("{:?}", error);
+    }
+}
+
+fn broadcast(sessions: &Sessions, message: impl Fn() -> WebsocketMessage) -> Result<(), CustomError> {
+    for (_, notifier) in sessions.lock()?.iter() {
+        notifier.send(message())?;
+    }
+
+    Ok(())
+}
+
+fn handle_transcription_thread(
+    receiver: Receiver<AssemblyResponse>,
+    sessions: Sessions,
+    meeting: SharedMeeting,
+    store: &dyn RecordingStore,
+    timeline: &SharedTimeline,
+) -> Result<(), CustomError> {
+    while let Ok(message) = receiver.recv() {
+        match handle_transcript(message, &meeting, store, timeline)? {
+            Some(TranscriptEvent::Partial(transcription)) => {
+                broadcast(&sessions, || WebsocketMessage::PartialTranscription(transcription.clone()))?
+            }
+            Some(TranscriptEvent::Final { transcription, .. }) => {
+                broadcast(&sessions, || WebsocketMessage::FinalTranscription(transcription.clone()))?
+            }
+            None => {}
+        }
+    }
+
+    Ok(())
+}
+
+fn handle_frontend_sent_commands(
+    receiver: Receiver<Command>,
+    mut assembly: Assembly<UreqClient>,
+    sessions: Sessions,
+    meeting: SharedMeeting,
+    store: &dyn RecordingStore,
+) {
+    while let Ok(command) = receiver.recv() {
+        if let Err(error) = handle_frontend_sent_command(command, &mut assembly, &sessions, &meeting, store) {
+            error!("failed to handle command: {:?}", error);
+        }
+    }
+}
+
+fn handle_frontend_sent_command(
+    command: Command,
+    assembly: &mut Assembly<UreqClient>,
+    sessions: &Sessions,
+    meeting: &SharedMeeting,
+    store: &dyn RecordingStore,
+) -> Result<(), CustomError> {
+    match command {
+        Command::StartMeeting => {
+            let current = {
+                let mut meeting = meeting.lock()?;
+                let previous = std::mem::replace(&mut *meeting, new_meeting());
+                store.save_meeting(&meeting)?;
+
+                info!("finished meeting {}, started meeting {}", previous.id, meeting.id);
+
+                meeting.id.clone()
+            };
+
+            broadcast(sessions, || WebsocketMessage::MeetingId(current.clone()))?;
+        }
+        Command::GetSummary => {
+            let summary = summarize(assembly, meeting, store)?;
+
+            broadcast(sessions, || WebsocketMessage::Summary(summary.clone()))?;
+        }
+        Command::AskQuestion { id, question } => {
+            let question_answer = answer_question(assembly, meeting, store, id, question)?;
+
+            broadcast(sessions, || WebsocketMessage::AnswerQuestion {
+                id: question_answer.id.clone(),
+                answer: question_answer.answer.clone(),
+            })?;
+        }
+        // The simulator has no email support, an empty list keeps the frontend happy
+        Command::GetDistributionLists => {
+            broadcast(sessions, || WebsocketMessage::DistributionLists(Default::default()))?;
+        }
+        Command::SetMeetingTitle { title } => {
+            let mut meeting = meeting.lock()?;
+            meeting.title = Some(title);
+            store.save_meeting(&meeting)?;
+        }
+        // Handled per session by the websocket handler
+        Command::MonitorAudio { .. } => warn!("MonitorAudio reached the command thread"),
+    }
+
+    Ok(())
+}
+
+fn monitor_audio(receiver: Receiver<Vec<u8>>, sessions: Sessions, monitors: Monitors) -> Result<(), CustomError> {
+    let mut frames = 0usize;
+
+    while let Ok(microphone_data) = receiver.recv() {
+        let monitors = monitors.lock()?.clone();
+
+        if monitors.is_empty() {
+            continue;
+        }
+
+        frames += 1;
+
+        let level = match frames % MONITOR_LEVEL_INTERVAL == 0 {
+            true => Some(AudioLevel::measure(microphone_data.as_slice())),
+            false => None,
+        };
+
+        let sessions = sessions.lock()?;
+
+        // A tab that was just closed may not be removed yet, it mustn't end the monitor and with it the microphone
+        for (session_id, notifier) in sessions.iter() {
+            if monitors.contains(session_id) == false {
+                continue;
+            }
+
+            if notifier.send(WebsocketMessage::Audio(microphone_data.clone())).is_err() {
+                warn!("monitoring session {} is gone", session_id);
+                continue;
+            }
+
+            if let Some(level) = level {
+                notifier.send(WebsocketMessage::AudioLevel { rms: level.rms, peak: level.peak }).ok();
+            }
+        }
+    }
+
+    Ok(())
+}
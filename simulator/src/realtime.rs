@@ -0,0 +1,105 @@
+use std::time::Duration;
+
+use crossbeam_channel::{unbounded, Receiver, Sender};
+use echosense_core::assembly::{Assembly, AssemblyResponse};
+use echosense_core::error::CoreError;
+use echosense_core::platform::{HttpClient, WsClient};
+use log::{info, warn};
+
+use crate::custom_error::CustomError;
+use crate::websocket::TungsteniteClient;
+
+const MAX_RECONNECT_ATTEMPTS: u32 = 5;
+const RECONNECT_BACKOFF: Duration = Duration::from_millis(500);
+
+// A realtime transcription session that survives dropped connections, every attempt fetches a fresh temporary token
+pub struct RealtimeSession<H: HttpClient> {
+    assembly: Assembly<H>,
+    sample_rate: u32,
+    responses: Sender<AssemblyResponse>,
+    client: Option<TungsteniteClient>,
+    reconnects: usize,
+}
+
+impl<H: HttpClient> RealtimeSession<H> {
+    pub fn connect(assembly: Assembly<H>, sample_rate: u32) -> Result<(Self, Receiver<AssemblyResponse>), CustomError> {
+        let (responses, receiver) = unbounded::<AssemblyResponse>();
+
+        let mut session = Self {
+            assembly,
+            sample_rate,
+            responses,
+            client: None,
+            reconnects: 0,
+        };
+
+        session.client = Some(session.open()?);
+
+        Ok((session, receiver))
+    }
+
+    pub fn reconnects(&self) -> usize {
+        self.reconnects
+    }
+
+    // Asks the realtime API to flush the last final transcript and close the session
+    pub fn terminate(&mut self) -> Result<(), CoreError> {
+        match self.client.as_mut() {
+            Some(client) => client.terminate(),
+            None => Ok(()),
+        }
+    }
+
+    fn open(&mut self) -> Result<TungsteniteClient, CustomError> {
+        let token = self.assembly.create_temporary_token()?.token;
+        let url = self.assembly.realtime_url(self.sample_rate, &token);
+
+        TungsteniteClient::connect(&url, self.responses.clone())
+    }
+
+    fn reconnect(&mut self) -> Result<&mut TungsteniteClient, CoreError> {
+        self.client = None;
+
+        for attempt in 1..=MAX_RECONNECT_ATTEMPTS {
+            match self.open() {
+                Ok(client) => {
+                    self.reconnects += 1;
+
+                    info!("realtime session reconnected after {} attempt(s)", attempt);
+
+                    return Ok(self.client.insert(client));
+                }
+                Err(error) if attempt < MAX_RECONNECT_ATTEMPTS => {
+                    warn!("failed to reconnect the realtime session: {:?}", error);
+
+                    std::thread::sleep(RECONNECT_BACKOFF * attempt);
+                }
+                Err(error) => return Err(error.into()),
+            }
+        }
+
+        unreachable!("the last attempt always returns")
+    }
+
+    fn send(&mut self, send: impl Fn(&mut TungsteniteClient) -> Result<(), CoreError>) -> Result<(), CoreError> {
+        if let Some(client) = self.client.as_mut().filter(|client| client.is_connected()) {
+            match send(client) {
+                Ok(()) => return Ok(()),
+                Err(error) => warn!("realtime session dropped: {:?}", error),
+            }
+        }
+
+        // Whatever was being sent goes out again on the new connection
+        send(self.reconnect()?)
+    }
+}
+
+impl<H: HttpClient> WsClient for RealtimeSession<H> {
+    fn send_binary(&mut self, data: &[u8]) -> Result<(), CoreError> {
+        self.send(|client| client.send_binary(data))
+    }
+
+    fn send_text(&mut self, text: &str) -> Result<(), CoreError> {
+        self.send(|client| client.send_text(text))
+    }
+}
@@ -0,0 +1,288 @@
+use std::collections::{BTreeMap, HashMap, HashSet};
+use std::io::{ErrorKind, Read, Write};
+use std::net::{SocketAddr, TcpListener, TcpStream};
+use std::path::PathBuf;
+use std::sync::atomic::{AtomicI32, Ordering};
+use std::sync::{Arc, Mutex};
+use std::thread::spawn;
+use std::time::Duration;
+
+use crossbeam_channel::{unbounded, Receiver, Sender};
+use echosense_core::meeting::{SharedMeeting, Transcription};
+use log::{error, info, warn};
+use serde::{Deserialize, Serialize};
+use tungstenite::{Message, WebSocket};
+
+use crate::custom_error::CustomError;
+
+const WEBSOCKET_POLL_INTERVAL: Duration = Duration::from_millis(20);
+
+pub type Sessions = Arc<Mutex<HashMap<i32, Sender<WebsocketMessage>>>>;
+pub type Monitors = Arc<Mutex<HashSet<i32>>>;
+
+// The commands the simulator understands, a subset of the device's, anything else is logged and ignored
+#[derive(Debug, Deserialize)]
+pub enum Command {
+    StartMeeting,
+    GetSummary,
+    AskQuestion { id: String, question: String },
+    GetDistributionLists,
+    SetMeetingTitle { title: String },
+    MonitorAudio { enabled: bool },
+}
+
+#[derive(Debug, Deserialize)]
+struct Action {
+    command: serde_json::Value,
+}
+
+// Serialized exactly like the device does, the frontend can't tell them apart
+#[derive(Debug, Serialize)]
+pub enum WebsocketMessage {
+    MeetingId(String),
+    SessionId(i32),
+    Transcriptions(Vec<Transcription>),
+    PartialTranscription(Transcription),
+    FinalTranscription(Transcription),
+    Summary(String),
+    AnswerQuestion { id: String, answer: String },
+    AudioLevel { rms: f32, peak: f32 },
+    MonitoringAudio(bool),
+    DistributionLists(BTreeMap<String, serde_json::Value>),
+    #[serde(skip)]
+    Audio(Vec<u8>),
+}
+
+pub struct Server {
+    listener: TcpListener,
+    index: PathBuf,
+    sessions: Sessions,
+    monitors: Monitors,
+    next_session: Arc<AtomicI32>,
+}
+
+impl Server {
+    pub fn new(port: u16, index: PathBuf) -> Result<Self, CustomError> {
+        Ok(Self {
+            listener: TcpListener::bind(("0.0.0.0", port))?,
+            index,
+            sessions: Arc::new(Mutex::new(HashMap::new())),
+            monitors: Arc::new(Mutex::new(HashSet::new())),
+            next_session: Arc::new(AtomicI32::new(1)),
+        })
+    }
+
+    pub fn local_addr(&self) -> Result<SocketAddr, CustomError> {
+        Ok(self.listener.local_addr()?)
+    }
+
+    pub fn sessions(&self) -> Sessions {
+        self.sessions.clone()
+    }
+
+    pub fn monitors(&self) -> Monitors {
+        self.monitors.clone()
+    }
+
+    pub fn run(self, commands: Sender<Command>, meeting: SharedMeeting) -> Result<(), CustomError> {
+        for stream in self.listener.incoming() {
+            let stream = match stream {
+                Ok(stream) => stream,
+                Err(error) => {
+                    warn!("failed to accept connection: {:?}", error);
+                    continue;
+                }
+            };
+
+            let index = self.index.clone();
+            let sessions = self.sessions.clone();
+            let monitors = self.monitors.clone();
+            let session_id = self.next_session.fetch_add(1, Ordering::Relaxed);
+            let commands = commands.clone();
+            let meeting = meeting.clone();
+
+            spawn(move || {
+                let result = match request_path(&stream) {
+                    Ok(path) if path == "/connect" => {
+                        handle_websocket(stream, session_id, sessions, monitors, commands, meeting)
+                    }
+                    Ok(path) => handle_http(stream, &path, &index),
+                    Err(error) => Err(error),
+                };
+
+                if let Err(error) = result {
+                    error!("connection failed: {:?}", error);
+                }
+            });
+        }
+
+        Ok(())
+    }
+}
+
+// Peeks at the request line without consuming it, the websocket handshake still needs the whole request
+fn request_path(stream: &TcpStream) -> Result<String, CustomError> {
+    let mut buffer = [0u8; 1024];
+
+    loop {
+        let length = stream.peek(&mut buffer)?;
+        let request = String::from_utf8_lossy(&buffer[..length]);
+
+        if let Some((line, _)) = request.split_once("\r\n") {
+            let path = line.split_whitespace().nth(1).unwrap_or("/");
+
+            return Ok(path.split('?').next().unwrap_or(path).to_string());
+        }
+
+        if length == 0 || length == buffer.len() {
+            return Err(CustomError::IOError(ErrorKind::InvalidData.into()));
+        }
+    }
+}
+
+fn handle_http(mut stream: TcpStream, path: &str, index: &PathBuf) -> Result<(), CustomError> {
+    // Requests are only ever GETs without a body, read up to the end of the headers
+    let mut request = vec![];
+    let mut buffer = [0u8; 1024];
+
+    while request.ends_with(b"\r\n\r\n") == false {
+        match stream.read(&mut buffer)? {
+            0 => break,
+            length => request.extend_from_slice(&buffer[..length]),
+        }
+    }
+
+    let (status, content_type, body) = match path {
+        "/" | "/index.html" => match std::fs::read(index) {
+            Ok(body) => ("200 OK", "text/html", body),
+            Err(_) => (
+                "200 OK",
+                "text/html",
+                format!(
+                    "<p>{} not found, build the frontend with <code>yarn build</code> or pass --frontend.</p>",
+                    index.display()
+                )
+                .into_bytes(),
+            ),
+        },
+        _ => ("404 Not Found", "text/plain", b"Not found".to_vec()),
+    };
+
+    write!(
+        stream,
+        "HTTP/1.1 {}\r\nContent-Type: {}\r\nContent-Length: {}\r\nConnection: close\r\n\r\n",
+        status,
+        content_type,
+        body.len()
+    )?;
+
+    stream.write_all(&body)?;
+
+    Ok(stream.flush()?)
+}
+
+fn handle_websocket(
+    stream: TcpStream,
+    session_id: i32,
+    sessions: Sessions,
+    monitors: Monitors,
+    commands: Sender<Command>,
+    meeting: SharedMeeting,
+) -> Result<(), CustomError> {
+    let mut socket = tungstenite::accept(stream).map_err(|error| match error {
+        tungstenite::HandshakeError::Failure(error) => CustomError::from(error),
+        tungstenite::HandshakeError::Interrupted(_) => CustomError::IOError(ErrorKind::WouldBlock.into()),
+    })?;
+
+    socket.get_ref().set_read_timeout(Some(WEBSOCKET_POLL_INTERVAL))?;
+
+    let (sender, receiver) = unbounded::<WebsocketMessage>();
+
+    info!("new session: {:?}", session_id);
+
+    {
+        let meeting = meeting.lock()?;
+
+        send(&mut socket, WebsocketMessage::MeetingId(meeting.id.clone()))?;
+
+        if meeting.transcriptions.is_empty() == false {
+            send(&mut socket, WebsocketMessage::Transcriptions(meeting.transcriptions.clone()))?;
+        }
+    }
+
+    send(&mut socket, WebsocketMessage::SessionId(session_id))?;
+
+    sessions.lock()?.insert(session_id, sender);
+
+    let result = serve_session(&mut socket, session_id, &receiver, &monitors, &commands);
+
+    sessions.lock()?.remove(&session_id);
+    monitors.lock()?.remove(&session_id);
+
+    info!("closed websocket session {:?}", session_id);
+
+    result
+}
+
+fn serve_session(
+    socket: &mut WebSocket<TcpStream>,
+    session_id: i32,
+    receiver: &Receiver<WebsocketMessage>,
+    monitors: &Monitors,
+    commands: &Sender<Command>,
+) -> Result<(), CustomError> {
+    loop {
+        while let Ok(message) = receiver.try_recv() {
+            send(socket, message)?;
+        }
+
+        let text = match socket.read() {
+            Ok(Message::Text(text)) => text,
+            Ok(Message::Close(_)) => return Ok(()),
+            Ok(_) => continue,
+            Err(tungstenite::Error::Io(error))
+                if matches!(error.kind(), ErrorKind::WouldBlock | ErrorKind::TimedOut) =>
+            {
+                continue
+            }
+            Err(tungstenite::Error::ConnectionClosed | tungstenite::Error::AlreadyClosed) => return Ok(()),
+            Err(tungstenite::Error::Protocol(tungstenite::error::ProtocolError::ResetWithoutClosingHandshake)) => {
+                return Ok(())
+            }
+            Err(error) => return Err(error.into()),
+        };
+
+        let action: Action = serde_json::from_str(&text)?;
+
+        let command = match serde_json::from_value::<Command>(action.command.clone()) {
+            Ok(command) => command,
+            Err(_) => {
+                warn!("command not supported by the simulator: {}", action.command);
+                continue;
+            }
+        };
+
+        // Monitoring is per session, so it is handled here rather than by the command thread
+        if let Command::MonitorAudio { enabled } = command {
+            match enabled {
+                true => monitors.lock()?.insert(session_id),
+                false => monitors.lock()?.remove(&session_id),
+            };
+
+            send(socket, WebsocketMessage::MonitoringAudio(enabled))?;
+
+            continue;
+        }
+
+        commands.send(command)?;
+    }
+}
+
+fn send(socket: &mut WebSocket<TcpStream>, message: WebsocketMessage) -> Result<(), CustomError> {
+    let message = match message {
+        WebsocketMessage::Audio(samples) => Message::Binary(samples),
+        message => Message::Text(serde_json::to_string(&message)?),
+    };
+
+    Ok(socket.send(message)?)
+}
@@ -0,0 +1,101 @@
+use std::io::ErrorKind;
+use std::net::TcpStream;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{Arc, Mutex};
+use std::thread::spawn;
+use std::time::Duration;
+
+use crossbeam_channel::Sender;
+use echosense_core::assembly::{AssemblyResponse, ResponseBuffer};
+use echosense_core::error::CoreError;
+use echosense_core::platform::WsClient;
+use log::{error, info};
+use tungstenite::stream::MaybeTlsStream;
+use tungstenite::{Message, WebSocket};
+
+use crate::custom_error::CustomError;
+
+// Short enough that the audio thread never waits long for the socket while the reader is polling it
+const READ_POLL_INTERVAL: Duration = Duration::from_millis(10);
+
+type Socket = Arc<Mutex<WebSocket<MaybeTlsStream<TcpStream>>>>;
+
+#[derive(Clone)]
+pub struct TungsteniteClient {
+    socket: Socket,
+    connected: Arc<AtomicBool>,
+}
+
+impl TungsteniteClient {
+    // Responses are forwarded to `sender`, which can outlive the connection, e.g. across reconnects
+    pub fn connect(url: &str, sender: Sender<AssemblyResponse>) -> Result<Self, CustomError> {
+        let (socket, _) = tungstenite::connect(url)?;
+
+        match socket.get_ref() {
+            MaybeTlsStream::Plain(stream) => stream.set_read_timeout(Some(READ_POLL_INTERVAL))?,
+            MaybeTlsStream::Rustls(stream) => stream.get_ref().set_read_timeout(Some(READ_POLL_INTERVAL))?,
+            _ => {}
+        }
+
+        info!("connected to {}", url.split('?').next().unwrap_or(url));
+
+        let socket = Arc::new(Mutex::new(socket));
+        let connected = Arc::new(AtomicBool::new(true));
+        let reader = socket.clone();
+        let alive = connected.clone();
+
+        spawn(move || {
+            let mut response_buffer = ResponseBuffer::default();
+
+            loop {
+                let message = match reader.lock() {
+                    Ok(mut socket) => socket.read(),
+                    Err(error) => break error!("websocket lock poisoned: {:?}", error),
+                };
+
+                match message {
+                    Ok(Message::Text(text)) => {
+                        if let Some(response) = response_buffer.push(text.as_bytes()) {
+                            if sender.send(response).is_err() {
+                                break;
+                            }
+                        }
+                    }
+                    Ok(Message::Close(frame)) => break info!("websocket closed: {:?}", frame),
+                    Ok(_) => {}
+                    Err(tungstenite::Error::Io(error))
+                        if matches!(error.kind(), ErrorKind::WouldBlock | ErrorKind::TimedOut) =>
+                    {
+                        // Give writers a chance to grab the socket
+                        std::thread::sleep(READ_POLL_INTERVAL / 2)
+                    }
+                    Err(error) => break error!("websocket error: {:?}", error),
+                }
+            }
+
+            alive.store(false, Ordering::Relaxed);
+        });
+
+        Ok(Self { socket, connected })
+    }
+
+    // Turns false as soon as the reader notices the connection is gone, which is usually before a write fails
+    pub fn is_connected(&self) -> bool {
+        self.connected.load(Ordering::Relaxed)
+    }
+
+    // Asks the realtime API to flush the last final transcript and close the session
+    pub fn terminate(&mut self) -> Result<(), CoreError> {
+        self.send_text("{\"terminate_session\": true}")
+    }
+}
+
+impl WsClient for TungsteniteClient {
+    fn send_binary(&mut self, data: &[u8]) -> Result<(), CoreError> {
+        Ok(self.socket.lock()?.send(Message::Binary(data.to_vec())).map_err(CustomError::from)?)
+    }
+
+    fn send_text(&mut self, text: &str) -> Result<(), CoreError> {
+        Ok(self.socket.lock()?.send(Message::Text(text.to_string())).map_err(CustomError::from)?)
+    }
+}
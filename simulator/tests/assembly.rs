@@ -0,0 +1,184 @@
+mod common;
+
+use echosense_core::assembly::{Assembly, TranscriptionStatus};
+use echosense_core::audio::wav_header;
+use echosense_core::codec::RecordingCodec;
+use echosense_core::error::CoreError;
+use echosense_core::meeting::Transcription;
+use echosense_mock::{Failure, MockAssembly, Script};
+use echosense_simulator::http_client::UreqClient;
+
+use common::assembly;
+
+fn transcriptions() -> Vec<Transcription> {
+    ["The release is planned for next Friday", "Anna will prepare the changelog"]
+        .iter()
+        .map(|text| Transcription {
+            text: text.to_string(),
+            timestamp: "2024-12-01T10:00:00".to_string(),
+            audio_start: 0,
+            audio_end: 0,
+        })
+        .collect()
+}
+
+#[test]
+fn summarizes_transcripts() {
+    let mock = MockAssembly::start(Script::default()).unwrap();
+
+    let response = assembly(&mock).summarize_transcripts(transcriptions()).unwrap();
+
+    assert_eq!(response.response, Script::default().summary);
+
+    let request = mock.requests().pop().unwrap();
+    let body: serde_json::Value = serde_json::from_slice(&request.body).unwrap();
+
+    assert_eq!(request.path, "/lemur/v3/generate/summary");
+    assert_eq!(
+        body["input_text"],
+        "The release is planned for next Friday\nAnna will prepare the changelog"
+    );
+}
+
+#[test]
+fn extracts_action_items() {
+    let mock = MockAssembly::start(Script::default()).unwrap();
+
+    let response = assembly(&mock).action_items(transcriptions()).unwrap();
+
+    assert_eq!(response.response, Script::default().action_items);
+    assert_eq!(mock.requests_to("/lemur/v3/generate/action-items"), 1);
+}
+
+#[test]
+fn answers_questions() {
+    let mock = MockAssembly::start(Script::default()).unwrap();
+
+    let response = assembly(&mock)
+        .ask_question("When is the release?", transcriptions())
+        .unwrap();
+
+    assert_eq!(response.response.len(), 1);
+    assert_eq!(response.response[0].question, "When is the release?");
+    assert_eq!(response.response[0].answer, Script::default().answer);
+}
+
+#[test]
+fn sends_the_api_key() {
+    let mock = MockAssembly::start(Script {
+        api_key: Some("test-key".to_string()),
+        ..Default::default()
+    })
+    .unwrap();
+
+    assert!(assembly(&mock).create_temporary_token().is_ok());
+
+    let error = Assembly::new("wrong-key", UreqClient::new())
+        .with_base_url(mock.base_url())
+        .create_temporary_token()
+        .unwrap_err();
+
+    assert!(matches!(error, CoreError::HttpError { status: 401, .. }));
+}
+
+#[test]
+fn surfaces_server_errors_with_their_body() {
+    let mock = MockAssembly::start(Script {
+        failures: vec![Failure {
+            path: "/lemur/v3/generate/summary".to_string(),
+            status: 500,
+            times: 1,
+        }],
+        ..Default::default()
+    })
+    .unwrap();
+
+    let mut assembly = assembly(&mock);
+
+    match assembly.summarize_transcripts(transcriptions()) {
+        Err(CoreError::HttpError { status, body }) => {
+            assert_eq!(status, 500);
+            assert!(body.contains("scripted failure"));
+        }
+        other => panic!("expected an http error, got {:?}", other),
+    }
+
+    // The failure was scripted once, the endpoint recovers afterwards
+    assert!(assembly.summarize_transcripts(transcriptions()).is_ok());
+}
+
+#[test]
+fn fails_when_the_backend_is_unreachable() {
+    let error = Assembly::new("test-key", UreqClient::new())
+        .with_base_url("http://127.0.0.1:9")
+        .create_temporary_token()
+        .unwrap_err();
+
+    assert!(matches!(error, CoreError::PlatformError(_)));
+}
+
+#[test]
+fn polls_until_the_transcript_completes() {
+    let mock = MockAssembly::start(Script {
+        transcript_polls: 3,
+        ..Default::default()
+    })
+    .unwrap();
+
+    let mut assembly = assembly(&mock);
+    let upload_url = "https://cdn.mock/uploads/1";
+
+    let queued = assembly.transcribe(upload_url).unwrap();
+    assert_eq!(queued.status, TranscriptionStatus::Queued);
+
+    let response = assembly.transcribe_wait(upload_url).unwrap();
+
+    assert_eq!(response.status, TranscriptionStatus::Completed);
+    assert_eq!(response.text.as_deref(), Some(Script::default().transcript_text.as_str()));
+
+    // Three polls while processing and the one that completed
+    assert_eq!(mock.requests_to("/v2/transcript/transcript-2"), 4);
+}
+
+#[test]
+fn reports_failed_transcripts() {
+    let mock = MockAssembly::start(Script::default()).unwrap();
+
+    let error = assembly(&mock)
+        .transcribe_wait("https://example.com/missing.wav")
+        .unwrap_err();
+
+    assert!(matches!(error, CoreError::HttpError { .. }));
+}
+
+#[test]
+fn reports_unknown_transcripts() {
+    let mock = MockAssembly::start(Script::default()).unwrap();
+
+    let error = assembly(&mock).get_transcript("does-not-exist").unwrap_err();
+
+    assert!(matches!(error, CoreError::HttpError { status: 404, .. }));
+}
+
+#[test]
+fn uploads_a_recording_and_transcribes_it() {
+    let mock = MockAssembly::start(Script::default()).unwrap();
+    let mut assembly = assembly(&mock);
+
+    let pcm = vec![0x2au8; 32_000];
+    let mut audio = wav_header(pcm.len() as u32, 16000, 1, 16).to_vec();
+    audio.extend_from_slice(&pcm);
+
+    let upload = assembly.upload(RecordingCodec::Pcm.content_type(), &audio).unwrap();
+    assert_eq!(upload.upload_url, "https://cdn.mock/uploads/1");
+
+    let request = mock.requests().pop().unwrap();
+
+    assert_eq!(request.path, "/v2/upload");
+    assert_eq!(request.content_type.as_deref(), Some("audio/wav"));
+    assert_eq!(request.body.len(), 44 + pcm.len());
+    assert_eq!(request.body, audio);
+
+    let response = assembly.transcribe_wait(upload.upload_url).unwrap();
+    assert_eq!(response.status, TranscriptionStatus::Completed);
+}
@@ -0,0 +1,16 @@
+// Every test file compiles its own copy of these and uses only some of them
+#![allow(dead_code, unused_imports)]
+
+use echosense_core::assembly::Assembly;
+use echosense_mock::MockAssembly;
+use echosense_simulator::http_client::UreqClient;
+
+// The helpers of the core tests, shared instead of copied
+#[path = "../../../core/tests/common/mod.rs"]
+mod core_common;
+
+pub use core_common::*;
+
+pub fn assembly(mock: &MockAssembly) -> Assembly<UreqClient> {
+    Assembly::new("test-key", UreqClient::new()).with_base_url(mock.base_url())
+}
@@ -0,0 +1,259 @@
+mod common;
+
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+
+use crossbeam_channel::{unbounded, Receiver};
+use echosense_core::assembly::AssemblyResponse;
+use echosense_core::audio::wav_header;
+use echosense_core::combine::{CombineMode, Combiner};
+use echosense_core::dsp::{DspChain, DspConfig};
+use echosense_core::host::{SystemClock, WavFileSource};
+use echosense_core::meeting::{Meeting, SilentRegion};
+use echosense_core::pipeline::{handle_transcript, record_audio, record_microphone, start_live_transcription, TranscriptEvent};
+use echosense_core::platform::{RecordingStore, WsClient};
+use echosense_core::store::FileStore;
+use echosense_core::vad::{VadConfig, VoiceGate};
+use echosense_mock::{Failure, MockAssembly, Script};
+use echosense_simulator::realtime::RealtimeSession;
+use echosense_simulator::websocket::TungsteniteClient;
+
+use common::{assembly, sine, temporary_directory};
+
+const FRAME: [u8; 1000] = [0; 1000];
+const TIMEOUT: Duration = Duration::from_secs(5);
+
+fn texts(receiver: &Receiver<AssemblyResponse>, until: impl Fn(&AssemblyResponse) -> bool) -> Vec<String> {
+    let mut texts = vec![];
+
+    loop {
+        let response = receiver.recv_timeout(TIMEOUT).expect("timed out waiting for the realtime api");
+
+        if let AssemblyResponse::FinalTranscript { text, .. } = &response {
+            texts.push(text.clone());
+        }
+
+        if until(&response) {
+            break texts;
+        }
+    }
+}
+
+#[test]
+fn streams_scripted_transcripts() {
+    let script = Script {
+        realtime: Script::final_transcripts(&["Hello there", "General Kenobi"]),
+        frames_per_message: 2,
+        ..Default::default()
+    };
+
+    let mock = MockAssembly::start(script).unwrap();
+    let (mut session, receiver) = RealtimeSession::connect(assembly(&mock), 16000).unwrap();
+
+    assert!(matches!(receiver.recv_timeout(TIMEOUT).unwrap(), AssemblyResponse::SessionBegins { .. }));
+
+    for _ in 0..8 {
+        session.send_binary(&FRAME).unwrap();
+    }
+
+    let partial = receiver.recv_timeout(TIMEOUT).unwrap();
+    assert!(matches!(partial, AssemblyResponse::PartialTranscript { ref text, .. } if text == "Hello"));
+
+    let texts = texts(&receiver, |response| {
+        matches!(response, AssemblyResponse::FinalTranscript { text, .. } if text == "General Kenobi")
+    });
+
+    assert_eq!(texts, vec!["Hello there", "General Kenobi"]);
+    assert_eq!(mock.sessions(), 1);
+    assert_eq!(mock.audio_bytes(), 8 * FRAME.len());
+    assert_eq!(session.reconnects(), 0);
+}
+
+#[test]
+fn terminating_flushes_the_session() {
+    let mock = MockAssembly::start(Script::default()).unwrap();
+    let (mut session, receiver) = RealtimeSession::connect(assembly(&mock), 16000).unwrap();
+
+    for _ in 0..32 {
+        session.send_binary(&FRAME).unwrap();
+    }
+
+    session.terminate().unwrap();
+
+    let mut duration = None;
+
+    loop {
+        match receiver.recv_timeout(TIMEOUT).unwrap() {
+            AssemblyResponse::SessionInformation { audio_duration_seconds } => duration = Some(audio_duration_seconds),
+            AssemblyResponse::SessionTerminated => break,
+            _ => {}
+        }
+    }
+
+    assert_eq!(duration, Some(1.0));
+}
+
+#[test]
+fn reconnects_after_the_connection_drops() {
+    let script = Script {
+        realtime: Script::final_transcripts(&["one", "two", "three"]),
+        frames_per_message: 1,
+        drop_after_frames: Some(2),
+        ..Default::default()
+    };
+
+    let mock = MockAssembly::start(script).unwrap();
+    let (mut session, receiver) = RealtimeSession::connect(assembly(&mock), 16000).unwrap();
+
+    session.send_binary(&FRAME).unwrap();
+
+    assert_eq!(texts(&receiver, |response| matches!(response, AssemblyResponse::FinalTranscript { .. })), vec!["one"]);
+
+    // The mock hangs up on the second frame, keep streaming until the session notices and comes back
+    for _ in 0..50 {
+        session.send_binary(&FRAME).unwrap();
+
+        if session.reconnects() > 0 {
+            break;
+        }
+
+        std::thread::sleep(Duration::from_millis(20));
+    }
+
+    assert_eq!(session.reconnects(), 1);
+    assert_eq!(mock.sessions(), 2);
+    assert_eq!(mock.requests_to("/v2/realtime/token"), 2);
+
+    // The new session starts over with the script, delivered on the same receiver
+    session.send_binary(&FRAME).unwrap();
+
+    let texts = texts(&receiver, |response| matches!(response, AssemblyResponse::FinalTranscript { .. }));
+    assert_eq!(texts, vec!["one"]);
+}
+
+#[test]
+fn fails_to_connect_without_a_token() {
+    let mock = MockAssembly::start(Script {
+        failures: vec![Failure {
+            path: "/v2/realtime/token".to_string(),
+            status: 503,
+            times: 1,
+        }],
+        ..Default::default()
+    })
+    .unwrap();
+
+    assert!(RealtimeSession::connect(assembly(&mock), 16000).is_err());
+    assert_eq!(mock.sessions(), 0);
+}
+
+#[test]
+fn rejects_unknown_tokens() {
+    let mock = MockAssembly::start(Script::default()).unwrap();
+    let (sender, _receiver) = unbounded();
+
+    let url = assembly(&mock).realtime_url(16000, "not-a-token");
+
+    assert!(TungsteniteClient::connect(&url, sender).is_err());
+    assert_eq!(mock.sessions(), 0);
+}
+
+#[test]
+fn runs_the_pipeline_on_a_wav_file() {
+    let directory = temporary_directory("pipeline");
+    std::fs::create_dir_all(&directory).unwrap();
+    let wav = directory.join("meeting.wav");
+
+    // Three seconds of stereo silence followed by a second of tone, the source keeps the first channel only
+    let mut samples = vec![0u8; 3 * 16000 * 2 * 2];
+
+    for sample in sine(440.0, 8000.0, 16000, 1.0) {
+        samples.extend_from_slice(&sample.to_le_bytes());
+        samples.extend_from_slice(&0i16.to_le_bytes());
+    }
+
+    let mut file = wav_header(samples.len() as u32, 16000, 2, 16).to_vec();
+    file.extend_from_slice(&samples);
+    std::fs::write(&wav, file).unwrap();
+
+    let script = Script {
+        realtime: Script::final_transcripts(&["Welcome to the meeting"]),
+        frames_per_message: 4,
+        ..Default::default()
+    };
+
+    let mock = MockAssembly::start(script).unwrap();
+    let store = FileStore::new(directory.join("sdcard"));
+    let meeting = Arc::new(Mutex::new(Meeting::new("cafe0001")));
+
+    let mut source = WavFileSource::open(&wav, 1000, Arc::new(SystemClock::new()), false).unwrap();
+    let (mut session, responses) = RealtimeSession::connect(assembly(&mock), 16000).unwrap();
+
+    let (sender_a, receiver_a) = unbounded::<Vec<u8>>();
+    let (sender_b, receiver_b) = unbounded::<Vec<u8>>();
+
+    let mut combiner = Combiner::new(CombineMode::Mix, 1);
+    let mut dsp = DspChain::new(&DspConfig::default(), 16000);
+
+    record_microphone(vec![sender_a, sender_b], &mut source, &mut combiner, &mut dsp, None).unwrap();
+
+    let mut gate = VoiceGate::new(16000, VadConfig::default());
+    let timeline = gate.timeline();
+
+    start_live_transcription(receiver_a, &mut session, &mut gate, &meeting, &store).unwrap();
+    record_audio(receiver_b, meeting.clone(), &store).unwrap();
+
+    let mut finals = 0;
+
+    while finals == 0 {
+        let response = responses.recv_timeout(TIMEOUT).unwrap();
+
+        if let Some(TranscriptEvent::Final { .. }) = handle_transcript(response, &meeting, &store, &timeline).unwrap() {
+            finals += 1;
+        }
+    }
+
+    // The silence is held back apart from 300 ms of pre-roll (nine 1000 byte frames), the recording keeps everything
+    assert_eq!(mock.audio_bytes(), 32000 + 9000);
+    assert_eq!(store.recording_length("cafe0001").unwrap(), 4 * 32000);
+
+    let saved = store.load_meeting("cafe0001").unwrap();
+    assert_eq!(saved.transcriptions.len(), 1);
+    assert_eq!(saved.transcriptions[0].text, "Welcome to the meeting");
+
+    // The API saw the pre-roll as its start, which is 2718 ms into the recording
+    assert_eq!(saved.transcriptions[0].audio_start, 2718);
+    assert_eq!(saved.silent_regions, vec![SilentRegion { start_ms: 0, end_ms: 2718 }]);
+    assert_eq!(saved.talk_time.streamed_ms, 1281);
+    assert_eq!(saved.talk_time.speech_ms + saved.talk_time.silence_ms, 4000);
+
+    std::fs::remove_dir_all(directory).ok();
+}
+
+#[test]
+fn keeps_transcripts_in_memory_without_storage() {
+    let directory = temporary_directory("no-storage");
+    std::fs::create_dir_all(&directory).unwrap();
+
+    // Nothing can be created under a file, like a device without an SD card
+    std::fs::write(directory.join("sdcard"), b"").unwrap();
+
+    let store = FileStore::new(directory.join("sdcard"));
+    let meeting = Arc::new(Mutex::new(Meeting::new("cafe0002")));
+    let timeline = VoiceGate::new(16000, VadConfig::default()).timeline();
+
+    let response = AssemblyResponse::FinalTranscript {
+        text: "Nobody wrote this down".to_string(),
+        created: "2024-12-01T10:00:00".to_string(),
+        audio_start: 0,
+        audio_end: 1000,
+    };
+
+    let event = handle_transcript(response, &meeting, &store, &timeline).unwrap();
+
+    assert!(matches!(event, Some(TranscriptEvent::Final { .. })));
+    assert_eq!(meeting.lock().unwrap().transcriptions[0].text, "Nobody wrote this down");
+    assert!(store.load_meeting("cafe0002").is_err());
+
+    std::fs::remove_dir_all(directory).ok();
+}
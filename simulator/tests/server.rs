@@ -0,0 +1,131 @@
+use std::io::{Read, Write};
+use std::net::{SocketAddr, TcpStream};
+use std::sync::{Arc, Mutex};
+use std::thread::spawn;
+use std::time::Duration;
+
+use crossbeam_channel::{unbounded, Receiver};
+use echosense_core::meeting::{Meeting, Transcription};
+use echosense_simulator::server::{Command, Server, Sessions, WebsocketMessage};
+use serde_json::{json, Value};
+use tungstenite::stream::MaybeTlsStream;
+use tungstenite::{Message, WebSocket};
+
+const TIMEOUT: Duration = Duration::from_secs(5);
+
+type Client = WebSocket<MaybeTlsStream<TcpStream>>;
+
+fn start() -> (SocketAddr, Sessions, Receiver<Command>) {
+    let index = std::env::temp_dir().join(format!("echosense-index-{}.html", std::process::id()));
+    std::fs::write(&index, "<h1>EchoSense</h1>").unwrap();
+
+    let mut meeting = Meeting::new("cafe0002");
+    meeting.transcriptions.push(Transcription {
+        text: "Hello".to_string(),
+        timestamp: "2024-12-01T10:00:00".to_string(),
+        audio_start: 0,
+        audio_end: 400,
+    });
+
+    let server = Server::new(0, index).unwrap();
+    let address = server.local_addr().unwrap();
+    let sessions = server.sessions();
+    let (commands, receiver) = unbounded::<Command>();
+
+    spawn(move || server.run(commands, Arc::new(Mutex::new(meeting))));
+
+    (address, sessions, receiver)
+}
+
+fn connect(address: SocketAddr) -> Client {
+    let (client, _) = tungstenite::connect(format!("ws://{}/connect", address)).unwrap();
+
+    if let MaybeTlsStream::Plain(stream) = client.get_ref() {
+        stream.set_read_timeout(Some(TIMEOUT)).unwrap();
+    }
+
+    client
+}
+
+fn next(client: &mut Client) -> Value {
+    loop {
+        if let Message::Text(text) = client.read().unwrap() {
+            return serde_json::from_str(&text).unwrap();
+        }
+    }
+}
+
+fn get(address: SocketAddr, path: &str) -> String {
+    let mut stream = TcpStream::connect(address).unwrap();
+    write!(stream, "GET {} HTTP/1.1\r\nHost: localhost\r\n\r\n", path).unwrap();
+
+    let mut response = String::new();
+    stream.read_to_string(&mut response).unwrap();
+
+    response
+}
+
+#[test]
+fn greets_new_sessions_like_the_device() {
+    let (address, _, _) = start();
+    let mut client = connect(address);
+
+    assert_eq!(next(&mut client), json!({ "MeetingId": "cafe0002" }));
+    assert_eq!(next(&mut client)["Transcriptions"][0]["text"], "Hello");
+    assert!(next(&mut client)["SessionId"].is_number());
+}
+
+#[test]
+fn forwards_commands_and_ignores_unsupported_ones() {
+    let (address, _, commands) = start();
+    let mut client = connect(address);
+
+    client
+        .send(Message::Text(json!({ "command": { "DeleteWebhook": { "url": "https://example.com" } } }).to_string()))
+        .unwrap();
+    client
+        .send(Message::Text(json!({ "command": { "AskQuestion": { "id": "1", "question": "When?" } } }).to_string()))
+        .unwrap();
+
+    match commands.recv_timeout(TIMEOUT).unwrap() {
+        Command::AskQuestion { id, question } => assert_eq!((id.as_str(), question.as_str()), ("1", "When?")),
+        command => panic!("unexpected command {:?}", command),
+    }
+
+    assert!(commands.try_recv().is_err());
+}
+
+#[test]
+fn monitors_audio_per_session() {
+    let (address, sessions, _) = start();
+    let mut client = connect(address);
+
+    for _ in 0..3 {
+        next(&mut client);
+    }
+
+    client
+        .send(Message::Text(json!({ "command": { "MonitorAudio": { "enabled": true } } }).to_string()))
+        .unwrap();
+
+    assert_eq!(next(&mut client), json!({ "MonitoringAudio": true }));
+
+    for (_, notifier) in sessions.lock().unwrap().iter() {
+        notifier.send(WebsocketMessage::Audio(vec![1, 2, 3, 4])).unwrap();
+        notifier.send(WebsocketMessage::Summary("done".to_string())).unwrap();
+    }
+
+    assert_eq!(client.read().unwrap(), Message::Binary(vec![1, 2, 3, 4]));
+    assert_eq!(next(&mut client), json!({ "Summary": "done" }));
+}
+
+#[test]
+fn serves_the_frontend() {
+    let (address, _, _) = start();
+
+    let index = get(address, "/");
+    assert!(index.starts_with("HTTP/1.1 200 OK"));
+    assert!(index.ends_with("<h1>EchoSense</h1>"));
+
+    assert!(get(address, "/missing").starts_with("HTTP/1.1 404"));
+}